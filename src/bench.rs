@@ -0,0 +1,117 @@
+use std::hint::black_box;
+use std::time::Instant;
+
+use matrix::Matrix;
+use geometry::PolygonList;
+use render::{ self, Canvas, Color, Point, Shading, Style, Styled };
+use solid;
+use exec;
+use config::RenderConfig;
+use consts::*;
+
+/// The scene rendered by the full-frame benchmark
+const SCENE: &str = "
+push
+move 250 250 0
+rotate x 30
+rotate y 20
+box -100 100 -100 200 200 200
+sphere 0 0 0 120
+torus 0 0 0 30 160
+";
+
+/// Run each benchmark and print how long one iteration takes on average.
+pub fn run_all() {
+    let rotation = Matrix::rotation_about_y(0.5);
+    let mut points = PolygonList::new();
+    solid::add_sphere(&mut points, 250.0, 250.0, 0.0, 200.0, solid::SPHERE_STEPS);
+    bench("matrix multiply (4x4 by 4x4800)", 2000, || {
+        black_box(points.transformed(&rotation));
+    });
+    bench("matrix multiply in place (4x4800)", 2000, || {
+        rotation.apply_to(&mut points);
+    });
+
+    let mut screen = vec![vec![Color::black(); WIDTH]; HEIGHT];
+    bench("lines (360 from the center)", 500, || {
+        for deg in 0..360 {
+            let a = (deg as f64).to_radians();
+            let end = Point::xy(250 + (240.0 * a.cos()) as i64, 250 + (240.0 * a.sin()) as i64);
+            render::line(&mut screen, Point::xy(250, 250), end, Color::white());
+        }
+    });
+
+    // A fan of long, thin triangles and a grid of small ones
+    let mut fills = vec![];
+    for deg in (0..360).filter(|deg| deg % 10 == 0) {
+        let (a, b) = ((deg as f64).to_radians(), (deg as f64 + 7.0).to_radians());
+        fills.push([Point::xy(250, 250), Point::xy(250 + (240.0 * a.cos()) as i64, 250 + (240.0 * a.sin()) as i64),
+                    Point::xy(250 + (240.0 * b.cos()) as i64, 250 + (240.0 * b.sin()) as i64)]);
+    }
+    for i in 0..400 {
+        let (x, y) = (20 + (i % 20) * 23, 20 + (i / 20) * 23);
+        fills.push([Point::xy(x, y), Point::xy(x + 20, y + 3), Point::xy(x + 6, y + 19)]);
+    }
+    bench("filled triangles", 500, || {
+        for t in &fills {
+            render::fill_triangle(&mut screen, t[0], t[1], t[2], Color::white());
+        }
+    });
+    let mut fill_list = PolygonList::new();
+    for t in &fills {
+        let corner = |p: Point| [p.x as f64 + 0.3, p.y as f64 + 0.6, 0.0, 1.0];
+        fill_list.add_triangle(corner(t[0]), corner(t[1]), corner(t[2]));
+    }
+    bench("triangle fragments", 500, || {
+        let mut count = 0;
+        render::fragments(&fill_list, WIDTH, HEIGHT, (0, HEIGHT), |f| count += f.x);
+        black_box(count);
+    });
+
+    // Spans of every length up to the width, over a plane sloping through
+    // what's already drawn, so about half of each is hidden
+    let mut depth = vec![vec![0.0; WIDTH]; HEIGHT];
+    let mut stencil = vec![vec![0u8; WIDTH]; HEIGHT];
+    bench("depth-tested spans", 500, || {
+        for row in depth.iter_mut() {
+            for z in row.iter_mut() {
+                *z = 0.0;
+            }
+        }
+        let mut canvas = Styled::new(&mut screen, Style::new(), &mut depth, &mut stencil, &mut [], 0);
+        canvas.depth_plane = Some([1.0, 0.0, -(WIDTH as f64) / 2.0]);
+        for y in 0..HEIGHT {
+            canvas.write_span(y as i64, 0, y % WIDTH, Color::white());
+        }
+    });
+
+    let mut triangles = PolygonList::new();
+    bench("sphere tessellation", 5000, || {
+        triangles.clear();
+        solid::add_sphere(&mut triangles, 0.0, 0.0, 0.0, 100.0, solid::SPHERE_STEPS);
+    });
+
+    bench("full frame", 200, || {
+        black_box(exec::render_headless(SCENE, &RenderConfig::new()).unwrap());
+    });
+    bench("full frame, filled", 200, || {
+        black_box(exec::render_headless(SCENE, &RenderConfig::new().shading(Shading::Filled)).unwrap());
+    });
+    bench("full frame, Gouraud shaded", 200, || {
+        black_box(exec::render_headless(SCENE, &RenderConfig::new().shading(Shading::Gouraud)).unwrap());
+    });
+}
+
+/// Time `iters` runs of `f` (after a few untimed ones) and print the average.
+pub fn bench<F: FnMut()>(name: &str, iters: usize, mut f: F) {
+    for _ in 0..iters / 10 + 1 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..iters {
+        f();
+    }
+    let elapsed = start.elapsed();
+    let nanos = elapsed.as_secs() as f64 * 1e9 + elapsed.subsec_nanos() as f64;
+    println!("{:<40} {:>14.0} ns/iter", name, nanos / iters as f64);
+}
@@ -0,0 +1,71 @@
+use std::io::{ self, Write };
+
+use render::Color;
+
+/// Write `image` as an uncompressed 24-bit BMP.
+pub fn write<W: Write>(out: &mut W, image: &[Vec<Color>]) -> io::Result<()> {
+    let height = image.len();
+    let width = if height > 0 { image[0].len() } else { 0 };
+    let row_size = (3 * width).div_ceil(4) * 4; // rows are padded to 4 bytes
+    let data_size = row_size * height;
+
+    let mut header = vec![];
+    // File header
+    header.extend_from_slice(b"BM");
+    push_u32(&mut header, (14 + 40 + data_size) as u32);
+    push_u32(&mut header, 0);
+    push_u32(&mut header, 14 + 40); // offset of pixel data
+    // BITMAPINFOHEADER
+    push_u32(&mut header, 40);
+    push_u32(&mut header, width as u32);
+    push_u32(&mut header, height as u32);
+    header.extend_from_slice(&[1, 0, 24, 0]); // 1 plane, 24 bits per pixel
+    push_u32(&mut header, 0); // no compression
+    push_u32(&mut header, data_size as u32);
+    push_u32(&mut header, 2835); // 72 DPI, horizontally
+    push_u32(&mut header, 2835); // and vertically
+    push_u32(&mut header, 0);
+    push_u32(&mut header, 0);
+    out.write_all(&header)?;
+
+    // Rows are stored bottom-up, pixels as BGR
+    let mut data = Vec::with_capacity(data_size);
+    for row in image.iter().rev() {
+        for px in row {
+            data.extend_from_slice(&[px.b, px.g, px.r]);
+        }
+        data.resize(data.len() + row_size - 3 * width, 0);
+    }
+    out.write_all(&data)
+}
+
+/// Append `x` to `v` in little-endian order.
+fn push_u32(v: &mut Vec<u8>, x: u32) {
+    v.extend_from_slice(&[x as u8, (x >> 8) as u8, (x >> 16) as u8, (x >> 24) as u8]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_padded_and_stored_bottom_up() {
+        // 3 pixels make 9 bytes a row, padded to 12
+        let image = vec![
+            vec![Color::rgb(1, 2, 3), Color::rgb(4, 5, 6), Color::rgb(7, 8, 9)],
+            vec![Color::rgb(10, 11, 12), Color::rgb(13, 14, 15), Color::rgb(16, 17, 18)]
+        ];
+        let mut bmp = vec![];
+        write(&mut bmp, &image).unwrap();
+        assert_eq!(bmp.len(), 14 + 40 + 24);
+        assert_eq!(&bmp[..6], &[b'B', b'M', 78, 0, 0, 0]);
+        // Offset of the pixels, width, height, and size of the pixels
+        assert_eq!(&bmp[10..14], &[54, 0, 0, 0]);
+        assert_eq!(&bmp[18..26], &[3, 0, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(&bmp[34..38], &[24, 0, 0, 0]);
+        assert_eq!(&bmp[54..], &[
+            12, 11, 10, 15, 14, 13, 18, 17, 16, 0, 0, 0,
+            3, 2, 1, 6, 5, 4, 9, 8, 7, 0, 0, 0
+        ]);
+    }
+}
@@ -0,0 +1,141 @@
+use matrix::Matrix;
+use transform::Transform;
+use std::f64;
+
+/// How much of the image `auto_scale_to_screen` fills, across whichever way
+/// the model fits tighter
+const FILL: f64 = 0.9;
+
+/// An axis-aligned bounding box: the points from `min` to `max` on every axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: [f64; 3],
+    pub max: [f64; 3]
+}
+
+impl Aabb {
+    /// The smallest box holding all of `points`, or `None` if there are none.
+    pub fn of(points: &[[f64; 4]]) -> Option<Aabb> {
+        if points.is_empty() {
+            return None;
+        }
+        let mut aabb = Aabb { min: [f64::INFINITY; 3], max: [f64::NEG_INFINITY; 3] };
+        for p in points {
+            for (axis, &x) in p.iter().enumerate().take(3) {
+                aabb.min[axis] = aabb.min[axis].min(x);
+                aabb.max[axis] = aabb.max[axis].max(x);
+            }
+        }
+        Some(aabb)
+    }
+
+    pub fn center(&self) -> [f64; 3] {
+        [(self.min[0] + self.max[0]) / 2.0, (self.min[1] + self.max[1]) / 2.0, (self.min[2] + self.max[2]) / 2.0]
+    }
+
+    /// Whether the box shares any points with `other`.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.min[axis] <= other.max[axis] && other.min[axis] <= self.max[axis])
+    }
+
+    /// Whether all of `sphere` is inside the box.
+    pub fn contains_sphere(&self, sphere: &BoundingSphere) -> bool {
+        (0..3).all(|axis| {
+            self.min[axis] <= sphere.center[axis] - sphere.radius && sphere.center[axis] + sphere.radius <= self.max[axis]
+        })
+    }
+}
+
+/// A sphere holding a set of points
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: [f64; 3],
+    pub radius: f64
+}
+
+/// The extent of a set of points, as a box and as a sphere. The sphere is
+/// centered on the box, so it isn't always the smallest one, but it's a
+/// handy size for fitting the points in view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere
+}
+
+impl Matrix {
+    /// The bounds of the columns of the matrix (a point, edge, or polygon
+    /// list), or `None` if it has none.
+    pub fn bounds(&self) -> Option<Bounds> {
+        let points: Vec<_> = (0..self.width()).map(|i| self.col(i)).collect();
+        let aabb = Aabb::of(&points)?;
+        let center = aabb.center();
+        let radius = points.iter().map(|p| {
+            ((p[0] - center[0]).powi(2) + (p[1] - center[1]).powi(2) + (p[2] - center[2]).powi(2)).sqrt()
+        }).fold(0.0, f64::max);
+        Some(Bounds { aabb, sphere: BoundingSphere { center, radius } })
+    }
+}
+
+/// The transformation fitting the points in `bounds` in the middle of an
+/// image `width` by `height`, as big as they'll go with a margin around them,
+/// as seen straight on. Depth is scaled as much as width and height, and
+/// centered on z = 0.
+///
+///     let fit = auto_scale_to_screen(&polygons.bounds()?, 500, 500);
+pub fn auto_scale_to_screen(bounds: &Bounds, width: usize, height: usize) -> Matrix {
+    let aabb = bounds.aabb;
+    let (w, h) = (aabb.max[0] - aabb.min[0], aabb.max[1] - aabb.min[1]);
+    let fits = |size: f64, extent: f64| if extent > 0.0 { FILL * size / extent } else { f64::INFINITY };
+    let scale = fits(width as f64, w).min(fits(height as f64, h));
+    // A single point, or points all in a line along z, are just moved
+    let scale = if scale.is_finite() { scale } else { 1.0 };
+    let center = aabb.center();
+    Transform::new()
+        .translate(-center[0], -center[1], -center[2])
+        .scale(scale, scale, scale)
+        .translate(width as f64 / 2.0, height as f64 / 2.0, 0.0)
+        .matrix()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fitted(points: Vec<[f64; 4]>, width: usize, height: usize) -> Aabb {
+        let fit = auto_scale_to_screen(&Matrix::new(points.clone()).bounds().unwrap(), width, height);
+        Aabb::of(&points.iter().map(|&p| fit.apply_to_point(p)).collect::<Vec<_>>()).unwrap()
+    }
+
+    fn near(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn models_fill_the_image_the_way_they_fit_tighter() {
+        let points = vec![[-40.0, 10.0, -30.0, 1.0], [160.0, 60.0, 30.0, 1.0], [0.0, 30.0, 0.0, 1.0]];
+        let (width, height) = (500, 300);
+        let aabb = fitted(points, width, height);
+        // 200 wide by 50 tall, so it's as wide as it can be and no taller
+        assert!(near(aabb.max[0] - aabb.min[0], FILL * width as f64));
+        assert!(aabb.max[1] - aabb.min[1] <= FILL * height as f64);
+        assert!(near(aabb.max[1] - aabb.min[1], (aabb.max[0] - aabb.min[0]) / 4.0));
+        // Centered, with depth scaled alike around 0
+        assert!(near(aabb.min[0] + aabb.max[0], width as f64));
+        assert!(near(aabb.min[1] + aabb.max[1], height as f64));
+        assert!(near(aabb.min[2], -aabb.max[2]));
+        assert!(near(aabb.max[2] - aabb.min[2], (aabb.max[0] - aabb.min[0]) * 60.0 / 200.0));
+    }
+
+    #[test]
+    fn tall_models_fit_by_height() {
+        let aabb = fitted(vec![[0.0, 0.0, 0.0, 1.0], [10.0, 100.0, 0.0, 1.0]], 400, 400);
+        assert!(near(aabb.max[1] - aabb.min[1], FILL * 400.0));
+        assert!(aabb.min[0] >= 0.0 && aabb.max[0] <= 400.0);
+    }
+
+    #[test]
+    fn single_points_are_just_centered() {
+        let aabb = fitted(vec![[7.0, 8.0, 9.0, 1.0]], 200, 100);
+        assert_eq!(aabb, Aabb { min: [100.0, 50.0, 0.0], max: [100.0, 50.0, 0.0] });
+    }
+}
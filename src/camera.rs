@@ -0,0 +1,255 @@
+use matrix::Matrix;
+use geometry::{ EdgeList, PolygonList };
+use bounds::Bounds;
+
+/// A viewer seeing the scene in perspective, from `eye` towards `target`,
+/// with `up` pointing up. Only what's between `near` and `far` away (along
+/// the view) is drawn, and the view is `fov` degrees from bottom to top.
+/// Positions are in the same coordinates as shapes, in pixels.
+///
+///     Camera::new([250.0, 250.0, 600.0], [250.0, 250.0, 0.0]).fov(45.0)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub eye: [f64; 3],
+    pub target: [f64; 3],
+    pub up: [f64; 3],
+    pub fov: f64,
+    pub near: f64,
+    pub far: f64
+}
+
+impl Camera {
+    /// A camera at `eye` looking at `target` with y up, seeing 60° from
+    /// bottom to top, from 1 to 10000 away
+    pub fn new(eye: [f64; 3], target: [f64; 3]) -> Camera {
+        Camera { eye, target, up: [0.0, 1.0, 0.0], fov: 60.0, near: 1.0, far: 10000.0 }
+    }
+
+    pub fn up(mut self, up: [f64; 3]) -> Camera {
+        self.up = up;
+        self
+    }
+
+    pub fn fov(mut self, degrees: f64) -> Camera {
+        self.fov = degrees;
+        self
+    }
+
+    pub fn clip(mut self, near: f64, far: f64) -> Camera {
+        self.near = near;
+        self.far = far;
+        self
+    }
+
+    /// The camera looking at the middle of `bounds` from the same way as
+    /// now, just far enough away to see all of it, for an image at least as
+    /// wide as it is tall. The far plane is moved back if it would cut any
+    /// of it off.
+    pub fn frame(mut self, bounds: &Bounds) -> Camera {
+        let sphere = bounds.sphere;
+        let back = [self.eye[0] - self.target[0], self.eye[1] - self.target[1], self.eye[2] - self.target[2]];
+        let length = (back[0] * back[0] + back[1] * back[1] + back[2] * back[2]).sqrt();
+        let back = if length > 0.0 { [back[0] / length, back[1] / length, back[2] / length] } else { [0.0, 0.0, 1.0] };
+        // Where the edges of the view just touch the bounding sphere
+        let distance = (sphere.radius / (self.fov.to_radians() / 2.0).sin()).max(self.near + sphere.radius);
+        self.target = sphere.center;
+        self.eye = [sphere.center[0] + back[0] * distance, sphere.center[1] + back[1] * distance, sphere.center[2] + back[2] * distance];
+        self.far = self.far.max(distance + sphere.radius);
+        self
+    }
+
+    /// The matrix taking points to clip coordinates, for an image `width`
+    /// by `height`, or `None` if the camera can't tell which way it's
+    /// looking (see `Matrix::look_at`)
+    pub fn matrix(&self, width: usize, height: usize) -> Option<Matrix> {
+        let view = Matrix::look_at(self.eye, self.target, self.up)?;
+        let aspect = width as f64 / height as f64;
+        Some(&Matrix::perspective(self.fov.to_radians(), aspect, self.near, self.far) * &view)
+    }
+
+    /// Whether the camera can see anything: it's looking somewhere, and its
+    /// field of view and clipping planes make sense
+    pub fn is_valid(&self) -> bool {
+        self.matrix(1, 1).is_some() && self.fov > 0.0 && self.fov < 180.0 && self.near > 0.0 && self.far > self.near
+    }
+
+    /// `polygons` as seen by the camera, in screen coordinates for an image
+    /// `width` by `height`. Triangles are clipped to the view frustum, so
+    /// only the parts which can be seen are kept. Each point's last
+    /// coordinate is 1/w rather than 1, so only affine transformations that
+    /// leave it be (see `Matrix::apply_to_point`) should be applied after.
+    /// Also returns how many triangles were clipped: cut down, or dropped
+    /// for being out of view.
+    pub fn project_polygons(&self, polygons: &PolygonList, width: usize, height: usize) -> (PolygonList, usize) {
+        let mut projected = PolygonList::new();
+        let mut clipped = 0;
+        let matrix = match self.matrix(width, height) {
+            Some(matrix) => matrix,
+            None => return (projected, clipped)
+        };
+        let clip = polygons.transformed(&matrix);
+        for i in 0..clip.len() {
+            let corners = clip.triangle(i);
+            if corners.iter().any(|&p| inside(p).iter().any(|&d| d < 0.0)) {
+                clipped += 1;
+            }
+            let uvs = polygons.uvs(i);
+            let polygon = clip_polygon((0..3).map(|j| (corners[j], uvs[j])).collect());
+            for j in 1..polygon.len().saturating_sub(1) {
+                let (a, b, c) = (polygon[0], polygon[j], polygon[j + 1]);
+                projected.add_textured(
+                    [self.to_screen(a.0, width, height), self.to_screen(b.0, width, height), self.to_screen(c.0, width, height)],
+                    [a.1, b.1, c.1]);
+            }
+        }
+        (projected, clipped)
+    }
+
+    /// `edges` as seen by the camera, in screen coordinates for an image
+    /// `width` by `height`, clipped to the view frustum like polygons.
+    pub fn project_edges(&self, edges: &EdgeList, width: usize, height: usize) -> EdgeList {
+        let mut projected = EdgeList::new();
+        let matrix = match self.matrix(width, height) {
+            Some(matrix) => matrix,
+            None => return projected
+        };
+        let clip = edges.transformed(&matrix);
+        for i in 0..clip.len() {
+            if let Some((p, q)) = clip_edge(clip.col(2 * i), clip.col(2 * i + 1)) {
+                projected.add_edge(self.to_screen(p, width, height), self.to_screen(q, width, height));
+            }
+        }
+        projected
+    }
+
+    /// The point in screen coordinates of `p`, in clip coordinates. Depth
+    /// goes with the reciprocal of the distance, which is what can be blended
+    /// across a triangle on the screen, scaled so that at the target, where
+    /// it's 0, it changes as fast with distance as x and y do across. The
+    /// last coordinate is 1/w, for blending anything else across it with
+    /// perspective (see `render::perspective_correct`).
+    fn to_screen(self, p: [f64; 4], width: usize, height: usize) -> [f64; 4] {
+        let w = p[3];
+        let to_target = [self.target[0] - self.eye[0], self.target[1] - self.eye[1], self.target[2] - self.eye[2]];
+        let distance = (to_target[0] * to_target[0] + to_target[1] * to_target[1] + to_target[2] * to_target[2]).sqrt();
+        // Pixels across for each unit across at a distance of 1
+        let focal = height as f64 / 2.0 / (self.fov.to_radians() / 2.0).tan();
+        [(p[0] / w + 1.0) * width as f64 / 2.0,
+         (p[1] / w + 1.0) * height as f64 / 2.0,
+         focal * (distance / w - 1.0),
+         1.0 / w]
+    }
+}
+
+/// How far inside each of the six planes of the view frustum the point `p`
+/// in clip coordinates is: left, right, bottom, top, near, and far. It's
+/// in the frustum where they're all at least 0.
+fn inside(p: [f64; 4]) -> [f64; 6] {
+    [p[3] + p[0], p[3] - p[0], p[3] + p[1], p[3] - p[1], p[3] + p[2], p[3] - p[2]]
+}
+
+/// The part of the convex polygon `polygon` (corners in clip coordinates,
+/// with their texture coordinates) inside the view frustum, cutting it at
+/// each plane in turn
+fn clip_polygon(mut polygon: Vec<([f64; 4], [f64; 2])>) -> Vec<([f64; 4], [f64; 2])> {
+    for plane in 0..6 {
+        let mut kept = Vec::with_capacity(polygon.len() + 1);
+        for j in 0..polygon.len() {
+            let (p, q) = (polygon[j], polygon[(j + 1) % polygon.len()]);
+            let (dp, dq) = (inside(p.0)[plane], inside(q.0)[plane]);
+            if dp >= 0.0 {
+                kept.push(p);
+            }
+            if (dp >= 0.0) != (dq >= 0.0) {
+                let t = dp / (dp - dq);
+                kept.push((lerp4(p.0, q.0, t), [p.1[0] + (q.1[0] - p.1[0]) * t, p.1[1] + (q.1[1] - p.1[1]) * t]));
+            }
+        }
+        if kept.len() < 3 {
+            return vec![];
+        }
+        polygon = kept;
+    }
+    polygon
+}
+
+/// The part of the segment from `p` to `q` (in clip coordinates) inside the
+/// view frustum, if any
+fn clip_edge(p: [f64; 4], q: [f64; 4]) -> Option<([f64; 4], [f64; 4])> {
+    let (dp, dq) = (inside(p), inside(q));
+    // The part kept, as fractions of the way from `p` to `q`
+    let (mut t0, mut t1) = (0.0, 1.0);
+    for plane in 0..6 {
+        let (a, b) = (dp[plane], dq[plane]);
+        if a < 0.0 && b < 0.0 {
+            return None;
+        }
+        if a < 0.0 {
+            t0 = f64::max(t0, a / (a - b));
+        } else if b < 0.0 {
+            t1 = f64::min(t1, a / (a - b));
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    Some((lerp4(p, q, t0), lerp4(p, q, t1)))
+}
+
+fn lerp4(p: [f64; 4], q: [f64; 4], t: f64) -> [f64; 4] {
+    [p[0] + (q[0] - p[0]) * t, p[1] + (q[1] - p[1]) * t, p[2] + (q[2] - p[2]) * t, p[3] + (q[3] - p[3]) * t]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// Points all over the surface of `sphere`
+    fn surface(sphere: &::bounds::BoundingSphere) -> Vec<[f64; 4]> {
+        let mut points = vec![];
+        for i in 0..=12 {
+            let polar = PI * i as f64 / 12.0;
+            for j in 0..24 {
+                let around = 2.0 * PI * j as f64 / 24.0;
+                points.push([sphere.center[0] + sphere.radius * polar.sin() * around.cos(),
+                             sphere.center[1] + sphere.radius * polar.cos(),
+                             sphere.center[2] + sphere.radius * polar.sin() * around.sin(),
+                             1.0]);
+            }
+        }
+        points
+    }
+
+    #[test]
+    fn framing_keeps_the_bounding_sphere_in_view() {
+        let bounds = Matrix::new(vec![[-300.0, 20.0, -50.0, 1.0], [500.0, 90.0, 400.0, 1.0]]).bounds().unwrap();
+        let cameras = [
+            Camera::new([0.0, 0.0, 10.0], [0.0, 0.0, 0.0]),
+            Camera::new([900.0, 300.0, -200.0], [0.0, 0.0, 0.0]).fov(30.0),
+            Camera::new([0.0, 0.0, 0.0], [1.0, -1.0, 0.5]).fov(90.0).clip(5.0, 100.0)
+        ];
+        for camera in &cameras {
+            let framed = camera.frame(&bounds);
+            assert!(framed.is_valid());
+            assert_eq!(framed.target, bounds.sphere.center);
+            let matrix = framed.matrix(640, 480).unwrap();
+            let mut clip = Matrix::new(surface(&bounds.sphere));
+            clip.transform_by(&matrix);
+            for i in 0..clip.width() {
+                let inside = inside(clip.col(i));
+                assert!(inside.iter().all(|&d| d > -1e-9), "point {} is outside {:?} framed as {:?}", i, camera, framed);
+            }
+        }
+    }
+
+    #[test]
+    fn framing_keeps_the_direction_of_view() {
+        let bounds = Matrix::new(vec![[0.0, 0.0, 0.0, 1.0], [100.0, 100.0, 100.0, 1.0]]).bounds().unwrap();
+        let framed = Camera::new([50.0, 50.0, 1000.0], [50.0, 50.0, 0.0]).frame(&bounds);
+        assert_eq!((framed.eye[0], framed.eye[1]), (50.0, 50.0));
+        // Just far enough back for the sphere to touch the top and bottom
+        let distance = framed.eye[2] - 50.0;
+        assert!((distance * (30.0f64).to_radians().sin() - bounds.sphere.radius).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::{ Arc, OnceLock };
+
+use error::{ Error, Result };
+
+/// A flag for stopping a render early. Clones share the flag, so one can be
+/// kept to cancel a render given another (through its `RenderConfig`).
+/// Renders check it between frames, and between commands of a script, and
+/// stop with `Error::Cancelled`, having saved the frames they finished.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+
+    /// Fail with `Error::Cancelled` if the token has been cancelled.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The token cancelled by Ctrl-C
+static INTERRUPT: OnceLock<CancelToken> = OnceLock::new();
+
+#[cfg(unix)]
+extern "C" {
+    fn signal(signum: i32, handler: usize) -> usize;
+    fn _exit(status: i32) -> !;
+}
+
+#[cfg(unix)]
+const SIGINT: i32 = 2;
+
+/// Make Ctrl-C cancel `token` (only the first token given is used). Pressing
+/// it again exits immediately.
+#[cfg(unix)]
+pub fn cancel_on_interrupt(token: &CancelToken) {
+    if INTERRUPT.set(token.clone()).is_ok() {
+        unsafe {
+            signal(SIGINT, on_interrupt as extern "C" fn(i32) as usize);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn cancel_on_interrupt(_token: &CancelToken) {}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_signum: i32) {
+    // Only atomic operations here, as in any signal handler
+    if let Some(token) = INTERRUPT.get() {
+        if token.is_cancelled() {
+            unsafe { _exit(130) }
+        }
+        token.cancel();
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::fs::{ self, File };
+use std::io::{ self, Read, Write };
+use std::path::Path;
+use std::sync::Mutex;
+
+use error::{ Error, Result };
+
+/// A record of the frames of an animation saved so far, kept in a file next
+/// to them, so that an interrupted render can be resumed. The file starts
+/// with a line identifying the script, frame count, and format, followed by
+/// a line for each frame saved, giving its number and the value of each knob
+/// in it, e.g. `12 spin=0.5 zoom=1.25`. A frame's line is only written once
+/// the frame is on disk.
+pub struct Checkpoint {
+    path: String,
+    /// The knob values of each frame found saved when the checkpoint was
+    /// opened
+    done: HashMap<usize, String>,
+    file: Mutex<File>
+}
+
+impl Checkpoint {
+    /// Open the checkpoint at `path` for rendering `frames` frames of
+    /// `script` in `format` (a file extension). If `resume` is set, the
+    /// frames a checkpoint there already records for the same script are
+    /// kept; otherwise, or if the script has changed, it is started over.
+    pub fn open(path: &str, script: &str, frames: usize, format: &str, resume: bool) -> Result<Checkpoint> {
+        let header = format!("# checkpoint: script {:016x}, {} frames, {}", fnv1a(script), frames, format);
+        let mut done = HashMap::new();
+        if resume {
+            let mut text = String::new();
+            match File::open(path).and_then(|mut file| file.read_to_string(&mut text)) {
+                Ok(_) => {},
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+                Err(e) => return Err(Error::io(path, e))
+            }
+            let mut lines = text.lines();
+            match lines.next() {
+                Some(line) if line == header => {
+                    // The last line may have been cut off, and is skipped if
+                    // it doesn't parse
+                    for line in lines {
+                        let mut words = line.splitn(2, ' ');
+                        if let Some(Ok(frame)) = words.next().map(|w| w.parse()) {
+                            done.insert(frame, String::from(words.next().unwrap_or("")));
+                        }
+                    }
+                },
+                Some(_) => warn!("{} is for another script; starting over", path),
+                None => {}
+            }
+            info!("Resuming with {} frames done", done.len());
+        }
+        // Rewrite the checkpoint with only what's kept
+        let mut file = File::create(path).map_err(|e| Error::io(path, e))?;
+        let mut text = format!("{}\n", header);
+        let mut frames: Vec<_> = done.iter().collect();
+        frames.sort();
+        for (frame, knobs) in frames {
+            text.push_str(&format!("{} {}\n", frame, knobs));
+        }
+        file.write_all(text.as_bytes()).map_err(|e| Error::io(path, e))?;
+        Ok(Checkpoint { path: String::from(path), done, file: Mutex::new(file) })
+    }
+
+    /// Whether frame `frame`, with knob values `knobs`, was saved to
+    /// `filename` before the checkpoint was opened (and is still there).
+    pub fn is_done(&self, frame: usize, knobs: &[(&str, f64)], filename: &str) -> bool {
+        self.done.get(&frame).is_some_and(|saved| *saved == format_knobs(knobs)) && Path::new(filename).exists()
+    }
+
+    /// Record that a frame has been saved, given its `entry`.
+    pub fn record(&self, entry: &str) -> Result<()> {
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", entry).map_err(|e| Error::io(&self.path, e))
+    }
+
+    /// Delete the checkpoint, once every frame is saved.
+    pub fn finish(self) -> Result<()> {
+        let Checkpoint { path, file, .. } = self;
+        drop(file);
+        fs::remove_file(&path).map_err(|e| Error::io(&path, e))
+    }
+}
+
+/// The line recording that frame `frame`, with knob values `knobs`, has been
+/// saved
+pub fn entry(frame: usize, knobs: &[(&str, f64)]) -> String {
+    format!("{} {}", frame, format_knobs(knobs))
+}
+
+/// Knob values as `name=value` pairs, with the values written so that they
+/// parse back exactly
+fn format_knobs(knobs: &[(&str, f64)]) -> String {
+    knobs.iter().map(|&(knob, val)| format!("{}={:?}", knob, val)).collect::<Vec<_>>().join(" ")
+}
+
+/// The 64-bit FNV-1a hash of `s`, which (unlike `std`'s hashers) is the same
+/// from one build to the next
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
@@ -0,0 +1,377 @@
+use std::env;
+use std::fs;
+use std::path::{ Component, Path, PathBuf };
+use std::thread;
+use std::cmp;
+use std::sync::Arc;
+
+use ppm::Format;
+use render::{ Color, Shading };
+use config::RenderConfig;
+use debug_view::View;
+use raytrace::Renderer;
+use shader::{ Shader, VertexShader };
+use projection::Projection;
+use plugin;
+use golden;
+
+/// Hundredths of a second each frame of the GIF `--gif` makes is shown for,
+/// as in `--preview`
+const GIF_DELAY: u16 = 4;
+
+/// Options given on the command line
+pub struct Options {
+    /// Paths of the scripts to run
+    pub scripts: Vec<String>,
+    /// Report the time spent in each stage of the pipeline
+    pub timings: bool,
+    /// Report what was rendered; see `stats`
+    pub stats: bool,
+    /// How much to log; see `logging`
+    pub verbosity: usize,
+    /// Run the scripts concurrently
+    pub parallel: bool,
+    /// Format of animation frames, and of saved files with no extension
+    pub format: Format,
+    /// How many threads to render with
+    pub threads: usize,
+    /// Show each script's result in an image viewer when it finishes
+    pub preview: bool,
+    /// Show a progress bar while rendering (unless running in parallel)
+    pub progress: bool,
+    /// Run the benchmarks instead of any scripts
+    pub bench: bool,
+    /// How to render (apart from the number of threads, which is `threads`)
+    pub render: RenderConfig,
+    /// Check each script against its reference image in this directory
+    /// instead of running it; see `golden`
+    pub golden: Option<String>,
+    /// Replace the reference images rather than checking against them
+    pub bless: bool,
+    /// Report which shape of each script drew the pixel at this column and
+    /// row (from the top left) instead of running it; see `pick`
+    pub pick: Option<(usize, usize)>,
+    /// Save the still image of the (one) script here, in the format its
+    /// extension names
+    pub output: Option<String>,
+    /// The directory to save animation frames in, instead of `anim`
+    pub frames_dir: Option<String>,
+    /// Print `USAGE` instead of doing anything else
+    pub help: bool,
+}
+
+/// How to run the program
+pub const USAGE: &str = "\
+Usage:
+    main --bench
+    main --golden DIRECTORY [--bless] [SCRIPT | DIRECTORY]...
+    main --pick X,Y [SCRIPT | DIRECTORY]...
+    main [render] [-v | -vv] [--timings] [--stats] [--parallel] [--preview]
+         [--progress] [--resume] [--gif] [--seed N] [--threads N]
+         [--format FORMAT] [-o FILE] [--frames-dir DIRECTORY] [--size WxH]
+         [--samples N] [--jitter N] [--fill | --flat | --gouraud | --phong]
+         [--crease DEGREES] [--shadows] [--shadow-size N] [--antialias]
+         [--no-cull] [--gamma G] [--background R,G,B] [--view VIEW]
+         [--renderer NAME] [--shader SHADER] [--vertex-shader SHADER]
+         [--projection NAME] [SCRIPT | DIRECTORY]...
+
+--renderer is rasterize (the default) or raytrace. The ray tracer casts
+orthographic rays straight into the screen, after any camera has projected
+the scene, rather than from the camera's eye.
+
+--seed seeds the sampling of --jitter, which is the only random part of a
+render; scripts have no random numbers of their own.";
+
+/// Read the options out of the program's arguments, as `USAGE` describes.
+///
+/// Each DIRECTORY stands for every file directly inside it. If no scripts
+/// are given, `script` is run. The number of threads defaults to the value of
+/// the environment variable CSTACK_THREADS, or else the number of cores.
+/// With --golden, the image is rendered as `golden::config` says unless the
+/// options say otherwise. `render` only spells out what is done by default.
+pub fn parse_args() -> Result<Options, String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    // --golden renders with the settings its references were made with,
+    // wherever among the arguments it is, so later options can override them
+    let render = if args.iter().any(|arg| arg == "--golden") { golden::config() } else { RenderConfig::new() };
+    let mut opts = Options {
+        scripts: vec![],
+        timings: false,
+        stats: false,
+        verbosity: 0,
+        parallel: false,
+        format: Format::Png,
+        threads: 1,
+        preview: false,
+        progress: false,
+        bench: false,
+        render: render.command(plugin::plot()),
+        golden: None,
+        bless: false,
+        pick: None,
+        output: None,
+        frames_dir: None,
+        help: false,
+    };
+    // CSTACK_THREADS is only read if --threads isn't given
+    let mut threads = None;
+    let mut args = args.into_iter().peekable();
+    if args.peek().map(|arg| arg.as_str()) == Some("render") {
+        args.next();
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => {
+                let seed = next_number(&mut args, "--seed")?;
+                opts.render = opts.render.seed(seed as u64);
+            },
+            "--format" => {
+                let name = args.next().unwrap_or_default();
+                match Format::from_name(&name) {
+                    Some(format) => opts.format = format,
+                    None => return Err(format!("Unknown format '{}' (expected ppm, p6, png, bmp, tga, or gif)", name))
+                }
+            },
+            "--threads" => match next_number(&mut args, "--threads")? {
+                0 => return Err(String::from("Expected at least one thread after --threads")),
+                n => threads = Some(n)
+            },
+            "--size" => {
+                let size = next_list(&mut args, "--size", 'x')?;
+                match size[..] {
+                    [w, h] if w > 0 && h > 0 => opts.render = opts.render.resolution(w, h),
+                    _ => return Err(String::from("Expected --size WIDTHxHEIGHT"))
+                }
+            },
+            "--samples" => {
+                let samples = next_number(&mut args, "--samples")?;
+                opts.render = opts.render.samples(cmp::max(samples, 1));
+            },
+            "--view" => {
+                let name = args.next().unwrap_or_default();
+                match View::from_name(&name) {
+                    Some(view) => opts.render = opts.render.view(view),
+                    None => return Err(format!("Unknown view '{}' (expected shaded, normals, depth, or overdraw)", name))
+                }
+            },
+            "--renderer" => {
+                let name = args.next().unwrap_or_default();
+                match Renderer::from_name(&name) {
+                    Some(renderer) => opts.render = opts.render.renderer(renderer),
+                    None => return Err(format!("Unknown renderer '{}' (expected rasterize or raytrace)", name))
+                }
+            },
+            "--jitter" => {
+                let passes = next_number(&mut args, "--jitter")?;
+                opts.render = opts.render.jitter(cmp::max(passes, 1));
+            },
+            "--shader" => {
+                let name = args.next().unwrap_or_default();
+                match Shader::from_name(&name) {
+                    Some(shader) => opts.render = opts.render.shader(Arc::new(shader)),
+                    None => return Err(format!("Unknown shader '{}' (expected lambert or checker)", name))
+                }
+            },
+            "--vertex-shader" => {
+                let name = args.next().unwrap_or_default();
+                match VertexShader::from_name(&name) {
+                    Some(shader) => opts.render = opts.render.vertex_shader(Arc::new(shader)),
+                    None => return Err(format!("Unknown vertex shader '{}' (expected wave or wobble)", name))
+                }
+            },
+            "--projection" => {
+                let name = args.next().unwrap_or_default();
+                match Projection::from_name(&name) {
+                    Some(projection) => opts.render = opts.render.projection(projection),
+                    None => return Err(format!("Unknown projection '{}' (expected isometric, dimetric, or cabinet)", name))
+                }
+            },
+            "--fill" => opts.render = opts.render.shading(Shading::Filled),
+            "--flat" => opts.render = opts.render.shading(Shading::Flat),
+            "--gouraud" => opts.render = opts.render.shading(Shading::Gouraud),
+            "--phong" => opts.render = opts.render.shading(Shading::Phong),
+            "--crease" => {
+                let degrees = args.next().and_then(|arg| arg.parse::<f64>().ok());
+                match degrees {
+                    Some(degrees) if degrees >= 0.0 => opts.render = opts.render.crease_angle(degrees),
+                    _ => return Err(String::from("Expected an angle in degrees after --crease"))
+                }
+            },
+            "--shadows" => opts.render = opts.render.shadows(true),
+            "--shadow-size" => {
+                let texels = next_number(&mut args, "--shadow-size")?;
+                opts.render = opts.render.shadows(true).shadow_resolution(cmp::max(texels, 1));
+            },
+            "--antialias" => opts.render = opts.render.antialias(true),
+            "--no-cull" => opts.render = opts.render.cull_backfaces(false),
+            "--gamma" => {
+                let gamma = args.next().and_then(|arg| arg.parse::<f64>().ok());
+                match gamma {
+                    Some(gamma) if gamma > 0.0 => opts.render = opts.render.gamma(gamma),
+                    _ => return Err(String::from("Expected a positive number after --gamma"))
+                }
+            },
+            "--background" => {
+                let arg = args.next();
+                let color = match arg.as_ref().and_then(|arg| Color::from_hex(arg)) {
+                    Some(color) => color,
+                    None => match next_list(&mut arg.into_iter(), "--background", ',')?[..] {
+                        [r, g, b] if r < 256 && g < 256 && b < 256 => Color::rgb(r as u8, g as u8, b as u8),
+                        _ => return Err(String::from("Expected --background R,G,B with each from 0 to 255, or #RRGGBB or #RGB"))
+                    }
+                };
+                opts.render = opts.render.background(color);
+            },
+            "--timings" => opts.timings = true,
+            "--stats" => opts.stats = true,
+            "--parallel" => opts.parallel = true,
+            "--preview" => opts.preview = true,
+            "--progress" => opts.progress = true,
+            "--resume" => opts.render = opts.render.resume(true),
+            "--gif" => opts.render = opts.render.gif_delay(Some(GIF_DELAY)),
+            "--bench" => opts.bench = true,
+            "--golden" => match args.next() {
+                Some(dir) => opts.golden = Some(dir),
+                None => return Err(String::from("Expected a directory after --golden"))
+            },
+            "--bless" => opts.bless = true,
+            "--pick" => {
+                let xy = next_list(&mut args, "--pick", ',')?;
+                match xy[..] {
+                    [x, y] => opts.pick = Some((x, y)),
+                    _ => return Err(String::from("Expected --pick X,Y"))
+                }
+            },
+            "-o" | "--output" => opts.output = Some(next_path(&mut args, &arg)?),
+            "--frames-dir" => opts.frames_dir = Some(next_path(&mut args, "--frames-dir")?),
+            "-h" | "--help" => opts.help = true,
+            "-v" => opts.verbosity += 1,
+            "-vv" => opts.verbosity += 2,
+            flag if flag.starts_with("-") => {
+                return Err(format!("Unknown option '{}'", flag));
+            },
+            path => add_scripts(&mut opts.scripts, path)?,
+        }
+    }
+    if opts.help {
+        return Ok(opts);
+    }
+    opts.threads = match threads {
+        Some(n) => n,
+        None => default_threads()?
+    };
+    if opts.scripts.is_empty() {
+        opts.scripts.push(String::from("script"));
+    }
+    if opts.output.is_some() && opts.scripts.len() > 1 {
+        return Err(String::from("-o can only be given with a single script"));
+    }
+    Ok(opts)
+}
+
+fn default_threads() -> Result<usize, String> {
+    if let Ok(val) = env::var("CSTACK_THREADS") {
+        return match val.parse::<usize>() {
+            Ok(0) => Err(String::from("Expected at least one thread in CSTACK_THREADS, found 0")),
+            Ok(n) => Ok(n),
+            Err(_) => Err(format!("Expected a number in CSTACK_THREADS, found '{}'", val))
+        };
+    }
+    Ok(thread::available_parallelism().map(|n| n.get()).unwrap_or(1))
+}
+
+/// Parse the argument after `flag` as a number.
+fn next_number<I: Iterator<Item=String>>(args: &mut I, flag: &str) -> Result<usize, String> {
+    match args.next() {
+        Some(arg) => match arg.parse::<usize>() {
+            Ok(n) => Ok(n),
+            Err(_) => Err(format!("Expected a number after {}, found '{}'", flag, arg))
+        },
+        None => Err(format!("Expected a number after {}", flag))
+    }
+}
+
+/// The argument after `flag`, a path.
+fn next_path<I: Iterator<Item=String>>(args: &mut I, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("Expected a path after {}", flag))
+}
+
+/// Parse the argument after `flag` as a list of numbers separated by `sep`.
+fn next_list<I: Iterator<Item=String>>(args: &mut I, flag: &str, sep: char) -> Result<Vec<usize>, String> {
+    let arg = match args.next() {
+        Some(arg) => arg,
+        None => return Err(format!("Expected numbers after {}", flag))
+    };
+    arg.split(sep).map(|n| n.parse::<usize>()).collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("Expected numbers separated by '{}' after {}, found '{}'", sep, flag, arg))
+}
+
+/// Add `path` to `scripts`, or, if it is a directory, the files in it (sorted
+/// by name).
+fn add_scripts(scripts: &mut Vec<String>, path: &str) -> Result<(), String> {
+    if !Path::new(path).is_dir() {
+        scripts.push(String::from(path));
+        return Ok(());
+    }
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) => return Err(format!("Could not read directory '{}': {}", path, e))
+    };
+    let mut files = vec![];
+    for entry in entries.flatten() {
+        if entry.path().is_file() {
+            files.push(entry.path().to_string_lossy().into_owned());
+        }
+    }
+    files.sort();
+    scripts.extend(files);
+    Ok(())
+}
+
+/// The directory in which to save the animation frames of `script`: `anim`,
+/// or the directory given by `--frames-dir`. When several scripts are run,
+/// each gets its own subdirectory of it, at the script's path without its
+/// extension, so scripts of the same name in different directories don't
+/// overwrite each other's frames.
+pub fn out_dir(opts: &Options, script: &str) -> String {
+    let dir = opts.frames_dir.as_ref().map_or("anim", |dir| dir.as_str());
+    if opts.scripts.len() == 1 {
+        return String::from(dir);
+    }
+    format!("{}/{}", dir, relative_stem(script))
+}
+
+/// The path of the reference image of `script`, one of `scripts`, in the
+/// directory `dir`: the path of `script` under `dir`, without its extension
+/// or the directory all of `scripts` are in, so scripts of the same name in
+/// different directories get different references, but `--golden golden
+/// tests/scenes` uses `golden/shapes.ppm` for `tests/scenes/shapes.mdl`.
+pub fn reference_path(dir: &str, script: &str, scripts: &[String]) -> String {
+    let base = common_dir(scripts);
+    let script = Path::new(script).strip_prefix(&base).unwrap_or_else(|_| Path::new(script));
+    format!("{}/{}.ppm", dir, relative_stem(&script.to_string_lossy()))
+}
+
+/// The deepest directory all of `scripts` are in.
+fn common_dir(scripts: &[String]) -> PathBuf {
+    let mut dirs = scripts.iter().map(|s| Path::new(s).parent().unwrap_or_else(|| Path::new("")));
+    let mut common = match dirs.next() {
+        Some(dir) => dir.to_path_buf(),
+        None => return PathBuf::new()
+    };
+    for dir in dirs {
+        while !dir.starts_with(&common) && common.pop() {}
+    }
+    common
+}
+
+/// The path `script` without its extension, and without anything (a root,
+/// `.` or `..`) that would take it outside a directory it's put under.
+fn relative_stem(script: &str) -> String {
+    let path = Path::new(script).with_extension("");
+    let names: Vec<_> = path.components().filter_map(|c| match c {
+        Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+        _ => None
+    }).collect();
+    if names.is_empty() { String::from(script) } else { names.join("/") }
+}
@@ -0,0 +1,257 @@
+use render::{ Color, Shading };
+use debug_view::View;
+use raytrace::Renderer;
+use screen::Screen;
+use progress::Tracker;
+use shader::{ Shader, VertexShader };
+use projection::Projection;
+use camera::Camera;
+use plugin::CommandPlugin;
+use cancel::CancelToken;
+use std::sync::Arc;
+use consts::*;
+
+/// Settings for rendering. Start from `RenderConfig::new()`, which has the
+/// defaults, and change only what's needed:
+///
+///     RenderConfig::new().resolution(800, 600).samples(2).threads(4)
+#[derive(Debug, Clone)]
+pub struct RenderConfig {
+    /// Width and height of the image, in pixels
+    pub width: usize,
+    pub height: usize,
+    /// How polygons are drawn
+    pub shading: Shading,
+    /// For Gouraud and Phong shading, the angle in degrees between two polygons
+    /// beyond which the edge between them is left sharp. `None` smooths
+    /// every edge.
+    pub crease_angle: Option<f64>,
+    /// Whether lit polygons cast shadows, by shadow maps
+    pub shadows: bool,
+    /// Texels along each side of the shadow map of each light
+    pub shadow_resolution: usize,
+    /// Whether edges are drawn antialiased, unless a script turns it off
+    pub antialias: bool,
+    /// Whether to draw the scene or a debug view of it
+    pub view: View,
+    /// Whether to draw the scene by rasterizing or ray tracing it
+    pub renderer: Renderer,
+    /// Samples per pixel along each axis, for antialiasing. 1 turns it off.
+    pub samples: usize,
+    /// Passes averaged for antialiasing, each shifted by a fraction of a
+    /// pixel. 1 turns it off.
+    pub jitter: usize,
+    /// The preset view scenes are drawn from, unless a script chooses
+    /// another. `None` looks straight down the z axis.
+    pub projection: Option<Projection>,
+    /// The camera scenes are seen in perspective from, unless a script
+    /// chooses another, in place of the preset view
+    pub camera: Option<Camera>,
+    /// Whether to skip polygons facing away from the viewer
+    pub cull_backfaces: bool,
+    /// What scenes are drawn on top of
+    pub background: Color,
+    /// Gamma the finished image is corrected for. 1.0 leaves it unchanged.
+    pub gamma: f64,
+    /// How many threads to rasterize with
+    pub threads: usize,
+    /// Moves the vertices of shapes as they're drawn, if set
+    pub vertex_shader: Option<Arc<VertexShader>>,
+    /// Colors filled polygons instead of plain white, if set
+    pub shader: Option<Arc<Shader>>,
+    /// Whether to keep an ID buffer, recording which shape drew each pixel
+    pub ids: bool,
+    /// What everything randomized is derived from (see `random`)
+    pub seed: u64,
+    /// Whether to skip the frames of an animation saved by an earlier,
+    /// interrupted render (see `checkpoint`)
+    pub resume: bool,
+    /// If set, each animation is also saved as one animated GIF, showing
+    /// each frame for this many hundredths of a second. Every frame is
+    /// rendered for it, even when resuming.
+    pub gif_delay: Option<u16>,
+    /// Commands scripts can use besides the built-in ones
+    pub plugins: Vec<Arc<CommandPlugin>>,
+    /// What to tell about the progress of rendering
+    pub progress: Option<Arc<Tracker>>,
+    /// Stops the render when cancelled
+    pub cancel: CancelToken
+}
+
+impl Default for RenderConfig {
+    fn default() -> RenderConfig {
+        RenderConfig::new()
+    }
+}
+
+impl RenderConfig {
+    /// The default settings: a `WIDTH` by `HEIGHT` wireframe on black, with
+    /// culling and no antialiasing or gamma correction, on one thread.
+    pub fn new() -> RenderConfig {
+        RenderConfig {
+            width: WIDTH,
+            height: HEIGHT,
+            shading: Shading::Wireframe,
+            crease_angle: None,
+            shadows: false,
+            shadow_resolution: SHADOW_RESOLUTION,
+            antialias: false,
+            view: View::Shaded,
+            renderer: Renderer::Rasterize,
+            samples: 1,
+            jitter: 1,
+            projection: None,
+            camera: None,
+            cull_backfaces: true,
+            background: Color::black(),
+            gamma: 1.0,
+            threads: 1,
+            vertex_shader: None,
+            shader: None,
+            ids: false,
+            seed: SEED,
+            resume: false,
+            gif_delay: None,
+            plugins: vec![],
+            progress: None,
+            cancel: CancelToken::new()
+        }
+    }
+
+    pub fn resolution(mut self, width: usize, height: usize) -> RenderConfig {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn shading(mut self, shading: Shading) -> RenderConfig {
+        self.shading = shading;
+        self
+    }
+
+    pub fn crease_angle(mut self, degrees: f64) -> RenderConfig {
+        self.crease_angle = Some(degrees);
+        self
+    }
+
+    pub fn shadows(mut self, shadows: bool) -> RenderConfig {
+        self.shadows = shadows;
+        self
+    }
+
+    pub fn shadow_resolution(mut self, texels: usize) -> RenderConfig {
+        self.shadow_resolution = texels;
+        self
+    }
+
+    pub fn antialias(mut self, antialias: bool) -> RenderConfig {
+        self.antialias = antialias;
+        self
+    }
+
+    pub fn view(mut self, view: View) -> RenderConfig {
+        self.view = view;
+        self
+    }
+
+    pub fn renderer(mut self, renderer: Renderer) -> RenderConfig {
+        self.renderer = renderer;
+        self
+    }
+
+    pub fn samples(mut self, samples: usize) -> RenderConfig {
+        self.samples = samples;
+        self
+    }
+
+    pub fn jitter(mut self, passes: usize) -> RenderConfig {
+        self.jitter = passes;
+        self
+    }
+
+    pub fn projection(mut self, projection: Projection) -> RenderConfig {
+        self.projection = Some(projection);
+        self
+    }
+
+    pub fn camera(mut self, camera: Camera) -> RenderConfig {
+        self.camera = Some(camera);
+        self
+    }
+
+    pub fn cull_backfaces(mut self, cull: bool) -> RenderConfig {
+        self.cull_backfaces = cull;
+        self
+    }
+
+    pub fn background(mut self, background: Color) -> RenderConfig {
+        self.background = background;
+        self
+    }
+
+    pub fn gamma(mut self, gamma: f64) -> RenderConfig {
+        self.gamma = gamma;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> RenderConfig {
+        self.threads = threads;
+        self
+    }
+
+    pub fn vertex_shader(mut self, shader: Arc<VertexShader>) -> RenderConfig {
+        self.vertex_shader = Some(shader);
+        self
+    }
+
+    pub fn shader(mut self, shader: Arc<Shader>) -> RenderConfig {
+        self.shader = Some(shader);
+        self
+    }
+
+    pub fn ids(mut self, ids: bool) -> RenderConfig {
+        self.ids = ids;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> RenderConfig {
+        self.seed = seed;
+        self
+    }
+
+    pub fn resume(mut self, resume: bool) -> RenderConfig {
+        self.resume = resume;
+        self
+    }
+
+    pub fn gif_delay(mut self, delay: Option<u16>) -> RenderConfig {
+        self.gif_delay = delay;
+        self
+    }
+
+    /// Add `plugin` to the commands scripts can use.
+    pub fn command(mut self, plugin: CommandPlugin) -> RenderConfig {
+        self.plugins.push(Arc::new(plugin));
+        self
+    }
+
+    pub fn progress(mut self, tracker: Arc<Tracker>) -> RenderConfig {
+        self.progress = Some(tracker);
+        self
+    }
+
+    pub fn cancel(mut self, token: CancelToken) -> RenderConfig {
+        self.cancel = token;
+        self
+    }
+
+    /// Make a screen of the configured size, filled with the background,
+    /// with an ID buffer if one is to be kept.
+    pub fn blank_screen(&self) -> Screen {
+        let mut screen = Screen::new(self.width, self.height, self.background);
+        if self.ids {
+            screen.track_ids();
+        }
+        screen
+    }
+}
@@ -1,2 +1,4 @@
 pub const WIDTH: usize = 500;
 pub const HEIGHT: usize = 500;
+pub const SHADOW_RESOLUTION: usize = 1024;
+pub const SEED: u64 = 0x2545F491;
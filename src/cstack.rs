@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use matrix::Matrix;
+
+/// The coordinate system stack. Shapes are drawn in the coordinate system on
+/// top, and each transformation applies to it, so that it happens to shapes
+/// drawn after it before everything already applied does: after `move` and
+/// then `rotate`, shapes are turned about the origin and then moved.
+/// `push` saves the coordinate system on top by pushing a copy of it, and
+/// `pop` goes back to the one saved, so that parts of a model can be placed
+/// relative to the part they're attached to. Coordinate systems can also be
+/// saved by name, to be put back on top later.
+#[derive(Clone)]
+pub struct CStack {
+    /// The coordinate systems, with the top last. There's always at least
+    /// one.
+    stack: Vec<Matrix>,
+    /// The coordinate systems saved with `save_coord_system`
+    saved: HashMap<String, Matrix>
+}
+
+impl Default for CStack {
+    fn default() -> CStack {
+        CStack::new()
+    }
+}
+
+impl CStack {
+    /// A stack of just the identity
+    pub fn new() -> CStack {
+        CStack { stack: vec![Matrix::identity()], saved: HashMap::new() }
+    }
+
+    /// Push a copy of the top coordinate system.
+    pub fn push(&mut self) {
+        let top = self.top().clone();
+        self.stack.push(top);
+    }
+
+    /// Remove and return the top coordinate system, or `None` (leaving the
+    /// stack as it is) if it's the only one.
+    pub fn pop(&mut self) -> Option<Matrix> {
+        if self.stack.len() == 1 {
+            None
+        } else {
+            self.stack.pop()
+        }
+    }
+
+    /// The coordinate system on top, which shapes are drawn in
+    pub fn top(&self) -> &Matrix {
+        &self.stack[self.stack.len() - 1]
+    }
+
+    /// Multiply the top coordinate system by `m` on the right.
+    pub fn apply(&mut self, m: &Matrix) {
+        let len = self.stack.len();
+        self.stack[len - 1].transform_on_right(m);
+    }
+
+    /// Save the top coordinate system as `name`, replacing any saved as it
+    /// before.
+    pub fn save_coord_system(&mut self, name: &str) {
+        let top = self.top().clone();
+        self.saved.insert(String::from(name), top);
+    }
+
+    /// Replace the top coordinate system with the one saved as `name`.
+    /// Returns whether there is one.
+    pub fn restore(&mut self, name: &str) -> bool {
+        match self.saved.get(name) {
+            Some(saved) => {
+                let len = self.stack.len();
+                self.stack[len - 1] = saved.clone();
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Go back to a stack of just the identity, with none saved.
+    pub fn clear(&mut self) {
+        self.stack.truncate(1);
+        self.stack[0] = Matrix::identity();
+        self.saved.clear();
+    }
+}
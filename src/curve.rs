@@ -1,12 +1,166 @@
-#![allow(dead_code)]
-// For the moment curves are not being used
-
 use std::f64::consts::PI;
+use std::cmp;
 
 use matrix::Matrix;
+use geometry::{ EdgeList, PolygonList };
+use drawable::Drawable;
+
+/// A straight line segment
+pub struct Line {
+    pub p0: [f64; 4],
+    pub p1: [f64; 4]
+}
+
+impl Drawable for Line {
+    fn emit(&self, edges: &mut EdgeList, _polys: &mut PolygonList) {
+        edges.add_edge(self.p0, self.p1);
+    }
+}
+
+/// A circle in the plane z = `cz`; see `circle`
+pub struct Circle {
+    pub cx: f64, pub cy: f64, pub cz: f64,
+    pub r: f64
+}
+
+impl Drawable for Circle {
+    fn emit(&self, edges: &mut EdgeList, _polys: &mut PolygonList) {
+        circle(edges, self.cx, self.cy, self.cz, self.r);
+    }
+}
+
+/// An ellipse about (`cx`, `cy`) in the plane z = `cz`, with radius `rx`
+/// along the x axis and `ry` along the y axis, with about as many segments as
+/// pixels around it
+pub struct Ellipse {
+    pub cx: f64, pub cy: f64, pub cz: f64,
+    pub rx: f64, pub ry: f64
+}
+
+impl Drawable for Ellipse {
+    fn emit(&self, edges: &mut EdgeList, _polys: &mut PolygonList) {
+        let steps = 6 * self.rx.abs().max(self.ry.abs()) as usize;
+        add_ellipse(edges, self.cx, self.cy, self.cz, self.rx, self.ry, steps);
+    }
+}
+
+/// A bezier curve with control points `p0` to `p3`, approximated with
+/// `points` points spaced by `sampling`
+pub struct Bezier {
+    pub points: usize,
+    pub sampling: Sampling,
+    pub p0: [f64; 4], pub p1: [f64; 4], pub p2: [f64; 4], pub p3: [f64; 4]
+}
+
+impl Drawable for Bezier {
+    fn emit(&self, edges: &mut EdgeList, _polys: &mut PolygonList) {
+        add_bezier(edges, self.points, self.sampling, self.p0, self.p1, self.p2, self.p3);
+    }
+}
+
+/// A hermite curve from `p0` to `p1` with tangents `m0` and `m1`,
+/// approximated with `points` points spaced by `sampling`
+pub struct Hermite {
+    pub points: usize,
+    pub sampling: Sampling,
+    pub p0: [f64; 4], pub p1: [f64; 4], pub m0: [f64; 4], pub m1: [f64; 4]
+}
+
+impl Drawable for Hermite {
+    fn emit(&self, edges: &mut EdgeList, _polys: &mut PolygonList) {
+        add_hermite(edges, self.points, self.sampling, self.p0, self.p1, self.m0, self.m1);
+    }
+}
+
+/// The kinds of spline through or near a list of control points
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Spline {
+    /// Through each point; see `add_catmull_rom`
+    CatmullRom,
+    /// Near each point; see `add_b_spline`
+    BSpline
+}
+
+/// A spline of the kind `spline` with control points `controls`, with
+/// `points` points per segment spaced by `sampling`
+pub struct SplineCurve {
+    pub spline: Spline,
+    pub points: usize,
+    pub sampling: Sampling,
+    pub controls: Vec<[f64; 4]>
+}
+
+impl Drawable for SplineCurve {
+    fn emit(&self, edges: &mut EdgeList, _polys: &mut PolygonList) {
+        match self.spline {
+            Spline::CatmullRom => add_catmull_rom(edges, self.points, self.sampling, &self.controls),
+            Spline::BSpline => add_b_spline(edges, self.points, self.sampling, &self.controls)
+        }
+    }
+}
+
+/// How the points approximating a curve are spread along it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sampling {
+    /// At even steps of the curve's parameter, which bunches them up
+    /// wherever the curve moves slowly
+    Uniform,
+    /// At (about) even distances along the curve, so that its edges are all
+    /// about as long, as dashes and motion along the curve need
+    ArcLength
+}
+
+/// How many times more finely a curve is sampled to measure its length
+const ARC_LENGTH_SAMPLES: usize = 16;
+
+/// Add a parametric curve with `points` points spaced by `sampling` to
+/// `edges`.
+pub fn parametric_sampled<F, G, H>(edges: &mut EdgeList, points: usize, sampling: Sampling, x: F, y: G, z: H)
+    where F: Fn(f64) -> f64,
+          G: Fn(f64) -> f64,
+          H: Fn(f64) -> f64
+{
+    if sampling == Sampling::Uniform || points == 0 {
+        return parametric(edges, points, x, y, z);
+    }
+    // Measure the distance along the curve to each of many finely spaced
+    // points
+    let fine = points * ARC_LENGTH_SAMPLES;
+    let point = |t: f64| [x(t), y(t), z(t), 1.0];
+    let mut lengths = vec![0.0];
+    let mut prev = point(0.0);
+    for i in 1..(fine + 1) {
+        let p = point(i as f64 / fine as f64);
+        let d = ((p[0] - prev[0]).powi(2) + (p[1] - prev[1]).powi(2) + (p[2] - prev[2]).powi(2)).sqrt();
+        let total = lengths[i - 1] + d;
+        lengths.push(total);
+        prev = p;
+    }
+    let total = lengths[fine];
+    if total == 0.0 {
+        return parametric(edges, points, x, y, z);
+    }
+
+    // Find the parameter of each point an even distance along, between
+    // those of the fine points either side of it
+    let mut prev_point = point(0.0);
+    let mut j = 1;
+    for k in 1..(points + 1) {
+        let target = total * k as f64 / points as f64;
+        while j < fine && lengths[j] < target {
+            j += 1;
+        }
+        let span = lengths[j] - lengths[j - 1];
+        let frac = if span > 0.0 { ((target - lengths[j - 1]) / span).min(1.0) } else { 1.0 };
+        let t = if k == points { 1.0 } else { (j as f64 - 1.0 + frac) / fine as f64 };
+        let new_point = point(t);
+        edges.add_edge(prev_point, new_point);
+        prev_point = new_point;
+    }
+}
 
 /// Add a parametric curve with `points` points to `edges`.
-pub fn parametric<F, G, H>(edges: &mut Matrix, points: usize, x: F, y: G, z: H)
+pub fn parametric<F, G, H>(edges: &mut EdgeList, points: usize, x: F, y: G, z: H)
     where F: Fn(f64) -> f64,
           G: Fn(f64) -> f64,
           H: Fn(f64) -> f64
@@ -18,32 +172,41 @@ pub fn parametric<F, G, H>(edges: &mut Matrix, points: usize, x: F, y: G, z: H)
     for i in 1..(points + 1) {
         let t = i as f64 / points as f64;
         let new_point = [x(t), y(t), z(t), 1.0];
-        edges.push_edge(prev_point, new_point);
+        edges.add_edge(prev_point, new_point);
         prev_point = new_point;
     }
 }
 
-pub fn circle(edges: &mut Matrix, cx: f64, cy: f64, cz: f64, r: f64) {
-    let points = 6 * r as usize; // Almost 2 * pi * r
+/// Add a circle of radius `r` about (`cx`, `cy`) in the plane z = `cz` to
+/// `edges`, with about as many segments as pixels around it.
+pub fn circle(edges: &mut EdgeList, cx: f64, cy: f64, cz: f64, r: f64) {
+    let steps = 6 * r as usize; // Almost 2 * pi * r
+    add_circle(edges, cx, cy, cz, r, steps);
+}
+
+/// Add a circle of radius `r` about (`cx`, `cy`) in the plane z = `cz` to
+/// `edges`, approximated with `steps` segments. More look smoother, and fewer
+/// are quicker to transform and draw.
+pub fn add_circle(edges: &mut EdgeList, cx: f64, cy: f64, cz: f64, r: f64, steps: usize) {
+    add_ellipse(edges, cx, cy, cz, r, r, steps);
+}
+
+/// Add an ellipse about (`cx`, `cy`) in the plane z = `cz` to `edges`, with
+/// radius `rx` along the x axis and `ry` along the y axis, approximated with
+/// `steps` segments.
+pub fn add_ellipse(edges: &mut EdgeList, cx: f64, cy: f64, cz: f64, rx: f64, ry: f64, steps: usize) {
     parametric(
         edges,
-        points,
-        |t| cx + r * (2.0 * PI * t).cos(), // x function
-        |t| cy + r * (2.0 * PI * t).sin(), // y function
+        steps,
+        |t| cx + rx * (2.0 * PI * t).cos(), // x function
+        |t| cy + ry * (2.0 * PI * t).sin(), // y function
         |_t| cz); // z function
 }
 
-fn bezier_matrix() -> Matrix {
-    Matrix::new4x4(
-        -1.0,  3.0, -3.0, 1.0,
-         3.0, -6.0,  3.0, 0.0,
-        -3.0,  3.0,  0.0, 0.0,
-         1.0,  0.0,  0.0, 0.0)
-}
-
-/// Add a bezier curve to `edges`, approximated with `points` points.
-pub fn bezier(edges: &mut Matrix, points: usize, p0: [f64; 4], p1: [f64; 4], p2: [f64; 4], p3: [f64; 4]) {
-    let bezier_mat = bezier_matrix();
+/// Add a bezier curve to `edges`, approximated with `points` points spaced
+/// by `sampling`.
+pub fn add_bezier(edges: &mut EdgeList, points: usize, sampling: Sampling, p0: [f64; 4], p1: [f64; 4], p2: [f64; 4], p3: [f64; 4]) {
+    let bezier_mat = Matrix::bezier_basis();
 
     let x_coefficients = &bezier_mat * &Matrix::column_vector(p0[0], p1[0], p2[0], p3[0]);
     let y_coefficients = &bezier_mat * &Matrix::column_vector(p0[1], p1[1], p2[1], p3[1]);
@@ -52,22 +215,14 @@ pub fn bezier(edges: &mut Matrix, points: usize, p0: [f64; 4], p1: [f64; 4], p2:
     let x_fn = |t| apply_cubic_coefficients(&x_coefficients, t);
     let y_fn = |t| apply_cubic_coefficients(&y_coefficients, t);
     let z_fn = |t| apply_cubic_coefficients(&z_coefficients, t);
-    parametric(edges, points, x_fn, y_fn, z_fn);
-}
-
-// the hermite_matrix (H-inverse) times the [p0, p1, m0, m1] matrix (G) creates the coefficient
-// matrix [a, b, c, d]
-fn hermite_matrix() -> Matrix {
-    Matrix::new4x4(
-        2.0, -2.0,  1.0,  1.0,
-       -3.0,  3.0, -2.0, -1.0,
-        0.0,  0.0,  1.0,  0.0,
-        1.0,  0.0,  0.0,  0.0)
+    parametric_sampled(edges, points, sampling, x_fn, y_fn, z_fn);
 }
 
-/// Add a hermite curve to `edges`, approximated with `points` points.
-pub fn hermite(edges: &mut Matrix, points: usize, p0: [f64; 4], p1: [f64; 4], m0: [f64; 4], m1: [f64; 4]) {
-    let hermite_mat = hermite_matrix();
+/// Add a hermite curve to `edges`, approximated with `points` points spaced
+/// by `sampling`. The coefficients come from the Hermite basis matrix times
+/// the column (p0, p1, m0, m1) of each coordinate.
+pub fn add_hermite(edges: &mut EdgeList, points: usize, sampling: Sampling, p0: [f64; 4], p1: [f64; 4], m0: [f64; 4], m1: [f64; 4]) {
+    let hermite_mat = Matrix::hermite_basis();
 
     let x_coefficients = &hermite_mat * &Matrix::column_vector(p0[0], p1[0], m0[0], m1[0]);
     let y_coefficients = &hermite_mat * &Matrix::column_vector(p0[1], p1[1], m0[1], m1[1]);
@@ -76,7 +231,63 @@ pub fn hermite(edges: &mut Matrix, points: usize, p0: [f64; 4], p1: [f64; 4], m0
     let x_fn = |t| apply_cubic_coefficients(&x_coefficients, t);
     let y_fn = |t| apply_cubic_coefficients(&y_coefficients, t);
     let z_fn = |t| apply_cubic_coefficients(&z_coefficients, t);
-    parametric(edges, points, x_fn, y_fn, z_fn);
+    parametric_sampled(edges, points, sampling, x_fn, y_fn, z_fn);
+}
+
+/// Add a Catmull-Rom spline through each of `controls` in turn to `edges`,
+/// approximating each segment between two of them with `points` points. The
+/// curve's tangent at each control point is parallel to the line between
+/// its neighbors, so segments join smoothly (with a continuous first
+/// derivative). With `Sampling::ArcLength`, the points are spread evenly
+/// along the whole curve instead. Nothing is added for fewer than two
+/// control points.
+pub fn add_catmull_rom(edges: &mut EdgeList, points: usize, sampling: Sampling, controls: &[[f64; 4]]) {
+    if controls.len() < 2 {
+        return;
+    }
+    // Repeat the ends, so the curve reaches them
+    let mut padded = vec![controls[0]];
+    padded.extend_from_slice(controls);
+    padded.push(controls[controls.len() - 1]);
+    add_spline(edges, points, sampling, &Matrix::catmull_rom_basis(), &padded);
+}
+
+/// Add a uniform cubic B-spline with control points `controls` to `edges`,
+/// approximating each segment with `points` points. The curve passes near
+/// the control points rather than through them (except the first and last,
+/// which it starts and ends at), and is smoother than a Catmull-Rom spline
+/// (with a continuous second derivative too). The points are spread by
+/// `sampling`, as for `add_catmull_rom`. Nothing is added for fewer than two
+/// control points.
+pub fn add_b_spline(edges: &mut EdgeList, points: usize, sampling: Sampling, controls: &[[f64; 4]]) {
+    if controls.len() < 2 {
+        return;
+    }
+    // Triple the ends, so the curve starts and ends at them
+    let (first, last) = (controls[0], controls[controls.len() - 1]);
+    let mut padded = vec![first, first];
+    padded.extend_from_slice(controls);
+    padded.push(last);
+    padded.push(last);
+    add_spline(edges, points, sampling, &Matrix::b_spline_basis(), &padded);
+}
+
+/// Add the segments given by `basis` for each run of four control points
+/// in `controls` to `edges` as one curve, with `points` points per segment
+/// spaced by `sampling`.
+fn add_spline(edges: &mut EdgeList, points: usize, sampling: Sampling, basis: &Matrix, controls: &[[f64; 4]]) {
+    let segments: Vec<[Matrix; 3]> = controls.windows(4).map(|w| {
+        let coefficients = |i: usize| basis * &Matrix::column_vector(w[0][i], w[1][i], w[2][i], w[3][i]);
+        [coefficients(0), coefficients(1), coefficients(2)]
+    }).collect();
+    let n = segments.len();
+    // The segment at `t` along the whole curve, and how far along it
+    let at = |t: f64, i: usize| {
+        let s = t * n as f64;
+        let k = cmp::min(s as usize, n - 1);
+        apply_cubic_coefficients(&segments[k][i], s - k as f64)
+    };
+    parametric_sampled(edges, points * n, sampling, |t| at(t, 0), |t| at(t, 1), |t| at(t, 2));
 }
 
 fn apply_cubic_coefficients(coefficients: &Matrix, t: f64) -> f64 {
@@ -86,3 +297,164 @@ fn apply_cubic_coefficients(coefficients: &Matrix, t: f64) -> f64 {
     let d = coefficients.get(3, 0);
     a * t.powi(3) + b * t.powi(2) + c * t + d
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The points `edges` joins, in order, if each edge starts where the
+    /// last one ended
+    fn points(edges: &EdgeList) -> Vec<[f64; 4]> {
+        let mut points = vec![edges.col(0)];
+        for i in 0..edges.len() {
+            assert_eq!(edges.col(2 * i), points[i]);
+            points.push(edges.col(2 * i + 1));
+        }
+        points
+    }
+
+    /// Whether `a` and `b` are within `tolerance` of each other in x, y,
+    /// and z
+    fn near(a: [f64; 4], b: [f64; 4], tolerance: f64) -> bool {
+        (0..3).all(|k| (a[k] - b[k]).abs() < tolerance)
+    }
+
+    /// The direction `points` leave their first point in, or arrive at
+    /// their last point in, as a tangent of a curve with a parameter from 0
+    /// to 1 sampled uniformly
+    fn end_tangents(points: &[[f64; 4]]) -> ([f64; 4], [f64; 4]) {
+        let n = (points.len() - 1) as f64;
+        let last = points.len() - 1;
+        let tangent = |a: [f64; 4], b: [f64; 4]| [(b[0] - a[0]) * n, (b[1] - a[1]) * n, (b[2] - a[2]) * n, 0.0];
+        (tangent(points[0], points[1]), tangent(points[last - 1], points[last]))
+    }
+
+    #[test]
+    fn circle_is_closed_with_a_segment_per_step() {
+        let mut edges = EdgeList::new();
+        add_circle(&mut edges, 10.0, 20.0, 5.0, 30.0, 12);
+        let points = points(&edges);
+        assert_eq!(points.len(), 13);
+        assert!(near(points[12], points[0], 1e-9));
+        for p in points {
+            assert!(((p[0] - 10.0).hypot(p[1] - 20.0) - 30.0).abs() < 1e-9);
+            assert_eq!(p[2], 5.0);
+        }
+    }
+
+    #[test]
+    fn ellipse_reaches_each_radius_along_its_axis() {
+        let mut edges = EdgeList::new();
+        add_ellipse(&mut edges, 0.0, 0.0, 0.0, 40.0, 10.0, 8);
+        let points = points(&edges);
+        assert!(near(points[0], [40.0, 0.0, 0.0, 1.0], 1e-9));
+        assert!(near(points[2], [0.0, 10.0, 0.0, 1.0], 1e-9));
+        assert!(near(points[4], [-40.0, 0.0, 0.0, 1.0], 1e-9));
+        for p in points {
+            assert!(((p[0] / 40.0).powi(2) + (p[1] / 10.0).powi(2) - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_every_control_point() {
+        let controls = [[0.0, 0.0, 0.0, 1.0], [10.0, 20.0, 0.0, 1.0], [30.0, 20.0, 5.0, 1.0], [40.0, 0.0, 5.0, 1.0]];
+        let mut edges = EdgeList::new();
+        add_catmull_rom(&mut edges, 10, Sampling::Uniform, &controls);
+        // A segment of 10 points between each pair of control points
+        let points = points(&edges);
+        assert_eq!(points.len(), 31);
+        for (i, &control) in controls.iter().enumerate() {
+            assert!(near(points[10 * i], control, 1e-9));
+        }
+    }
+
+    #[test]
+    fn b_spline_starts_and_ends_at_its_ends_but_only_nears_the_rest() {
+        let controls = [[0.0, 0.0, 0.0, 1.0], [10.0, 20.0, 0.0, 1.0], [30.0, 20.0, 5.0, 1.0], [40.0, 0.0, 5.0, 1.0]];
+        let mut edges = EdgeList::new();
+        add_b_spline(&mut edges, 10, Sampling::Uniform, &controls);
+        let points = points(&edges);
+        // With the ends tripled there are five segments
+        assert_eq!(points.len(), 51);
+        assert!(near(points[0], controls[0], 1e-9));
+        assert!(near(points[50], controls[3], 1e-9));
+        // The second point pulls the curve towards it without reaching it
+        let closest = points.iter().map(|p| (p[0] - 10.0).hypot(p[1] - 20.0)).fold(f64::MAX, f64::min);
+        assert!(closest > 1.0 && closest < 10.0);
+    }
+
+    #[test]
+    fn splines_need_two_control_points() {
+        let mut edges = EdgeList::new();
+        add_catmull_rom(&mut edges, 10, Sampling::Uniform, &[[0.0, 0.0, 0.0, 1.0]]);
+        add_b_spline(&mut edges, 10, Sampling::ArcLength, &[[0.0, 0.0, 0.0, 1.0]]);
+        assert_eq!(edges.len(), 0);
+    }
+
+    /// The length of each edge of `edges`
+    fn edge_lengths(edges: &EdgeList) -> Vec<f64> {
+        (0..edges.len()).map(|i| {
+            let (a, b) = (edges.col(2 * i), edges.col(2 * i + 1));
+            ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2) + (b[2] - a[2]).powi(2)).sqrt()
+        }).collect()
+    }
+
+    /// Add a quarter circle of radius 100 about the origin, with `points`
+    /// points spread by `sampling`, whose parameter speeds up along it
+    fn quarter_circle(sampling: Sampling, points: usize) -> EdgeList {
+        let mut edges = EdgeList::new();
+        let angle = |t: f64| t * t * PI / 2.0;
+        parametric_sampled(&mut edges, points, sampling, |t| 100.0 * angle(t).cos(), |t| 100.0 * angle(t).sin(), |_| 0.0);
+        edges
+    }
+
+    #[test]
+    fn arc_length_sampling_spaces_points_evenly() {
+        let edges = quarter_circle(Sampling::ArcLength, 20);
+        assert_eq!(points(&edges).len(), 21);
+        // Each chord spans an even share of the arc, a twentieth of 90 degrees
+        let chord = 200.0 * (PI / 80.0).sin();
+        for length in edge_lengths(&edges) {
+            assert!((length - chord).abs() < 0.01 * chord, "{} is not {}", length, chord);
+        }
+        let ends = points(&edges);
+        assert!(near(ends[0], [100.0, 0.0, 0.0, 1.0], 1e-9));
+        assert!(near(ends[20], [0.0, 100.0, 0.0, 1.0], 1e-9));
+    }
+
+    #[test]
+    fn uniform_sampling_follows_the_parameter() {
+        // ...which here starts slowly, bunching the points up at the start
+        let lengths = edge_lengths(&quarter_circle(Sampling::Uniform, 20));
+        assert!(lengths[19] > 10.0 * lengths[0]);
+    }
+
+    #[test]
+    fn bezier_runs_from_its_first_to_its_last_control_point() {
+        let (p0, p1, p2, p3) = ([0.0, 0.0, 0.0, 1.0], [10.0, 30.0, 5.0, 1.0], [40.0, 30.0, -5.0, 1.0], [50.0, 0.0, 10.0, 1.0]);
+        let mut edges = EdgeList::new();
+        add_bezier(&mut edges, 1000, Sampling::Uniform, p0, p1, p2, p3);
+        let points = points(&edges);
+        assert_eq!(points.len(), 1001);
+        assert!(near(points[0], p0, 1e-9));
+        assert!(near(points[1000], p3, 1e-9));
+        // ...heading towards the second control point and from the third,
+        // three times as fast as the distance to them
+        let (start, end) = end_tangents(&points);
+        assert!(near(start, [30.0, 90.0, 15.0, 0.0], 0.2));
+        assert!(near(end, [30.0, -90.0, 45.0, 0.0], 0.2));
+    }
+
+    #[test]
+    fn hermite_runs_between_its_ends_with_its_tangents() {
+        let (p0, p1, m0, m1) = ([0.0, 0.0, 0.0, 1.0], [20.0, 10.0, 0.0, 1.0], [0.0, 40.0, 10.0, 1.0], [30.0, 0.0, -20.0, 1.0]);
+        let mut edges = EdgeList::new();
+        add_hermite(&mut edges, 1000, Sampling::Uniform, p0, p1, m0, m1);
+        let points = points(&edges);
+        assert!(near(points[0], p0, 1e-9));
+        assert!(near(points[1000], p1, 1e-9));
+        let (start, end) = end_tangents(&points);
+        assert!(near(start, m0, 0.2));
+        assert!(near(end, m1, 0.2));
+    }
+}
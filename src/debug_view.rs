@@ -0,0 +1,112 @@
+use std::f64;
+
+use geometry::PolygonList;
+use render::{ self, Color };
+use screen;
+
+/// What the renderer shows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    /// The scene itself
+    Shaded,
+    /// Each polygon filled with its face normal, with x, y, and z mapped to
+    /// red, green, and blue
+    Normals,
+    /// The depth of the nearest polygon at each pixel, from black (farthest)
+    /// to white (nearest)
+    Depth,
+    /// How many polygons cover each pixel, from blue (one) through red to
+    /// yellow (the most)
+    Overdraw
+}
+
+impl View {
+    /// The view called `name` (e.g. "depth").
+    pub fn from_name(name: &str) -> Option<View> {
+        match name {
+            "shaded" => Some(View::Shaded),
+            "normals" => Some(View::Normals),
+            "depth" => Some(View::Depth),
+            "overdraw" => Some(View::Overdraw),
+            _ => None
+        }
+    }
+}
+
+/// Draw `view` (other than `Shaded`) of the triangle list `polygons` on
+/// `screen`. Pixels no polygon covers are black.
+pub fn draw(screen: &mut Vec<Vec<Color>>, polygons: &PolygonList, view: View) {
+    let height = screen.len();
+    let width = if height > 0 { screen[0].len() } else { 0 };
+    match view {
+        View::Shaded => panic!("debug_view::draw called for the shaded view"),
+        View::Normals => {
+            let mut depth = vec![vec![f64::NEG_INFINITY; width]; height];
+            let mut normal = [0.0; 3];
+            let mut last = None;
+            render::fragments(polygons, width, height, (0, height), |f| {
+                if f.z > depth[f.row][f.x] {
+                    depth[f.row][f.x] = f.z;
+                    if last != Some(f.triangle) {
+                        normal = render::face_normal(polygons, f.triangle);
+                        last = Some(f.triangle);
+                    }
+                    screen[f.row][f.x] = Color::rgb(unit_to_byte(normal[0]), unit_to_byte(normal[1]), unit_to_byte(normal[2]));
+                }
+            });
+        },
+        View::Depth => {
+            *screen = screen::depth_image(&depth_buffer(polygons, width, height));
+        },
+        View::Overdraw => {
+            let mut count = vec![vec![0u32; width]; height];
+            render::fragments(polygons, width, height, (0, height), |f| count[f.row][f.x] += 1);
+            let max = count.iter().flat_map(|row| row.iter()).cloned().max().unwrap_or(0);
+            for (row, count_row) in screen.iter_mut().zip(count.iter()) {
+                for (px, &n) in row.iter_mut().zip(count_row.iter()) {
+                    *px = heat(n, max);
+                }
+            }
+        }
+    }
+}
+
+/// Find the depth of the nearest of the triangles in `polygons` at each
+/// pixel of a `width` by `height` screen (indexed like the screen, top row
+/// first), or negative infinity where there are none. Greater z is nearer.
+pub fn depth_buffer(polygons: &PolygonList, width: usize, height: usize) -> Vec<Vec<f64>> {
+    let mut depth = vec![vec![f64::NEG_INFINITY; width]; height];
+    fill_depth(polygons, width, height, 0, &mut depth);
+    depth
+}
+
+/// Like `depth_buffer`, but only for the rows from `top` down of the screen,
+/// which are `depth`.
+pub fn fill_depth(polygons: &PolygonList, width: usize, height: usize, top: usize, depth: &mut [Vec<f64>]) {
+    for row in depth.iter_mut() {
+        for z in row.iter_mut() {
+            *z = f64::NEG_INFINITY;
+        }
+    }
+    render::fragments(polygons, width, height, (top, top + depth.len()), |f| {
+        let row = &mut depth[f.row - top];
+        if f.z > row[f.x] {
+            row[f.x] = f.z;
+        }
+    });
+}
+
+/// Map `v` from [-1, 1] to [0, 255].
+fn unit_to_byte(v: f64) -> u8 {
+    (127.5 * (v + 1.0)).round() as u8
+}
+
+/// The color of `n` of `max` on the overdraw heat map.
+fn heat(n: u32, max: u32) -> Color {
+    if n == 0 {
+        return Color::black();
+    }
+    let t = if max > 1 { (n - 1) as f64 / (max - 1) as f64 } else { 0.0 };
+    // From blue round through magenta and red to yellow
+    Color::hsv(240.0 + 180.0 * t, 1.0, 1.0)
+}
@@ -0,0 +1,98 @@
+use geometry::EdgeList;
+use render::{ self, Color, LineStyle, Point, Style };
+use screen::Screen;
+
+/// Draw a line on the back buffer of `screen` from (x0, y0) to (x1, y1),
+/// both ends included, with Bresenham's line algorithm. Coordinates go the
+/// way the screen's y axis says, and the parts of the line off the screen
+/// are left out.
+pub fn draw_line(screen: &mut Screen, x0: i64, y0: i64, x1: i64, y1: i64, color: Color) {
+    render::line(screen, Point::xy(x0, y0), Point::xy(x1, y1), color);
+}
+
+/// Draw each edge of `edges` with `draw_line`, as it is, without any
+/// transformation or depth test. Coordinates are rounded down to whole
+/// pixels.
+pub fn draw_lines(screen: &mut Screen, edges: &EdgeList, color: Color) {
+    for i in 0..edges.len() {
+        let (p, q) = (edges.col(2 * i), edges.col(2 * i + 1));
+        draw_line(screen, p[0] as i64, p[1] as i64, q[0] as i64, q[1] as i64, color);
+    }
+}
+
+/// Draw an antialiased line on the back buffer of `screen` from (x0, y0) to
+/// (x1, y1) with `render::smooth_line`, mixing `color` into what's there.
+pub fn draw_smooth_line(screen: &mut Screen, x0: f64, y0: f64, x1: f64, y1: f64, color: Color) {
+    render::smooth_line(screen, [x0, y0], [x1, y1], color);
+}
+
+/// Draw a line on the back buffer of `screen` from (x0, y0) to (x1, y1), as
+/// wide and dashed as `line_style` says.
+pub fn draw_styled_line(screen: &mut Screen, x0: f64, y0: f64, x1: f64, y1: f64, line_style: LineStyle, color: Color) {
+    let style = Style { line: line_style, ..Style::new() };
+    render::styled_line(screen, [x0, y0], [x1, y1], &style, 0.0, color);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use screen::YAxis;
+
+    /// Width and height of the screens lines are drawn on
+    const SIZE: usize = 16;
+
+    /// The pixels (column, and row counted the way `screen`'s y axis goes)
+    /// drawn on `screen`, a blank screen before
+    fn drawn(screen: &Screen) -> HashSet<(i64, i64)> {
+        let mut pixels = HashSet::new();
+        for y in 0..SIZE as i64 {
+            for (x, px) in screen.row(y).unwrap().iter().enumerate() {
+                if *px != Color::black() {
+                    pixels.insert((x as i64, y));
+                }
+            }
+        }
+        pixels
+    }
+
+    fn line(x0: i64, y0: i64, x1: i64, y1: i64) -> HashSet<(i64, i64)> {
+        let mut screen = Screen::new(SIZE, SIZE, Color::black());
+        draw_line(&mut screen, x0, y0, x1, y1, Color::white());
+        drawn(&screen)
+    }
+
+    #[test]
+    fn lines_are_connected_in_every_octant() {
+        for &(dx, dy) in [(11, 4), (4, 11), (-4, 11), (-11, 4), (-11, -4), (-4, -11), (4, -11), (11, -4)].iter() {
+            let (x0, y0) = (7 - dx / 2, 7 - dy / 2);
+            let pixels = line(x0, y0, x0 + dx, y0 + dy);
+            assert!(pixels.contains(&(x0, y0)) && pixels.contains(&(x0 + dx, y0 + dy)), "{} {}", dx, dy);
+            // One pixel per step along the longer way
+            assert_eq!(pixels.len() as i64, dx.abs().max(dy.abs()) + 1, "{} {}", dx, dy);
+            assert_eq!(pixels, line(x0 + dx, y0 + dy, x0, y0), "{} {}", dx, dy);
+        }
+    }
+
+    #[test]
+    fn lines_off_the_screen_are_cut_off() {
+        let expected: HashSet<_> = (0..SIZE as i64).map(|x| (x, 5)).collect();
+        assert_eq!(line(-10, 5, 40, 5), expected);
+    }
+
+    #[test]
+    fn lines_follow_the_y_axis() {
+        let mut screen = Screen::new(SIZE, SIZE, Color::black());
+        screen.set_y_axis(YAxis::Down);
+        draw_line(&mut screen, 0, 0, 3, 0, Color::white());
+        assert!(screen.pixels[0][..4].iter().all(|&px| px == Color::white()));
+    }
+
+    #[test]
+    fn styled_lines_are_as_wide_as_their_style() {
+        let mut screen = Screen::new(SIZE, SIZE, Color::black());
+        draw_styled_line(&mut screen, 2.0, 8.0, 13.0, 8.0, LineStyle { width: 3.0, ..LineStyle::new() }, Color::white());
+        let rows: HashSet<_> = drawn(&screen).iter().map(|&(_, y)| y).collect();
+        assert_eq!(rows.len(), 3);
+    }
+}
@@ -0,0 +1,17 @@
+use geometry::{ EdgeList, PolygonList };
+
+/// Something that can be drawn. It emits its edges and polygons in model
+/// coordinates, which are then transformed, culled, and rasterized like
+/// those of any of the built-in shapes and curves.
+pub trait Drawable {
+    /// Append this object's edges to `edges` and its polygons to `polys`
+    /// (each a triangle, clockwise as seen from the front).
+    fn emit(&self, edges: &mut EdgeList, polys: &mut PolygonList);
+}
+
+/// A list of triangles, such as a loaded mesh, draws itself as it is.
+impl Drawable for PolygonList {
+    fn emit(&self, _edges: &mut EdgeList, polys: &mut PolygonList) {
+        polys.extend(self);
+    }
+}
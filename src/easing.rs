@@ -0,0 +1,95 @@
+use std::f64::consts::PI;
+
+/// How a knob moves from its start value to its end value over a `vary`.
+/// Each maps how far through the `vary` a frame is, from 0 to 1, to how far
+/// the knob has gone, also starting at 0 and ending at 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Easing {
+    /// At a steady speed, as by default
+    Linear,
+    /// Starting slowly and speeding up
+    EaseIn,
+    /// Starting quickly and slowing down
+    EaseOut,
+    /// Starting and ending slowly, speeding up in between
+    Cubic,
+    /// Like `Cubic`, but more gently
+    Sine,
+    /// Reaching the end value quickly and bouncing back from it a few times,
+    /// like a dropped ball
+    Bounce
+}
+
+impl Easing {
+    pub fn from_name(name: &str) -> Option<Easing> {
+        match name {
+            "linear" => Some(Easing::Linear),
+            "ease_in" => Some(Easing::EaseIn),
+            "ease_out" => Some(Easing::EaseOut),
+            "cubic" => Some(Easing::Cubic),
+            "sine" => Some(Easing::Sine),
+            "bounce" => Some(Easing::Bounce),
+            _ => None
+        }
+    }
+
+    /// How far along the knob is when `t` of the way through the `vary`
+    pub fn apply(self, t: f64) -> f64 {
+        match self {
+            Easing::Linear => linear(t),
+            Easing::EaseIn => ease_in(t),
+            Easing::EaseOut => ease_out(t),
+            Easing::Cubic => cubic(t),
+            Easing::Sine => sine(t),
+            Easing::Bounce => bounce(t)
+        }
+    }
+}
+
+pub fn linear(t: f64) -> f64 {
+    t
+}
+
+/// Quadratic, starting at rest
+pub fn ease_in(t: f64) -> f64 {
+    t * t
+}
+
+/// Quadratic, ending at rest
+pub fn ease_out(t: f64) -> f64 {
+    t * (2.0 - t)
+}
+
+/// Cubic, starting and ending at rest
+pub fn cubic(t: f64) -> f64 {
+    if t < 0.5 {
+        4.0 * t * t * t
+    } else {
+        let u = 2.0 - 2.0 * t;
+        1.0 - u * u * u / 2.0
+    }
+}
+
+/// Half a cosine wave, starting and ending at rest
+pub fn sine(t: f64) -> f64 {
+    (1.0 - (PI * t).cos()) / 2.0
+}
+
+/// Four parabolic arcs, each a quarter as high as the last, the first
+/// reaching 1 at t = 4/11
+pub fn bounce(t: f64) -> f64 {
+    const N: f64 = 7.5625;
+    const D: f64 = 2.75;
+    if t < 1.0 / D {
+        N * t * t
+    } else if t < 2.0 / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
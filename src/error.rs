@@ -0,0 +1,91 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+use matrix::MatrixError;
+
+/// Everything that can go wrong running a script
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing `path` failed
+    Io { path: String, err: io::Error },
+    /// A script could not be parsed, for each of these reasons
+    Parse(Vec<ParseError>),
+    /// A computation has no sensible result
+    Math(String),
+    /// An index or size was out of range
+    Bounds(String),
+    /// A well-formed script did something it can't, such as popping the
+    /// last coordinate system
+    Script(String),
+    /// A rendered image did not match its reference image
+    Mismatch(String),
+    /// The render was cancelled through its `CancelToken`
+    Cancelled
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// A problem with a line of a script, found while parsing it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// The line it's on, from 1
+    pub line: usize,
+    /// The column (in characters, from 1) of `token`
+    pub column: usize,
+    /// The word or number the problem is with, or "" at the end of a line
+    pub token: String,
+    pub msg: String,
+    /// What might have been meant instead of `token`, if anything close
+    pub suggestion: Option<String>
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}, column {}: {}", self.line, self.column, self.msg)?;
+        match self.suggestion {
+            Some(ref suggestion) => write!(f, " (did you mean '{}'?)", suggestion),
+            None => Ok(())
+        }
+    }
+}
+
+impl Error {
+    /// Make an `Io` error for a failure reading or writing `path`.
+    pub fn io(path: &str, err: io::Error) -> Error {
+        Error::Io { path: String::from(path), err }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io { ref path, ref err } => write!(f, "Could not access '{}': {}", path, err),
+            Error::Parse(ref errors) => {
+                let lines: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            },
+            Error::Math(ref msg) => write!(f, "{}", msg),
+            Error::Bounds(ref msg) => write!(f, "{}", msg),
+            Error::Script(ref msg) => write!(f, "{}", msg),
+            Error::Mismatch(ref msg) => write!(f, "{}", msg),
+            Error::Cancelled => write!(f, "Cancelled")
+        }
+    }
+}
+
+impl From<MatrixError> for Error {
+    fn from(err: MatrixError) -> Error {
+        Error::Bounds(err.to_string())
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io { ref err, .. } => Some(err),
+            _ => None
+        }
+    }
+}
@@ -1,51 +1,239 @@
 use parse::{ self, Command, Axis };
 use matrix::Matrix;
+use cstack::CStack;
+use symbols::SymbolTable;
+use geometry::{ EdgeList, PolygonList };
+use transform::Transform;
 use solid;
-use render;
-use ppm;
-use consts::*;
+use curve;
+use mesh;
+use drawable::Drawable;
+use scene::{ self, Scene };
+use config::RenderConfig;
+use turtle::Turtle;
+use screen::Screen;
+use render::{ self, Style };
+use lighting::Material;
+use camera::Camera;
+use texture::{ Filter, Texture };
+use ppm::{ self, Format };
+use writer::FrameWriter;
+use gif::Animation;
+use checkpoint::Checkpoint;
+use timing::{ Timings, Stage };
+use stats::Stats;
+use std::time::Instant;
+use error::{ Error, Result };
+use std::fs;
+use std::cmp;
+use std::thread;
+use std::mem;
+use std::sync::{ Arc, Mutex };
+use std::collections::HashMap;
 
-pub fn run_script(script: &str) -> Result<(), String> {
-    let mut screen = vec![vec![render::Color::black(); WIDTH]; HEIGHT];
+/// How many points each curve a script draws is approximated with
+const CURVE_POINTS: usize = 64;
 
-    let cmds = parse::parse(script)?;
+/// What running a script produced
+pub enum Rendered {
+    /// The final contents of the screen of a still image
+    Image(Vec<Vec<render::Color>>),
+    /// The files the frames of an animation were saved to, in order
+    Frames(Vec<String>)
+}
+
+/// Run `script`, rendering with `config`. Frames of an animation are saved in
+/// the directory `out_dir` in `format`, and are rendered on `config.threads`
+/// threads. `format` is also used for files saved by the script whose names
+/// have no extension. What was rendered is counted in `stats`.
+pub fn run_script(script: &str, out_dir: &str, format: Format, config: &RenderConfig, timings: &mut Timings, stats: &mut Stats) -> Result<Rendered> {
+    let cmds = timings.time(Stage::Parse, || parse::parse(script, &config.plugins))?;
+    let anim_data = get_anim_data(&cmds)?;
+    if let Some(ref tracker) = config.progress {
+        tracker.start(anim_data.as_ref().map_or(1, |anim_data| anim_data.frames));
+    }
 
-    match get_anim_data(&cmds) {
+    match anim_data {
         Some(anim_data) => {
-            println!("{:?}", &anim_data);
-
-            let basename = anim_data.basename.unwrap_or("anim");
-            let digits_for_name = dec_digits(anim_data.frames);
-
-            // Render and save each frame:
-            for i in 0..anim_data.frames {
-                let knobvals = knobs_for_frame(i, &anim_data.varies);
-                let mut transforms = vec![Matrix::identity()];
-                clear_screen(&mut screen);
-                for cmd in &cmds {
-                    run_cmd(&mut screen, &mut transforms, Some(&knobvals), cmd)?;
-                }
-                let filename = format!("anim/{}{:0digits$}.png", basename, i, digits=digits_for_name);
-                ppm::save_png(&screen, &filename);
+            info!("Rendering {} frames on {} threads", anim_data.frames, config.threads);
+            debug!("{:?}", &anim_data);
+
+            fs::create_dir_all(out_dir).map_err(|e| Error::io(out_dir, e))?;
+            let checkpoint_path = format!("{}/{}.checkpoint", out_dir, anim_data.basename.unwrap_or("anim"));
+            let checkpoint = Arc::new(Checkpoint::open(&checkpoint_path, script, anim_data.frames, format.extension(), config.resume)?);
+
+            // Thread t renders frames t, t + threads, t + 2 * threads, etc.
+            let threads = cmp::max(1, cmp::min(config.threads, anim_data.frames));
+            // ...and each frame on only the one thread
+            let frame_config = config.clone().threads(1);
+            let animation = config.gif_delay.map(|_| Mutex::new(Animation::new()));
+            let results = thread::scope(|s| {
+                let handles: Vec<_> = (0..threads).map(|t| {
+                    let job = Job {
+                        cmds: &cmds,
+                        anim_data: &anim_data,
+                        out_dir,
+                        format,
+                        checkpoint: &checkpoint,
+                        animation: animation.as_ref(),
+                        first: t,
+                        step: threads
+                    };
+                    let config = &frame_config;
+                    let mut thread_timings = timings.empty_copy();
+                    let mut thread_stats = stats.empty_copy();
+                    s.spawn(move || {
+                        let result = render_frames(&job, config, &mut thread_timings, &mut thread_stats);
+                        (result, thread_timings, thread_stats)
+                    })
+                }).collect();
+                handles.into_iter().map(|h| h.join().unwrap()).collect::<Vec<_>>()
+            });
+            for (result, thread_timings, thread_stats) in results {
+                timings.merge(thread_timings);
+                stats.merge(thread_stats);
+                result?;
+            }
+            if let Some(animation) = animation {
+                let path = format!("{}/{}.gif", out_dir, anim_data.basename.unwrap_or("anim"));
+                info!("Saving the animation to {}", path);
+                timings.time(Stage::Write, || animation.into_inner().unwrap().save(&path))?;
             }
-            ppm::clean_up();
+            // Every frame is saved, so there's nothing left to resume
+            if let Ok(checkpoint) = Arc::try_unwrap(checkpoint) {
+                checkpoint.finish()?;
+            }
+
+            let filenames = (0..anim_data.frames).map(|i| frame_filename(&anim_data, out_dir, format, i)).collect();
+            Ok(Rendered::Frames(filenames))
         },
         None => {
-            let mut transforms = vec![Matrix::identity()];
-            for cmd in &cmds {
-                run_cmd(&mut screen, &mut transforms, None, cmd)?;
+            // A still image is split into bands drawn on separate threads
+            let mut screen = config.blank_screen();
+            let mut state = State::new(config.clone());
+            {
+                let _span = span!("scene");
+                run_cmds(&mut state, false, &cmds, |state, _, cmd| {
+                    config.cancel.check()?;
+                    run_cmd(&mut screen, state, false, format, timings, stats, cmd)
+                })?;
             }
+            config.cancel.check()?;
+            render_scene(&mut screen, &state, timings, stats);
+            stats.frame(0);
+            if let Some(ref tracker) = config.progress {
+                tracker.frame_done();
+            }
+            screen.swap();
+            Ok(Rendered::Image(screen.into_front()))
         }
     }
-    Ok(())
 }
 
-fn clear_screen(screen: &mut Vec<Vec<render::Color>>) {
-    for v in screen {
-        for i in 0..v.len() {
-            v[i] = render::Color::black();
+/// Render the still image drawn by `script`, without saving or displaying
+/// anything, and return the screen. Animation commands are ignored, so the
+/// result is what frame 0 would be if no knobs were used. This is the hook
+/// for benchmarking the whole pipeline.
+pub fn render_headless(script: &str, config: &RenderConfig) -> Result<Vec<Vec<render::Color>>> {
+    Ok(render_still(script, config)?.0.pixels)
+}
+
+/// Render the still image drawn by `script` as `render_headless` does, and
+/// return the whole screen, with its depth, stencil, and ID buffers, the
+/// scene drawn on it, and the index among the script's commands of the one
+/// drawing each shape, in the order they were drawn.
+pub fn render_still(script: &str, config: &RenderConfig) -> Result<(Screen, Scene, Vec<usize>)> {
+    let cmds = parse::parse(script, &config.plugins)?;
+    let mut screen = config.blank_screen();
+    let mut state = State::new(config.clone());
+    let mut timings = Timings::new(false);
+    let mut stats = Stats::new(false);
+    let mut shapes = vec![];
+    run_cmds(&mut state, false, &cmds, |state, i, cmd| {
+        if cmd.is_shape() {
+            shapes.push(i);
+        }
+        match cmd {
+            &Command::Save(..) | &Command::SaveDepth(..) | &Command::SaveMesh(..) | &Command::Display => Ok(()),
+            _ => run_cmd(&mut screen, state, false, Format::Ppm, &mut timings, &mut stats, cmd)
+        }
+    })?;
+    scene::render(&state.scene, &mut screen, &state.config);
+    Ok((screen, state.scene, shapes))
+}
+
+/// One thread's share of rendering an animation: every `step`th frame,
+/// starting at frame `first`, saved in `out_dir` in `format`
+struct Job<'a> {
+    cmds: &'a Vec<Command<'a>>,
+    anim_data: &'a AnimData<'a>,
+    out_dir: &'a str,
+    format: Format,
+    /// Where saved frames are recorded
+    checkpoint: &'a Arc<Checkpoint>,
+    /// Where frames are collected for a GIF, if they are
+    animation: Option<&'a Mutex<Animation>>,
+    first: usize,
+    step: usize
+}
+
+/// Render and save the frames of `job`. Frames are saved by a `FrameWriter`
+/// while the next is rendered, and recorded in its checkpoint. Frames it
+/// already records are skipped, unless they're also collected in its
+/// animation.
+fn render_frames(job: &Job, config: &RenderConfig, timings: &mut Timings, stats: &mut Stats) -> Result<()> {
+    let Job { cmds, anim_data, out_dir, format, checkpoint, animation, first, step } = *job;
+    let writer = FrameWriter::start(format, timings.empty_copy(), Some(checkpoint.clone()));
+    let mut screen = config.blank_screen();
+    let mut state = State::new(config.clone());
+    let mut i = first;
+    // Once cancelled, frames already sent to the writer are still saved
+    while i < anim_data.frames && !config.cancel.is_cancelled() {
+        let _frame_span = span!("frame", frame = i);
+        let frame_start = Instant::now();
+        let knobvals = knobs_for_frame(i, &anim_data.varies);
+        let filename = frame_filename(anim_data, out_dir, format, i);
+        if animation.is_none() && checkpoint.is_done(i, &knobvals, &filename) {
+            debug!("Frame {} was saved before; skipping it", i);
+            if let Some(ref tracker) = config.progress {
+                tracker.frame_done();
+            }
+            i += step;
+            continue;
+        }
+        state.reset(i);
+        for &(knob, val) in &knobvals {
+            state.symbols.set_knob(knob, val);
+        }
+        {
+            let _span = span!("scene");
+            run_cmds(&mut state, true, cmds, |state, _, cmd| {
+                run_cmd(&mut screen, state, true, format, timings, stats, cmd)
+            })?;
         }
+        render_scene(&mut screen, &state, timings, stats);
+        stats.frame(i);
+        if let Some(animation) = animation {
+            animation.lock().unwrap().set_frame(i, screen.pixels.clone(), config.gif_delay.unwrap_or(0));
+        }
+        info!("Saving frame {} to {}", i, filename);
+        let next_screen = writer.spare_image().unwrap_or_else(|| screen.pixels.clone());
+        writer.save(mem::replace(&mut screen.pixels, next_screen), filename);
+        writer.frame_saved(i, &knobvals);
+        timings.frame(i, frame_start.elapsed());
+        if let Some(ref tracker) = config.progress {
+            tracker.frame_done();
+        }
+        i += step;
     }
+    timings.merge(writer.finish()?);
+    config.cancel.check()
+}
+
+fn frame_filename(anim_data: &AnimData, out_dir: &str, format: Format, frame: usize) -> String {
+    let basename = anim_data.basename.unwrap_or("anim");
+    let digits = dec_digits(anim_data.frames);
+    format!("{}/{}{:0digits$}.{}", out_dir, basename, frame, format.extension(), digits=digits)
 }
 
 fn dec_digits(mut n: usize) -> usize {
@@ -64,51 +252,74 @@ struct AnimData<'a> {
     varies: Vec<parse::Variation<'a>>
 }
 
-fn get_anim_data<'a>(commands: &Vec<Command<'a>>) -> Option<AnimData<'a>> {
+/// Collect the animation commands, if there are any, checking that each
+/// `vary` is within the frames of the animation and that no two `vary`s set
+/// the same knob in the same frame (except one ending where the next starts,
+/// which can't both be the one frame).
+fn get_anim_data<'a>(commands: &Vec<Command<'a>>) -> Result<Option<AnimData<'a>>> {
     let mut mframes = None;
     let mut mbasename = None;
     let mut varies = vec![];
     for cmd in commands {
-        match cmd {
-            &Command::Frames(f) => {
+        match *cmd {
+            Command::Frames(f) => {
                 mframes = Some(f);
             },
-            &Command::Basename(s) => {
+            Command::Basename(s) => {
                 mbasename = Some(s);
             },
-            &Command::Vary(ref variation) => {
+            Command::Vary(ref variation) => {
                 varies.push(variation.clone());
             },
             _ => {}
         }
     }
     if let Some(frames) = mframes {
-        return Some(AnimData {
-            frames: frames,
+        for vary in &varies {
+            if vary.last_frame >= frames {
+                return Err(Error::Bounds(format!(
+                    "'vary {}' runs to frame {}, but the last frame is {}",
+                    vary.knob, vary.last_frame, frames as isize - 1)));
+            }
+            if vary.last_frame < vary.fst_frame {
+                return Err(Error::Math(format!(
+                    "'vary {}' can't end before it starts (frames {} to {})",
+                    vary.knob, vary.fst_frame, vary.last_frame)));
+            }
+        }
+        for (i, vary) in varies.iter().enumerate() {
+            for other in &varies[..i] {
+                let overlap = vary.fst_frame < other.last_frame && other.fst_frame < vary.last_frame;
+                let same_frame = vary.fst_frame == vary.last_frame && (other.fst_frame, other.last_frame) == (vary.fst_frame, vary.last_frame);
+                if other.knob == vary.knob && (overlap || same_frame) {
+                    return Err(Error::Script(format!(
+                        "'vary {}' over frames {} to {} overlaps another over frames {} to {}",
+                        vary.knob, vary.fst_frame, vary.last_frame, other.fst_frame, other.last_frame)));
+                }
+            }
+        }
+        return Ok(Some(AnimData {
+            frames,
             basename: mbasename,
-            varies: varies
-        });
+            varies
+        }));
     }
-    if varies.len() > 0 {
-        println!("WARNING: found 'vary' but not 'frames'");
-    }
-    return None;
-}
-
-fn knob_val<'a>(knobs: &Vec<(&'a str, f64)>, knob: &'a str) -> f64 {
-    for &(k, v) in knobs {
-        if k == knob {
-            return v;
-        }
+    if !varies.is_empty() {
+        warn!("found 'vary' but not 'frames'");
     }
-    panic!("Knob '{}' not defined for every frame");
+    Ok(None)
 }
 
-fn optknob_val<'a>(optknobs: Option<&Vec<(&'a str, f64)>>, optknob: Option<&'a str>) -> f64 {
-    if let (Some(knobs), Some(knob)) = (optknobs, optknob) {
-        knob_val(knobs, knob)
-    } else {
-        1.0
+/// The value of the knob `optknob` in `symbols`, or 1 if there's none. In
+/// an animation (if `animating` is set), every knob used must have a value.
+fn optknob_val(symbols: &SymbolTable, animating: bool, optknob: Option<&str>) -> Result<f64> {
+    match optknob {
+        Some(knob) => match symbols.knob(knob) {
+            Some(val) => Ok(val),
+            None if animating => Err(Error::Script(format!("Knob '{}' not defined for every frame", knob))),
+            None => Ok(1.0)
+        },
+        None => Ok(1.0)
     }
 }
 
@@ -116,124 +327,883 @@ fn knobs_for_frame<'a>(frame: usize, varies: &Vec<parse::Variation<'a>>) -> Vec<
     let mut knob_vals = vec![];
     for vary in varies {
         if vary.fst_frame <= frame && frame <= vary.last_frame {
-            // // FIXME: doing this with binary search is cute but may well
-            // // be slower than just using linear search and appending to the end
-            // // Insert the knob-value association unless there already is one for this knob
-            // match knob_vals.binary_search_by_key(vary.knob, |v| v.knob) {
-            //     Ok(pos) => {
-            //         // There is already a val for this knob
-            //         panic!("ERROR: Overlapping vary commands for knob '{}'", vary.knob);
-            //     },
-            //     Err(pos) => {
-            //         // The knob is not yet in the list
-            //         knob_vals.insert(pos, ...);
-            //         // Yes, this is O(n). See FIXME above.
-            //     }
-            // }
-            let progress = (frame - vary.fst_frame) as f64 / (vary.last_frame - vary.fst_frame) as f64;
-            let val = vary.min_val + (vary.max_val - vary.min_val) * progress;
+            // `get_anim_data` ensures this knob can only have another value
+            // here from a `vary` ending where this one starts, which this
+            // (later) value replaces when the knobs are set
+            // A `vary` over a single frame jumps straight to its end value
+            let progress = if vary.last_frame > vary.fst_frame {
+                (frame - vary.fst_frame) as f64 / (vary.last_frame - vary.fst_frame) as f64
+            } else {
+                1.0
+            };
+            let val = vary.min_val + (vary.max_val - vary.min_val) * vary.easing.apply(progress);
             knob_vals.push((vary.knob, val))
         }
         // Otherwise, this 'vary' doesn't apply to the current frame.
     }
-    return knob_vals;
+    knob_vals
+}
+
+/// Everything needed to run commands besides the screen. It is kept from one
+/// frame of an animation to the next.
+struct State {
+    /// What has been drawn so far
+    scene: Scene,
+    /// The coordinate system stack
+    transforms: CStack,
+    rotations: RotationCache,
+    scratch: Scratch,
+    config: RenderConfig,
+    /// The number of the frame being drawn
+    frame: usize,
+    /// The constants and knobs defined so far
+    symbols: SymbolTable,
+    /// How shapes drawn now are drawn
+    style: Style,
+    /// Where in the image shapes drawn now are put: the whole image is
+    /// shrunk to fit this rectangle, if there is one
+    viewport: Option<Matrix>,
+    /// What takes the world coordinates shapes drawn now are in onto the
+    /// whole image, if they aren't already in pixels
+    window: Option<Matrix>,
+    /// The preset view shapes drawn now are seen from, if any
+    projection: Option<Matrix>,
+    /// The camera shapes drawn now are seen in perspective from, if any,
+    /// in place of the preset view
+    camera: Option<Camera>,
+    /// Draws the lines of the turtle commands
+    turtle: Turtle,
+    /// Whether coordinates are 2D, with y increasing downwards
+    two_d: bool,
+    /// Whether the script has added lights in this frame, replacing the
+    /// default one
+    lit: bool,
+    /// The textures loaded so far, by file and filter, so they're only read
+    /// once however many frames use them
+    textures: HashMap<(String, Filter), Arc<Texture>>,
+    /// The meshes loaded so far, by file, likewise
+    meshes: HashMap<String, Arc<PolygonList>>,
+    /// How many times shapes drawn now are subdivided
+    subdivisions: usize
+}
+
+impl State {
+    fn new(config: RenderConfig) -> State {
+        let projection = config.projection.map(|p| p.matrix(config.width, config.height));
+        let style = Style { antialias: config.antialias, ..Style::new() };
+        let camera = config.camera;
+        State {
+            scene: Scene::new(config.background),
+            transforms: CStack::new(),
+            rotations: RotationCache::new(),
+            scratch: Scratch::new(),
+            config,
+            frame: 0,
+            symbols: SymbolTable::new(),
+            style,
+            viewport: None,
+            window: None,
+            projection,
+            camera,
+            turtle: Turtle::new(),
+            two_d: false,
+            lit: false,
+            textures: HashMap::new(),
+            meshes: HashMap::new(),
+            subdivisions: 0
+        }
+    }
+
+    /// Get ready to draw frame `frame`.
+    fn reset(&mut self, frame: usize) {
+        self.frame = frame;
+        self.symbols.clear_frame();
+        self.style = Style { antialias: self.config.antialias, ..Style::new() };
+        self.viewport = None;
+        self.window = None;
+        self.projection = self.config.projection.map(|p| p.matrix(self.config.width, self.config.height));
+        self.camera = self.config.camera;
+        self.turtle = Turtle::new();
+        self.two_d = false;
+        self.lit = false;
+        self.subdivisions = 0;
+        self.scene.clear();
+        self.scene.frame = frame;
+        self.transforms.clear();
+        self.rotations.next_frame();
+    }
+}
+
+/// What a plugin command (see `plugin::CommandPlugin`) can get at while it
+/// runs. Not every part is used by the built-in plugins, but all are there
+/// for library users.
+pub struct Context<'a> {
+    state: &'a mut State,
+    screen: &'a mut Screen,
+    timings: &'a mut Timings,
+    /// Whether a frame of an animation is being drawn
+    animating: bool,
+    /// The number of the shape the command draws, for picking
+    object: u32
+}
+
+impl<'a> Context<'a> {
+    /// Everything drawn so far in the frame
+    pub fn scene(&mut self) -> &mut Scene {
+        &mut self.state.scene
+    }
+
+    /// The coordinate system stack, with the current one last
+    pub fn stack(&mut self) -> &mut CStack {
+        &mut self.state.transforms
+    }
+
+    pub fn screen(&mut self) -> &mut Screen {
+        self.screen
+    }
+
+    /// Add `shape` to the scene, as the built-in shape commands do.
+    pub fn draw<D: Drawable + ?Sized>(&mut self, shape: &D) {
+        self.state.style.object = self.object - 1;
+        draw(self.state, self.timings, shape);
+    }
+
+    /// The value of the knob `knob` in this frame, or 1 outside of an
+    /// animation if it isn't set.
+    pub fn knob(&self, knob: &str) -> Result<f64> {
+        optknob_val(&self.state.symbols, self.animating, Some(knob))
+    }
+
+    /// The constants and knobs defined so far
+    pub fn symbols(&mut self) -> &mut SymbolTable {
+        &mut self.state.symbols
+    }
+}
+
+/// Buffers for the geometry made while drawing, reused from one shape (and
+/// frame) to the next so that animations don't keep reallocating them.
+struct Scratch {
+    /// Edges emitted by a shape, before transformation
+    edges: EdgeList,
+    /// Polygons emitted by a shape, before transformation
+    polys: PolygonList,
+    /// Edges and polygons after transformation by the top of the stack
+    world_edges: EdgeList,
+    world_polys: PolygonList
+}
+
+impl Scratch {
+    fn new() -> Scratch {
+        Scratch {
+            edges: EdgeList::new(),
+            polys: PolygonList::new(),
+            world_edges: EdgeList::new(),
+            world_polys: PolygonList::new()
+        }
+    }
+}
+
+/// Rotation matrices that have already been made, by axis and angle. Across
+/// the frames of an animation the same rotations come up again and again
+/// (from rotations that don't use a knob, or knobs that hold steady for a
+/// while), so they are only computed once. Only those used in the current
+/// frame or the one before are kept, so knobs that change every frame don't
+/// fill it up.
+struct RotationCache {
+    /// The rotations used so far in the current frame
+    rotations: HashMap<(u8, u64), Matrix>,
+    /// The rotations used in the previous frame
+    previous: HashMap<(u8, u64), Matrix>
+}
+
+impl RotationCache {
+    fn new() -> RotationCache {
+        RotationCache { rotations: HashMap::new(), previous: HashMap::new() }
+    }
+
+    /// Start a new frame, forgetting the rotations the last frame didn't use.
+    fn next_frame(&mut self) {
+        mem::swap(&mut self.rotations, &mut self.previous);
+        self.rotations.clear();
+    }
+
+    /// Get the matrix rotating by `radians` about `axis`.
+    fn get(&mut self, axis: Axis, radians: f64) -> &Matrix {
+        let key = (axis as u8, radians.to_bits());
+        let previous = &mut self.previous;
+        self.rotations.entry(key).or_insert_with(|| {
+            previous.remove(&key).unwrap_or_else(|| match axis {
+                Axis::X => Transform::new().rotate_x(radians),
+                Axis::Y => Transform::new().rotate_y(radians),
+                Axis::Z => Transform::new().rotate_z(radians)
+            }.matrix())
+        })
+    }
+}
+
+/// How to spread the points of the curves drawn now: evenly along them if
+/// they're dashed, so the dashes are even, and otherwise by their parameter,
+/// which is quicker.
+fn curve_sampling(state: &State) -> curve::Sampling {
+    if state.style.line.dash_pattern.is_some() {
+        curve::Sampling::ArcLength
+    } else {
+        curve::Sampling::Uniform
+    }
+}
+
+fn homogeneous(p: [f64; 3]) -> [f64; 4] {
+    [p[0], p[1], p[2], 1.0]
+}
+
+/// Add `shape` to the scene, transformed by the top of the stack.
+fn draw<D: Drawable + ?Sized>(state: &mut State, timings: &mut Timings, shape: &D) {
+    let scratch = &mut state.scratch;
+    let scene = &mut state.scene;
+    let top = state.transforms.top();
+    let (vertex_shader, frame, viewport) = (&state.config.vertex_shader, state.frame, &state.viewport);
+    let (width, height) = (state.config.width, state.config.height);
+    let camera = &state.camera;
+    let projection = if camera.is_some() { &None } else { &state.projection };
+    let window = &state.window;
+    // 2D coordinates are flipped over to put y = 0 at the top of the image
+    let flip = if state.two_d {
+        Some(Transform::new().scale(1.0, -1.0, 1.0).translate(0.0, state.config.height as f64 - 1.0, 0.0).matrix())
+    } else {
+        None
+    };
+    // Then the projection and viewport, in that order
+    let views: Vec<&Matrix> = projection.iter().chain(viewport.iter()).collect();
+    scratch.edges.clear();
+    scratch.polys.clear();
+    shape.emit(&mut scratch.edges, &mut scratch.polys);
+    if state.subdivisions > 0 {
+        scratch.polys = mesh::subdivide(&scratch.polys, state.subdivisions);
+    }
+    timings.time(Stage::Transform, || {
+        let polys = &mut scratch.world_polys;
+        scratch.polys.transform_into(top, polys);
+        if let Some(ref shader) = *vertex_shader {
+            polys.map_points(|p| shader.displace(p, frame));
+        }
+        if let Some(ref window) = *window {
+            window.apply_to(polys);
+        }
+        if let Some(ref flip) = flip {
+            flip.apply_to(polys);
+            // Flipping turns the polygons' fronts away, so turn them back
+            polys.flip_winding();
+        }
+        if let Some(ref camera) = *camera {
+            let (projected, clipped) = camera.project_polygons(polys, width, height);
+            *polys = projected;
+            scene.clipped += clipped;
+        }
+        for view in &views {
+            polys.map_points(|p| view.apply_to_point(p));
+        }
+        scene.polygons.extend(polys);
+        let edges = &mut scratch.world_edges;
+        scratch.edges.transform_into(top, edges);
+        if let Some(ref shader) = *vertex_shader {
+            edges.map_points(|p| shader.displace(p, frame));
+        }
+        if let Some(ref window) = *window {
+            window.apply_to(edges);
+        }
+        if let Some(ref flip) = flip {
+            flip.apply_to(edges);
+        }
+        if let Some(ref camera) = *camera {
+            *edges = camera.project_edges(edges, width, height);
+        }
+        for view in &views {
+            edges.map_points(|p| view.apply_to_point(p));
+        }
+        scene.edges.extend(edges);
+    });
+    // Number the shape, for picking
+    state.style.object += 1;
+    let style = state.style;
+    scene.polygon_styles.resize(scene.polygons.len(), style);
+    scene.edge_styles.resize(scene.edges.len(), style);
 }
 
-fn last<T>(v: &Vec<T>) -> &T {
-    &v[v.len() - 1]
+/// Draw everything in the scene on `screen`.
+fn render_scene(screen: &mut Screen, state: &State, timings: &mut Timings, stats: &mut Stats) {
+    let (visible, styles) = timings.time(Stage::Cull, || scene::visible_polygons(&state.scene, &state.config));
+    let rendered = scene::draw_visible(&state.scene, &visible, &styles, screen, &state.config, timings);
+    stats.record(&rendered);
+    stats.record_image(screen);
+}
+
+/// A `repeat` or `if` being run
+enum Block<'a> {
+    /// A `repeat` at command `start`, on its `i`th time round of `count`,
+    /// with `knob` set to `i`
+    Repeat { start: usize, knob: &'a str, i: usize, count: usize },
+    /// The branch of an `if` being run
+    If
+}
+
+/// Run `cmds` with `run`, which is given each command and its index, going
+/// round their loops and taking the branches of their conditionals. The
+/// blocks are known to match, since the commands were parsed.
+fn run_cmds<'a, F>(state: &mut State, animating: bool, cmds: &[Command<'a>], mut run: F) -> Result<()>
+    where F: FnMut(&mut State, usize, &Command<'a>) -> Result<()>
+{
+    let mut blocks = vec![];
+    let mut i = 0;
+    while i < cmds.len() {
+        match &cmds[i] {
+            &Command::Repeat(knob, count) => {
+                debug!("{:?}", cmds[i]);
+                if count == 0 {
+                    i = block_end(cmds, i).1;
+                } else {
+                    state.symbols.set_knob(knob, 0.0);
+                    blocks.push(Block::Repeat { start: i, knob, i: 0, count });
+                }
+            },
+            Command::If(condition) => {
+                debug!("{:?}", cmds[i]);
+                let val = optknob_val(&state.symbols, animating, Some(condition.knob))?;
+                if condition.comparison.holds(val, condition.value) {
+                    blocks.push(Block::If);
+                } else {
+                    match block_end(cmds, i) {
+                        (Some(else_at), _) => {
+                            blocks.push(Block::If);
+                            i = else_at;
+                        },
+                        (None, end) => i = end
+                    }
+                }
+            },
+            &Command::Else => {
+                // The branch taken is done, so skip the other
+                blocks.pop();
+                i = block_end(cmds, i).1;
+            },
+            &Command::End => {
+                if let Some(Block::Repeat { start, knob, i: n, count }) = blocks.pop() {
+                    if n + 1 < count {
+                        state.symbols.set_knob(knob, (n + 1) as f64);
+                        blocks.push(Block::Repeat { start, knob, i: n + 1, count });
+                        i = start;
+                    }
+                }
+            },
+            cmd => run(state, i, cmd)?
+        }
+        i += 1;
+    }
+    Ok(())
 }
 
-fn transform_last(mat: &Matrix, transforms: &mut Vec<Matrix>) {
-    let len = transforms.len();
-    transforms[len - 1].transform_on_right(mat);
+/// The indices of the `else` (if any) and `end` of the block starting at (or
+/// with the `else` at) command `start`
+fn block_end(cmds: &[Command], start: usize) -> (Option<usize>, usize) {
+    let mut depth = 0;
+    let mut else_at = None;
+    for (i, cmd) in cmds.iter().enumerate().skip(start + 1) {
+        match cmd {
+            &Command::Repeat(..) | &Command::If(..) => depth += 1,
+            &Command::Else if depth == 0 => else_at = Some(i),
+            &Command::End if depth == 0 => return (else_at, i),
+            &Command::End => depth -= 1,
+            _ => {}
+        }
+    }
+    (else_at, cmds.len())
 }
 
-fn run_cmd(screen: &mut Vec<Vec<render::Color>>, transforms: &mut Vec<Matrix>, knobs: Option<&Vec<(&str, f64)>>, cmd: &Command) -> Result<(), String> {
+fn run_cmd(screen: &mut Screen, state: &mut State, animating: bool, format: Format, timings: &mut Timings, stats: &mut Stats, cmd: &Command) -> Result<()> {
+    debug!("{:?}", cmd);
+    let transforms = &mut state.transforms;
     match cmd {
         &Command::Line { x0, y0, z0, x1, y1, z1 } => {
-            let mut edges = Matrix::empty();
-            edges.push_edge(
-                [x0, y0, z0, 1.0],
-                [x1, y1, z1, 1.0]);
-            edges = last(&transforms) * &edges;
-            render::edge_list(screen, &edges);
+            draw(state, timings, &curve::Line {
+                p0: [x0, y0, z0, 1.0],
+                p1: [x1, y1, z1, 1.0]
+            });
+            Ok(())
+        },
+
+        &Command::Forward(distance, knob) => {
+            let segment = state.turtle.forward(optknob_val(&state.symbols, animating, knob)? * distance);
+            draw(state, timings, &segment);
+            Ok(())
+        },
+
+        &Command::Turn(degrees, knob) => {
+            state.turtle.turn(optknob_val(&state.symbols, animating, knob)? * degrees);
+            Ok(())
+        },
+
+        &Command::Pitch(degrees, knob) => {
+            state.turtle.pitch(optknob_val(&state.symbols, animating, knob)? * degrees);
+            Ok(())
+        },
+
+        &Command::Roll(degrees, knob) => {
+            state.turtle.roll(optknob_val(&state.symbols, animating, knob)? * degrees);
+            Ok(())
+        },
+
+        &Command::Rect { x, y, w, h } => {
+            draw(state, timings, &solid::Rect { x, y, w, h });
+            Ok(())
+        },
+
+        &Command::Circle { x, y, r } => {
+            draw(state, timings, &curve::Circle { cx: x, cy: y, cz: 0.0, r });
+            Ok(())
+        },
+
+        &Command::Ellipse { x, y, rx, ry } => {
+            draw(state, timings, &curve::Ellipse { cx: x, cy: y, cz: 0.0, rx, ry });
+            Ok(())
+        },
+
+        &Command::Mode2d(on) => {
+            state.two_d = on;
+            Ok(())
+        },
+
+        Command::Plugin(plugin, args) => {
+            // Whatever the command draws is one shape
+            let object = state.style.object + 1;
+            plugin.run(args, &mut Context { state, screen, timings, animating, object })?;
+            state.style.object = object;
             Ok(())
         },
 
-        // TODO: (Parse and) draw curves as well. It was not assigned, but is nice to have.
+        &Command::PenUp => {
+            state.turtle.pen_down = false;
+            Ok(())
+        },
+
+        &Command::PenDown => {
+            state.turtle.pen_down = true;
+            Ok(())
+        },
+
+        &Command::Bezier([p0, p1, p2, p3]) => {
+            let sampling = curve_sampling(state);
+            draw(state, timings, &curve::Bezier {
+                points: CURVE_POINTS,
+                sampling,
+                p0: homogeneous(p0), p1: homogeneous(p1), p2: homogeneous(p2), p3: homogeneous(p3)
+            });
+            Ok(())
+        },
+
+        &Command::Hermite([p0, p1, m0, m1]) => {
+            let sampling = curve_sampling(state);
+            draw(state, timings, &curve::Hermite {
+                points: CURVE_POINTS,
+                sampling,
+                p0: homogeneous(p0), p1: homogeneous(p1), m0: homogeneous(m0), m1: homogeneous(m1)
+            });
+            Ok(())
+        },
+
+        Command::Spline(controls) | Command::BSpline(controls) => {
+            let sampling = curve_sampling(state);
+            let controls = controls.iter().cloned().map(homogeneous).collect();
+            let spline = match cmd {
+                &Command::Spline(..) => curve::Spline::CatmullRom,
+                _ => curve::Spline::BSpline
+            };
+            draw(state, timings, &curve::SplineCurve { spline, points: CURVE_POINTS, sampling, controls });
+            Ok(())
+        },
 
         &Command::Box { x, y, z, w, h, d } => {
-            let mut triangles = Matrix::empty();
-            solid::rect_prism(&mut triangles, x, y, z, w, h, d);
-            triangles = last(&transforms) * &triangles;
-            render::triangle_list(screen, &triangles);
+            draw(state, timings, &solid::RectPrism { x, y, z, dx: w, dy: h, dz: d });
+            Ok(())
+        },
+
+        &Command::Subdivide(levels) => {
+            state.subdivisions = levels;
+            Ok(())
+        },
+
+        &Command::Mesh(filename) => {
+            let mesh = match state.meshes.get(filename) {
+                Some(mesh) => mesh.clone(),
+                None => Arc::new(mesh::load_obj(filename)?)
+            };
+            state.meshes.insert(String::from(filename), mesh.clone());
+            draw(state, timings, &*mesh);
             Ok(())
         },
 
         &Command::Sphere { x, y, z, r } => {
-            let mut triangles = Matrix::empty();
-            solid::sphere(&mut triangles, x, y, z, r);
-            triangles = last(&transforms) * &triangles;
-            render::triangle_list(screen, &triangles);
+            draw(state, timings, &solid::Sphere { cx: x, cy: y, cz: z, r });
             Ok(())
         },
 
         &Command::Torus { x, y, z, r0, r1 } => {
-            let mut triangles = Matrix::empty();
-            solid::torus(&mut triangles, x, y, z, r0, r1);
-            triangles = last(&transforms) * &triangles;
-            render::triangle_list(screen, &triangles);
+            draw(state, timings, &solid::Torus { x, y, z, r1: r0, r2: r1 });
+            Ok(())
+        },
+
+        &Command::Cylinder { x, y, z, r, h } => {
+            draw(state, timings, &solid::Cylinder { x, y, z, r, h });
+            Ok(())
+        },
+
+        &Command::Cone { x, y, z, r, h } => {
+            draw(state, timings, &solid::Cone { x, y, z, r, h });
+            Ok(())
+        },
+
+        Command::Revolve(profile) => {
+            draw(state, timings, &solid::Revolution { profile: profile.iter().map(|p| [p[0], p[1]]).collect() });
+            Ok(())
+        },
+
+        Command::Extrude { depth, outline } => {
+            draw(state, timings, &solid::Extrusion { outline: outline.iter().map(|p| [p[0], p[1]]).collect(), depth: *depth });
             Ok(())
         },
 
         &Command::Push => {
-            let top = last(&transforms).clone();
-            transforms.push(top);
+            transforms.push();
             Ok(())
         },
 
         &Command::Pop => {
-            transforms.pop();
+            match transforms.pop() {
+                Some(_) => Ok(()),
+                None => Err(Error::Script(String::from("Cannot pop the last coordinate system")))
+            }
+        },
+
+        &Command::SaveCoords(name) => {
+            transforms.save_coord_system(name);
             Ok(())
         },
 
+        &Command::RestoreCoords(name) => {
+            if transforms.restore(name) {
+                Ok(())
+            } else {
+                Err(Error::Script(format!("No coordinate system saved as '{}'", name)))
+            }
+        },
+
         &Command::Scale { x, y, z, knob } => {
-            let t = optknob_val(knobs, knob);
-            transform_last(&Matrix::dilation_xyz(t * x, t * y, t * z), transforms);
+            let t = optknob_val(&state.symbols, animating, knob)?;
+            transforms.apply(&Matrix::dilation_xyz(t * x, t * y, t * z));
             Ok(())
         },
 
         &Command::Move { x, y, z, knob } => {
-            let t = optknob_val(knobs, knob);
-            transform_last(&Matrix::translation_xyz(t * x, t * y, t * z), transforms);
+            let t = optknob_val(&state.symbols, animating, knob)?;
+            transforms.apply(&Matrix::translation(t * x, t * y, t * z));
             Ok(())
         },
 
         &Command::Rotate(axis, degrees, knob) => {
-            let t = optknob_val(knobs, knob);
+            let t = optknob_val(&state.symbols, animating, knob)?;
             let radians = degrees.to_radians();
-            let rotation = match axis {
-                Axis::X => Matrix::rotation_about_x(t * radians),
-                Axis::Y => Matrix::rotation_about_y(t * radians),
-                Axis::Z => Matrix::rotation_about_z(t * radians)
+            transforms.apply(state.rotations.get(axis, t * radians));
+            Ok(())
+        },
+
+        &Command::RotateAbout(axis, degrees, knob) => {
+            let t = optknob_val(&state.symbols, animating, knob)?;
+            transforms.apply(&Transform::new().rotate(axis, t * degrees.to_radians()).matrix());
+            Ok(())
+        },
+
+        &Command::Blend(mode) => {
+            state.style.blend = mode;
+            Ok(())
+        },
+
+        &Command::Ambient(color) => {
+            state.scene.lighting.ambient = color;
+            Ok(())
+        },
+
+        &Command::Light(name, light) => {
+            if !state.lit {
+                state.scene.lighting.lights.clear();
+                state.lit = true;
+            }
+            let (symbols, lights) = (&mut state.symbols, &mut state.scene.lighting.lights);
+            match name.and_then(|name| symbols.set_light(name, lights.len())) {
+                Some(i) => lights[i] = light,
+                None => lights.push(light)
+            }
+            Ok(())
+        },
+
+        &Command::Material(name) => {
+            state.style.material = match name {
+                Some(name) => match state.symbols.constants(name) {
+                    Some(constants) => Material::from_constants(constants),
+                    None => return Err(Error::Script(format!("No constants defined as '{}'", name)))
+                },
+                None => Material::new()
             };
-            transform_last(&rotation, transforms);
             Ok(())
         },
 
-        &Command::Display => {
-            ppm::display_image(&screen);
+        &Command::Texture(None) => {
+            state.style.texture = None;
+            Ok(())
+        },
+
+        &Command::Texture(Some((filename, filter))) => {
+            let key = (String::from(filename), filter);
+            let texture = match state.textures.get(&key) {
+                Some(texture) => texture.clone(),
+                None => Arc::new(Texture::load(filename, filter)?)
+            };
+            state.textures.insert(key, texture.clone());
+            let textures = &mut state.scene.textures;
+            let index = match textures.iter().position(|t| Arc::ptr_eq(t, &texture)) {
+                Some(i) => i,
+                None => {
+                    textures.push(texture);
+                    textures.len() - 1
+                }
+            };
+            state.style.texture = Some(index);
+            Ok(())
+        },
+
+        &Command::Stencil(op) => {
+            state.style.stencil = op;
+            Ok(())
+        },
+
+        &Command::Antialias(on) => {
+            state.style.antialias = on;
+            Ok(())
+        },
+
+        &Command::LineWidth(width) => {
+            state.style.line.width = width;
+            Ok(())
+        },
+
+        &Command::Dash(pattern) => {
+            state.style.line.dash_pattern = pattern;
+            Ok(())
+        },
+
+        &Command::Viewport(rect) => {
+            // Map the whole image onto the rectangle, and draw only in it
+            state.viewport = rect.map(|r| {
+                let sx = r.width as f64 / state.config.width as f64;
+                let sy = r.height as f64 / state.config.height as f64;
+                Transform::new().scale(sx, sy, 1.0).translate(r.x as f64, r.y as f64, 0.0).matrix()
+            });
+            state.style.scissor = rect;
+            Ok(())
+        },
+
+        &Command::Window(window) => {
+            // Stretch the window over the whole image; a viewport then puts
+            // it wherever in the image it goes
+            state.window = window.map(|[x0, y0, x1, y1]| {
+                let sx = state.config.width as f64 / (x1 - x0);
+                let sy = state.config.height as f64 / (y1 - y0);
+                Transform::new().translate(-x0, -y0, 0.0).scale(sx, sy, 1.0).matrix()
+            });
+            Ok(())
+        },
+
+        &Command::Camera(camera) => {
+            state.camera = camera;
+            Ok(())
+        },
+
+        &Command::Projection(projection) => {
+            state.projection = projection.map(|p| p.matrix(state.config.width, state.config.height));
+            Ok(())
+        },
+
+        &Command::Scissor(rect) => {
+            state.style.scissor = rect;
             Ok(())
         },
 
+        &Command::Display => {
+            render_scene(screen, state, timings, stats);
+            screen.swap();
+            timings.time(Stage::Write, || ppm::display_image(screen.front()))
+        },
+
         &Command::Save(name) => {
-            ppm::save_png(&screen, name);
+            render_scene(screen, state, timings, stats);
+            timings.time(Stage::Write, || ppm::save(&screen.pixels, name, format))
+        },
+
+        &Command::SaveDepth(name) => {
+            timings.time(Stage::Rasterize, || scene::render_depth(&state.scene, screen, state.config.threads));
+            timings.time(Stage::Write, || screen.save_depth(name, format))
+        },
+
+        &Command::SaveMesh(name) => {
+            let polygons = &state.scene.polygons;
+            timings.time(Stage::Write, || if name.ends_with(".stl") { mesh::save_stl(polygons, name) } else { mesh::save_obj(polygons, name) })
+        },
+
+        &Command::Constants(name, constants) => {
+            state.symbols.set_constants(name, constants);
+            Ok(())
+        },
+
+        &Command::SetKnob(knob, val) => {
+            state.symbols.set_knob(knob, val);
+            Ok(())
+        },
+
+        &Command::SetKnobs(val) => {
+            state.symbols.set_all_knobs(val);
+            Ok(())
+        },
+
+        &Command::SaveKnobs(name) => {
+            state.symbols.save_knobs(name);
             Ok(())
         },
 
+        &Command::RestoreKnobs(name) => {
+            if state.symbols.restore_knobs(name) {
+                Ok(())
+            } else {
+                Err(Error::Script(format!("No knob list saved as '{}'", name)))
+            }
+        },
+
         &Command::Frames(..) | &Command::Basename(..) | &Command::Vary { .. } => {
             Ok(())
+        },
+
+        // Followed by `run_cmds`
+        &Command::Repeat(..) | &Command::If(..) | &Command::Else | &Command::End => {
+            Ok(())
         }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The commands of `script` that `run_cmds` runs, by their index, with
+    /// the values of the knobs `i` and `j` as each is run
+    fn trace(script: &str) -> Vec<(usize, Option<f64>, Option<f64>)> {
+        let cmds = parse::parse(script, &[]).unwrap();
+        let mut state = State::new(RenderConfig::new());
+        let mut ran = vec![];
+        run_cmds(&mut state, false, &cmds, |state, i, _| {
+            ran.push((i, state.symbols.knob("i"), state.symbols.knob("j")));
+            Ok(())
+        }).unwrap();
+        ran
+    }
+
+    const LINE: &str = "line 0 0 0 1 1 1";
+
+    #[test]
+    fn repeats_run_their_body_once_for_each_value_of_the_knob() {
+        let ran = trace(&format!("repeat i 3\n{}\nend\n{}", LINE, LINE));
+        assert_eq!(ran, vec![(1, Some(0.0), None), (1, Some(1.0), None), (1, Some(2.0), None), (3, Some(2.0), None)]);
+        assert_eq!(trace(&format!("repeat i 0\n{}\nend", LINE)), vec![]);
+    }
+
+    #[test]
+    fn nested_repeats_pick_up_where_they_left_off() {
+        let ran = trace(&format!("repeat i 2\nrepeat j 3\n{}\nend\n{}\nend", LINE, LINE));
+        let inner = |i: f64| (0..3).map(move |j| (2, Some(i), Some(j as f64)));
+        let expected: Vec<_> = inner(0.0).chain(Some((4, Some(0.0), Some(2.0))))
+            .chain(inner(1.0)).chain(Some((4, Some(1.0), Some(2.0))))
+            .collect();
+        assert_eq!(ran, expected);
+    }
+
+    #[test]
+    fn conditionals_take_exactly_one_branch() {
+        let script = format!("repeat i 3\nif i < 1\n{}\nelse\n{}\nend\nif i >= 2\n{}\nend\n{}\nend", LINE, LINE, LINE, LINE);
+        let ran: Vec<_> = trace(&script).iter().map(|&(n, i, _)| (n, i.unwrap())).collect();
+        assert_eq!(ran, vec![
+            (2, 0.0), (9, 0.0),
+            (4, 1.0), (9, 1.0),
+            (4, 2.0), (7, 2.0), (9, 2.0)
+        ]);
+    }
+
+    /// The animation of `script`
+    fn anim_data(script: &str) -> Result<Option<AnimData<'_>>> {
+        get_anim_data(&parse::parse(script, &[]).unwrap())
+    }
+
+    #[test]
+    fn varies_of_a_knob_can_only_meet_at_their_ends() {
+        let overlaps = ["vary k 0 5 0 1\nvary k 4 9 1 0", "vary k 0 9 0 1\nvary k 5 5 0 1", "vary k 5 5 0 1\nvary k 5 5 1 2"];
+        for script in overlaps.iter() {
+            match anim_data(&format!("frames 10\n{}", script)) {
+                Err(Error::Script(msg)) => assert!(msg.contains("overlaps another"), "{}", msg),
+                other => panic!("{:?} gave {:?}", script, other.map(|_| ()))
+            }
+        }
+        let meeting = ["vary k 0 5 0 1\nvary k 5 9 1 0", "vary k 0 5 0 1\nvary k 5 5 0 1", "vary k 5 5 0 1\nvary j 5 5 0 1"];
+        for script in meeting.iter() {
+            assert!(anim_data(&format!("frames 10\n{}", script)).unwrap().is_some(), "{}", script);
+        }
+    }
+
+    #[test]
+    fn knobs_are_interpolated_across_their_varies() {
+        let anim = anim_data("frames 11\nvary k 0 4 0 2\nvary k 4 10 3 -1\nvary j 7 7 0 5").unwrap().unwrap();
+        let knob = |frame: usize, name: &str| knobs_for_frame(frame, &anim.varies).iter().rev()
+            .find(|&&(knob, _)| knob == name).map(|&(_, val)| val);
+        assert_eq!(knob(0, "k"), Some(0.0));
+        assert_eq!(knob(1, "k"), Some(0.5));
+        // Where they meet, the later one's value
+        assert_eq!(knob(4, "k"), Some(3.0));
+        assert_eq!(knob(7, "k"), Some(1.0));
+        assert_eq!(knob(10, "k"), Some(-1.0));
+        // A single frame jumps to its end value
+        assert_eq!(knob(6, "j"), None);
+        assert_eq!(knob(7, "j"), Some(5.0));
+    }
+
+    #[test]
+    fn revolved_profiles_are_drawn_around_the_y_axis() {
+        let config = RenderConfig::new().resolution(100, 100).shading(render::Shading::Filled);
+        // A can 40 high and 60 across, standing in the middle of the image
+        let pixels = render_headless("move 50 20 0\nrevolve 0 40 30 40 30 0 0 0", &config).unwrap();
+        let background = config.background;
+        for &(x, y) in &[(50, 60), (22, 45), (78, 75)] {
+            assert!(pixels[y][x] != background, "{},{} wasn't drawn", x, y);
+        }
+        for &(x, y) in &[(18, 60), (82, 60), (50, 38), (50, 82)] {
+            assert_eq!(pixels[y][x], background, "{},{} was drawn", x, y);
+        }
+    }
+
+    #[test]
+    fn extruded_outlines_are_drawn() {
+        let config = RenderConfig::new().resolution(100, 100).shading(render::Shading::Filled);
+        // An L, 20 deep
+        let pixels = render_headless("extrude -20 20 20 80 20 80 40 40 40 40 80 20 80", &config).unwrap();
+        let background = config.background;
+        for &(x, y) in &[(30, 30), (30, 70), (70, 70)] {
+            assert!(pixels[y][x] != background, "{},{} wasn't drawn", x, y);
+        }
+        for &(x, y) in &[(60, 40), (10, 50), (90, 70)] {
+            assert_eq!(pixels[y][x], background, "{},{} was drawn", x, y);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::ops::Deref;
+
+use matrix::{ Matrix, Transformable };
+
+/// A list of edges, each a pair of points. The points are kept as the
+/// columns of a matrix, which can be read through it (`col`, `width`,
+/// `bounds`, and so on), but the list can only be changed a whole edge at a
+/// time, and only transformed by multiplying it on the left (in place with
+/// `Matrix::apply_to`).
+#[derive(Clone)]
+pub struct EdgeList {
+    points: Matrix
+}
+
+impl Default for EdgeList {
+    fn default() -> EdgeList {
+        EdgeList::new()
+    }
+}
+
+impl EdgeList {
+    pub fn new() -> EdgeList {
+        EdgeList { points: Matrix::empty() }
+    }
+
+    /// The number of edges
+    pub fn len(&self) -> usize {
+        self.points.width() / 2
+    }
+
+    /// Whether there are no edges
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn add_edge(&mut self, p0: [f64; 4], p1: [f64; 4]) {
+        self.points.push_col(p0);
+        self.points.push_col(p1);
+    }
+
+    /// Add all the edges of `other`.
+    pub fn extend(&mut self, other: &EdgeList) {
+        self.points.extend(&other.points);
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear_cols();
+    }
+
+    /// The edges transformed by `transform`
+    pub fn transformed(&self, transform: &Matrix) -> EdgeList {
+        EdgeList { points: transform * &self.points }
+    }
+
+    /// Put the edges transformed by `transform` in `out`, reusing its
+    /// storage.
+    pub fn transform_into(&self, transform: &Matrix, out: &mut EdgeList) {
+        transform.mul_into(&self.points, &mut out.points);
+    }
+
+    /// Replace each point `p` with `f(p)`.
+    pub fn map_points<F: FnMut([f64; 4]) -> [f64; 4]>(&mut self, mut f: F) {
+        for i in 0..self.points.width() {
+            let p = f(self.points.col(i));
+            self.points.set_col(i, p);
+        }
+    }
+}
+
+impl Transformable for EdgeList {
+    fn transform(&mut self, transform: &Matrix) {
+        transform.apply_to(&mut self.points);
+    }
+}
+
+impl Deref for EdgeList {
+    type Target = Matrix;
+
+    fn deref(&self) -> &Matrix {
+        &self.points
+    }
+}
+
+/// A list of triangles, each three points clockwise as seen from the front.
+/// Like an `EdgeList`, its points can be read as the columns of a matrix,
+/// but it's changed a triangle at a time. Each point also has texture
+/// coordinates (u, v), from (0, 0) at the top left of a texture to (1, 1) at
+/// its bottom right, which transformations leave alone.
+#[derive(Clone)]
+pub struct PolygonList {
+    points: Matrix,
+    uvs: Vec<[f64; 2]>
+}
+
+impl Default for PolygonList {
+    fn default() -> PolygonList {
+        PolygonList::new()
+    }
+}
+
+impl PolygonList {
+    pub fn new() -> PolygonList {
+        PolygonList { points: Matrix::empty(), uvs: vec![] }
+    }
+
+    /// The number of triangles
+    pub fn len(&self) -> usize {
+        self.points.width() / 3
+    }
+
+    /// Whether there are no triangles
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add a triangle with texture coordinates (0, 0) at each corner.
+    pub fn add_triangle(&mut self, p0: [f64; 4], p1: [f64; 4], p2: [f64; 4]) {
+        self.add_textured([p0, p1, p2], [[0.0; 2]; 3]);
+    }
+
+    /// Add a triangle with the corners `corners`, with the texture
+    /// coordinates `uvs` at them.
+    pub fn add_textured(&mut self, corners: [[f64; 4]; 3], uvs: [[f64; 2]; 3]) {
+        for (&corner, &uv) in corners.iter().zip(uvs.iter()) {
+            self.points.push_col(corner);
+            self.uvs.push(uv);
+        }
+    }
+
+    /// The corners of triangle `i`
+    pub fn triangle(&self, i: usize) -> [[f64; 4]; 3] {
+        [self.points.col(3 * i), self.points.col(3 * i + 1), self.points.col(3 * i + 2)]
+    }
+
+    /// The texture coordinates of the corners of triangle `i`
+    pub fn uvs(&self, i: usize) -> [[f64; 2]; 3] {
+        [self.uvs[3 * i], self.uvs[3 * i + 1], self.uvs[3 * i + 2]]
+    }
+
+    /// Replace triangle `i` with the one with corners `corners`, keeping its
+    /// texture coordinates.
+    pub fn set_triangle(&mut self, i: usize, corners: [[f64; 4]; 3]) {
+        for (j, &corner) in corners.iter().enumerate() {
+            self.points.set_col(3 * i + j, corner);
+        }
+    }
+
+    /// Replace triangle `to` with triangle `from`, texture coordinates and
+    /// all.
+    pub fn copy_triangle(&mut self, from: usize, to: usize) {
+        let corners = self.triangle(from);
+        self.set_triangle(to, corners);
+        for j in 0..3 {
+            self.uvs[3 * to + j] = self.uvs[3 * from + j];
+        }
+    }
+
+    /// Keep only the first `len` triangles.
+    pub fn truncate(&mut self, len: usize) {
+        self.points.truncate(3 * len);
+        self.uvs.truncate(3 * len);
+    }
+
+    /// Add all the triangles of `other`.
+    pub fn extend(&mut self, other: &PolygonList) {
+        self.points.extend(&other.points);
+        self.uvs.extend_from_slice(&other.uvs);
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear_cols();
+        self.uvs.clear();
+    }
+
+    /// Turn each triangle around, so its back is its front.
+    pub fn flip_winding(&mut self) {
+        for i in 0..self.len() {
+            let [a, b, c] = self.triangle(i);
+            self.set_triangle(i, [a, c, b]);
+            self.uvs.swap(3 * i + 1, 3 * i + 2);
+        }
+    }
+
+    /// The triangles transformed by `transform`
+    pub fn transformed(&self, transform: &Matrix) -> PolygonList {
+        PolygonList { points: transform * &self.points, uvs: self.uvs.clone() }
+    }
+
+    /// Put the triangles transformed by `transform` in `out`, reusing its
+    /// storage.
+    pub fn transform_into(&self, transform: &Matrix, out: &mut PolygonList) {
+        transform.mul_into(&self.points, &mut out.points);
+        out.uvs.clear();
+        out.uvs.extend_from_slice(&self.uvs);
+    }
+
+    /// Replace each point `p` with `f(p)`.
+    pub fn map_points<F: FnMut([f64; 4]) -> [f64; 4]>(&mut self, mut f: F) {
+        for i in 0..self.points.width() {
+            let p = f(self.points.col(i));
+            self.points.set_col(i, p);
+        }
+    }
+}
+
+impl Transformable for PolygonList {
+    fn transform(&mut self, transform: &Matrix) {
+        transform.apply_to(&mut self.points);
+    }
+}
+
+impl Deref for PolygonList {
+    type Target = Matrix;
+
+    fn deref(&self) -> &Matrix {
+        &self.points
+    }
+}
@@ -0,0 +1,554 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{ self, BufWriter, Write };
+
+use render::Color;
+use error::{ Error, Result };
+
+/// Write `image` as a GIF. Colors are reduced to a 6x6x6 color cube, which
+/// represents black, white, and the primary colors exactly.
+pub fn write<W: Write>(out: &mut W, image: &[Vec<Color>]) -> io::Result<()> {
+    let height = image.len();
+    let width = if height > 0 { image[0].len() } else { 0 };
+    let palette: Vec<_> = (0..216).map(|i| Color::rgb(cube_level(i / 36), cube_level(i / 6 % 6), cube_level(i % 6))).collect();
+    write_header(out, width, height, &palette)?;
+    let mut indices = Vec::with_capacity(width * height);
+    for row in image {
+        for px in row {
+            indices.push(cube_index(*px));
+        }
+    }
+    write_image(out, (0, 0, width, height), &indices)?;
+    out.write_all(b";")
+}
+
+/// The side of the square tiles frames are compared in, to find the part of
+/// each that changed since the last
+const TILE: usize = 16;
+
+/// The frames of an animation, to be written as a single animated GIF which
+/// loops forever. All the frames share one palette of up to 256 colors,
+/// chosen by median cut from the colors of every frame (or all of them, if
+/// there are no more than 256).
+pub struct Animation {
+    /// Each frame, and how long it's shown for in hundredths of a second.
+    /// Frames not given yet are `None`.
+    frames: Vec<Option<(Vec<Vec<Color>>, u16)>>
+}
+
+impl Default for Animation {
+    fn default() -> Animation {
+        Animation::new()
+    }
+}
+
+impl Animation {
+    pub fn new() -> Animation {
+        Animation { frames: vec![] }
+    }
+
+    /// Make `image` frame `i`, shown for `delay` hundredths of a second, so
+    /// that frames can be given out of order (as they're rendered on several
+    /// threads).
+    pub fn set_frame(&mut self, i: usize, image: Vec<Vec<Color>>, delay: u16) {
+        if self.frames.len() <= i {
+            self.frames.resize(i + 1, None);
+        }
+        self.frames[i] = Some((image, delay));
+    }
+
+    /// Write the animation as a GIF, failing if a frame is missing. Frames
+    /// smaller than the largest are drawn at its top left. Each frame after
+    /// the first is compared with the one before in tiles, and only the
+    /// rectangle around the tiles that changed is written, over what was
+    /// there (the frames aren't disposed of).
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut frames = vec![];
+        for (i, frame) in self.frames.iter().enumerate() {
+            match *frame {
+                Some((ref image, delay)) => frames.push((image, delay)),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("frame {} of the animation is missing", i)))
+            }
+        }
+        let size = |image: &Vec<Vec<Color>>| (if !image.is_empty() { image[0].len() } else { 0 }, image.len());
+        let width = frames.iter().map(|&(image, _)| size(image).0).max().unwrap_or(0);
+        let height = frames.iter().map(|&(image, _)| size(image).1).max().unwrap_or(0);
+        let palette = median_cut(&frames.iter().map(|&(image, _)| image).collect::<Vec<_>>(), 256);
+        write_header(out, width, height, &palette)?;
+        // Application extension to loop forever
+        out.write_all(&[0x21, 0xff, 11])?;
+        out.write_all(b"NETSCAPE2.0")?;
+        out.write_all(&[3, 1, 0, 0, 0])?;
+        let mut nearest = HashMap::new();
+        let mut prev: Option<((usize, usize), Vec<u8>)> = None;
+        for (image, delay) in frames {
+            // Graphic control extension giving the delay, and that the frame
+            // is left in place for the next to be drawn over
+            out.write_all(&[0x21, 0xf9, 4, 1 << 2])?;
+            let mut control = vec![];
+            push_u16(&mut control, delay);
+            out.write_all(&control)?;
+            out.write_all(&[0, 0])?;
+            let (w, h) = size(image);
+            let mut indices = Vec::with_capacity(w * h);
+            for px in image.iter().flat_map(|row| row.iter()) {
+                let key = (px.r, px.g, px.b);
+                let index = *nearest.entry(key).or_insert_with(|| nearest_index(&palette, *px));
+                indices.push(index);
+            }
+            let rect = match prev {
+                Some((prev_size, ref prev_indices)) if prev_size == (w, h) => {
+                    // A frame like the last still needs an image to show
+                    // for its delay, so it gets a pixel of it
+                    changed_rect(prev_indices, &indices, w, h).unwrap_or((0, 0, 1, 1))
+                },
+                _ => (0, 0, w, h)
+            };
+            let (x, y, rect_w, rect_h) = rect;
+            let part: Vec<u8> = (y..y + rect_h).flat_map(|row| indices[row * w + x..row * w + x + rect_w].iter().cloned()).collect();
+            write_image(out, rect, &part)?;
+            prev = Some(((w, h), indices));
+        }
+        out.write_all(b";")
+    }
+
+    /// Save the animation to `path` as a GIF.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let mut file = BufWriter::new(File::create(path).map_err(|e| Error::io(path, e))?);
+        self.write(&mut file).and_then(|_| file.flush()).map_err(|e| Error::io(path, e))
+    }
+}
+
+/// Write the header of a GIF `width` by `height`, with `palette` (at most
+/// 256 colors) as its global color table.
+fn write_header<W: Write>(out: &mut W, width: usize, height: usize, palette: &[Color]) -> io::Result<()> {
+    let mut header = vec![];
+    header.extend_from_slice(b"GIF89a");
+    push_u16(&mut header, width as u16);
+    push_u16(&mut header, height as u16);
+    header.extend_from_slice(&[0xf7, 0, 0]); // global color table of 256 entries
+    for i in 0..256 {
+        let c = palette.get(i).cloned().unwrap_or(Color::black());
+        header.extend_from_slice(&[c.r, c.g, c.b]);
+    }
+    out.write_all(&header)
+}
+
+/// Compare `prev` and `next`, the color indices of two frames `width` by
+/// `height`, in tiles of `TILE` pixels, and give the rectangle (left, top,
+/// width, height) around the tiles that differ, if any do.
+fn changed_rect(prev: &[u8], next: &[u8], width: usize, height: usize) -> Option<(usize, usize, usize, usize)> {
+    let mut changed: Option<(usize, usize, usize, usize)> = None;
+    for ty in 0..height.div_ceil(TILE) {
+        for tx in 0..width.div_ceil(TILE) {
+            let (x0, y0) = (tx * TILE, ty * TILE);
+            let (x1, y1) = (cmp::min(x0 + TILE, width), cmp::min(y0 + TILE, height));
+            let differs = (y0..y1).any(|y| prev[y * width + x0..y * width + x1] != next[y * width + x0..y * width + x1]);
+            if differs {
+                changed = Some(match changed {
+                    Some((l, t, r, b)) => (cmp::min(l, x0), cmp::min(t, y0), cmp::max(r, x1), cmp::max(b, y1)),
+                    None => (x0, y0, x1, y1)
+                });
+            }
+        }
+    }
+    changed.map(|(l, t, r, b)| (l, t, r - l, b - t))
+}
+
+/// Write an image at `rect` (its left, top, width, and height) in the GIF,
+/// made of `indices` into the global color table.
+fn write_image<W: Write>(out: &mut W, rect: (usize, usize, usize, usize), indices: &[u8]) -> io::Result<()> {
+    let (left, top, width, height) = rect;
+    // Image descriptor, without a local color table
+    let mut descriptor = vec![b','];
+    push_u16(&mut descriptor, left as u16);
+    push_u16(&mut descriptor, top as u16);
+    push_u16(&mut descriptor, width as u16);
+    push_u16(&mut descriptor, height as u16);
+    descriptor.push(0);
+    out.write_all(&descriptor)?;
+    out.write_all(&[8])?; // LZW minimum code size
+    let data = lzw_encode(indices, 8);
+    for block in data.chunks(255) {
+        out.write_all(&[block.len() as u8])?;
+        out.write_all(block)?;
+    }
+    out.write_all(&[0])
+}
+
+/// Choose a palette of at most `size` colors for `images` by median cut:
+/// starting with a box holding every color used, split the box with the
+/// widest range of a channel at the median of that channel (counting each
+/// color as often as it's used) until there are `size` boxes, and take the
+/// average color of each.
+fn median_cut(images: &[&Vec<Vec<Color>>], size: usize) -> Vec<Color> {
+    let mut counts: HashMap<(u8, u8, u8), usize> = HashMap::new();
+    for px in images.iter().flat_map(|image| image.iter()).flat_map(|row| row.iter()) {
+        *counts.entry((px.r, px.g, px.b)).or_insert(0) += 1;
+    }
+    let mut colors: Vec<([u8; 3], usize)> = counts.into_iter().map(|((r, g, b), n)| ([r, g, b], n)).collect();
+    colors.sort();
+    if colors.len() <= size {
+        return colors.iter().map(|&(c, _)| Color::rgb(c[0], c[1], c[2])).collect();
+    }
+    let mut boxes = vec![colors];
+    while boxes.len() < size {
+        // The box with the widest channel, and that channel
+        let widest = boxes.iter().enumerate().filter(|&(_, b)| b.len() > 1).map(|(i, b)| {
+            let (channel, range) = (0..3).map(|ch| {
+                let lo = b.iter().map(|&(c, _)| c[ch]).min().unwrap_or(0);
+                let hi = b.iter().map(|&(c, _)| c[ch]).max().unwrap_or(0);
+                (ch, hi - lo)
+            }).max_by_key(|&(_, range)| range).unwrap();
+            (i, channel, range)
+        }).max_by_key(|&(_, _, range)| range);
+        let (i, channel) = match widest {
+            Some((i, channel, _)) => (i, channel),
+            None => break
+        };
+        let mut b = boxes.swap_remove(i);
+        b.sort_by_key(|&(c, _)| c[channel]);
+        // Split where half the pixels are on each side, keeping both halves
+        // nonempty
+        let total: usize = b.iter().map(|&(_, n)| n).sum();
+        let mut seen = 0;
+        let mut split = 1;
+        for (j, &(_, n)) in b.iter().enumerate() {
+            seen += n;
+            if 2 * seen >= total {
+                split = cmp::max(1, cmp::min(j + 1, b.len() - 1));
+                break;
+            }
+        }
+        let upper = b.split_off(split);
+        boxes.push(b);
+        boxes.push(upper);
+    }
+    boxes.iter().map(|b| {
+        let total: usize = b.iter().map(|&(_, n)| n).sum();
+        let mean = |ch: usize| b.iter().map(|&(c, n)| c[ch] as f64 * n as f64).sum::<f64>() / total as f64;
+        Color::clamped(mean(0), mean(1), mean(2))
+    }).collect()
+}
+
+/// The index of the color of `palette` nearest to `c`.
+fn nearest_index(palette: &[Color], c: Color) -> u8 {
+    let distance = |p: &Color| {
+        let (dr, dg, db) = (p.r as i32 - c.r as i32, p.g as i32 - c.g as i32, p.b as i32 - c.b as i32);
+        dr * dr + dg * dg + db * db
+    };
+    palette.iter().enumerate().min_by_key(|&(_, p)| distance(p)).map_or(0, |(i, _)| i as u8)
+}
+
+fn cube_level(i: usize) -> u8 {
+    (i * 51) as u8
+}
+
+fn cube_index(c: Color) -> u8 {
+    let level = |x: u8| (x as usize + 25) / 51;
+    (level(c.r) * 36 + level(c.g) * 6 + level(c.b)) as u8
+}
+
+/// Compress `indices` with GIF's variant of LZW.
+fn lzw_encode(indices: &[u8], min_code_size: u32) -> Vec<u8> {
+    let clear = 1u32 << min_code_size;
+    let end = clear + 1;
+    let mut out = BitWriter::new();
+    let mut dict: HashMap<(u32, u8), u32> = HashMap::new();
+    let mut code_size = min_code_size + 1;
+    let mut next_code = end + 1;
+
+    out.write(clear, code_size);
+    let mut prefix: Option<u32> = None;
+    for &k in indices {
+        prefix = match prefix {
+            None => Some(k as u32),
+            Some(p) => match dict.get(&(p, k)) {
+                Some(&code) => Some(code),
+                None => {
+                    out.write(p, code_size);
+                    if next_code < 4096 {
+                        dict.insert((p, k), next_code);
+                        if next_code == 1 << code_size && code_size < 12 {
+                            code_size += 1;
+                        }
+                        next_code += 1;
+                    } else {
+                        // The table is full; start over
+                        out.write(clear, code_size);
+                        dict.clear();
+                        code_size = min_code_size + 1;
+                        next_code = end + 1;
+                    }
+                    Some(k as u32)
+                }
+            }
+        };
+    }
+    if let Some(p) = prefix {
+        out.write(p, code_size);
+        if next_code == 1 << code_size && code_size < 12 {
+            code_size += 1;
+        }
+    }
+    out.write(end, code_size);
+    out.finish()
+}
+
+/// Packs codes into bytes, least significant bit first.
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u32,
+    bits: u32
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter { bytes: vec![], acc: 0, bits: 0 }
+    }
+
+    fn write(&mut self, code: u32, size: u32) {
+        self.acc |= code << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+        self.bytes
+    }
+}
+
+/// Append `x` to `v` in little-endian order.
+fn push_u16(v: &mut Vec<u8>, x: u16) {
+    v.extend_from_slice(&[x as u8, (x >> 8) as u8]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use random::Rng;
+
+    /// The codes of `data`, packed least significant bit first
+    struct BitReader<'a> {
+        data: &'a [u8],
+        pos: usize
+    }
+
+    impl<'a> BitReader<'a> {
+        fn read(&mut self, size: u32) -> u32 {
+            let mut code = 0;
+            for i in 0..size {
+                let bit = self.data[self.pos / 8] >> (self.pos % 8) & 1;
+                code |= (bit as u32) << i;
+                self.pos += 1;
+            }
+            code
+        }
+    }
+
+    /// Decompress `data` as `lzw_encode` compresses it.
+    fn lzw_decode(data: &[u8], min_code_size: u32) -> Vec<u8> {
+        let (clear, end) = (1u32 << min_code_size, (1u32 << min_code_size) + 1);
+        let initial: Vec<Vec<u8>> = (0..clear + 2).map(|i| vec![i as u8]).collect();
+        let mut bits = BitReader { data, pos: 0 };
+        let mut table = initial.clone();
+        let mut code_size = min_code_size + 1;
+        let mut prev: Option<Vec<u8>> = None;
+        let mut out = vec![];
+        loop {
+            let code = bits.read(code_size);
+            if code == clear {
+                table = initial.clone();
+                code_size = min_code_size + 1;
+                prev = None;
+                continue;
+            }
+            if code == end {
+                return out;
+            }
+            let entry = match table.get(code as usize) {
+                Some(entry) => entry.clone(),
+                None => {
+                    // The code being defined by this very step
+                    assert_eq!(code as usize, table.len());
+                    let mut entry = prev.clone().unwrap();
+                    entry.push(entry[0]);
+                    entry
+                }
+            };
+            out.extend_from_slice(&entry);
+            if let Some(mut p) = prev {
+                if table.len() < 4096 {
+                    p.push(entry[0]);
+                    table.push(p);
+                }
+            }
+            if table.len() == 1 << code_size && code_size < 12 {
+                code_size += 1;
+            }
+            prev = Some(entry);
+        }
+    }
+
+    /// A decoded GIF: its palette, and each frame's delay and color indices,
+    /// and the rectangle each frame's image covers
+    struct Decoded {
+        width: usize,
+        height: usize,
+        palette: Vec<Color>,
+        frames: Vec<(u16, Vec<u8>)>,
+        rects: Vec<(usize, usize, usize, usize)>
+    }
+
+    fn u16_at(bytes: &[u8], i: usize) -> u16 {
+        bytes[i] as u16 | (bytes[i + 1] as u16) << 8
+    }
+
+    /// Decode `gif`, as written by `write` or `Animation::write`.
+    fn decode(gif: &[u8]) -> Decoded {
+        assert_eq!(&gif[..6], b"GIF89a");
+        let (width, height) = (u16_at(gif, 6) as usize, u16_at(gif, 8) as usize);
+        assert_eq!(gif[10], 0xf7);
+        let palette = gif[13..13 + 768].chunks(3).map(|c| Color::rgb(c[0], c[1], c[2])).collect();
+        let mut pos = 13 + 768;
+        let mut frames = vec![];
+        let mut rects = vec![];
+        let mut canvas = vec![0; width * height];
+        let mut delay = 0;
+        loop {
+            match gif[pos] {
+                b';' => break,
+                0x21 => {
+                    if gif[pos + 1] == 0xf9 {
+                        // Every frame is left for the next to be drawn over
+                        assert_eq!(gif[pos + 3] >> 2 & 7, 1);
+                        delay = u16_at(gif, pos + 4);
+                    }
+                    // Skip the extension's sub-blocks
+                    pos += 2;
+                    while gif[pos] != 0 {
+                        pos += 1 + gif[pos] as usize;
+                    }
+                    pos += 1;
+                },
+                b',' => {
+                    let (x, y) = (u16_at(gif, pos + 1) as usize, u16_at(gif, pos + 3) as usize);
+                    let (w, h) = (u16_at(gif, pos + 5) as usize, u16_at(gif, pos + 7) as usize);
+                    let min_code_size = gif[pos + 10] as u32;
+                    pos += 11;
+                    let mut data = vec![];
+                    while gif[pos] != 0 {
+                        data.extend_from_slice(&gif[pos + 1..pos + 1 + gif[pos] as usize]);
+                        pos += 1 + gif[pos] as usize;
+                    }
+                    pos += 1;
+                    let indices = lzw_decode(&data, min_code_size);
+                    assert_eq!(indices.len(), w * h);
+                    assert!(x + w <= width && y + h <= height);
+                    for row in 0..h {
+                        canvas[(y + row) * width + x..(y + row) * width + x + w].copy_from_slice(&indices[row * w..(row + 1) * w]);
+                    }
+                    frames.push((delay, canvas.clone()));
+                    rects.push((x, y, w, h));
+                },
+                byte => panic!("unexpected block {:#x} at {}", byte, pos)
+            }
+        }
+        assert_eq!(pos + 1, gif.len());
+        Decoded { width, height, palette, frames, rects }
+    }
+
+    fn pixels(decoded: &Decoded, frame: usize) -> Vec<Color> {
+        decoded.frames[frame].1.iter().map(|&i| decoded.palette[i as usize]).collect()
+    }
+
+    #[test]
+    fn lzw_round_trips_long_runs_and_noise() {
+        let mut rng = Rng::new(1, 2);
+        let noise: Vec<u8> = (0..20000).map(|_| rng.range(0.0, 256.0) as u8).collect();
+        // Enough distinct strings to fill the table and clear it
+        assert_eq!(lzw_decode(&lzw_encode(&noise, 8), 8), noise);
+        let runs: Vec<u8> = (0..20000).map(|i| (i / 1000) as u8).collect();
+        assert_eq!(lzw_decode(&lzw_encode(&runs, 8), 8), runs);
+        assert_eq!(lzw_decode(&lzw_encode(&[], 8), 8), vec![]);
+    }
+
+    #[test]
+    fn stills_keep_the_colors_of_the_cube_exactly() {
+        let colors = [Color::black(), Color::rgb(255, 255, 255), Color::rgb(255, 0, 0), Color::rgb(0, 255, 0), Color::rgb(0, 0, 255), Color::rgb(51, 102, 153)];
+        let image: Vec<Vec<Color>> = (0..4).map(|y| (0..5).map(|x| colors[(x + y) % colors.len()]).collect()).collect();
+        let mut gif = vec![];
+        write(&mut gif, &image).unwrap();
+        let decoded = decode(&gif);
+        assert_eq!((decoded.width, decoded.height), (5, 4));
+        assert_eq!(pixels(&decoded, 0), image.concat());
+    }
+
+    #[test]
+    fn animations_with_few_colors_keep_them_exactly() {
+        let frame = |shade: u8| vec![vec![Color::rgb(shade, 10, 200); 3], vec![Color::rgb(7, shade, 9); 3]];
+        let mut animation = Animation::new();
+        // Given out of order, as threads finish them
+        animation.set_frame(1, frame(100), 8);
+        animation.set_frame(0, frame(50), 4);
+        let mut gif = vec![];
+        animation.write(&mut gif).unwrap();
+        let decoded = decode(&gif);
+        assert_eq!(decoded.frames.len(), 2);
+        assert_eq!((decoded.frames[0].0, decoded.frames[1].0), (4, 8));
+        assert_eq!(pixels(&decoded, 0), frame(50).concat());
+        assert_eq!(pixels(&decoded, 1), frame(100).concat());
+    }
+
+    #[test]
+    fn animations_only_write_the_tiles_that_changed() {
+        let first: Vec<Vec<Color>> = (0..40).map(|y| (0..40).map(|x| Color::rgb((x + y) as u8 % 4 * 60, 0, 0)).collect()).collect();
+        let mut second = first.clone();
+        second[18][18] = Color::rgb(255, 255, 255);
+        second[35][20] = Color::rgb(0, 0, 255);
+        let mut animation = Animation::new();
+        animation.set_frame(0, first.clone(), 4);
+        animation.set_frame(1, second.clone(), 4);
+        animation.set_frame(2, second.clone(), 4);
+        let mut gif = vec![];
+        animation.write(&mut gif).unwrap();
+        let decoded = decode(&gif);
+        // The tiles from 16 to 32 across and 16 to 40 down, then a single
+        // pixel for the frame that didn't change
+        assert_eq!(decoded.rects, vec![(0, 0, 40, 40), (16, 16, 16, 24), (0, 0, 1, 1)]);
+        assert_eq!(pixels(&decoded, 0), first.concat());
+        assert_eq!(pixels(&decoded, 1), second.concat());
+        assert_eq!(pixels(&decoded, 2), second.concat());
+    }
+
+    #[test]
+    fn animations_with_many_colors_get_a_palette_near_them() {
+        // 4096 colors, a gradient in each channel
+        let image: Vec<Vec<Color>> = (0..64).map(|y| (0..64).map(|x| Color::rgb(x as u8 * 4, y as u8 * 4, (x + y) as u8 * 2)).collect()).collect();
+        let mut animation = Animation::new();
+        animation.set_frame(0, image.clone(), 4);
+        let mut gif = vec![];
+        animation.write(&mut gif).unwrap();
+        let decoded = decode(&gif);
+        let worst = pixels(&decoded, 0).iter().zip(image.concat().iter()).map(|(a, b)| {
+            let d = |x: u8, y: u8| (x as i32 - y as i32).abs();
+            cmp::max(d(a.r, b.r), cmp::max(d(a.g, b.g), d(a.b, b.b)))
+        }).max().unwrap();
+        assert!(worst <= 24, "a pixel is off by {}", worst);
+    }
+
+    #[test]
+    fn animations_missing_a_frame_fail() {
+        let mut animation = Animation::new();
+        animation.set_frame(1, vec![vec![Color::black()]], 4);
+        assert!(animation.write(&mut vec![]).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use std::fs;
+use std::path::Path;
+
+use config::RenderConfig;
+use error::{ Error, Result };
+use exec;
+use ppm::{ self, Format };
+use render::{ Color, Shading };
+
+/// The seed every golden image is rendered with
+pub const SEED: u64 = 0;
+
+/// The settings golden images are rendered with unless others are given:
+/// small, so references stay small, and Phong shaded, so they cover lighting.
+/// The references in `tests/golden` were all made with these.
+pub fn config() -> RenderConfig {
+    RenderConfig::new().resolution(100, 100).shading(Shading::Phong)
+}
+
+/// How far an image may stray from its reference and still match it
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    /// The most any channel of a pixel may differ by without the pixel
+    /// counting as different
+    pub channel: u8,
+    /// How many pixels may be different
+    pub pixels: usize
+}
+
+impl Tolerance {
+    /// Match only identical images.
+    pub fn exact() -> Tolerance {
+        Tolerance { channel: 0, pixels: 0 }
+    }
+}
+
+/// How an image differs from its reference
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    /// Whether the images have the same width and height. If not, the rest
+    /// is meaningless.
+    pub same_size: bool,
+    /// The number of pixels differing by more than the tolerance
+    pub differing: usize,
+    /// The most any channel of any pixel differs by
+    pub max_channel_diff: u8
+}
+
+impl Comparison {
+    pub fn passes(&self, tolerance: Tolerance) -> bool {
+        self.same_size && self.differing <= tolerance.pixels
+    }
+}
+
+/// Render the still image drawn by `script` deterministically: with the seed
+/// `SEED` and on a single thread, but otherwise as `config` says. Saving,
+/// displaying, and animation are ignored, as in `exec::render_headless`.
+pub fn render(script: &str, config: &RenderConfig) -> Result<Vec<Vec<Color>>> {
+    exec::render_headless(script, &config.clone().threads(1).seed(SEED))
+}
+
+/// Compare `image` to `reference`, counting pixels as different when a
+/// channel differs by more than `channel`.
+pub fn compare(image: &[Vec<Color>], reference: &[Vec<Color>], channel: u8) -> Comparison {
+    let same_size = image.len() == reference.len() &&
+        image.iter().zip(reference.iter()).all(|(a, b)| a.len() == b.len());
+    let mut comparison = Comparison { same_size, differing: 0, max_channel_diff: 0 };
+    if !same_size {
+        return comparison;
+    }
+    for (row, ref_row) in image.iter().zip(reference.iter()) {
+        for (px, ref_px) in row.iter().zip(ref_row.iter()) {
+            let diff = [(px.r, ref_px.r), (px.g, ref_px.g), (px.b, ref_px.b)].iter()
+                .map(|&(a, b)| a.abs_diff(b))
+                .max().unwrap();
+            if diff > channel {
+                comparison.differing += 1;
+            }
+            if diff > comparison.max_channel_diff {
+                comparison.max_channel_diff = diff;
+            }
+        }
+    }
+    comparison
+}
+
+/// Render `script` (see `render`) and check it against the PPM at
+/// `reference`, failing with a `Mismatch` error if it doesn't pass.
+pub fn check(script: &str, reference: &str, config: &RenderConfig, tolerance: Tolerance) -> Result<Comparison> {
+    let image = render(script, config)?;
+    let expected = ppm::load_ppm(reference)?;
+    verify(&image, &expected, reference, tolerance)
+}
+
+/// Compare `image` to `expected`, the reference image called `reference`,
+/// failing with a `Mismatch` error if it doesn't pass.
+pub fn verify(image: &[Vec<Color>], expected: &[Vec<Color>], reference: &str, tolerance: Tolerance) -> Result<Comparison> {
+    let comparison = compare(image, expected, tolerance.channel);
+    if !comparison.same_size {
+        return Err(Error::Mismatch(format!("Rendered {}x{}, but {} is {}x{}",
+            width(image), image.len(), reference, width(expected), expected.len())));
+    }
+    if !comparison.passes(tolerance) {
+        return Err(Error::Mismatch(format!("{} pixels differ from {} (by up to {}); at most {} may",
+            comparison.differing, reference, comparison.max_channel_diff, tolerance.pixels)));
+    }
+    Ok(comparison)
+}
+
+/// Render `script` (see `render`) and save it as the new reference image
+/// `reference`, making its directory if need be.
+pub fn bless(script: &str, reference: &str, config: &RenderConfig) -> Result<()> {
+    let image = render(script, config)?;
+    if let Some(dir) = Path::new(reference).parent() {
+        fs::create_dir_all(dir).map_err(|e| Error::io(&dir.to_string_lossy(), e))?;
+    }
+    ppm::save_as(&image, reference, Format::RawPpm)
+}
+
+fn width(image: &[Vec<Color>]) -> usize {
+    if !image.is_empty() { image[0].len() } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cli;
+
+    /// Check the script `tests/scenes/$name.mdl` against its reference,
+    /// `tests/golden/$name.ppm`, as `./main --golden tests/golden
+    /// tests/scenes` would. Both are found relative to this file, so the
+    /// tests pass wherever they are run from.
+    macro_rules! check_scene {
+        ($name:expr) => {
+            check_scene($name,
+                include_str!(concat!("../tests/scenes/", $name, ".mdl")),
+                include_bytes!(concat!("../tests/golden/", $name, ".ppm")))
+        }
+    }
+
+    fn check_scene(name: &str, script: &str, reference: &[u8]) {
+        let image = render(script, &config()).unwrap();
+        let expected = ppm::parse_ppm(reference).unwrap();
+        if let Err(e) = verify(&image, &expected, name, Tolerance::exact()) {
+            panic!("{}: {}", name, e);
+        }
+    }
+
+    #[test]
+    fn shapes_match_reference() {
+        check_scene!("shapes");
+    }
+
+    #[test]
+    fn lines_match_reference() {
+        check_scene!("lines");
+    }
+
+    #[test]
+    fn lights_match_reference() {
+        check_scene!("lights");
+    }
+
+    #[test]
+    fn references_are_kept_by_path_under_the_scripts_directory() {
+        let scripts = vec![String::from("tests/scenes/shapes.mdl"), String::from("tests/scenes/lines.mdl")];
+        assert_eq!(cli::reference_path("golden", &scripts[0], &scripts), "golden/shapes.ppm");
+        let one = vec![String::from("./a/scene.mdl")];
+        assert_eq!(cli::reference_path("golden", &one[0], &one), "golden/scene.ppm");
+        let apart = vec![String::from("a/b/scene.mdl"), String::from("a/c/scene.mdl"), String::from("../scene")];
+        assert_eq!(cli::reference_path("golden", &apart[0], &apart), "golden/a/b/scene.ppm");
+        assert_eq!(cli::reference_path("golden", &apart[1], &apart), "golden/a/c/scene.ppm");
+        assert_eq!(cli::reference_path("golden", &apart[2], &apart), "golden/scene.ppm");
+    }
+
+    #[test]
+    fn renders_ignore_thread_count() {
+        let script = include_str!("../tests/scenes/shapes.mdl");
+        let config = config().seed(SEED);
+        let threaded = exec::render_headless(script, &config.clone().threads(4)).unwrap();
+        assert!(threaded == exec::render_headless(script, &config.threads(1)).unwrap());
+    }
+}
@@ -0,0 +1,186 @@
+
+use std::sync::Arc;
+
+use matrix::Matrix;
+use cstack::CStack;
+use drawable::Drawable;
+use geometry::{ EdgeList, PolygonList };
+use render::{ Color, Style };
+use lighting::Lighting;
+use texture::Texture;
+use camera::Camera;
+use config::RenderConfig;
+use scene::{ self, Scene };
+use screen::Screen;
+use stats::RenderStats;
+
+/// A part of a scene graph: a shape, or a group of other nodes, placed by
+/// `transform` relative to the node it's in. Moving a group moves
+/// everything in it, as pushing a coordinate system does for a script.
+///
+///     let arm = Node::group(vec![Node::shape(upper), Node::shape(lower).transform(&elbow)]);
+///     let robot = Node::group(vec![Node::shape(body), arm.transform(&shoulder)]);
+pub struct Node {
+    pub transform: Matrix,
+    pub content: Content
+}
+
+/// What a node holds
+pub enum Content {
+    /// A shape, drawn in `style`
+    Shape(Box<dyn Drawable + Send + Sync>, Style),
+    /// Other nodes, in the coordinate system of this one
+    Group(Vec<Node>)
+}
+
+impl Node {
+    /// A node of just `shape`, in the default style and where it is
+    pub fn shape<D: Drawable + Send + Sync + 'static>(shape: D) -> Node {
+        Node { transform: Matrix::identity(), content: Content::Shape(Box::new(shape), Style::new()) }
+    }
+
+    /// A node grouping `children`, where they are
+    pub fn group(children: Vec<Node>) -> Node {
+        Node { transform: Matrix::identity(), content: Content::Group(children) }
+    }
+
+    /// Apply `m` to the node, after the transformations already applied.
+    pub fn transform(mut self, m: &Matrix) -> Node {
+        self.transform = m * &self.transform;
+        self
+    }
+
+    /// Draw the node's shape in `style`. Groups have no style of their own.
+    pub fn style(mut self, style: Style) -> Node {
+        if let Content::Shape(_, ref mut shape_style) = self.content {
+            *shape_style = style;
+        }
+        self
+    }
+
+    /// Add `child` to the group, or make a group of the shape and `child`.
+    pub fn with_child(mut self, child: Node) -> Node {
+        self.content = match self.content {
+            Content::Group(mut children) => {
+                children.push(child);
+                Content::Group(children)
+            },
+            shape => Content::Group(vec![Node { transform: Matrix::identity(), content: shape }, child])
+        };
+        self
+    }
+}
+
+/// A tree of nodes to draw, with the light they're seen in: a retained
+/// alternative to pushing and popping coordinate systems by hand.
+pub struct SceneGraph {
+    pub root: Node,
+    pub background: Color,
+    pub lighting: Lighting,
+    /// The textures the nodes' styles are painted with, by index
+    pub textures: Vec<Arc<Texture>>
+}
+
+impl SceneGraph {
+    /// A scene of `root` over a black background, in the default light
+    pub fn new(root: Node) -> SceneGraph {
+        SceneGraph { root, background: Color::black(), lighting: Lighting::new(), textures: vec![] }
+    }
+
+    /// Draw the scene on `screen` as `camera` sees it, rendering as `config`
+    /// says, replacing whatever was there.
+    pub fn render(&self, camera: &Camera, screen: &mut Screen, config: &RenderConfig) -> RenderStats {
+        let mut scene = Scene::new(self.background);
+        scene.lighting = self.lighting.clone();
+        scene.textures = self.textures.clone();
+        let mut transforms = CStack::new();
+        let mut object = 0;
+        add_node(&self.root, &mut transforms, camera, (screen.width(), screen.height()), &mut scene, &mut object);
+        scene::render(&scene, screen, config)
+    }
+}
+
+/// Add the shapes of `node` and all the nodes in it to `scene`, as seen by
+/// `camera` on a screen of `size`, with the coordinate system of the node
+/// it's in on top of `transforms`. Each shape is numbered from `object`,
+/// for picking.
+fn add_node(node: &Node, transforms: &mut CStack, camera: &Camera, size: (usize, usize), scene: &mut Scene, object: &mut u32) {
+    transforms.push();
+    transforms.apply(&node.transform);
+    match node.content {
+        Content::Shape(ref shape, style) => {
+            let (mut edges, mut polys) = (EdgeList::new(), PolygonList::new());
+            shape.emit(&mut edges, &mut polys);
+            let top = transforms.top();
+            let (projected, clipped) = camera.project_polygons(&polys.transformed(top), size.0, size.1);
+            scene.polygons.extend(&projected);
+            scene.clipped += clipped;
+            scene.edges.extend(&camera.project_edges(&edges.transformed(top), size.0, size.1));
+            *object += 1;
+            let style = Style { object: *object, ..style };
+            let (polygons, edges) = (scene.polygons.len(), scene.edges.len());
+            scene.polygon_styles.resize(polygons, style);
+            scene.edge_styles.resize(edges, style);
+        },
+        Content::Group(ref children) => {
+            for child in children {
+                add_node(child, transforms, camera, size, scene, object);
+            }
+        }
+    }
+    transforms.pop();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use render::Shading;
+    use solid::Rect;
+
+    /// Width and height of the screens scenes are drawn on
+    const SIZE: usize = 50;
+
+    /// A square 10 across, centered where it's put
+    fn square() -> Node {
+        Node::shape(Rect { x: -5.0, y: -5.0, w: 10.0, h: 10.0 })
+    }
+
+    /// The ID of the object drawn at the middle of `p` (from the bottom
+    /// left) of a screen showing `graph` from 100 in front of the origin
+    fn ids_at(graph: &SceneGraph, points: &[[usize; 2]]) -> Vec<u32> {
+        let camera = Camera::new([0.0, 0.0, 100.0], [0.0, 0.0, 0.0]);
+        let mut screen = Screen::new(SIZE, SIZE, Color::black());
+        screen.track_ids();
+        graph.render(&camera, &mut screen, &RenderConfig::new().resolution(SIZE, SIZE).shading(Shading::Filled));
+        points.iter().map(|p| screen.ids[SIZE - 1 - p[1]][p[0]]).collect()
+    }
+
+    #[test]
+    fn groups_move_what_is_in_them() {
+        // Up 20 in a group moved right 20, so up and to the right of the
+        // middle, at 20 * focal length / 100 = 8.7 pixels each way
+        let graph = SceneGraph::new(Node::group(vec![
+            square().transform(&Matrix::translation(0.0, 20.0, 0.0))
+        ]).transform(&Matrix::translation(20.0, 0.0, 0.0)));
+        let mid = SIZE / 2;
+        assert_eq!(ids_at(&graph, &[[mid + 9, mid + 9], [mid, mid], [mid + 9, mid], [mid, mid + 9]]), vec![1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn each_shape_is_its_own_object() {
+        // What's added to a shape is in its coordinate system
+        let graph = SceneGraph::new(square().transform(&Matrix::translation(-20.0, 0.0, 0.0))
+            .with_child(square().transform(&Matrix::translation(40.0, 0.0, 0.0))));
+        let mid = SIZE / 2;
+        assert_eq!(ids_at(&graph, &[[mid - 9, mid], [mid + 9, mid], [mid, mid]]), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn nearer_shapes_hide_further_ones() {
+        let graph = SceneGraph::new(Node::group(vec![
+            square(),
+            square().transform(&Matrix::translation(0.0, 0.0, -20.0))
+        ]));
+        assert_eq!(ids_at(&graph, &[[SIZE / 2, SIZE / 2]]), vec![1]);
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use error::{ Error, Result };
+
+/// Read the script in the file `path`, with each `include FILE` line in it
+/// replaced by the script in `FILE`, and so on for the includes in that.
+/// `FILE` may be in double quotes, and is found relative to the directory of
+/// the script including it. A script including itself, directly or through
+/// others, is an error.
+pub fn read_script(path: &str) -> Result<String> {
+    let mut script = String::new();
+    expand(Path::new(path), &mut vec![], &mut script)?;
+    Ok(script)
+}
+
+/// Add the script in `path` to `script`, expanding its includes. `including`
+/// holds the (canonical) paths of the scripts which include it.
+fn expand(path: &Path, including: &mut Vec<PathBuf>, script: &mut String) -> Result<()> {
+    let name = path.to_string_lossy().into_owned();
+    let canonical = fs::canonicalize(path).map_err(|e| Error::io(&name, e))?;
+    if including.contains(&canonical) {
+        return Err(Error::Script(format!("'{}' includes itself", name)));
+    }
+    let text = fs::read_to_string(path).map_err(|e| Error::io(&name, e))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    including.push(canonical);
+    for (i, line) in text.lines().enumerate() {
+        match included_file(line) {
+            Some(Ok(file)) => expand(&dir.join(file), including, script)?,
+            Some(Err(msg)) => return Err(Error::Script(format!("{}:{}: {}", name, i + 1, msg))),
+            None => {
+                script.push_str(line);
+                script.push('\n');
+            }
+        }
+    }
+    including.pop();
+    Ok(())
+}
+
+/// The file `line` includes, if it's an include
+fn included_file(line: &str) -> Option<::std::result::Result<&str, String>> {
+    let line = line.trim();
+    if line != "include" && !line.starts_with("include ") && !line.starts_with("include\t") {
+        return None;
+    }
+    let file = line["include".len()..].trim();
+    Some(if file.is_empty() || file == "\"\"" {
+        Err(String::from("Expected a file to include, found end of line"))
+    } else if file.starts_with('"') {
+        if file.len() > 1 && file.ends_with('"') {
+            Ok(&file[1..file.len() - 1])
+        } else {
+            Err(format!("Expected a closing quote, found '{}'", file))
+        }
+    } else {
+        Ok(file)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    /// Make an empty directory for the test `test` to write scripts in.
+    fn scratch_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("cstack-include-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Write each (name, text) of `scripts` in `dir`, and read the first.
+    fn read_first(dir: &Path, scripts: &[(&str, &str)]) -> Result<String> {
+        for &(name, text) in scripts {
+            fs::write(dir.join(name), text).unwrap();
+        }
+        let result = read_script(&dir.join(scripts[0].0).to_string_lossy());
+        fs::remove_dir_all(dir).unwrap();
+        result
+    }
+
+    #[test]
+    fn includes_are_expanded_relative_to_the_including_script() {
+        let dir = scratch_dir("expand");
+        fs::create_dir(dir.join("lib")).unwrap();
+        let script = read_first(&dir, &[
+            ("main.mdl", "push\ninclude \"lib/shapes.mdl\"\npop\n"),
+            ("lib/shapes.mdl", "include light.mdl\nsphere 0 0 0 10\n"),
+            ("lib/light.mdl", "ambient 20 20 20\n")
+        ]);
+        assert_eq!(script.unwrap(), "push\nambient 20 20 20\nsphere 0 0 0 10\npop\n");
+    }
+
+    #[test]
+    fn including_a_script_twice_is_not_a_cycle() {
+        let dir = scratch_dir("twice");
+        let script = read_first(&dir, &[("main.mdl", "include a.mdl\ninclude a.mdl\n"), ("a.mdl", "push\n")]);
+        assert_eq!(script.unwrap(), "push\npush\n");
+    }
+
+    #[test]
+    fn a_script_including_itself_is_an_error() {
+        let dir = scratch_dir("self");
+        match read_first(&dir, &[("loop.mdl", "push\ninclude loop.mdl\n")]) {
+            Err(Error::Script(msg)) => assert!(msg.ends_with("loop.mdl' includes itself"), "{}", msg),
+            result => panic!("expected a cycle, found {:?}", result.map_err(|e| e.to_string()))
+        }
+    }
+
+    #[test]
+    fn scripts_including_each_other_are_an_error() {
+        let dir = scratch_dir("cycle");
+        match read_first(&dir, &[("a.mdl", "include b.mdl\n"), ("b.mdl", "include a.mdl\n")]) {
+            Err(Error::Script(msg)) => assert!(msg.ends_with("a.mdl' includes itself"), "{}", msg),
+            result => panic!("expected a cycle, found {:?}", result.map_err(|e| e.to_string()))
+        }
+    }
+}
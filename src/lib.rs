@@ -0,0 +1,168 @@
+//! Renders MDL scripts of shapes, transformations, and animations to
+//! images. The `cstack` program runs scripts from the command line; other
+//! programs can run them with `exec`, add commands of their own with
+//! `plugin`, or build scenes without a script with `graph`.
+
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "tracing")]
+extern crate tracing_subscriber;
+#[cfg(feature = "tracing")]
+extern crate tracing_flame;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+
+/// Leveled log messages (declared first so its macros are visible everywhere)
+#[macro_use]
+pub mod logging;
+
+/// Spans for profiling with `tracing`
+#[macro_use]
+pub mod trace;
+
+/// Matrix math
+pub mod matrix;
+
+/// Edge and polygon lists
+pub mod geometry;
+
+/// The coordinate system stack
+pub mod cstack;
+
+/// Building transformations step by step
+pub mod transform;
+
+/// Bounding boxes and spheres of points
+pub mod bounds;
+
+/// Intersecting rays with geometry
+pub mod ray;
+
+/// Preset views for technical illustrations
+pub mod projection;
+
+/// Seeing the scene in perspective
+pub mod camera;
+
+/// Add curves to an edge matrix
+pub mod curve;
+
+/// Add 3D solids to an edge matrix
+pub mod solid;
+
+/// Objects that can be drawn
+pub mod drawable;
+
+/// Load models made in other programs
+pub mod mesh;
+
+/// Drawing by steering a turtle
+pub mod turtle;
+
+/// Everything to be drawn in an image
+pub mod scene;
+
+/// Trees of shapes, for placing parts without a coordinate stack
+pub mod graph;
+
+/// Settings for rendering
+pub mod config;
+
+/// Debug visualizations of a scene
+pub mod debug_view;
+
+/// Rendering by casting rays through each pixel
+pub mod raytrace;
+
+/// Lights and materials, for flat, Gouraud, and Phong shading
+pub mod lighting;
+
+/// Shadow maps, for the shadows lights cast
+pub mod shadow;
+
+/// Images painted over polygons
+pub mod texture;
+
+/// The image being drawn and its depth buffer
+pub mod screen;
+
+/// Render edges to an in-memory representation of the pixels of the screen
+pub mod render;
+
+/// Draw lines straight onto a screen
+pub mod draw;
+
+/// Custom moving of vertices and coloring of pixels
+pub mod shader;
+
+/// Create image files
+pub mod ppm;
+
+/// Encode PNG files
+pub mod png;
+
+/// Encode BMP files
+pub mod bmp;
+
+/// Encode TGA files
+pub mod tga;
+
+/// Encode GIF files
+pub mod gif;
+
+/// Read scripts, with the scripts they include
+pub mod include;
+
+/// Parse MDL scripts into commands
+pub mod parse;
+
+/// Execute commands from a script
+pub mod exec;
+
+/// The constants and knobs a script defines
+pub mod symbols;
+
+/// How knobs move over a `vary`
+pub mod easing;
+
+/// Script commands added by library users
+pub mod plugin;
+
+/// Crate-wide constants
+pub mod consts;
+
+/// Save frames on a background thread
+pub mod writer;
+
+/// Record the frames saved, to resume interrupted animations
+pub mod checkpoint;
+
+/// Benchmarks of the pipeline
+pub mod bench;
+
+/// The crate-wide error type
+pub mod error;
+
+/// Parse command-line arguments
+pub mod cli;
+
+/// Time the stages of the pipeline
+pub mod timing;
+
+/// Count the work done rendering
+pub mod stats;
+
+/// Report the progress of long renders
+pub mod progress;
+
+/// Stop renders early
+pub mod cancel;
+
+/// Seedable pseudo-random numbers
+pub mod random;
+
+/// Regression tests against reference images
+pub mod golden;
+
+/// Finding which shape drew a pixel
+pub mod pick;
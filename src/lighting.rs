@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use geometry::PolygonList;
+use render::{ self, Color, Style };
+use symbols::Constants;
+use shadow::Shadows;
+
+/// How sharp the highlights of a material are when it isn't said
+const SHININESS: f64 = 8.0;
+
+/// The attenuation of a point light which doesn't fade with distance
+pub const NO_FALLOFF: [f64; 3] = [1.0, 0.0, 0.0];
+
+/// A source of light, with the color of its light (each channel from 0 to
+/// 255). Positions and directions are in screen coordinates, as polygons are
+/// when they're lit: x to the right and y up in pixels, and z towards the
+/// viewer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Light {
+    /// Light shining out from a point, fading with distance `d` to
+    /// `1 / (c + l * d + q * d * d)` of its color, where `attenuation` is
+    /// `[c, l, q]`
+    Point { position: [f64; 3], color: [f64; 3], attenuation: [f64; 3] },
+    /// Light from so far away that it all comes the same way, like the
+    /// sun's. `direction` points towards the light.
+    Directional { direction: [f64; 3], color: [f64; 3] }
+}
+
+impl Light {
+    /// The unit vector from `point` towards the light
+    pub fn towards(&self, point: [f64; 3]) -> [f64; 3] {
+        match *self {
+            Light::Point { position, .. } => normalized([position[0] - point[0], position[1] - point[1], position[2] - point[2]]),
+            Light::Directional { direction, .. } => normalized(direction)
+        }
+    }
+
+    /// The color of the light reaching `point`
+    fn color_at(&self, point: [f64; 3]) -> [f64; 3] {
+        match *self {
+            Light::Point { position, color, attenuation: [c, l, q] } => {
+                let v = [position[0] - point[0], position[1] - point[1], position[2] - point[2]];
+                let d = dot(v, v).sqrt();
+                let falloff = c + l * d + q * d * d;
+                if falloff <= 0.0 {
+                    return color;
+                }
+                [color[0] / falloff, color[1] / falloff, color[2] / falloff]
+            },
+            Light::Directional { color, .. } => color
+        }
+    }
+}
+
+/// How a surface reflects light: the fraction of each of red, green, and
+/// blue it reflects of the ambient light (`ka`), diffusely in every
+/// direction (`kd`), and specularly, as highlights (`ks`), and how small and
+/// sharp its highlights are (`shininess`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material {
+    pub ka: [f64; 3],
+    pub kd: [f64; 3],
+    pub ks: [f64; 3],
+    pub shininess: f64
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material::new()
+    }
+}
+
+impl Material {
+    /// A plain gray material, reflecting half of all light
+    pub fn new() -> Material {
+        Material { ka: [0.5; 3], kd: [0.5; 3], ks: [0.5; 3], shininess: SHININESS }
+    }
+
+    /// The material `constants` describe
+    pub fn from_constants(constants: &Constants) -> Material {
+        Material { ka: constants.ambient, kd: constants.diffuse, ks: constants.specular, shininess: SHININESS }
+    }
+}
+
+/// The light in a scene: ambient light, which lights everything evenly, and
+/// the lights shining on it, which add up
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lighting {
+    pub ambient: [f64; 3],
+    pub lights: Vec<Light>
+}
+
+impl Default for Lighting {
+    fn default() -> Lighting {
+        Lighting::new()
+    }
+}
+
+impl Lighting {
+    /// Dim ambient light and a white light from up, to the right, and in
+    /// front
+    pub fn new() -> Lighting {
+        Lighting {
+            ambient: [50.0; 3],
+            lights: vec![Light::Directional { direction: [0.5, 0.75, 1.0], color: [255.0; 3] }]
+        }
+    }
+
+    /// The color of a surface of `material` at `point` facing the way of the
+    /// unit vector `normal`, seen from in front, by the Phong reflection
+    /// model, leaving out the lights `shadows` says don't reach it.
+    pub fn phong(&self, material: &Material, point: [f64; 3], normal: [f64; 3], shadows: &Shadows) -> Color {
+        self.phong_where(material, point, normal, |i| shadows.lit(i, point, normal))
+    }
+
+    /// The color of a surface as `phong` gives it, leaving out the lights
+    /// (by index) which `reaches` says don't reach it.
+    pub fn phong_where<F: Fn(usize) -> bool>(&self, material: &Material, point: [f64; 3], normal: [f64; 3], reaches: F) -> Color {
+        let view = [0.0, 0.0, 1.0];
+        let mut rgb = [0, 1, 2].map(|c| self.ambient[c] * material.ka[c]);
+        for (i, light) in self.lights.iter().enumerate() {
+            if !reaches(i) {
+                continue;
+            }
+            let l = light.towards(point);
+            let diffuse = dot(normal, l).max(0.0);
+            // The light reflected about the normal
+            let r = [2.0 * diffuse * normal[0] - l[0], 2.0 * diffuse * normal[1] - l[1], 2.0 * diffuse * normal[2] - l[2]];
+            let specular = if diffuse > 0.0 { dot(r, view).max(0.0).powf(material.shininess) } else { 0.0 };
+            let color = light.color_at(point);
+            for c in 0..3 {
+                rgb[c] += color[c] * (material.kd[c] * diffuse + material.ks[c] * specular);
+            }
+        }
+        Color::clamped(rgb[0], rgb[1], rgb[2])
+    }
+}
+
+/// The color of each triangle in a triangle list in screen coordinates, lit
+/// by `lighting` as a flat surface of the material of its style in `styles`,
+/// at its center, in the shadows `shadows`.
+pub fn flat_colors(triangles: &PolygonList, styles: &[Style], lighting: &Lighting, shadows: &Shadows) -> Vec<Color> {
+    (0..triangles.len()).map(|i| {
+        let [a, b, c] = triangles.triangle(i);
+        let center = [(a[0] + b[0] + c[0]) / 3.0, (a[1] + b[1] + c[1]) / 3.0, (a[2] + b[2] + c[2]) / 3.0];
+        lighting.phong(&styles[i].material, center, render::face_normal(triangles, i), shadows)
+    }).collect()
+}
+
+/// The colors of the corners of each triangle in a triangle list in screen
+/// coordinates, lit by `lighting` as surfaces of the material of its style in
+/// `styles` facing the way of its vertex normals (see `vertex_normals`), in
+/// the shadows `shadows`, for Gouraud shading.
+pub fn gouraud_colors(triangles: &PolygonList, styles: &[Style], lighting: &Lighting, shadows: &Shadows, crease: Option<f64>) -> Vec<[Color; 3]> {
+    vertex_normals(triangles, crease).iter().enumerate().map(|(i, normals)| {
+        let corners = triangles.triangle(i);
+        let material = &styles[i].material;
+        [lighting.phong(material, xyz(corners[0]), normals[0], shadows),
+         lighting.phong(material, xyz(corners[1]), normals[1], shadows),
+         lighting.phong(material, xyz(corners[2]), normals[2], shadows)]
+    }).collect()
+}
+
+/// The unit normal at each corner of each triangle in a triangle list: the
+/// average of the face normals of the triangles meeting at that point, so
+/// that a mesh of triangles is lit as a smooth surface. If `crease` is set,
+/// triangles facing more than that many degrees away from a triangle don't
+/// count towards its corners, so sharp edges stay sharp.
+pub fn vertex_normals(triangles: &PolygonList, crease: Option<f64>) -> Vec<[[f64; 3]; 3]> {
+    let faces: Vec<_> = (0..triangles.len()).map(|i| render::face_normal(triangles, i)).collect();
+    // The triangles at each point, by its position rounded off, since the
+    // same point of a mesh can come out slightly differently for each
+    // triangle
+    let key = |p: [f64; 4]| ((p[0] * 1e6).round() as i64, (p[1] * 1e6).round() as i64, (p[2] * 1e6).round() as i64);
+    let mut adjacent = HashMap::new();
+    for i in 0..triangles.len() {
+        for &p in triangles.triangle(i).iter() {
+            adjacent.entry(key(p)).or_insert_with(Vec::new).push(i);
+        }
+    }
+    let min_cos = crease.map_or(-1.0, |degrees| degrees.to_radians().cos());
+    (0..triangles.len()).map(|i| {
+        let mut normals = [[0.0; 3]; 3];
+        for (normal, &p) in normals.iter_mut().zip(triangles.triangle(i).iter()) {
+            let mut sum = [0.0; 3];
+            for &j in &adjacent[&key(p)] {
+                if j == i || dot(faces[i], faces[j]) >= min_cos {
+                    for c in 0..3 {
+                        sum[c] += faces[j][c];
+                    }
+                }
+            }
+            *normal = normalized(sum);
+        }
+        normals
+    }).collect()
+}
+
+fn xyz(p: [f64; 4]) -> [f64; 3] {
+    [p[0], p[1], p[2]]
+}
+
+pub fn dot(u: [f64; 3], v: [f64; 3]) -> f64 {
+    u[0] * v[0] + u[1] * v[1] + u[2] * v[2]
+}
+
+/// `v` scaled to a length of 1, unless it's zero
+pub fn normalized(v: [f64; 3]) -> [f64; 3] {
+    let len = dot(v, v).sqrt();
+    if len == 0.0 {
+        return v;
+    }
+    [v[0] / len, v[1] / len, v[2] / len]
+}
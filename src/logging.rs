@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Verbosity levels. Warnings are always shown; `-v` shows info
+/// messages and `-vv` shows debug messages as well.
+pub const WARN: usize = 0;
+pub const INFO: usize = 1;
+pub const DEBUG: usize = 2;
+
+static VERBOSITY: AtomicUsize = AtomicUsize::new(WARN);
+
+pub fn set_verbosity(level: usize) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+/// Whether messages at `level` should be shown.
+pub fn enabled(level: usize) -> bool {
+    level <= VERBOSITY.load(Ordering::Relaxed)
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::WARN) {
+            eprintln!("WARNING: {}", format_args!($($arg)*));
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::INFO) {
+            eprintln!("{}", format_args!($($arg)*));
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::DEBUG) {
+            eprintln!("debug: {}", format_args!($($arg)*));
+        }
+    }
+}
@@ -1,46 +1,193 @@
-/// Matrix math
-mod matrix;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[macro_use]
+extern crate cstack;
 
-/// Add curves to an edge matrix
-mod curve;
-
-/// Add 3D solids to an edge matrix
-mod solid;
-
-/// Render edges to an in-memory representation of the pixels of the screen
-mod render;
-
-/// Create image files
-mod ppm;
-
-mod parse;
-
-/// Execute commands from a script
-mod exec;
-
-/// Crate-wide constants
-mod consts;
-
-use std::fs::File;
-use std::io::prelude::*;
+use cstack::{ bench, cancel, cli, config, error, exec, golden, include, logging, pick, ppm, progress, stats, timing };
+#[cfg(feature = "tracing")]
+use cstack::trace;
+use std::process;
+use std::thread;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::sync::Arc;
 
 fn main() {
-    match File::open("script") {
+    let opts = match cli::parse_args() {
+        Ok(opts) => opts,
+        Err(msg) => {
+            println!("Error!\n{}", msg);
+            return;
+        }
+    };
+    if opts.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    logging::set_verbosity(opts.verbosity);
+    #[cfg(feature = "tracing")]
+    let _trace = trace::init();
+    #[cfg(feature = "parallel")]
+    init_thread_pool(opts.threads);
+    if opts.bench {
+        bench::run_all();
+        return;
+    }
+    if let Some((x, y)) = opts.pick {
+        if !pick_pixel(&opts, x, y) {
+            process::exit(1);
+        }
+        return;
+    }
+    if let Some(ref dir) = opts.golden {
+        if !check_golden(&opts, dir) {
+            process::exit(1);
+        }
+        return;
+    }
+    // Ctrl-C stops rendering after the frames in progress are saved
+    let cancel = cancel::CancelToken::new();
+    cancel::cancel_on_interrupt(&cancel);
+    let render = opts.render.clone().cancel(cancel.clone());
+    if opts.parallel {
+        // Each of `opts.threads` workers takes the next script to be run
+        // until there are none left. Each script's frames are then rendered
+        // on just the one thread.
+        let next = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..opts.threads {
+                s.spawn(|| {
+                    loop {
+                        let i = next.fetch_add(1, Ordering::SeqCst);
+                        if i >= opts.scripts.len() || cancel.is_cancelled() {
+                            break;
+                        }
+                        run_file(&opts, &opts.scripts[i], &render);
+                    }
+                });
+            }
+        });
+    } else {
+        let mut config = render.threads(opts.threads);
+        if opts.progress {
+            config = config.progress(Arc::new(progress::Tracker::new(|p| {
+                eprint!("\r{:<60}", progress::bar(p));
+            })));
+        }
+        for script in &opts.scripts {
+            run_file(&opts, script, &config);
+            if opts.progress {
+                eprintln!();
+            }
+            if cancel.is_cancelled() {
+                break;
+            }
+        }
+    }
+}
+
+/// Run the script in the file `script`, reporting any errors.
+fn run_file(opts: &cli::Options, script: &str, config: &config::RenderConfig) {
+    let s = match include::read_script(script) {
+        Ok(s) => s,
         Err(e) => {
-            panic!("Could not open file 'script'. Error: {}", e);
-        },
-        Ok(mut file) => {
-            let mut s = String::from("");
-            match file.read_to_string(&mut s) {
-                Ok(_) => {
-                    if let Err(msg) = exec::run_script(&s) {
-                        println!("Error!\n{}", msg);
+            println!("Error in {}!\n{}", script, e);
+            return;
+        }
+    };
+    info!("Running {}", script);
+    let mut timings = timing::Timings::new(opts.timings);
+    let mut stats = stats::Stats::new(opts.stats);
+    match exec::run_script(&s, &cli::out_dir(opts, script), opts.format, config, &mut timings, &mut stats) {
+        Ok(rendered) => {
+            if let Some(ref output) = opts.output {
+                match rendered {
+                    exec::Rendered::Image(ref pixels) => if let Err(e) = timings.time(timing::Stage::Write, || ppm::save(pixels, output, opts.format)) {
+                        println!("Error saving {}!\n{}", script, e);
+                    },
+                    exec::Rendered::Frames(..) => {
+                        warn!("{} is an animation, so its frames were saved in {} rather than {}", script, cli::out_dir(opts, script), output);
                     }
-                },
-                Err(e) => {
-                    panic!("Error reading text in ./script: {}", e);
                 }
             }
+            if opts.preview {
+                if let Err(e) = preview(&rendered) {
+                    println!("Error previewing {}!\n{}", script, e);
+                }
+            }
+        },
+        Err(error::Error::Cancelled) => {
+            println!("Cancelled {}", script);
+        },
+        Err(msg) => {
+            println!("Error in {}!\n{}", script, msg);
+        }
+    }
+    timings.report(script);
+    stats.report(script);
+}
+
+/// Check each script against its reference image in `dir` (or, with
+/// `--bless`, replace the reference images), reporting the results. Returns
+/// whether every script passed.
+fn check_golden(opts: &cli::Options, dir: &str) -> bool {
+    let mut passed = true;
+    for script in &opts.scripts {
+        let reference = cli::reference_path(dir, script, &opts.scripts);
+        let result = include::read_script(script).and_then(|s| {
+            if opts.bless {
+                golden::bless(&s, &reference, &opts.render)
+            } else {
+                golden::check(&s, &reference, &opts.render, golden::Tolerance::exact()).map(|_| ())
+            }
+        });
+        match result {
+            Ok(()) if opts.bless => println!("{}: saved {}", script, reference),
+            Ok(()) => println!("{}: ok", script),
+            Err(e) => {
+                println!("{}: FAILED\n{}", script, e);
+                passed = false;
+            }
         }
     }
+    passed
+}
+
+/// Report which shape of each script drew the pixel at `x`, `y`, returning
+/// whether every script could be picked from.
+fn pick_pixel(opts: &cli::Options, x: usize, y: usize) -> bool {
+    let mut picked_all = true;
+    for script in &opts.scripts {
+        let result = include::read_script(script).and_then(|s| {
+            pick::pick(&s, &opts.render, x, y)
+        });
+        match result {
+            Ok(Some(picked)) => match picked.point {
+                Some(p) => println!("{}:{}: {} (shape {}, at {:.1}, {:.1}, {:.1})", script, picked.line, picked.source, picked.object, p[0], p[1], p[2]),
+                None => println!("{}:{}: {} (shape {})", script, picked.line, picked.source, picked.object)
+            },
+            Ok(None) => println!("{}: background", script),
+            Err(e) => {
+                println!("Error in {}!\n{}", script, e);
+                picked_all = false;
+            }
+        }
+    }
+    picked_all
+}
+
+/// Open what a script rendered in the system image viewer.
+fn preview(rendered: &exec::Rendered) -> error::Result<()> {
+    match *rendered {
+        exec::Rendered::Image(ref screen) => ppm::display_image(screen),
+        exec::Rendered::Frames(ref filenames) => ppm::animate_files(filenames)
+    }
+}
+
+/// Size rayon's thread pool, which transforms and rasterizes with the
+/// `parallel` feature, to `threads` threads.
+#[cfg(feature = "parallel")]
+fn init_thread_pool(threads: usize) {
+    if let Err(e) = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global() {
+        warn!("Could not start a thread pool of {} threads: {}", threads, e);
+    }
 }
@@ -1,6 +1,10 @@
+use std::error;
 use std::fmt;
 use std::ops::{Add, Sub, Mul};
 use std::iter::Iterator;
+use std::cmp::Ordering;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 /// 4xN matrices
 #[derive(Clone)]
@@ -35,19 +39,10 @@ impl Matrix {
         Matrix::new(vec![[0.0, 0.0, 0.0, 1.0]])
     }
 
-    /// Make a 4x4 matrix given each cell value (parameters listed row-by-row).
-    pub fn new4x4(
-        a: f64, b: f64, c: f64, d: f64,
-        e: f64, f: f64, g: f64, h: f64,
-        i: f64, j: f64, k: f64, l: f64,
-        m: f64, n: f64, o: f64, p: f64) -> Matrix {
+    /// Make a 4x4 matrix given each cell value, row by row.
+    pub fn new4x4(rows: [[f64; 4]; 4]) -> Matrix {
         Matrix {
-            cols: vec![
-                [a, e, i, m],
-                [b, f, j, n],
-                [c, g, k, o],
-                [d, h, l, p]
-            ]
+            cols: (0..4).map(|col| [rows[0][col], rows[1][col], rows[2][col], rows[3][col]]).collect()
         }
     }
 
@@ -61,12 +56,12 @@ impl Matrix {
     }
 
     /// Make a translation matrix for translation by (dx, dy, dz)
-    pub fn translation_xyz(dx: f64, dy: f64, dz: f64) -> Matrix {
-        Matrix::new4x4(
-            1.0, 0.0, 0.0, dx,
-            0.0, 1.0, 0.0, dy,
-            0.0, 0.0, 1.0, dz,
-            0.0, 0.0, 0.0, 1.0)
+    pub fn translation(dx: f64, dy: f64, dz: f64) -> Matrix {
+        Matrix::new4x4([
+            [1.0, 0.0, 0.0, dx],
+            [0.0, 1.0, 0.0, dy],
+            [0.0, 0.0, 1.0, dz],
+            [0.0, 0.0, 0.0, 1.0]])
     }
 
     /// Make a 4x4 dilation matrix dilating by `s` in
@@ -78,74 +73,178 @@ impl Matrix {
     /// Make a 4x4 dilation matrix dilating by `sx` in
     /// x, `sy`, in y, and `sz` in z.
     pub fn dilation_xyz(sx: f64, sy: f64, sz: f64) -> Matrix {
-        Matrix::new4x4(
-            sx, 0.0, 0.0, 0.0,
-            0.0, sy, 0.0, 0.0,
-            0.0, 0.0, sz, 0.0,
-            0.0, 0.0, 0.0, 1.0)
+        Matrix::new4x4([
+            [sx, 0.0, 0.0, 0.0],
+            [0.0, sy, 0.0, 0.0],
+            [0.0, 0.0, sz, 0.0],
+            [0.0, 0.0, 0.0, 1.0]])
     }
 
+    /// Make a 4x4 rotation matrix for a rotation of `angle` radians
+    /// about the x axis.
     pub fn rotation_about_x(angle: f64) -> Matrix {
-        println!("rotation_about_x({})", angle);
         let cos = f64::cos(angle);
         let sin = f64::sin(angle);
-        Matrix::new4x4(
-            1.0, 0.0, 0.0, 0.0,
-            0.0, cos, -sin, 0.0,
-            0.0, sin, cos, 0.0,
-            0.0, 0.0, 0.0, 1.0)
+        Matrix::new4x4([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, cos, -sin, 0.0],
+            [0.0, sin, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0]])
     }
 
+    /// Make a 4x4 rotation matrix for a rotation of `angle` radians
+    /// about the y axis.
     pub fn rotation_about_y(angle: f64) -> Matrix {
-        println!("rotation_about_y({})", angle);
         let cos = f64::cos(angle);
         let sin = f64::sin(angle);
-        Matrix::new4x4(
-            cos, 0.0, sin, 0.0,
-            0.0, 1.0, 0.0, 0.0,
-            -sin, 0.0, cos, 0.0,
-            0.0, 0.0, 0.0, 1.0)
+        Matrix::new4x4([
+            [cos, 0.0, sin, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-sin, 0.0, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0]])
     }
 
     /// Make a 4x4 rotation matrix for a rotation of `angle` radians
     /// about the z axis.
     pub fn rotation_about_z(angle: f64) -> Matrix {
-        println!("rotation_about_z({})", angle);
         let cos = f64::cos(angle);
         let sin = f64::sin(angle);
-        Matrix::new4x4(
-            cos, -sin, 0.0, 0.0,
-            sin, cos, 0.0, 0.0,
-            0.0, 0.0, 1.0, 0.0,
-            0.0, 0.0, 0.0, 1.0)
+        Matrix::new4x4([
+            [cos, -sin, 0.0, 0.0],
+            [sin, cos, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    /// Make a 4x4 rotation matrix for a rotation of `angle` radians about
+    /// the line through the origin in the direction `axis`, which needn't be
+    /// a unit vector. Like the other rotations, it turns counterclockwise as
+    /// seen looking back along the axis towards the origin. Returns `None`
+    /// if `axis` is the zero vector.
+    pub fn rotation_about_axis(axis: [f64; 3], angle: f64) -> Option<Matrix> {
+        let len = (axis[0] * axis[0] + axis[1] * axis[1] + axis[2] * axis[2]).sqrt();
+        if len == 0.0 {
+            return None;
+        }
+        let (x, y, z) = (axis[0] / len, axis[1] / len, axis[2] / len);
+        let cos = f64::cos(angle);
+        let sin = f64::sin(angle);
+        let t = 1.0 - cos;
+        // Rodrigues' rotation formula
+        Some(Matrix::new4x4([
+            [t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y, 0.0],
+            [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x, 0.0],
+            [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0]]))
+    }
+
+    /// Make a 4x4 matrix moving and turning the world so that a viewer at
+    /// `eye` looking towards `target`, with `up` pointing up, ends up at the
+    /// origin looking down the negative z axis with y up. Returns `None` if
+    /// `eye` is `target`, or `up` points the same way as the view.
+    pub fn look_at(eye: [f64; 3], target: [f64; 3], up: [f64; 3]) -> Option<Matrix> {
+        let normalized = |v: [f64; 3]| {
+            let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+            if len == 0.0 { None } else { Some([v[0] / len, v[1] / len, v[2] / len]) }
+        };
+        let cross = |u: [f64; 3], v: [f64; 3]| [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+        let dot = |u: [f64; 3], v: [f64; 3]| u[0] * v[0] + u[1] * v[1] + u[2] * v[2];
+        // The way the viewer looks, and to their right and up
+        let f = normalized([target[0] - eye[0], target[1] - eye[1], target[2] - eye[2]])?;
+        let r = normalized(cross(f, up))?;
+        let u = cross(r, f);
+        Some(Matrix::new4x4([
+            [r[0], r[1], r[2], -dot(r, eye)],
+            [u[0], u[1], u[2], -dot(u, eye)],
+            [-f[0], -f[1], -f[2], dot(f, eye)],
+            [0.0, 0.0, 0.0, 1.0]]))
+    }
+
+    /// Make a 4x4 perspective projection matrix, for a viewer at the origin
+    /// looking down the negative z axis who sees `fov` radians from the
+    /// bottom of the view to the top, and `aspect` times as much across.
+    /// Points from `near` to `far` away end up with z from -w to w, and w is
+    /// their distance along the view, to divide the other coordinates by.
+    pub fn perspective(fov: f64, aspect: f64, near: f64, far: f64) -> Matrix {
+        let f = 1.0 / (fov / 2.0).tan();
+        Matrix::new4x4([
+            [f / aspect, 0.0, 0.0, 0.0],
+            [0.0, f, 0.0, 0.0],
+            [0.0, 0.0, (far + near) / (near - far), 2.0 * far * near / (near - far)],
+            [0.0, 0.0, -1.0, 0.0]])
     }
 
     /// Make a 4x4 shear matrix for a shear in the XY plane.
     pub fn shear_2d(dx: f64, dy: f64) -> Matrix {
-        Matrix::new4x4(
-            1.0, dx, 0.0, 0.0,
-            dy, 1.0, 0.0, 0.0,
-            0.0, 0.0, 1.0, 0.0,
-            0.0, 0.0, 0.0, 1.0)
+        Matrix::new4x4([
+            [1.0, dx, 0.0, 0.0],
+            [dy, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    /// The basis matrix of cubic Bezier curves: times the column of a
+    /// coordinate of the four control points, it gives the column of the
+    /// coefficients of t^3, t^2, t, and 1 of that coordinate.
+    pub fn bezier_basis() -> Matrix {
+        Matrix::new4x4([
+            [-1.0,  3.0, -3.0, 1.0],
+             [3.0, -6.0,  3.0, 0.0],
+            [-3.0,  3.0,  0.0, 0.0],
+             [1.0,  0.0,  0.0, 0.0]])
+    }
+
+    /// The basis matrix of cubic Hermite curves, which does the same as
+    /// `bezier_basis` for the column (p0, p1, m0, m1) of a coordinate of the
+    /// ends and the tangents there.
+    pub fn hermite_basis() -> Matrix {
+        Matrix::new4x4([
+             [2.0, -2.0,  1.0,  1.0],
+            [-3.0,  3.0, -2.0, -1.0],
+             [0.0,  0.0,  1.0,  0.0],
+             [1.0,  0.0,  0.0,  0.0]])
+    }
+
+    /// The basis matrix of Catmull-Rom splines, for the column of a
+    /// coordinate of four points in a row, giving the segment between the
+    /// middle two.
+    pub fn catmull_rom_basis() -> Matrix {
+        Matrix::new4x4([
+            [-0.5,  1.5, -1.5,  0.5],
+             [1.0, -2.5,  2.0, -0.5],
+            [-0.5,  0.0,  0.5,  0.0],
+             [0.0,  1.0,  0.0,  0.0]])
+    }
+
+    /// The basis matrix of uniform cubic B-splines, for the column of a
+    /// coordinate of four control points in a row, giving the segment near
+    /// the middle two.
+    pub fn b_spline_basis() -> Matrix {
+        let sixth = 1.0 / 6.0;
+        Matrix::new4x4([
+            [-sixth,  0.5, -0.5, sixth],
+               [0.5, -1.0,  0.5,   0.0],
+              [-0.5,  0.0,  0.5,   0.0],
+             [sixth, 4.0 * sixth, sixth, 0.0]])
+    }
+
+    /// Get an array of the elements in column `colnum`. Panics if there is
+    /// no such column; see `try_col`.
+    pub fn col(&self, colnum: usize) -> [f64; 4] {
+        self.try_col(colnum).unwrap_or_else(|e| panic!("{}", e))
     }
 
-    /// Get an array of the elements in column `colnum`.
-    pub fn col(&self, colnum: usize) -> [f64; 4] {
-        let width = self.cols.len();
-        if colnum > width {
-            panic!("Attempted to get column {} of a matrix of width {}", colnum, width);
-        }
-        self.cols[colnum]
+    /// Get an array of the elements in column `colnum`, or an error if there
+    /// is no such column.
+    pub fn try_col(&self, colnum: usize) -> Result<[f64; 4], MatrixError> {
+        self.check(0, colnum)?;
+        Ok(self.cols[colnum])
     }
 
-    /// Get a Vec of the elements in column `colnum`.
+    /// Get a Vec of the elements in column `colnum`. Panics if there is no
+    /// such column.
     pub fn col_vec(&self, colnum: usize) -> Vec<f64> {
-        let width = self.cols.len();
-        if colnum > width {
-            panic!("Attempted to get column {} of a matrix of width {}", colnum, width);
-        }
-        let col = &self.cols[colnum];
-        vec![col[0], col[1], col[2], col[3]] // TODO: Into<Vec<T>>?
+        self.col(colnum).to_vec()
     }
 
     /// Push a column to the right side of `self`.
@@ -160,54 +259,188 @@ impl Matrix {
         }
     }
 
-    /// Push an edge, i.e. two points, to `self` (think of `self` as an edge list).
-    pub fn push_edge(&mut self, pt0: [f64; 4], pt1: [f64; 4]) {
-        self.push_col(pt0);
-        self.push_col(pt1);
-    }
-
-    pub fn push_triangle(&mut self, pt0: [f64; 4], pt1: [f64; 4], pt2: [f64; 4]) {
-        self.push_col(pt0);
-        self.push_col(pt1);
-        self.push_col(pt2);
-    }
-
     pub fn clear_cols(&mut self) {
         self.cols.clear();
     }
 
     /// Get a vector of entries in row `rownum`.
     pub fn row(&self, rownum: usize) -> Vec<f64> {
-        if rownum > 3 {
-            panic!("Attempted to get row {} of a matrix of height 4", rownum);
-        }
-        let mut items = vec![];
-        for column in &self.cols {
-            items.push(column[rownum]);
+        self.try_row(rownum).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Get a Vec of the elements in row `rownum`, or an error if there is no
+    /// such row.
+    pub fn try_row(&self, rownum: usize) -> Result<Vec<f64>, MatrixError> {
+        if rownum >= 4 {
+            return Err(MatrixError::Row(rownum));
         }
-        items
+        Ok(self.cols.iter().map(|column| column[rownum]).collect())
     }
 
-    pub fn row_iter(&self, rownum: usize) -> MatrixRowIter {
+    pub fn row_iter(&self, rownum: usize) -> MatrixRowIter<'_> {
         MatrixRowIter::new(self, rownum)
     }
 
-    pub fn col_iter(&self, colnum: usize) -> MatrixColIter {
+    pub fn col_iter(&self, colnum: usize) -> MatrixColIter<'_> {
         MatrixColIter::new(self, colnum)
     }
 
-    /// Get the entry at row `row` and column `col`.
+    /// Compute the product of the 4x4 matrix `self` and `rhs` into `out`,
+    /// reusing the storage `out` already has.
+    pub fn mul_into(&self, rhs: &Matrix, out: &mut Matrix) {
+        out.cols.clear();
+        out.cols.extend_from_slice(&rhs.cols);
+        transform_points(self, &mut out.cols);
+    }
+
+    /// The point `p` transformed by the affine transformation `self`, with
+    /// its last coordinate left as it is rather than taken as 1 (after a
+    /// camera, it's 1/w; see `Camera::project_polygons`).
+    pub fn apply_to_point(&self, p: [f64; 4]) -> [f64; 4] {
+        let mut points = [[p[0], p[1], p[2], 1.0]];
+        transform_chunk(self, &mut points);
+        [points[0][0], points[0][1], points[0][2], p[3]]
+    }
+
+    /// Push each column of `m` to `self`, without consuming `m`.
+    pub fn extend(&mut self, m: &Matrix) {
+        self.cols.extend_from_slice(&m.cols);
+    }
+
+    /// Remove all but the first `width` columns.
+    pub fn truncate(&mut self, width: usize) {
+        self.cols.truncate(width);
+    }
+
+    /// Get the rows of a 4x4 matrix.
+    fn rows4(&self) -> [[f64; 4]; 4] {
+        let mut rows = [[0.0; 4]; 4];
+        for (j, col) in self.cols.iter().take(4).enumerate() {
+            for i in 0..4 {
+                rows[i][j] = col[i];
+            }
+        }
+        rows
+    }
+
+    /// The determinant of a 4x4 matrix (or of the first four columns of a
+    /// wider one), found by Gaussian elimination.
+    pub fn determinant(&self) -> f64 {
+        let mut rows = self.rows4();
+        let mut det = 1.0;
+        for col in 0..4 {
+            let pivot = match pivot_row(&rows, col) {
+                Some(pivot) => pivot,
+                None => return 0.0
+            };
+            if pivot != col {
+                rows.swap(pivot, col);
+                det = -det;
+            }
+            det *= rows[col][col];
+            for row in col + 1..4 {
+                let k = rows[row][col] / rows[col][col];
+                let above = rows[col];
+                for (x, p) in rows[row].iter_mut().zip(above.iter()).skip(col) {
+                    *x -= k * p;
+                }
+            }
+        }
+        det
+    }
+
+    /// The inverse of a 4x4 matrix (or of the first four columns of a wider
+    /// one), found by Gauss-Jordan elimination, or `None` if it's singular.
+    pub fn inverse(&self) -> Option<Matrix> {
+        let mut rows = self.rows4();
+        let mut inv = Matrix::identity().rows4();
+        for col in 0..4 {
+            let pivot = pivot_row(&rows, col)?;
+            rows.swap(pivot, col);
+            inv.swap(pivot, col);
+            let k = rows[col][col];
+            for j in 0..4 {
+                rows[col][j] /= k;
+                inv[col][j] /= k;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let k = rows[row][col];
+                    for j in 0..4 {
+                        rows[row][j] -= k * rows[col][j];
+                        inv[row][j] -= k * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Matrix::new((0..4).map(|j| [inv[0][j], inv[1][j], inv[2][j], inv[3][j]]).collect()))
+    }
+
+    /// The transpose of a 4x4 matrix (or of the first four columns of a
+    /// wider one).
+    pub fn transpose(&self) -> Matrix {
+        Matrix::new(self.rows4().to_vec())
+    }
+
+    /// The matrix which transforms normals (as directions, with h = 0) to
+    /// match the 4x4 transformation `self`: the inverse transpose of its
+    /// upper left 3x3, which keeps normals perpendicular to their surfaces
+    /// even under non-uniform scaling. `None` if `self` squashes space flat.
+    /// The renderer itself doesn't need it, as it finds normals from
+    /// triangles already transformed (see `render::face_normal`); it's for
+    /// normals given along with the points they belong to.
+    pub fn normal_matrix(&self) -> Option<Matrix> {
+        let mut linear = self.clone();
+        linear.cols.truncate(3);
+        for col in &mut linear.cols {
+            col[3] = 0.0;
+        }
+        linear.cols.push([0.0, 0.0, 0.0, 1.0]);
+        Some(linear.inverse()?.transpose())
+    }
+
+    /// Get the entry at row `row` and column `col`. Panics if there is no
+    /// such entry; see `try_get`.
     pub fn get(&self, row: usize, col: usize) -> f64 {
-        self.cols[col][row]
+        self.try_get(row, col).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Get the entry at row `row` and column `col`, or an error if there is
+    /// no such entry.
+    pub fn try_get(&self, row: usize, col: usize) -> Result<f64, MatrixError> {
+        self.check(row, col)?;
+        Ok(self.cols[col][row])
     }
 
 
-    /// Set the entry at row `row` and column `col` to `val`.
+    /// Set the entry at row `row` and column `col` to `val`. Panics if there
+    /// is no such entry; see `try_set`.
     pub fn set(&mut self, row: usize, col: usize, val: f64) {
+        self.try_set(row, col, val).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Set the entry at row `row` and column `col` to `val`, or return an
+    /// error if there is no such entry.
+    pub fn try_set(&mut self, row: usize, col: usize, val: f64) -> Result<(), MatrixError> {
+        self.check(row, col)?;
         self.cols[col][row] = val;
+        Ok(())
     }
 
+    /// Check that the matrix has an entry at row `row` and column `col`.
+    fn check(&self, row: usize, col: usize) -> Result<(), MatrixError> {
+        if row >= 4 {
+            Err(MatrixError::Row(row))
+        } else if col >= self.cols.len() {
+            Err(MatrixError::Column { col, width: self.cols.len() })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Set column `col` to `items`. Panics if there is no such column.
     pub fn set_col(&mut self, col: usize, items: [f64; 4]) {
+        self.check(0, col).unwrap_or_else(|e| panic!("{}", e));
         self.cols[col] = items;
     }
 
@@ -218,24 +451,22 @@ impl Matrix {
 
     /// Perform the matrix product `lhs` * `self`, in-place in `self`.
     pub fn transform_by(&mut self, lhs: &Matrix) {
-        for j in 0..self.width() {
-            let mut col = [0.0f64; 4];
-            for i in 0..4 {
-                col[i] = dot_product(lhs.row_iter(i), self.col_iter(j));
-            }
-            self.set_col(j, col);
-        }
+        lhs.apply_to(self);
+    }
+
+    /// Transform the points of `points` by the 4x4 matrix `self`, in place.
+    /// Unlike `self * points`, this allocates nothing, so it can be done to
+    /// the same list every frame.
+    pub fn apply_to<T: Transformable + ?Sized>(&self, points: &mut T) {
+        points.transform(self);
     }
 
     /// Perform the matrix product `self` * `rhs`, in-place in `self`.
     pub fn transform_on_right(&mut self, rhs: &Matrix) {
-        for j in 0..self.width() {
-            let mut col = [0.0f64; 4];
-            for i in 0..4 {
-                col[i] = dot_product(self.row_iter(i), rhs.col_iter(j));
-            }
-            self.set_col(j, col);
-        }
+        // Each column of the product needs every column of `self`, so none
+        // can be overwritten until they're all found
+        let product = &*self * rhs;
+        *self = product;
     }
 }
 
@@ -248,8 +479,8 @@ pub struct MatrixRowIter<'a> {
 impl<'a> MatrixRowIter<'a> {
     pub fn new<'b>(mat: &'b Matrix, row: usize) -> MatrixRowIter<'b> {
         MatrixRowIter {
-            mat: mat,
-            row: row,
+            mat,
+            row,
             col: 0
         }
     }
@@ -258,13 +489,10 @@ impl<'a> MatrixRowIter<'a> {
 impl<'a> Iterator for MatrixRowIter<'a> {
     type Item = f64;
     fn next(&mut self) -> Option<f64> {
-        if self.col < self.mat.width() {
-            let result = self.mat.get(self.row, self.col);
-            self.col += 1;
-            Some(result)
-        } else {
-            None
-        }
+        // Ends at the last column, or at once if there is no such row
+        let result = self.mat.try_get(self.row, self.col).ok()?;
+        self.col += 1;
+        Some(result)
     }
 }
 
@@ -277,9 +505,9 @@ pub struct MatrixColIter<'a> {
 impl<'a> MatrixColIter<'a> {
     pub fn new<'b>(mat: &'b Matrix, col: usize) -> MatrixColIter<'b> {
         MatrixColIter {
-            mat: mat,
+            mat,
             row: 0,
-            col: col
+            col
         }
     }
 }
@@ -287,18 +515,15 @@ impl<'a> MatrixColIter<'a> {
 impl<'a> Iterator for MatrixColIter<'a> {
     type Item = f64;
     fn next(&mut self) -> Option<f64> {
-        if self.row < 4 {
-            let result = self.mat.get(self.row, self.col);
-            self.row += 1;
-            Some(result)
-        } else {
-            None
-        }
+        // Ends at the last row, or at once if there is no such column
+        let result = self.mat.try_get(self.row, self.col).ok()?;
+        self.row += 1;
+        Some(result)
     }
 }
 
 // ref plus ref
-impl<'a, 'b> Add<&'a Matrix> for &'b Matrix {
+impl Add<&Matrix> for &Matrix {
     type Output = Matrix;
     /// Add two matrices, assuming they are of the same width
     fn add(self, rhs: &Matrix) -> Matrix {
@@ -315,7 +540,7 @@ impl<'a, 'b> Add<&'a Matrix> for &'b Matrix {
 
 // TODO: remove all but ref plus ref
 // owned plus ref
-impl<'a> Add<&'a Matrix> for Matrix {
+impl Add<&Matrix> for Matrix {
     type Output = Matrix;
     /// Add two matrices, assuming they are of the same width
     fn add(self, rhs: &Matrix) -> Matrix {
@@ -324,7 +549,7 @@ impl<'a> Add<&'a Matrix> for Matrix {
 }
 
 // ref plus owned
-impl<'a> Add<Matrix> for &'a Matrix {
+impl Add<Matrix> for &Matrix {
     type Output = Matrix;
     /// Add two matrices, assuming they are of the same width
     fn add(self, rhs: Matrix) -> Matrix {
@@ -341,7 +566,7 @@ impl Add<Matrix> for Matrix {
     }
 }
 
-impl<'a, 'b> Sub<&'a Matrix> for &'b Matrix {
+impl Sub<&Matrix> for &Matrix {
     type Output = Matrix;
     /// Add two matrices, assuming they are of the same width
     fn sub(self, rhs: &Matrix) -> Matrix {
@@ -350,22 +575,20 @@ impl<'a, 'b> Sub<&'a Matrix> for &'b Matrix {
     }
 }
 
-impl<'a, 'b> Mul<&'a Matrix> for &'b Matrix {
+impl Mul<&Matrix> for &Matrix {
     type Output = Matrix;
+    /// Multiply a 4x4 matrix by a 4xN matrix. This is the hot path when
+    /// transforming large edge lists, so it avoids allocating anything but
+    /// the result (and is vectorized with the `simd` feature).
     fn mul(self, rhs: &Matrix) -> Matrix {
-        let mut m = Matrix::with_capacity(rhs.width(), 0.0);
-        for i in 0..4 {
-            for j in 0..rhs.width() {
-                let val = dot_product(self.row_iter(i), rhs.col_iter(j));
-                m.set(i, j, val);
-            }
-        }
+        let mut m = Matrix::new(Vec::with_capacity(rhs.width()));
+        self.mul_into(rhs, &mut m);
         m
     }
 }
 
 /// Mutates the right hand side.
-impl<'a> Mul<Matrix> for &'a Matrix {
+impl Mul<Matrix> for &Matrix {
     type Output = Matrix;
     fn mul(self, mut rhs: Matrix) -> Matrix {
         rhs.transform_by(self);
@@ -373,12 +596,147 @@ impl<'a> Mul<Matrix> for &'a Matrix {
     }
 }
 
-fn dot_product<'a, 'b, T: Iterator<Item=f64>, U: Iterator<Item=f64>>(v: T, u: U) -> f64 {
-    let mut sum = 0.0;
-    for (a, b) in v.zip(u) {
-        sum += a * b;
+/// Something made of points, which a matrix can transform in place with
+/// `Matrix::apply_to`
+pub trait Transformable {
+    /// Replace each point `p` with `transform * p`.
+    fn transform(&mut self, transform: &Matrix);
+}
+
+/// Each column is a point.
+impl Transformable for Matrix {
+    fn transform(&mut self, transform: &Matrix) {
+        transform_points(transform, &mut self.cols);
     }
-    sum
+}
+
+/// Points in each chunk transformed on its own by `transform_points`, with
+/// the `parallel` feature. Fewer points than this are done on the calling
+/// thread, as handing them to the thread pool would take longer.
+#[cfg(feature = "parallel")]
+const CHUNK_POINTS: usize = 4096;
+
+/// Replace each of `points` with its product with the 4x4 matrix `m`, in
+/// chunks on rayon's thread pool.
+#[cfg(feature = "parallel")]
+fn transform_points(m: &Matrix, points: &mut [[f64; 4]]) {
+    if points.len() <= CHUNK_POINTS {
+        transform_chunk(m, points);
+    } else {
+        points.par_chunks_mut(CHUNK_POINTS).for_each(|chunk| transform_chunk(m, chunk));
+    }
+}
+
+/// Replace each of `points` with its product with the 4x4 matrix `m`.
+#[cfg(not(feature = "parallel"))]
+fn transform_points(m: &Matrix, points: &mut [[f64; 4]]) {
+    transform_chunk(m, points);
+}
+
+/// Replace each of `points` with its product with the 4x4 matrix `m`. Every
+/// point the renderer draws goes through here, so with the `simd` feature on
+/// x86-64, a point is done a whole column of `m` at a time, with AVX if the
+/// processor has it and SSE2 (which every x86-64 processor has) if not. The
+/// sums are added up in the same order either way, so the results are the
+/// same to the bit.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+fn transform_chunk(m: &Matrix, points: &mut [[f64; 4]]) {
+    let mut cols = [[0.0; 4]; 4];
+    for (col, m_col) in cols.iter_mut().zip(m.cols.iter()) {
+        *col = *m_col;
+    }
+    unsafe {
+        if is_x86_feature_detected!("avx") {
+            simd::transform_points_avx(&cols, points);
+        } else {
+            simd::transform_points_sse2(&cols, points);
+        }
+    }
+}
+
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+fn transform_chunk(m: &Matrix, points: &mut [[f64; 4]]) {
+    let rows = m.rows4();
+    for point in points.iter_mut() {
+        let p = *point;
+        *point = [dot4(&rows[0], &p), dot4(&rows[1], &p), dot4(&rows[2], &p), dot4(&rows[3], &p)];
+    }
+}
+
+/// Vectorized kernels for `transform_chunk`. Each computes `m * p` as
+/// `m[0] * p[0] + m[1] * p[1] + m[2] * p[2] + m[3] * p[3]`, where `m` is the
+/// columns of the matrix.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd {
+    use std::arch::x86_64::*;
+
+    /// The processor must support AVX.
+    #[target_feature(enable = "avx")]
+    pub unsafe fn transform_points_avx(m: &[[f64; 4]; 4], points: &mut [[f64; 4]]) {
+        let c0 = _mm256_loadu_pd(m[0].as_ptr());
+        let c1 = _mm256_loadu_pd(m[1].as_ptr());
+        let c2 = _mm256_loadu_pd(m[2].as_ptr());
+        let c3 = _mm256_loadu_pd(m[3].as_ptr());
+        for p in points.iter_mut() {
+            let sum = _mm256_mul_pd(c0, _mm256_set1_pd(p[0]));
+            let sum = _mm256_add_pd(sum, _mm256_mul_pd(c1, _mm256_set1_pd(p[1])));
+            let sum = _mm256_add_pd(sum, _mm256_mul_pd(c2, _mm256_set1_pd(p[2])));
+            let sum = _mm256_add_pd(sum, _mm256_mul_pd(c3, _mm256_set1_pd(p[3])));
+            _mm256_storeu_pd(p.as_mut_ptr(), sum);
+        }
+    }
+
+    /// Does the top two rows and then the bottom two.
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn transform_points_sse2(m: &[[f64; 4]; 4], points: &mut [[f64; 4]]) {
+        let (c0, c1, c2, c3) = (m[0].as_ptr(), m[1].as_ptr(), m[2].as_ptr(), m[3].as_ptr());
+        let lo = [_mm_loadu_pd(c0), _mm_loadu_pd(c1), _mm_loadu_pd(c2), _mm_loadu_pd(c3)];
+        let hi = [_mm_loadu_pd(c0.add(2)), _mm_loadu_pd(c1.add(2)), _mm_loadu_pd(c2.add(2)), _mm_loadu_pd(c3.add(2))];
+        for p in points.iter_mut() {
+            let xs = [_mm_set1_pd(p[0]), _mm_set1_pd(p[1]), _mm_set1_pd(p[2]), _mm_set1_pd(p[3])];
+            for (half, c) in [lo, hi].iter().enumerate() {
+                let sum = _mm_mul_pd(c[0], xs[0]);
+                let sum = _mm_add_pd(sum, _mm_mul_pd(c[1], xs[1]));
+                let sum = _mm_add_pd(sum, _mm_mul_pd(c[2], xs[2]));
+                let sum = _mm_add_pd(sum, _mm_mul_pd(c[3], xs[3]));
+                _mm_storeu_pd(p.as_mut_ptr().add(2 * half), sum);
+            }
+        }
+    }
+}
+
+/// An index past the edge of a matrix
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatrixError {
+    /// Row `row` was asked for, but every matrix has just 4 rows
+    Row(usize),
+    /// Column `col` was asked for in a matrix `width` columns wide
+    Column { col: usize, width: usize }
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MatrixError::Row(row) => write!(f, "Attempted to get row {} of a matrix of height 4", row),
+            MatrixError::Column { col, width } => write!(f, "Attempted to get column {} of a matrix of width {}", col, width)
+        }
+    }
+}
+
+impl error::Error for MatrixError {}
+
+/// The row, from `col` down, with the biggest entry in column `col` of
+/// `rows`, to eliminate the others with. `None` if the column is all zeros
+/// (as far as rounding goes), meaning the matrix is singular.
+fn pivot_row(rows: &[[f64; 4]; 4], col: usize) -> Option<usize> {
+    let pivot = (col..4).max_by(|&a, &b| rows[a][col].abs().partial_cmp(&rows[b][col].abs()).unwrap_or(Ordering::Equal))?;
+    if rows[pivot][col].abs() < 1e-12 { None } else { Some(pivot) }
+}
+
+/// Dot product of two 4-vectors.
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+fn dot4(u: &[f64; 4], v: &[f64; 4]) -> f64 {
+    u[0] * v[0] + u[1] * v[1] + u[2] * v[2] + u[3] * v[3]
 }
 
 fn scale_matrix(scalar: f64, mat: &Matrix) -> Matrix {
@@ -391,14 +749,14 @@ fn scale_matrix(scalar: f64, mat: &Matrix) -> Matrix {
     result
 }
 
-impl<'a> Mul<f64> for &'a Matrix {
+impl Mul<f64> for &Matrix {
     type Output = Matrix;
     fn mul(self, rhs: f64) -> Matrix {
         scale_matrix(rhs, self)
     }
 }
 
-impl<'a> Mul<&'a Matrix> for f64 {
+impl Mul<&Matrix> for f64 {
     type Output = Matrix;
     fn mul(self, rhs: &Matrix) -> Matrix {
         scale_matrix(self, rhs)
@@ -426,3 +784,195 @@ impl fmt::Display for Matrix {
         write!(f, "{}", s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// Whether every entry of `a` is within 1e-9 of that of `b`.
+    fn close(a: &Matrix, b: &Matrix) -> bool {
+        a.width() == b.width() && (0..a.width()).all(|col| {
+            (0..4).all(|row| (a.get(row, col) - b.get(row, col)).abs() < 1e-9)
+        })
+    }
+
+    #[test]
+    fn inverse_undoes_transformations() {
+        let m = &(&Matrix::translation(3.0, -2.0, 5.0) * &Matrix::rotation_about_y(0.7)) * &Matrix::dilation_xyz(2.0, 0.5, 4.0);
+        let inverse = m.inverse().unwrap();
+        assert!(close(&(&inverse * &m), &Matrix::identity()));
+        assert!(close(&(&m * &inverse), &Matrix::identity()));
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        assert!(Matrix::dilation_xyz(2.0, 0.0, 3.0).inverse().is_none());
+        assert!(Matrix::new(vec![[0.0; 4]; 4]).inverse().is_none());
+    }
+
+    #[test]
+    fn determinant_is_the_change_in_volume() {
+        assert!((Matrix::dilation_xyz(2.0, 3.0, 4.0).determinant() - 24.0).abs() < 1e-9);
+        // Moving and turning things don't change their volume
+        let m = &(&Matrix::translation(1.0, 2.0, 3.0) * &Matrix::rotation_about_x(1.2)) * &Matrix::dilation_xyz(2.0, 3.0, 4.0);
+        assert!((m.determinant() - 24.0).abs() < 1e-9);
+        // ...and a reflection turns them inside out
+        assert!((Matrix::dilation_xyz(-1.0, 1.0, 1.0).determinant() + 1.0).abs() < 1e-9);
+        assert_eq!(Matrix::dilation_xyz(2.0, 0.0, 3.0).determinant(), 0.0);
+    }
+
+    #[test]
+    fn accessors_fail_just_past_the_edge() {
+        let mut m = Matrix::new(vec![[1.0, 2.0, 3.0, 4.0], [5.0, 6.0, 7.0, 8.0]]);
+        assert_eq!(m.try_col(1), Ok([5.0, 6.0, 7.0, 8.0]));
+        assert_eq!(m.try_col(2), Err(MatrixError::Column { col: 2, width: 2 }));
+        assert_eq!(m.try_row(3), Ok(vec![4.0, 8.0]));
+        assert_eq!(m.try_row(4), Err(MatrixError::Row(4)));
+        assert_eq!(m.try_get(3, 1), Ok(8.0));
+        assert_eq!(m.try_get(4, 1), Err(MatrixError::Row(4)));
+        assert_eq!(m.try_get(0, 2), Err(MatrixError::Column { col: 2, width: 2 }));
+        assert_eq!(m.try_set(3, 1, 9.0), Ok(()));
+        assert_eq!(m.get(3, 1), 9.0);
+        assert_eq!(m.try_set(0, 2, 9.0), Err(MatrixError::Column { col: 2, width: 2 }));
+        assert_eq!(Matrix::empty().try_col(0), Err(MatrixError::Column { col: 0, width: 0 }));
+    }
+
+    #[test]
+    fn iterators_stop_at_the_edge() {
+        let m = Matrix::new(vec![[1.0, 2.0, 3.0, 4.0], [5.0, 6.0, 7.0, 8.0]]);
+        assert_eq!(m.row_iter(1).collect::<Vec<_>>(), vec![2.0, 6.0]);
+        assert_eq!(m.col_iter(1).collect::<Vec<_>>(), vec![5.0, 6.0, 7.0, 8.0]);
+        assert_eq!(m.row_iter(4).count(), 0);
+        assert_eq!(m.col_iter(2).count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Attempted to get column 2 of a matrix of width 2")]
+    fn col_panics_just_past_the_edge() {
+        Matrix::new(vec![[0.0; 4]; 2]).col(2);
+    }
+
+    /// `p` transformed by `m`
+    fn turned(m: &Matrix, p: [f64; 3]) -> [f64; 3] {
+        let mut points = Matrix::new(vec![[p[0], p[1], p[2], 1.0]]);
+        points.transform_by(m);
+        let q = points.col(0);
+        [q[0], q[1], q[2]]
+    }
+
+    fn assert_near(p: [f64; 3], q: [f64; 3]) {
+        assert!((0..3).all(|i| (p[i] - q[i]).abs() < 1e-9), "{:?} isn't {:?}", p, q);
+    }
+
+    #[test]
+    fn translations_move_points() {
+        assert_near(turned(&Matrix::translation(1.0, -2.0, 0.5), [4.0, 5.0, 6.0]), [5.0, 3.0, 6.5]);
+        assert_near(turned(&Matrix::translation(0.0, 0.0, 0.0), [4.0, 5.0, 6.0]), [4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn rotations_turn_counterclockwise_about_their_axes() {
+        let quarter = PI / 2.0;
+        assert_near(turned(&Matrix::rotation_about_z(quarter), [1.0, 0.0, 0.0]), [0.0, 1.0, 0.0]);
+        assert_near(turned(&Matrix::rotation_about_x(quarter), [0.0, 1.0, 0.0]), [0.0, 0.0, 1.0]);
+        assert_near(turned(&Matrix::rotation_about_y(quarter), [0.0, 0.0, 1.0]), [1.0, 0.0, 0.0]);
+        // Points on the axis stay put
+        assert_near(turned(&Matrix::rotation_about_z(1.3), [0.0, 0.0, 2.0]), [0.0, 0.0, 2.0]);
+        assert_near(turned(&Matrix::rotation_about_x(PI), [0.0, 3.0, 4.0]), [0.0, -3.0, -4.0]);
+    }
+
+    #[test]
+    fn rotations_about_an_axis_match_those_about_x_y_and_z() {
+        for &angle in &[0.0, 0.4, -2.0, PI] {
+            assert!(close(&Matrix::rotation_about_axis([0.0, 0.0, 1.0], angle).unwrap(), &Matrix::rotation_about_z(angle)));
+            assert!(close(&Matrix::rotation_about_axis([5.0, 0.0, 0.0], angle).unwrap(), &Matrix::rotation_about_x(angle)));
+            assert!(close(&Matrix::rotation_about_axis([0.0, 0.1, 0.0], angle).unwrap(), &Matrix::rotation_about_y(angle)));
+        }
+        assert!(Matrix::rotation_about_axis([0.0; 3], 1.0).is_none());
+    }
+
+    #[test]
+    fn rotations_about_a_diagonal_cycle_the_axes() {
+        // A third of a turn about (1, 1, 1) takes each axis to the next
+        let m = Matrix::rotation_about_axis([1.0, 1.0, 1.0], 2.0 * PI / 3.0).unwrap();
+        assert_near(turned(&m, [1.0, 0.0, 0.0]), [0.0, 1.0, 0.0]);
+        assert_near(turned(&m, [0.0, 1.0, 0.0]), [0.0, 0.0, 1.0]);
+        assert_near(turned(&m, [2.0, 2.0, 2.0]), [2.0, 2.0, 2.0]);
+        assert!((m.determinant() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn transpose_swaps_rows_and_columns() {
+        let m = Matrix::translation(1.0, 2.0, 3.0).transpose();
+        assert_eq!(m.row(3), vec![1.0, 2.0, 3.0, 1.0]);
+        assert_eq!(m.col(3), [0.0, 0.0, 0.0, 1.0]);
+    }
+
+    /// `v` transformed by `m` as a direction, with h = 0.
+    fn direction(m: &Matrix, v: [f64; 3]) -> [f64; 3] {
+        let entry = |row| m.get(row, 0) * v[0] + m.get(row, 1) * v[1] + m.get(row, 2) * v[2];
+        [entry(0), entry(1), entry(2)]
+    }
+
+    fn dot(u: [f64; 3], v: [f64; 3]) -> f64 {
+        u[0] * v[0] + u[1] * v[1] + u[2] * v[2]
+    }
+
+    #[test]
+    fn normal_matrix_keeps_normals_perpendicular() {
+        let m = &(&Matrix::translation(5.0, 0.0, 0.0) * &Matrix::rotation_about_z(0.4)) * &Matrix::dilation_xyz(4.0, 1.0, 0.5);
+        // A surface along (1, 1, 0), facing (1, -1, 0)
+        let tangent = direction(&m, [1.0, 1.0, 0.0]);
+        let normal = [1.0, -1.0, 0.0];
+        // Transformed like the surface, the normal is skewed by the scaling...
+        assert!(dot(direction(&m, normal), tangent).abs() > 1.0);
+        // ...but by the normal matrix it stays perpendicular to the surface
+        let normal_matrix = m.normal_matrix().unwrap();
+        assert!(dot(direction(&normal_matrix, normal), tangent).abs() < 1e-9);
+        // ...and isn't moved
+        assert_eq!(normal_matrix.col(3), [0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn flattening_matrices_have_no_normal_matrix() {
+        assert!(Matrix::dilation_xyz(1.0, 1.0, 0.0).normal_matrix().is_none());
+    }
+
+    /// The value and slope at `t` of the cubic whose coefficients `basis`
+    /// gives for the column `column` of a coordinate.
+    fn cubic(basis: &Matrix, column: [f64; 4], t: f64) -> (f64, f64) {
+        let c = basis * &Matrix::column_vector(column[0], column[1], column[2], column[3]);
+        let (a, b, c, d) = (c.get(0, 0), c.get(1, 0), c.get(2, 0), c.get(3, 0));
+        (a * t.powi(3) + b * t * t + c * t + d, 3.0 * a * t * t + 2.0 * b * t + c)
+    }
+
+    fn assert_ends(basis: &Matrix, column: [f64; 4], start: (f64, f64), end: (f64, f64)) {
+        let ((v0, s0), (v1, s1)) = (cubic(basis, column, 0.0), cubic(basis, column, 1.0));
+        assert!((v0 - start.0).abs() < 1e-9 && (s0 - start.1).abs() < 1e-9, "starts at {} with slope {}", v0, s0);
+        assert!((v1 - end.0).abs() < 1e-9 && (s1 - end.1).abs() < 1e-9, "ends at {} with slope {}", v1, s1);
+    }
+
+    #[test]
+    fn bezier_basis_runs_between_the_outer_points_towards_the_inner_ones() {
+        // Slopes are three times the distance to the next control point
+        assert_ends(&Matrix::bezier_basis(), [1.0, 2.0, 4.0, 8.0], (1.0, 3.0), (8.0, 12.0));
+    }
+
+    #[test]
+    fn hermite_basis_runs_between_the_ends_with_the_tangents() {
+        assert_ends(&Matrix::hermite_basis(), [1.0, 5.0, 2.0, -3.0], (1.0, 2.0), (5.0, -3.0));
+    }
+
+    #[test]
+    fn catmull_rom_basis_runs_between_the_inner_points() {
+        // Slopes are half the difference of the points either side
+        assert_ends(&Matrix::catmull_rom_basis(), [1.0, 2.0, 4.0, 8.0], (2.0, 1.5), (4.0, 3.0));
+    }
+
+    #[test]
+    fn b_spline_basis_runs_near_the_inner_points() {
+        // Each end is a sixth of the way from the middle of its neighbors
+        assert_ends(&Matrix::b_spline_basis(), [1.0, 2.0, 4.0, 8.0], (13.0 / 6.0, 1.5), (26.0 / 6.0, 3.0));
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{ self, BufWriter, Read, Write };
+
+use geometry::PolygonList;
+use error::{ Error, Result };
+
+/// Load the Wavefront OBJ model in `filename` as a list of triangles, with
+/// faces of more than three corners split into fans of triangles. Texture
+/// coordinates are kept. Vertex normals only decide which way each face
+/// faces, when it's wound the other way from them; the triangles are still
+/// shaded with normals blended across them as for any other shape.
+pub fn load_obj(filename: &str) -> Result<PolygonList> {
+    let mut text = String::new();
+    File::open(filename).and_then(|mut file| file.read_to_string(&mut text)).map_err(|e| Error::io(filename, e))?;
+    parse_obj(&text).map_err(|msg| Error::io(filename, io::Error::new(io::ErrorKind::InvalidData, msg)))
+}
+
+/// Save the triangles of `polygons` to `filename` as a Wavefront OBJ model,
+/// with their texture coordinates. Corners in the same place share a vertex.
+pub fn save_obj(polygons: &PolygonList, filename: &str) -> Result<()> {
+    let mut file = BufWriter::new(File::create(filename).map_err(|e| Error::io(filename, e))?);
+    write_obj(polygons, &mut file).and_then(|_| file.flush()).map_err(|e| Error::io(filename, e))
+}
+
+fn write_obj<W: Write>(polygons: &PolygonList, out: &mut W) -> io::Result<()> {
+    // The number (from 1) of each position and texture coordinate written
+    // so far, by their bits
+    let mut positions: HashMap<[u64; 3], usize> = HashMap::new();
+    let mut uvs: HashMap<[u64; 2], usize> = HashMap::new();
+    let mut faces = vec![];
+    for i in 0..polygons.len() {
+        let (corners, coords) = (polygons.triangle(i), polygons.uvs(i));
+        let mut face = [(0, 0); 3];
+        // Turned around to be counterclockwise from the front, as OBJ faces are
+        for (j, out_corner) in face.iter_mut().enumerate() {
+            let (p, uv) = (corners[2 - j], coords[2 - j]);
+            let key = [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()];
+            let position = match positions.get(&key) {
+                Some(&n) => n,
+                None => {
+                    writeln!(out, "v {} {} {}", p[0], p[1], p[2])?;
+                    positions.insert(key, positions.len() + 1);
+                    positions.len()
+                }
+            };
+            let key = [uv[0].to_bits(), uv[1].to_bits()];
+            let texture = match uvs.get(&key) {
+                Some(&n) => n,
+                None => {
+                    // OBJ counts v up from the bottom of a texture
+                    writeln!(out, "vt {} {}", uv[0], 1.0 - uv[1])?;
+                    uvs.insert(key, uvs.len() + 1);
+                    uvs.len()
+                }
+            };
+            *out_corner = (position, texture);
+        }
+        faces.push(face);
+    }
+    for face in faces {
+        writeln!(out, "f {}/{} {}/{} {}/{}", face[0].0, face[0].1, face[1].0, face[1].1, face[2].0, face[2].1)?;
+    }
+    Ok(())
+}
+
+/// Save the triangles of `polygons` to `filename` as a binary STL model, for
+/// slicers and the like, which don't keep texture coordinates.
+pub fn save_stl(polygons: &PolygonList, filename: &str) -> Result<()> {
+    let mut file = BufWriter::new(File::create(filename).map_err(|e| Error::io(filename, e))?);
+    write_stl(polygons, &mut file).and_then(|_| file.flush()).map_err(|e| Error::io(filename, e))
+}
+
+fn write_stl<W: Write>(polygons: &PolygonList, out: &mut W) -> io::Result<()> {
+    let mut header = [0u8; 80];
+    let title = b"Binary STL";
+    header[..title.len()].copy_from_slice(title);
+    out.write_all(&header)?;
+    out.write_all(&(polygons.len() as u32).to_le_bytes())?;
+    for i in 0..polygons.len() {
+        let [c, b, a] = polygons.triangle(i);
+        // Counterclockwise from the front, with the normal facing out
+        let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+        let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+        let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+        let normal = if length > 0.0 { [n[0] / length, n[1] / length, n[2] / length] } else { [0.0; 3] };
+        for &p in &[[normal[0], normal[1], normal[2], 0.0], a, b, c] {
+            for &coord in &p[..3] {
+                out.write_all(&(coord as f32).to_le_bytes())?;
+            }
+        }
+        // No attributes
+        out.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+/// A corner of a face: indices (from 0) of its position, and of its
+/// texture coordinates and normal if it has them
+type Corner = (usize, Option<usize>, Option<usize>);
+
+fn parse_obj(text: &str) -> ::std::result::Result<PolygonList, String> {
+    let mut positions: Vec<[f64; 4]> = vec![];
+    let mut uvs: Vec<[f64; 2]> = vec![];
+    let mut normals: Vec<[f64; 3]> = vec![];
+    let mut polygons = PolygonList::new();
+    for (i, line) in text.lines().enumerate() {
+        let at_line = |msg: String| format!("line {}: {}", i + 1, msg);
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line
+        };
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => {
+                let v = numbers(words, 3).map_err(&at_line)?;
+                positions.push([v[0], v[1], v[2], 1.0]);
+            },
+            Some("vt") => {
+                let vt = numbers(words, 2).map_err(&at_line)?;
+                // OBJ counts v up from the bottom of a texture
+                uvs.push([vt[0], 1.0 - vt[1]]);
+            },
+            Some("vn") => {
+                let vn = numbers(words, 3).map_err(&at_line)?;
+                normals.push([vn[0], vn[1], vn[2]]);
+            },
+            Some("f") => {
+                let corners = words
+                    .map(|word| corner(word, positions.len(), uvs.len(), normals.len()))
+                    .collect::<::std::result::Result<Vec<Corner>, String>>()
+                    .map_err(&at_line)?;
+                if corners.len() < 3 {
+                    return Err(at_line(String::from("a face needs at least three corners")));
+                }
+                for j in 1..corners.len() - 1 {
+                    add_face(&mut polygons, [corners[0], corners[j], corners[j + 1]], &positions, &uvs, &normals);
+                }
+            },
+            // Groups, materials, smoothing, lines, and so on don't change
+            // the triangles
+            _ => {}
+        }
+    }
+    Ok(polygons)
+}
+
+/// The first `n` numbers of `words`, ignoring any after them
+fn numbers<'a, I: Iterator<Item = &'a str>>(words: I, n: usize) -> ::std::result::Result<Vec<f64>, String> {
+    let numbers = words.take(n)
+        .map(|word| word.parse::<f64>().map_err(|_| format!("expected a number, found '{}'", word)))
+        .collect::<::std::result::Result<Vec<f64>, String>>()?;
+    if numbers.len() < n {
+        return Err(format!("expected {} numbers, found {}", n, numbers.len()));
+    }
+    Ok(numbers)
+}
+
+/// The corner `word` of a face, `V`, `V/T`, `V//N`, or `V/T/N`, with indices
+/// counting from 1, or back from the last one read if they're negative
+fn corner(word: &str, positions: usize, uvs: usize, normals: usize) -> ::std::result::Result<Corner, String> {
+    let mut parts = word.split('/');
+    let position = index(parts.next().unwrap_or(""), positions)?;
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(part) => Some(index(part, uvs)?)
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(part) => Some(index(part, normals)?)
+    };
+    Ok((position, uv, normal))
+}
+
+fn index(part: &str, count: usize) -> ::std::result::Result<usize, String> {
+    let i = part.parse::<i64>().map_err(|_| format!("expected an index, found '{}'", part))?;
+    let from_zero = if i < 0 { count as i64 + i } else { i - 1 };
+    if from_zero < 0 || from_zero >= count as i64 {
+        return Err(format!("index {} is out of range", i));
+    }
+    Ok(from_zero as usize)
+}
+
+/// Add the triangle with the corners `corners` to `polygons`. OBJ faces are
+/// counterclockwise as seen from the front, so they're turned around, unless
+/// their normals say the front is the other side.
+fn add_face(polygons: &mut PolygonList, corners: [Corner; 3], positions: &[[f64; 4]], uvs: &[[f64; 2]], normals: &[[f64; 3]]) {
+    let points = [positions[corners[0].0], positions[corners[1].0], positions[corners[2].0]];
+    let uv = |corner: Corner| corner.1.map_or([0.0; 2], |t| uvs[t]);
+    let coords = [uv(corners[0]), uv(corners[1]), uv(corners[2])];
+    let (a, b, c) = (points[0], points[1], points[2]);
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let facing = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    let mut along_normals = 0.0;
+    for &(_, _, normal) in &corners {
+        if let Some(n) = normal {
+            along_normals += facing[0] * normals[n][0] + facing[1] * normals[n][1] + facing[2] * normals[n][2];
+        }
+    }
+    if along_normals < 0.0 {
+        polygons.add_textured(points, coords);
+    } else {
+        polygons.add_textured([c, b, a], [coords[2], coords[1], coords[0]]);
+    }
+}
+
+/// `triangles` refined `levels` times by Loop subdivision, each time
+/// splitting every triangle into four and moving every point towards the
+/// points around it, so faceted shapes come out smooth. Triangles which
+/// share corners (by position) are treated as one surface, and its edges,
+/// where only one triangle meets another, stay where they are along their
+/// length, as do the corners of triangles on their own there. Texture
+/// coordinates are blended across each triangle. Normals for shading are
+/// found from the refined triangles, as for any other.
+pub fn subdivide(triangles: &PolygonList, levels: usize) -> PolygonList {
+    let mut refined = triangles.clone();
+    for _ in 0..levels {
+        refined = subdivide_once(&refined);
+    }
+    refined
+}
+
+fn subdivide_once(triangles: &PolygonList) -> PolygonList {
+    // Number each point, by its position rounded off, since the same point
+    // of a mesh can come out slightly differently for each triangle
+    let key = |p: [f64; 4]| ((p[0] * 1e6).round() as i64, (p[1] * 1e6).round() as i64, (p[2] * 1e6).round() as i64);
+    let mut numbers = HashMap::new();
+    let mut points: Vec<[f64; 4]> = vec![];
+    let mut faces: Vec<([usize; 3], [[f64; 2]; 3])> = vec![];
+    for i in 0..triangles.len() {
+        let mut face = [0; 3];
+        for (n, &p) in face.iter_mut().zip(triangles.triangle(i).iter()) {
+            *n = *numbers.entry(key(p)).or_insert_with(|| {
+                points.push(p);
+                points.len() - 1
+            });
+        }
+        // Triangles squashed to a line or a point, as at the poles of a
+        // sphere, have nothing to refine
+        if face[0] != face[1] && face[1] != face[2] && face[2] != face[0] {
+            faces.push((face, triangles.uvs(i)));
+        }
+    }
+    // The corners opposite each edge, in the triangles it's on
+    let edge = |a: usize, b: usize| if a < b { (a, b) } else { (b, a) };
+    let mut opposite: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for &(face, _) in &faces {
+        for j in 0..3 {
+            opposite.entry(edge(face[j], face[(j + 1) % 3])).or_default().push(face[(j + 2) % 3]);
+        }
+    }
+    // The points joined to each point, and the ones joined along the
+    // surface's own edges, and how many triangles each point is on
+    let mut faces_at = vec![0; points.len()];
+    for &(face, _) in &faces {
+        for &n in &face {
+            faces_at[n] += 1;
+        }
+    }
+    let mut neighbors = vec![vec![]; points.len()];
+    let mut edge_neighbors = vec![vec![]; points.len()];
+    for (&(a, b), across) in &opposite {
+        neighbors[a].push(b);
+        neighbors[b].push(a);
+        if across.len() != 2 {
+            edge_neighbors[a].push(b);
+            edge_neighbors[b].push(a);
+        }
+    }
+    let moved: Vec<[f64; 4]> = (0..points.len()).map(|i| {
+        let around = if edge_neighbors[i].is_empty() { &neighbors[i] } else { &edge_neighbors[i] };
+        let n = around.len();
+        let weight = if !edge_neighbors[i].is_empty() {
+            // Along the surface's edge, as a curve: only corners of it (where
+            // it doesn't just continue on, or turns around a lone triangle)
+            // stay put
+            if n == 2 && faces_at[i] > 1 { 1.0 / 8.0 } else { 0.0 }
+        } else if n == 3 {
+            3.0 / 16.0
+        } else {
+            3.0 / (8.0 * n as f64)
+        };
+        let mut p = scaled(points[i], 1.0 - n as f64 * weight);
+        for &j in around {
+            p = plus(p, scaled(points[j], weight));
+        }
+        p
+    }).collect();
+    let mut midpoints = HashMap::new();
+    for (&(a, b), across) in &opposite {
+        let midpoint = if across.len() == 2 {
+            plus(scaled(plus(points[a], points[b]), 3.0 / 8.0), scaled(plus(points[across[0]], points[across[1]]), 1.0 / 8.0))
+        } else {
+            scaled(plus(points[a], points[b]), 0.5)
+        };
+        midpoints.insert((a, b), midpoint);
+    }
+    let mut refined = PolygonList::new();
+    for &(face, uvs) in &faces {
+        let [a, b, c] = face;
+        let (ab, bc, ca) = (midpoints[&edge(a, b)], midpoints[&edge(b, c)], midpoints[&edge(c, a)]);
+        let half = |u: [f64; 2], v: [f64; 2]| [(u[0] + v[0]) / 2.0, (u[1] + v[1]) / 2.0];
+        let (uv_ab, uv_bc, uv_ca) = (half(uvs[0], uvs[1]), half(uvs[1], uvs[2]), half(uvs[2], uvs[0]));
+        // Each corner's triangle, then the one in the middle, all wound as
+        // the triangle was
+        refined.add_textured([moved[a], ab, ca], [uvs[0], uv_ab, uv_ca]);
+        refined.add_textured([ab, moved[b], bc], [uv_ab, uvs[1], uv_bc]);
+        refined.add_textured([ca, bc, moved[c]], [uv_ca, uv_bc, uvs[2]]);
+        refined.add_textured([ab, bc, ca], [uv_ab, uv_bc, uv_ca]);
+    }
+    refined
+}
+
+fn plus(p: [f64; 4], q: [f64; 4]) -> [f64; 4] {
+    [p[0] + q[0], p[1] + q[1], p[2] + q[2], 1.0]
+}
+
+fn scaled(p: [f64; 4], s: f64) -> [f64; 4] {
+    [p[0] * s, p[1] * s, p[2] * s, 1.0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solid;
+
+    /// The corners of a square in the z = 0 plane, and one more point to
+    /// the left of it, counterclockwise as seen from in front
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv -1 1 0\n";
+
+    fn point(x: f64, y: f64) -> [f64; 4] {
+        [x, y, 0.0, 1.0]
+    }
+
+    /// The problem with `text`, which must have one
+    fn error(text: &str) -> String {
+        match parse_obj(text) {
+            Err(msg) => msg,
+            Ok(polygons) => panic!("expected an error, found {} triangles", polygons.len())
+        }
+    }
+
+    fn triangles(polygons: &PolygonList) -> Vec<[[f64; 4]; 3]> {
+        (0..polygons.len()).map(|i| polygons.triangle(i)).collect()
+    }
+
+    #[test]
+    fn faces_are_split_into_fans_and_turned_around() {
+        let quad = parse_obj(&format!("{}f 1 2 3 4\n", SQUARE)).unwrap();
+        assert_eq!(triangles(&quad), vec![
+            [point(1.0, 1.0), point(1.0, 0.0), point(0.0, 0.0)],
+            [point(0.0, 1.0), point(1.0, 1.0), point(0.0, 0.0)]
+        ]);
+        let pentagon = parse_obj(&format!("{}f 1 2 3 4 5\n", SQUARE)).unwrap();
+        assert_eq!(pentagon.len(), 3);
+        assert_eq!(pentagon.triangle(2), [point(-1.0, 1.0), point(0.0, 1.0), point(0.0, 0.0)]);
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_point() {
+        let absolute = parse_obj("v 5 5 5\nv 0 0 0\nv 1 0 0\nv 1 1 0\nf 2 3 4\n").unwrap();
+        let relative = parse_obj("v 5 5 5\nv 0 0 0\nv 1 0 0\nv 1 1 0\nf -3 -2 -1\n").unwrap();
+        assert_eq!(triangles(&relative), triangles(&absolute));
+        // Only the points read before the face count
+        let early = parse_obj("v 0 0 0\nv 1 0 0\nv 1 1 0\nf -3 -2 -1\nv 9 9 9\n").unwrap();
+        assert_eq!(triangles(&early), triangles(&absolute));
+    }
+
+    #[test]
+    fn corners_can_skip_texture_coordinates() {
+        let polygons = parse_obj(&format!("{}vt 0.5 0.25\nvn 0 0 1\nf 1//1 2//1 3//1\nf 1/1 3/1/1 4\n", SQUARE)).unwrap();
+        assert_eq!(polygons.triangle(0), [point(1.0, 1.0), point(1.0, 0.0), point(0.0, 0.0)]);
+        assert_eq!(polygons.uvs(0), [[0.0; 2]; 3]);
+        // Turned around with the corners, and with v counted down
+        assert_eq!(polygons.uvs(1), [[0.0; 2], [0.5, 0.75], [0.5, 0.75]]);
+    }
+
+    #[test]
+    fn normals_pointing_away_keep_the_winding() {
+        let toward = parse_obj(&format!("{}vn 0 0 1\nf 1//1 2//1 3//1\n", SQUARE)).unwrap();
+        let away = parse_obj(&format!("{}vn 0 0 -1\nf 1//1 2//1 3//1\n", SQUARE)).unwrap();
+        assert_eq!(toward.triangle(0), [point(1.0, 1.0), point(1.0, 0.0), point(0.0, 0.0)]);
+        assert_eq!(away.triangle(0), [point(0.0, 0.0), point(1.0, 0.0), point(1.0, 1.0)]);
+    }
+
+    #[test]
+    fn bad_faces_are_reported_with_their_line() {
+        assert_eq!(error("v 0 0 0\n\nf 1 2 1\n"), "line 3: index 2 is out of range");
+        assert_eq!(error(&format!("{}f 1 -6 2\n", SQUARE)), "line 6: index -6 is out of range");
+        assert_eq!(error(&format!("{}f 1 0 2\n", SQUARE)), "line 6: index 0 is out of range");
+        assert_eq!(error(&format!("{}f 1/1 2 3\n", SQUARE)), "line 6: index 1 is out of range");
+        assert_eq!(error(&format!("{}f 1 2\n", SQUARE)), "line 6: a face needs at least three corners");
+        assert_eq!(error("v 0 x 0\n"), "line 1: expected a number, found 'x'");
+    }
+
+    #[test]
+    fn saved_models_load_the_same() {
+        let mut cube = PolygonList::new();
+        solid::add_box(&mut cube, -1.0, 2.0, 3.0, 4.0, 5.0, 6.0);
+        let mut obj = vec![];
+        write_obj(&cube, &mut obj).unwrap();
+        let text = String::from_utf8(obj).unwrap();
+        // Each corner of the box is written once
+        assert_eq!(text.lines().filter(|line| line.starts_with("v ")).count(), 8);
+        let loaded = parse_obj(&text).unwrap();
+        assert_eq!(triangles(&loaded), triangles(&cube));
+        assert_eq!((0..loaded.len()).map(|i| loaded.uvs(i)).collect::<Vec<_>>(), (0..cube.len()).map(|i| cube.uvs(i)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stl_models_have_a_record_per_triangle_facing_out() {
+        let mut cube = PolygonList::new();
+        // Reaching back from its front face, at z = 1, so its faces are
+        // wound clockwise as seen from outside
+        solid::add_box(&mut cube, -1.0, 1.0, 1.0, 2.0, 2.0, -2.0);
+        let mut stl = vec![];
+        write_stl(&cube, &mut stl).unwrap();
+        assert_eq!(stl.len(), 84 + 50 * 12);
+        assert_eq!(&stl[80..84], &12u32.to_le_bytes());
+        let float = |at: usize| f32::from_le_bytes([stl[at], stl[at + 1], stl[at + 2], stl[at + 3]]) as f64;
+        for i in 0..cube.len() {
+            let record = 84 + 50 * i;
+            let normal = [float(record), float(record + 4), float(record + 8)];
+            // From the middle of the box, at the origin, out to the triangle
+            let corners = cube.triangle(i);
+            let out: Vec<f64> = (0..3).map(|c| corners[0][c] + corners[1][c] + corners[2][c]).collect();
+            assert!(normal[0] * out[0] + normal[1] * out[1] + normal[2] * out[2] > 0.5, "{:?} for {:?}", normal, corners);
+        }
+    }
+
+    /// A tetrahedron, with its faces wound clockwise from outside
+    fn tetrahedron() -> PolygonList {
+        let p = [[1.0, 1.0, 1.0, 1.0], [1.0, -1.0, -1.0, 1.0], [-1.0, 1.0, -1.0, 1.0], [-1.0, -1.0, 1.0, 1.0]];
+        let mut triangles = PolygonList::new();
+        for &[a, b, c] in &[[0, 1, 2], [0, 2, 3], [0, 3, 1], [1, 3, 2]] {
+            triangles.add_triangle(p[a], p[c], p[b]);
+        }
+        triangles
+    }
+
+    /// The corners of every triangle of `triangles`
+    fn corners(triangles: &PolygonList) -> Vec<[f64; 4]> {
+        (0..triangles.len()).flat_map(|i| triangles.triangle(i).to_vec()).collect()
+    }
+
+    /// How far the farthest corner of `triangles` is from the origin
+    fn reach(triangles: &PolygonList) -> f64 {
+        corners(triangles).iter().map(|p| (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt()).fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn subdividing_splits_each_triangle_into_four() {
+        assert_eq!(subdivide(&tetrahedron(), 1).len(), 16);
+        assert_eq!(subdivide(&tetrahedron(), 2).len(), 64);
+        assert_eq!(subdivide(&tetrahedron(), 0).len(), 4);
+    }
+
+    #[test]
+    fn closed_meshes_shrink_towards_their_limit() {
+        let mut previous = reach(&tetrahedron());
+        for levels in 1..4 {
+            let refined = reach(&subdivide(&tetrahedron(), levels));
+            assert!(refined < previous, "{} levels reach {}, past {}", levels, refined, previous);
+            previous = refined;
+        }
+        // ...but not to nothing
+        assert!(previous > 0.3);
+    }
+
+    #[test]
+    fn lone_triangles_keep_their_corners() {
+        let ends = [[0.0, 0.0, 0.0, 1.0], [4.0, 0.0, 0.0, 1.0], [0.0, 4.0, 0.0, 1.0]];
+        let mut triangle = PolygonList::new();
+        triangle.add_triangle(ends[0], ends[1], ends[2]);
+        let refined = subdivide(&triangle, 2);
+        assert_eq!(refined.len(), 16);
+        let points = corners(&refined);
+        for end in &ends {
+            assert!(points.contains(end), "{:?} moved", end);
+        }
+        // Its edges stay straight
+        assert!(points.iter().all(|p| p[0] >= 0.0 && p[1] >= 0.0 && p[0] + p[1] <= 4.0 + 1e-9 && p[2] == 0.0));
+    }
+
+    #[test]
+    fn triangles_squashed_to_a_line_are_dropped() {
+        let mut triangles = tetrahedron();
+        // As at the pole of a sphere
+        triangles.add_triangle([1.0, 1.0, 1.0, 1.0], [1.0, 1.0, 1.0, 1.0], [1.0, -1.0, -1.0, 1.0]);
+        triangles.add_triangle([0.0; 4], [0.0; 4], [0.0; 4]);
+        // Off by less than rounding, as corners worked out for each triangle
+        // can be
+        triangles.add_triangle([-1.0, -1.0, 1.0, 1.0], [-1.0, -1.0, 1.0 + 1e-9, 1.0], [1.0, -1.0, -1.0, 1.0]);
+        assert_eq!(subdivide(&triangles, 1).len(), 16);
+    }
+}
@@ -1,5 +1,14 @@
-// TODO: Use Result instead of panics for error handling
-// The error handling here and in mod exec is a mess.
+use error::{ Error, ParseError, Result };
+use render::{ Blend, Rect, Stencil };
+use projection::Projection;
+use camera::Camera;
+use symbols::Constants;
+use easing::Easing;
+use lighting::{ self, Light };
+use texture::Filter;
+use plugin::{ Arg, CommandPlugin, Value };
+use std::sync::Arc;
+use std::cmp;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Axis {
@@ -14,25 +23,150 @@ pub struct Variation<'a> {
     pub fst_frame: usize,
     pub last_frame: usize,
     pub min_val: f64,
-    pub max_val: f64
+    pub max_val: f64,
+    pub easing: Easing
+}
+
+/// How an `if` compares a knob to a value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual
+}
+
+impl Comparison {
+    /// Whether `a` compares so to `b`
+    pub fn holds(self, a: f64, b: f64) -> bool {
+        match self {
+            Comparison::Less => a < b,
+            Comparison::LessEqual => a <= b,
+            Comparison::Greater => a > b,
+            Comparison::GreaterEqual => a >= b,
+            Comparison::Equal => a == b,
+            Comparison::NotEqual => a != b
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Condition<'a> {
+    pub knob: &'a str,
+    pub comparison: Comparison,
+    pub value: f64
 }
 
 #[derive(Debug)]
 pub enum Command<'a> {
+    /// Run the commands up to the matching `End` a number of times, setting
+    /// the knob to 0 the first time, 1 the next, and so on
+    Repeat(&'a str, usize),
+    /// Run the commands up to the matching `Else` or `End` if the condition
+    /// holds, or else those from the `Else` (if there is one) to the `End`
+    If(Condition<'a>),
+    Else,
+    /// The end of a `Repeat` or `If`
+    End,
     Push,
     Pop,
+    /// Save the top coordinate system by name
+    SaveCoords(&'a str),
+    /// Replace the top coordinate system with one saved by name
+    RestoreCoords(&'a str),
     Save(&'a str),
+    SaveDepth(&'a str),
+    /// Save the triangles drawn so far as a model, as OBJ or STL by the
+    /// file's extension
+    SaveMesh(&'a str),
     Display,
+    Blend(Blend),
+    /// Light the shapes after this with the ambient light of this color
+    Ambient([f64; 3]),
+    /// Add a light, replacing the default one. A named light replaces the
+    /// one of the same name, if there is one.
+    Light(Option<&'a str>, Light),
+    /// Draw the shapes after this as made of the material the named
+    /// constants describe, or of the default material
+    Material(Option<&'a str>),
+    /// Paint the filled shapes after this with the texture in the named
+    /// file, read with the filter, or with none
+    Texture(Option<(&'a str, Filter)>),
+    Stencil(Stencil),
+    /// Draw edges antialiased (true) or not (false)
+    Antialias(bool),
+    LineWidth(f64),
+    /// Dash edges with dashes and gaps of these lengths, or draw them solid
+    /// with `None`
+    Dash(Option<[f64; 2]>),
+    Viewport(Option<Rect>),
+    /// Draw the rectangle of world coordinates from (XMIN, YMIN) to (XMAX,
+    /// YMAX) over the whole image, or stop with `None`
+    Window(Option<[f64; 4]>),
+    /// Switch to a preset view, or back to the default with `None`
+    Projection(Option<Projection>),
+    /// See the shapes after this in perspective from a camera, or stop
+    Camera(Option<Camera>),
+    Scissor(Option<Rect>),
     Move { x: f64, y: f64, z: f64, knob: Option<&'a str> },
     Rotate(Axis, f64, Option<&'a str>),
+    /// Rotate by some degrees about the axis through the origin along a
+    /// vector, which isn't zero
+    RotateAbout([f64; 3], f64, Option<&'a str>),
     Scale { x: f64, y: f64, z: f64, knob: Option<&'a str> },
     Box { x: f64, y: f64, z: f64, w: f64, h: f64, d: f64 }, // TODO: add Option<...>s for cs and constants
     Sphere { x: f64, y: f64, z: f64, r: f64 },
     Torus { x: f64, y: f64, z: f64, r0: f64, r1: f64 },
+    Cylinder { x: f64, y: f64, z: f64, r: f64, h: f64 },
+    Cone { x: f64, y: f64, z: f64, r: f64, h: f64 },
     Line { x0: f64, y0: f64, z0: f64, x1: f64, y1: f64, z1: f64 },
+    /// A Bezier curve with these four control points
+    Bezier([[f64; 3]; 4]),
+    /// A Hermite curve between the first two points, with the last two as its
+    /// tangents there
+    Hermite([[f64; 3]; 4]),
+    /// A Catmull-Rom spline through each of these points in turn
+    Spline(Vec<[f64; 3]>),
+    /// A B-spline with these control points
+    BSpline(Vec<[f64; 3]>),
+    /// The surface swept around the y axis by the path through these points
+    /// (with z unused)
+    Revolve(Vec<[f64; 3]>),
+    /// The prism made by pushing the polygon with these corners (with z
+    /// unused) out from z = 0 to z = `depth`
+    Extrude { depth: f64, outline: Vec<[f64; 3]> },
+    /// Draw the model in an OBJ file
+    Mesh(&'a str),
+    /// Smooth the shapes after this by subdividing them this many times
+    Subdivide(usize),
+    Forward(f64, Option<&'a str>),
+    Turn(f64, Option<&'a str>),
+    Pitch(f64, Option<&'a str>),
+    Roll(f64, Option<&'a str>),
+    PenUp,
+    PenDown,
+    /// Switch to 2D coordinates (true) or back to 3D ones (false)
+    Mode2d(bool),
+    Rect { x: f64, y: f64, w: f64, h: f64 },
+    Circle { x: f64, y: f64, r: f64 },
+    Ellipse { x: f64, y: f64, rx: f64, ry: f64 },
+    /// Define lighting constants by name
+    Constants(&'a str, Constants),
+    /// Set a knob to a value
+    SetKnob(&'a str, f64),
+    /// Set every knob to a value
+    SetKnobs(f64),
+    /// Save the values of all the knobs as a knob list
+    SaveKnobs(&'a str),
+    /// Set the knobs to the values in a knob list
+    RestoreKnobs(&'a str),
     Frames(usize),
     Basename(&'a str),
-    Vary(Variation<'a>)
+    Vary(Variation<'a>),
+    /// A command registered by a library user, with its arguments
+    Plugin(Arc<CommandPlugin>, Vec<Value<'a>>)
 }
 
 //impl<'a> Ord for Command<'a> {
@@ -41,120 +175,570 @@ pub enum Command<'a> {
 //    }
 //}
 
-pub fn parse<'a>(script: &'a str) -> Result<Vec<Command<'a>>, &'static str> {
+impl<'a> Command<'a> {
+    /// Whether the command draws a shape.
+    pub fn is_shape(&self) -> bool {
+        matches!(*self,
+            Command::Line { .. } | Command::Bezier(..) | Command::Hermite(..) |
+            Command::Spline(..) | Command::BSpline(..) | Command::Box { .. } | Command::Sphere { .. } | Command::Torus { .. } |
+            Command::Cylinder { .. } | Command::Cone { .. } | Command::Revolve(..) |
+            Command::Extrude { .. } | Command::Mesh(..) |
+            Command::Forward(..) | Command::Rect { .. } | Command::Circle { .. } |
+            Command::Ellipse { .. } | Command::Plugin(..))
+    }
+}
+
+/// Parse `script`, which may also use the commands of `plugins`.
+pub fn parse<'a>(script: &'a str, plugins: &[Arc<CommandPlugin>]) -> Result<Vec<Command<'a>>> {
+    Ok(parse_numbered(script, plugins)?.into_iter().map(|(_, cmd)| cmd).collect())
+}
+
+/// Parse `script` like `parse`, pairing each command with the number of the
+/// line it's on (counting from 1).
+///
+/// A line which doesn't parse doesn't stop the rest of the script being
+/// parsed, so that every line with a problem is reported at once.
+pub fn parse_numbered<'a>(script: &'a str, plugins: &[Arc<CommandPlugin>]) -> Result<Vec<(usize, Command<'a>)>> {
     let mut cmds = vec![];
+    let mut errors = vec![];
+    // Whether the script has switched to 2D coordinates
+    let mut two_d = false;
+    // The line number and text of each `repeat` and `if` not yet ended, and
+    // whether each `if` has had its `else`
+    let mut blocks: Vec<(usize, &str, bool)> = vec![];
 
-    for mut line in script.lines() {
+    for (i, raw) in script.lines().enumerate() {
+        let raw = strip_comment(raw);
+        let mut line = raw;
         skip_linespace(&mut line);
         // Skip blank lines and comments
-        // TODO: handle comments at end of lines with commands
-        if line.chars().nth(0) == Some('#') || line.len() == 0 {
+        if line.is_empty() {
             continue;
         }
 
-        let command = match next_lexeme(&mut line)? {
-            "push" => Command::Push,
+        match parse_command(&mut line, two_d, plugins) {
+            Ok(command) => {
+                if let Command::Mode2d(on) = command {
+                    debug!("Line {} switches to {} coordinates", i + 1, if on { "2D" } else { "3D" });
+                    two_d = on;
+                }
+                match command {
+                    Command::Repeat(..) => blocks.push((i + 1, raw, true)),
+                    Command::If(..) => blocks.push((i + 1, raw, false)),
+                    Command::Else => match blocks.last_mut() {
+                        Some(&mut (_, _, ref mut has_else)) if !*has_else => *has_else = true,
+                        _ => errors.push(block_error(i + 1, raw, "'else' without an 'if'", plugins))
+                    },
+                    Command::End if blocks.pop().is_none() => {
+                        errors.push(block_error(i + 1, raw, "'end' without a 'repeat' or 'if'", plugins));
+                    },
+                    _ => {}
+                }
+                debug!("Parsed {:?}", command);
+                cmds.push((i + 1, command));
+            },
+            Err(msg) => errors.push(locate_error(i + 1, raw, line, msg, plugins))
+        }
+    }
+    for (line, raw, _) in blocks {
+        errors.push(block_error(line, raw, "Missing 'end' for this block", plugins));
+    }
+    errors.sort_by_key(|error| error.line);
+    if errors.is_empty() {
+        info!("Parsed {} commands", cmds.len());
+        Ok(cmds)
+    } else {
+        Err(Error::Parse(errors))
+    }
+}
 
-            "pop" => Command::Pop,
+/// `line` without the comment at its end, if it has one: from a `#`
+/// starting a word to the end of the line.
+fn strip_comment(line: &str) -> &str {
+    let mut word_start = true;
+    for (i, c) in line.char_indices() {
+        if c == '#' && word_start {
+            return &line[..i];
+        }
+        word_start = c.is_whitespace();
+    }
+    line
+}
 
-            "save" => {
-                let filename = next_lexeme(&mut line)?;
-                Command::Save(filename)
-            },
+/// The commands built into the parser, for suggesting what a misspelled one
+/// might have been
+const COMMANDS: &[&str] = &[
+    "push", "pop", "save_coord_system", "restore", "save", "save_depth", "save_mesh", "display", "blend",
+    "ambient", "light", "point_light", "material", "texture", "antialias", "linewidth", "dash", "viewport", "set_window", "projection", "camera", "scissor", "stencil", "move",
+    "rotate", "scale", "box", "sphere", "torus", "cylinder", "cone", "revolve", "extrude", "mesh", "subdivide", "forward", "turn", "pitch",
+    "roll", "penup", "pendown", "line", "bezier", "hermite", "spline", "bspline", "mode", "rect", "circle", "ellipse", "constants", "set", "setknobs",
+    "save_knobs", "restore_knobs", "frames", "basename", "vary", "repeat", "if", "else", "end"
+];
 
-            "display" => Command::Display,
+/// Make a `ParseError` for line `line`, `raw`, which failed to parse with
+/// `msg` when `rest` of it was left. The problem is taken to be with the
+/// last word read, or with the end of the line if that's what was reached.
+fn locate_error(line: usize, raw: &str, rest: &str, msg: String, plugins: &[Arc<CommandPlugin>]) -> ParseError {
+    let read = &raw[..raw.len() - rest.len()];
+    let (start, token) = if msg.ends_with("end of line") {
+        (raw.trim_end().len(), "")
+    } else {
+        let end = read.trim_end().len();
+        let start = read[..end].rfind(|c: char| c.is_whitespace()).map_or(0, |i| i + 1);
+        (start, &read[start..end])
+    };
+    // Only a command (the first word) can be misspelled into an unknown one
+    let suggestion = if msg.starts_with("Unknown command") {
+        let names = COMMANDS.iter().cloned().chain(plugins.iter().map(|plugin| &plugin.name[..]));
+        names.map(|name| (edit_distance(token, name), name))
+            .filter(|&(d, _)| d <= 2 && d < token.len())
+            .min()
+            .map(|(_, name)| String::from(name))
+    } else {
+        None
+    };
+    ParseError {
+        line,
+        column: raw[..start].chars().count() + 1,
+        token: String::from(token),
+        msg,
+        suggestion
+    }
+}
 
-            "move" => {
-                Command::Move {
-                    x: next_float(&mut line),
-                    y: next_float(&mut line),
-                    z: next_float(&mut line),
-                    knob: next_lexeme(&mut line).ok()
-                }
-            },
+/// Make a `ParseError` for the command on line `line`, `raw`, which begins or
+/// ends a block wrongly.
+fn block_error(line: usize, raw: &str, msg: &str, plugins: &[Arc<CommandPlugin>]) -> ParseError {
+    let start = raw.len() - raw.trim_start().len();
+    let end = raw[start..].find(char::is_whitespace).map_or(raw.len(), |i| start + i);
+    locate_error(line, raw, &raw[end..], String::from(msg), plugins)
+}
 
-            "rotate" => {
-                Command::Rotate(
-                    next_axis(&mut line),
-                    next_float(&mut line),
-                    next_lexeme(&mut line).ok())
-            },
+/// The number of characters which must be inserted, deleted, or replaced to
+/// turn `a` into `b` (their Levenshtein distance)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let replace = prev[j] + if ca == cb { 0 } else { 1 };
+            row.push(cmp::min(replace, cmp::min(prev[j + 1], row[j]) + 1));
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
 
-            "scale" => {
-                Command::Scale {
-                    x: next_float(&mut line),
-                    y: next_float(&mut line),
-                    z: next_float(&mut line),
-                    knob: next_lexeme(&mut line).ok()
-                }
-            },
+/// Parse the command on the (non-blank) line `line`, in 2D coordinates if
+/// `two_d` is true, and looking in `plugins` for commands not built in.
+/// What's left of `line` is where it stopped, if it fails.
+fn parse_command<'a>(line: &mut &'a str, two_d: bool, plugins: &[Arc<CommandPlugin>]) -> ::std::result::Result<Command<'a>, String> {
+    let command = parse_command_words(line, two_d, plugins)?;
+    if let Ok(word) = next_lexeme(line) {
+        return Err(format!("Unexpected argument '{}'", word));
+    }
+    Ok(command)
+}
 
-            "box" => {
-                Command::Box {
-                    x: next_float(&mut line),
-                    y: next_float(&mut line),
-                    z: next_float(&mut line),
-                    w: next_float(&mut line),
-                    h: next_float(&mut line),
-                    d: next_float(&mut line)
-                }
-            },
+/// Parse as much of `line` as its command takes, as `parse_command` does.
+fn parse_command_words<'a>(line: &mut &'a str, two_d: bool, plugins: &[Arc<CommandPlugin>]) -> ::std::result::Result<Command<'a>, String> {
+    let word = next_lexeme(line)?;
+    if two_d {
+        if let Some(command) = parse_2d_command(word, line)? {
+            return Ok(command);
+        }
+    }
+    let command = match word {
+        "push" => Command::Push,
+
+        "pop" => Command::Pop,
+
+        "save_coord_system" => Command::SaveCoords(next_lexeme(line)?),
+
+        "restore" => Command::RestoreCoords(next_lexeme(line)?),
+
+        "save" => {
+            let filename = next_lexeme(line)?;
+            Command::Save(filename)
+        },
+
+        "save_depth" => {
+            let filename = next_lexeme(line)?;
+            Command::SaveDepth(filename)
+        },
+
+        "save_mesh" => {
+            let filename = next_lexeme(line)?;
+            if !filename.ends_with(".obj") && !filename.ends_with(".stl") {
+                return Err(format!("Expected a file ending in .obj or .stl, found '{}'", filename));
+            }
+            Command::SaveMesh(filename)
+        },
+
+        "display" => Command::Display,
+
+        "blend" => {
+            let name = next_lexeme(line)?;
+            match Blend::from_name(name) {
+                Some(mode) => Command::Blend(mode),
+                None => return Err(format!("Expected blend mode (replace, add, multiply, screen, min, or max), found '{}'", name))
+            }
+        },
+
+        "ambient" => Command::Ambient(next_triple(line)?),
 
-            "sphere" => {
-                Command::Sphere {
-                    x: next_float(&mut line),
-                    y: next_float(&mut line),
-                    z: next_float(&mut line),
-                    r: next_float(&mut line),
+        "light" => {
+            let name = next_name(line);
+            let color = next_triple(line)?;
+            Command::Light(name, Light::Directional { direction: next_triple(line)?, color })
+        },
+
+        "point_light" => {
+            let name = next_name(line);
+            let color = next_triple(line)?;
+            let position = next_triple(line)?;
+            let mut rest = *line;
+            let attenuation = if next_lexeme(&mut rest).is_ok() {
+                next_triple(line)?
+            } else {
+                lighting::NO_FALLOFF
+            };
+            Command::Light(name, Light::Point { position, color, attenuation })
+        },
+
+        "material" => match next_lexeme(line)? {
+            "off" => Command::Material(None),
+            name => Command::Material(Some(name))
+        },
+
+        "texture" => match next_lexeme(line)? {
+            "off" => Command::Texture(None),
+            filename => {
+                let filter = match next_lexeme(line) {
+                    Ok(name) => Filter::from_name(name).ok_or_else(|| format!("Expected filter (nearest or bilinear), found '{}'", name))?,
+                    Err(_) => Filter::Bilinear
+                };
+                Command::Texture(Some((filename, filter)))
+            }
+        },
+
+        "antialias" => match next_lexeme(line) {
+            Ok("on") => Command::Antialias(true),
+            Ok("off") => Command::Antialias(false),
+            _ => return Err(String::from("Expected 'antialias on' or 'antialias off'"))
+        },
+
+        "subdivide" => {
+            let levels = next_usize(line)?;
+            // Each level makes four times as many triangles
+            if levels > 5 {
+                return Err(String::from("Shapes can be subdivided at most 5 times"));
+            }
+            Command::Subdivide(levels)
+        },
+
+        "linewidth" => {
+            let width = next_float(line)?;
+            if width <= 0.0 {
+                return Err(String::from("A line's width must be positive"));
+            }
+            Command::LineWidth(width)
+        },
+
+        "dash" => Command::Dash(next_dash(line)?),
+
+        "viewport" => Command::Viewport(next_rect(line)?),
+        "set_window" => {
+            let mut rest = *line;
+            if next_lexeme(&mut rest) == Ok("off") {
+                *line = rest;
+                Command::Window(None)
+            } else {
+                let (x0, y0, x1, y1) = (next_float(line)?, next_float(line)?, next_float(line)?, next_float(line)?);
+                if x1 <= x0 || y1 <= y0 {
+                    return Err(String::from("A window's XMAX and YMAX must be greater than its XMIN and YMIN"));
                 }
-            },
+                Command::Window(Some([x0, y0, x1, y1]))
+            }
+        },
+        "projection" => {
+            let name = next_lexeme(line)?;
+            match Projection::from_name(name) {
+                Some(projection) => Command::Projection(Some(projection)),
+                None if name == "off" => Command::Projection(None),
+                None => return Err(format!("Expected projection (isometric, dimetric, cabinet, or off), found '{}'", name))
+            }
+        },
 
-            "torus" => {
-                Command::Torus {
-                    x: next_float(&mut line),
-                    y: next_float(&mut line),
-                    z: next_float(&mut line),
-                    r0: next_float(&mut line),
-                    r1: next_float(&mut line),
+        "camera" => {
+            let mut rest = *line;
+            if next_lexeme(&mut rest) == Ok("off") {
+                *line = rest;
+                Command::Camera(None)
+            } else {
+                let camera = Camera::new(next_triple(line)?, next_triple(line)?);
+                let mut rest = *line;
+                let camera = if next_lexeme(&mut rest).is_ok() { camera.fov(next_float(line)?) } else { camera };
+                if !camera.is_valid() {
+                    return Err(String::from("A camera can't look at where it is or straight up or down, and its view must be between 0 and 180 degrees"));
                 }
-            },
+                Command::Camera(Some(camera))
+            }
+        },
+
+        "scissor" => Command::Scissor(next_rect(line)?),
+
+        "stencil" => {
+            match next_lexeme(line)? {
+                "off" => Command::Stencil(Stencil::Off),
+                "write" => Command::Stencil(Stencil::Write(next_stencil_value(line)?)),
+                "equal" => Command::Stencil(Stencil::Equal(next_stencil_value(line)?)),
+                "notequal" => Command::Stencil(Stencil::NotEqual(next_stencil_value(line)?)),
+                op => return Err(format!("Expected stencil operation (off, write, equal, or notequal), found '{}'", op))
+            }
+        },
+
+        "move" => {
+            Command::Move {
+                x: next_float(line)?,
+                y: next_float(line)?,
+                z: next_float(line)?,
+                knob: next_name(line)
+            }
+        },
 
-            "line" => {
-                Command::Line {
-                    x0: next_float(&mut line),
-                    y0: next_float(&mut line),
-                    z0: next_float(&mut line),
-                    x1: next_float(&mut line),
-                    y1: next_float(&mut line),
-                    z1: next_float(&mut line)
+        "rotate" => {
+            let mut peek = *line;
+            if next_float(&mut peek).is_ok() {
+                let axis = [next_float(line)?, next_float(line)?, next_float(line)?];
+                if axis == [0.0; 3] {
+                    return Err(String::from("Can't rotate about the zero vector"));
                 }
-            },
+                Command::RotateAbout(axis, next_float(line)?, next_name(line))
+            } else {
+                Command::Rotate(
+                    next_axis(line)?,
+                    next_float(line)?,
+                    next_name(line))
+            }
+        },
 
-            "frames" => Command::Frames(next_usize(&mut line)),
+        "scale" => {
+            Command::Scale {
+                x: next_float(line)?,
+                y: next_float(line)?,
+                z: next_float(line)?,
+                knob: next_name(line)
+            }
+        },
 
-            "basename" => Command::Basename(next_lexeme(&mut line)?),
+        "mesh" => {
+            // The file is written with a colon before it, as `mesh :model.obj`
+            let filename = next_lexeme(line)?;
+            Command::Mesh(filename.strip_prefix(':').unwrap_or(filename))
+        },
 
-            "vary" => {
-                Command::Vary(Variation {
-                    knob: next_lexeme(&mut line)?,
-                    fst_frame: next_usize(&mut line),
-                    last_frame: next_usize(&mut line),
-                    min_val: next_float(&mut line),
-                    max_val: next_float(&mut line)
-                })
-            },
+        "box" => {
+            Command::Box {
+                x: next_float(line)?,
+                y: next_float(line)?,
+                z: next_float(line)?,
+                w: next_float(line)?,
+                h: next_float(line)?,
+                d: next_float(line)?
+            }
+        },
 
-            other => {
-                panic!("Error! Unknown command '{}'!", other);
+        "sphere" => {
+            Command::Sphere {
+                x: next_float(line)?,
+                y: next_float(line)?,
+                z: next_float(line)?,
+                r: next_float(line)?,
             }
-        };
-        cmds.push(command);
-        // TODO: error on extra input
-    }
-    Ok(cmds)
+        },
+
+        "torus" => {
+            Command::Torus {
+                x: next_float(line)?,
+                y: next_float(line)?,
+                z: next_float(line)?,
+                r0: next_float(line)?,
+                r1: next_float(line)?,
+            }
+        },
+
+        "cylinder" | "cone" => {
+            let (x, y, z) = (next_float(line)?, next_float(line)?, next_float(line)?);
+            let (r, h) = (next_float(line)?, next_float(line)?);
+            if word == "cylinder" {
+                Command::Cylinder { x, y, z, r, h }
+            } else {
+                Command::Cone { x, y, z, r, h }
+            }
+        },
+
+        "revolve" => {
+            let profile = next_points(line, 2)?;
+            if profile.iter().any(|p| p[0] < 0.0) {
+                return Err(String::from("A profile to revolve can't go past the axis (to x below 0)"));
+            }
+            Command::Revolve(profile)
+        },
+
+        "extrude" => {
+            let depth = next_float(line)?;
+            let outline = next_points(line, 2)?;
+            if outline.len() < 3 {
+                return Err(format!("Expected at least three points to extrude, found {}", outline.len()));
+            }
+            Command::Extrude { depth, outline }
+        },
+
+        "forward" => Command::Forward(next_float(line)?, next_name(line)),
+
+        "turn" => Command::Turn(next_float(line)?, next_name(line)),
+
+        "pitch" => Command::Pitch(next_float(line)?, next_name(line)),
+
+        "roll" => Command::Roll(next_float(line)?, next_name(line)),
+
+        "penup" => Command::PenUp,
+
+        "pendown" => Command::PenDown,
+
+        "line" => {
+            Command::Line {
+                x0: next_float(line)?,
+                y0: next_float(line)?,
+                z0: next_float(line)?,
+                x1: next_float(line)?,
+                y1: next_float(line)?,
+                z1: next_float(line)?
+            }
+        },
+
+        "bezier" | "hermite" => {
+            let points = [next_triple(line)?, next_triple(line)?, next_triple(line)?, next_triple(line)?];
+            if word == "bezier" { Command::Bezier(points) } else { Command::Hermite(points) }
+        },
+
+        "spline" => Command::Spline(next_points(line, 3)?),
+
+        "bspline" => Command::BSpline(next_points(line, 3)?),
+
+        "repeat" => Command::Repeat(next_lexeme(line)?, next_usize(line)?),
+
+        "if" => Command::If(Condition {
+            knob: next_lexeme(line)?,
+            comparison: next_comparison(line)?,
+            value: next_float(line)?
+        }),
+
+        "else" => Command::Else,
+
+        "end" => Command::End,
+
+        "mode" => match next_lexeme(line) {
+            Ok("2d") => Command::Mode2d(true),
+            Ok("3d") => Command::Mode2d(false),
+            _ => return Err(String::from("Expected 'mode 2d' or 'mode 3d'"))
+        },
+
+        "rect" | "circle" | "ellipse" => {
+            return Err(format!("'{}' is only available in 2D mode (after 'mode 2d')", word));
+        },
+
+        "constants" => {
+            let name = next_lexeme(line)?;
+            // Ambient, diffuse, and specular for red, then green, then blue
+            let mut k = [[0.0; 3]; 3];
+            for i in 0..9 {
+                k[i % 3][i / 3] = next_float(line)?;
+            }
+            let intensity = match next_lexeme(line) {
+                Ok(r) => {
+                    let r = r.parse::<f64>().map_err(|_| format!("Expected floating point number, found '{}'", r))?;
+                    Some([r, next_float(line)?, next_float(line)?])
+                },
+                Err(_) => None
+            };
+            Command::Constants(name, Constants { ambient: k[0], diffuse: k[1], specular: k[2], intensity })
+        },
+
+        "set" => Command::SetKnob(next_lexeme(line)?, next_float(line)?),
+
+        "setknobs" => Command::SetKnobs(next_float(line)?),
+
+        "save_knobs" => Command::SaveKnobs(next_lexeme(line)?),
+
+        "restore_knobs" => Command::RestoreKnobs(next_lexeme(line)?),
+
+        "frames" => Command::Frames(next_usize(line)?),
+
+        "basename" => Command::Basename(next_lexeme(line)?),
+
+        "vary" => {
+            Command::Vary(Variation {
+                knob: next_lexeme(line)?,
+                fst_frame: next_usize(line)?,
+                last_frame: next_usize(line)?,
+                min_val: next_float(line)?,
+                max_val: next_float(line)?,
+                easing: next_easing(line)?
+            })
+        },
+
+        other => match plugins.iter().find(|plugin| plugin.name == other) {
+            Some(plugin) => {
+                let mut args = vec![];
+                for arg in &plugin.args {
+                    args.push(match *arg {
+                        Arg::Number => Value::Number(next_float(line)?),
+                        Arg::Word => Value::Word(next_lexeme(line)?)
+                    });
+                }
+                Command::Plugin(plugin.clone(), args)
+            },
+            None => return Err(format!("Unknown command '{}'", other))
+        }
+    };
+    Ok(command)
+}
+
+/// Parse the command `word`, the rest of whose line is `line`, if it takes 2D
+/// coordinates (which have y increasing downwards). Returns `None` for
+/// commands which are the same in 2D and 3D.
+fn parse_2d_command<'a>(word: &str, line: &mut &'a str) -> ::std::result::Result<Option<Command<'a>>, String> {
+    let command = match word {
+        "line" => Command::Line {
+            x0: next_float(line)?,
+            y0: next_float(line)?,
+            z0: 0.0,
+            x1: next_float(line)?,
+            y1: next_float(line)?,
+            z1: 0.0
+        },
+        "bezier" => Command::Bezier([next_point_2d(line)?, next_point_2d(line)?, next_point_2d(line)?, next_point_2d(line)?]),
+        "hermite" => Command::Hermite([next_point_2d(line)?, next_point_2d(line)?, next_point_2d(line)?, next_point_2d(line)?]),
+        "spline" => Command::Spline(next_points(line, 2)?),
+        "bspline" => Command::BSpline(next_points(line, 2)?),
+        "move" => Command::Move { x: next_float(line)?, y: next_float(line)?, z: 0.0, knob: next_name(line) },
+        "scale" => Command::Scale { x: next_float(line)?, y: next_float(line)?, z: 1.0, knob: next_name(line) },
+        "rotate" => Command::Rotate(Axis::Z, next_float(line)?, next_name(line)),
+        "rect" => Command::Rect { x: next_float(line)?, y: next_float(line)?, w: next_float(line)?, h: next_float(line)? },
+        "circle" => Command::Circle { x: next_float(line)?, y: next_float(line)?, r: next_float(line)? },
+        "ellipse" => Command::Ellipse { x: next_float(line)?, y: next_float(line)?, rx: next_float(line)?, ry: next_float(line)? },
+        "box" | "sphere" | "torus" | "pitch" | "roll" => {
+            return Err(format!("'{}' is only available in 3D mode (after 'mode 3d')", word));
+        },
+        _ => return Ok(None)
+    };
+    Ok(Some(command))
 }
 
-fn skip_linespace<'a, 'b>(src: &'b mut &'a str) {
+fn skip_linespace(src: &mut &str) {
     for (i, c) in src.char_indices() {
         // Plow through src until we hit a newline or non-linespace char
         if c == '\n' || (c != ' ' && c != '\t') {
@@ -166,11 +750,10 @@ fn skip_linespace<'a, 'b>(src: &'b mut &'a str) {
     *src = ""; // no input after whitespace
 }
 
-fn next_lexeme<'a, 'b>(src: &'b mut &'a str) -> Result<&'a str, &'static str> {
+fn next_lexeme<'a>(src: &mut &'a str) -> ::std::result::Result<&'a str, String> {
     skip_linespace(src);
-    if src.len() == 0 {
-        // TODO: take metadata as a parameter to have better error messages
-        Err("Unexpected end of line in script!")
+    if src.is_empty() {
+        Err(String::from("Unexpected end of line"))
     } else {
         for (i, c) in src.char_indices() {
             if c.is_whitespace() {
@@ -186,35 +769,244 @@ fn next_lexeme<'a, 'b>(src: &'b mut &'a str) -> Result<&'a str, &'static str> {
     }
 }
 
-fn next_usize(srcref: &mut &str) -> usize {
-    if let Ok(lexeme) = next_lexeme(srcref) {
-        match lexeme.parse::<usize>() {
-            Ok(x) => x,
-            Err(_) => panic!("Error! Expected floating point number, found {}", lexeme)
-        }
-    } else {
-        panic!("Error! Expected floating point number, found end of line");
+fn next_usize(srcref: &mut &str) -> ::std::result::Result<usize, String> {
+    match next_lexeme(srcref) {
+        Ok(lexeme) => lexeme.parse::<usize>().map_err(|_| {
+            format!("Expected a whole number, found '{}'", lexeme)
+        }),
+        Err(_) => Err(String::from("Expected a whole number, found end of line"))
     }
 }
 
-fn next_float(srcref: &mut &str) -> f64 {
-    if let Ok(lexeme) = next_lexeme(srcref) {
-        match lexeme.parse::<f64>() {
-            Ok(x) => x,
-            Err(_) => panic!("Error! Expected floating point number, found {}", lexeme)
-        }
-    } else {
-        panic!("Error! Expected floating point number, found end of line");
+fn next_float(srcref: &mut &str) -> ::std::result::Result<f64, String> {
+    match next_lexeme(srcref) {
+        Ok(lexeme) => lexeme.parse::<f64>().map_err(|_| {
+            format!("Expected floating point number, found '{}'", lexeme)
+        }),
+        Err(_) => Err(String::from("Expected floating point number, found end of line"))
+    }
+}
+
+fn next_stencil_value(srcref: &mut &str) -> ::std::result::Result<u8, String> {
+    match next_lexeme(srcref) {
+        Ok(lexeme) => lexeme.parse::<u8>().map_err(|_| {
+            format!("Expected a stencil value from 0 to 255, found '{}'", lexeme)
+        }),
+        Err(_) => Err(String::from("Expected a stencil value from 0 to 255, found end of line"))
+    }
+}
+
+/// Parse a rectangle given as `X Y WIDTH HEIGHT`, or `off` for none.
+fn next_rect(srcref: &mut &str) -> ::std::result::Result<Option<Rect>, String> {
+    let x = match next_lexeme(srcref) {
+        Ok("off") => return Ok(None),
+        Ok(lexeme) => lexeme.parse::<f64>().map_err(|_| {
+            format!("Expected a rectangle (X Y WIDTH HEIGHT) or 'off', found '{}'", lexeme)
+        })?,
+        Err(_) => return Err(String::from("Expected a rectangle (X Y WIDTH HEIGHT) or 'off', found end of line"))
+    };
+    let (y, width, height) = (next_float(srcref)?, next_float(srcref)?, next_float(srcref)?);
+    if width <= 0.0 || height <= 0.0 {
+        return Err(String::from("A rectangle's width and height must be positive"));
+    }
+    Ok(Some(Rect { x: x as i64, y: y as i64, width: width as i64, height: height as i64 }))
+}
+
+fn next_dash(srcref: &mut &str) -> ::std::result::Result<Option<[f64; 2]>, String> {
+    let dash = match next_lexeme(srcref) {
+        Ok("off") => return Ok(None),
+        Ok(lexeme) => lexeme.parse::<f64>().map_err(|_| {
+            format!("Expected dash and gap lengths (DASH GAP) or 'off', found '{}'", lexeme)
+        })?,
+        Err(_) => return Err(String::from("Expected dash and gap lengths (DASH GAP) or 'off', found end of line"))
+    };
+    let gap = next_float(srcref)?;
+    if dash <= 0.0 || gap < 0.0 {
+        return Err(String::from("A dash's length must be positive, and its gap's can't be negative"));
+    }
+    Ok(Some([dash, gap]))
+}
+
+/// The easing named next, if any, or else `Easing::Linear`
+fn next_easing(srcref: &mut &str) -> ::std::result::Result<Easing, String> {
+    match next_lexeme(srcref) {
+        Ok(name) => Easing::from_name(name).ok_or_else(|| format!(
+            "Expected easing (linear, ease_in, ease_out, cubic, sine, or bounce), found '{}'", name)),
+        Err(_) => Ok(Easing::Linear)
+    }
+}
+
+/// The next word, if it's a name (such as a knob's) rather than a number
+fn next_name<'a>(srcref: &mut &'a str) -> Option<&'a str> {
+    let mut rest = *srcref;
+    match next_lexeme(&mut rest) {
+        Ok(word) if word.parse::<f64>().is_err() => {
+            *srcref = rest;
+            Some(word)
+        },
+        _ => None
+    }
+}
+
+/// The next three numbers, as a color (from 0 to 255), point, or direction
+fn next_triple(srcref: &mut &str) -> ::std::result::Result<[f64; 3], String> {
+    Ok([next_float(srcref)?, next_float(srcref)?, next_float(srcref)?])
+}
+
+/// The next two numbers, as a point (or direction) in the plane z = 0
+fn next_point_2d(srcref: &mut &str) -> ::std::result::Result<[f64; 3], String> {
+    Ok([next_float(srcref)?, next_float(srcref)?, 0.0])
+}
+
+/// The rest of the line, as at least two points of `dims` (2 or 3)
+/// coordinates each, with z = 0 if there are only two
+fn next_points(srcref: &mut &str, dims: usize) -> ::std::result::Result<Vec<[f64; 3]>, String> {
+    let mut numbers = vec![];
+    while !srcref.trim().is_empty() {
+        numbers.push(next_float(srcref)?);
+    }
+    if numbers.len() < 2 * dims || numbers.len() % dims != 0 {
+        return Err(format!("Expected at least two points of {} numbers each, found {} numbers", dims, numbers.len()));
+    }
+    Ok(numbers.chunks(dims).map(|p| [p[0], p[1], if dims == 3 { p[2] } else { 0.0 }]).collect())
+}
+
+fn next_comparison(srcref: &mut &str) -> ::std::result::Result<Comparison, String> {
+    match next_lexeme(srcref) {
+        Ok("<") => Ok(Comparison::Less),
+        Ok("<=") => Ok(Comparison::LessEqual),
+        Ok(">") => Ok(Comparison::Greater),
+        Ok(">=") => Ok(Comparison::GreaterEqual),
+        Ok("==") => Ok(Comparison::Equal),
+        Ok("!=") => Ok(Comparison::NotEqual),
+        Ok(word) => Err(format!("Expected comparison (<, <=, >, >=, ==, or !=), found '{}'", word)),
+        Err(_) => Err(String::from("Expected comparison (<, <=, >, >=, ==, or !=), found end of line"))
+    }
+}
+
+fn next_axis(srcref: &mut &str) -> ::std::result::Result<Axis, String> {
+    match next_lexeme(srcref) {
+        Ok("x") => Ok(Axis::X),
+        Ok("y") => Ok(Axis::Y),
+        Ok("z") => Ok(Axis::Z),
+        Ok(word) => Err(format!("Expected axis (x, y, or z), found '{}'", word)),
+        Err(_) => Err(String::from("Expected axis (x, y, or z), found end of line"))
     }
 }
 
-fn next_axis(srcref: &mut &str) -> Axis {
-    let lexeme = next_lexeme(srcref);
-    match lexeme {
-        Ok("x") => Axis::X,
-        Ok("y") => Axis::Y,
-        Ok("z") => Axis::Z,
-        Ok(word) => panic!("Error! Expected floating point number, found {}", word),
-        Err(_) => panic!("Error! Expected floating points number, found end of line")
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The problems with `script`, which must have some
+    fn errors(script: &str) -> Vec<ParseError> {
+        match parse(script, &[]) {
+            Err(Error::Parse(errors)) => errors,
+            Err(e) => panic!("expected parse errors, found {}", e),
+            Ok(cmds) => panic!("expected parse errors, found {:?}", cmds)
+        }
+    }
+
+    /// The line, column, and token of `error`
+    fn place(error: &ParseError) -> (usize, usize, &str) {
+        (error.line, error.column, &error.token[..])
+    }
+
+    #[test]
+    fn unknown_commands_are_reported_with_a_suggestion() {
+        let errors = errors("push\n  sphre 0 0 0 10\nfrobnicate 1");
+        assert_eq!(errors.len(), 2);
+        assert_eq!(place(&errors[0]), (2, 3, "sphre"));
+        assert_eq!(errors[0].suggestion, Some(String::from("sphere")));
+        // ...unless nothing is close to it
+        assert_eq!(place(&errors[1]), (3, 1, "frobnicate"));
+        assert_eq!(errors[1].suggestion, None);
+    }
+
+    #[test]
+    fn missing_arguments_are_reported_at_the_end_of_the_line() {
+        let errors = errors("sphere 0 0 0 ");
+        assert_eq!(place(&errors[0]), (1, 13, ""));
+        assert!(errors[0].msg.ends_with("found end of line"));
+    }
+
+    #[test]
+    fn extra_arguments_are_reported() {
+        let errors = errors("move 1 2 3 4 5\nline 0 0 0 1 1 1 extra\nmove 1 2 3 k 5\npush pop");
+        assert_eq!(errors.len(), 4);
+        // A number can't be a knob
+        assert_eq!(place(&errors[0]), (1, 12, "4"));
+        assert_eq!(errors[0].msg, "Unexpected argument '4'");
+        assert_eq!(place(&errors[1]), (2, 18, "extra"));
+        assert_eq!(place(&errors[2]), (3, 14, "5"));
+        assert_eq!(place(&errors[3]), (4, 6, "pop"));
+    }
+
+    #[test]
+    fn rotations_take_an_axis_name_or_vector() {
+        match &parse("rotate y 30 k\nrotate 1 1 0 45", &[]).unwrap()[..] {
+            [Command::Rotate(Axis::Y, a, Some("k")), Command::RotateAbout(v, b, None)] => {
+                assert_eq!((*a, *v, *b), (30.0, [1.0, 1.0, 0.0], 45.0));
+            },
+            cmds => panic!("parsed {:?}", cmds)
+        }
+        let errors = errors("rotate 0 0 0 45\nrotate w 45");
+        assert_eq!(errors[0].msg, "Can't rotate about the zero vector");
+        assert_eq!(errors[1].msg, "Expected axis (x, y, or z), found 'w'");
+    }
+
+    #[test]
+    fn profiles_to_revolve_stay_right_of_the_axis() {
+        match &parse("revolve 0 10 5 10 5 0", &[]).unwrap()[..] {
+            [Command::Revolve(profile)] => assert_eq!(profile, &vec![[0.0, 10.0, 0.0], [5.0, 10.0, 0.0], [5.0, 0.0, 0.0]]),
+            cmds => panic!("parsed {:?}", cmds)
+        }
+        let errors = errors("revolve 5 10 -5 0\nrevolve 5 10");
+        assert_eq!(errors[0].msg, "A profile to revolve can't go past the axis (to x below 0)");
+        assert_eq!(errors[1].msg, "Expected at least two points of 2 numbers each, found 2 numbers");
+    }
+
+    #[test]
+    fn outlines_to_extrude_have_three_points() {
+        match &parse("extrude -5 0 0 10 0 0 10", &[]).unwrap()[..] {
+            [Command::Extrude { depth, outline }] => {
+                assert_eq!(*depth, -5.0);
+                assert_eq!(outline, &vec![[0.0, 0.0, 0.0], [10.0, 0.0, 0.0], [0.0, 10.0, 0.0]]);
+            },
+            cmds => panic!("parsed {:?}", cmds)
+        }
+        let errors = errors("extrude 5 0 0 10 0\nextrude 0 0 1");
+        assert_eq!(errors[0].msg, "Expected at least three points to extrude, found 2");
+        assert_eq!(errors[1].msg, "Expected at least two points of 2 numbers each, found 2 numbers");
+    }
+
+    #[test]
+    fn bad_numbers_are_reported_and_the_rest_still_parsed() {
+        let errors = errors("move 1 x 3\nsphere 0 0 0 10\nscale 1 1 1.5.2");
+        assert_eq!(errors.len(), 2);
+        assert_eq!(place(&errors[0]), (1, 8, "x"));
+        assert_eq!(errors[0].msg, "Expected floating point number, found 'x'");
+        assert_eq!(place(&errors[1]), (3, 11, "1.5.2"));
+    }
+
+    #[test]
+    fn mismatched_blocks_are_reported() {
+        let errors = errors("end\nrepeat i 3\nif i < 2\nelse\nelse\nend");
+        assert_eq!(errors.len(), 3);
+        assert_eq!(place(&errors[0]), (1, 1, "end"));
+        assert_eq!(errors[0].msg, "'end' without a 'repeat' or 'if'");
+        assert_eq!(place(&errors[1]), (2, 1, "repeat"));
+        assert_eq!(errors[1].msg, "Missing 'end' for this block");
+        assert_eq!(place(&errors[2]), (5, 1, "else"));
+    }
+
+    #[test]
+    fn comments_can_follow_commands() {
+        let cmds = parse("# A ball\nsphere 0 0 0 10 # in the middle\nsave a#b.png", &[]).unwrap();
+        assert_eq!(cmds.len(), 2);
+        match cmds[1] {
+            Command::Save(name) => assert_eq!(name, "a#b.png"),
+            ref cmd => panic!("expected a save, found {:?}", cmd)
+        }
     }
 }
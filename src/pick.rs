@@ -0,0 +1,83 @@
+use config::RenderConfig;
+use error::{ Error, Result };
+use exec;
+use parse;
+use ray::{ Bvh, Ray };
+use std::f64;
+
+/// The shape which drew a pixel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Picked {
+    /// The number of the shape (from 1) in the order the script draws them
+    pub object: u32,
+    /// The line of the script which draws it (counting from 1), and the text
+    /// of that line
+    pub line: usize,
+    pub source: String,
+    /// The point of the shape seen at the center of the pixel, if the pixel
+    /// was drawn by a polygon found there (rather than an edge, say)
+    pub point: Option<[f64; 3]>
+}
+
+/// Render the still image drawn by `script`, as `exec::render_headless`
+/// does, and find the shape which drew the pixel in column `x` and row `y`
+/// (counting from the top left corner). Returns `None` for the background,
+/// and an error if the pixel is outside the image.
+pub fn pick(script: &str, config: &RenderConfig, x: usize, y: usize) -> Result<Option<Picked>> {
+    let (screen, scene, shapes) = exec::render_still(script, &config.clone().ids(true))?;
+    if x >= screen.width() || y >= screen.height() {
+        return Err(Error::Bounds(format!("Pixel {},{} is outside the {} by {} image", x, y, screen.width(), screen.height())));
+    }
+    let object = match screen.pick(x, y) {
+        Some(object) => object,
+        None => return Ok(None)
+    };
+    // Shapes are numbered in the order their commands run, which loops and
+    // conditionals can make different from the order of the script
+    let cmds = parse::parse_numbered(script, &config.plugins)?;
+    let line = shapes.get(object as usize - 1).map(|&i| cmds[i].0);
+    // Look for the point by casting a ray from in front of the scene
+    let point = scene.polygons.bounds().and_then(|bounds| {
+        Ray::through_pixel(x, y, screen.height(), bounds.aabb.max[2] + 1.0)
+    }).and_then(|ray| {
+        Bvh::new(&scene.polygons).cast(&ray, &scene.polygons, f64::INFINITY)
+    }).and_then(|hit| {
+        if scene.polygon_styles[hit.triangle].object == object { Some(hit.point) } else { None }
+    });
+    Ok(line.map(|line| Picked {
+        object,
+        line,
+        source: String::from(script.lines().nth(line - 1).unwrap_or("").trim()),
+        point
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use render::Shading;
+
+    fn config() -> RenderConfig {
+        RenderConfig::new().resolution(100, 80).shading(Shading::Filled)
+    }
+
+    #[test]
+    fn picks_the_line_drawing_the_pixel() {
+        let script = "box 10 70 0 30 30 30\n\nbox 60 70 0 30 30 30\n";
+        let picked = pick(script, &config(), 70, 20).unwrap().unwrap();
+        assert_eq!((picked.object, picked.line, picked.source.as_str()), (2, 3, "box 60 70 0 30 30 30"));
+        assert_eq!(pick(script, &config(), 45, 20).unwrap(), None);
+    }
+
+    #[test]
+    fn pixels_outside_the_image_are_errors() {
+        let script = "box 10 70 0 30 30 30\n";
+        assert_eq!(pick(script, &config(), 99, 79).unwrap(), None);
+        for &(x, y) in &[(100, 0), (0, 80), (5000, 5000)] {
+            match pick(script, &config(), x, y) {
+                Err(Error::Bounds(msg)) => assert!(msg.contains("outside the 100 by 80 image"), "{}", msg),
+                other => panic!("{},{} gave {:?}", x, y, other)
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+
+use error::{ Error, Result };
+use exec::Context;
+use geometry::{ EdgeList, PolygonList };
+use drawable::Drawable;
+
+/// The kind of each argument a plugin command takes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arg {
+    /// A floating point number
+    Number,
+    /// A single word, such as a name
+    Word
+}
+
+/// An argument given to a plugin command, of the kind its `Arg` says
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<'a> {
+    Number(f64),
+    Word(&'a str)
+}
+
+impl<'a> Value<'a> {
+    /// The number, or 0 for a word (which the signature rules out).
+    pub fn number(&self) -> f64 {
+        match *self {
+            Value::Number(n) => n,
+            Value::Word(_) => 0.0
+        }
+    }
+
+    /// The word, or "" for a number (which the signature rules out).
+    pub fn word(&self) -> &'a str {
+        match *self {
+            Value::Word(w) => w,
+            Value::Number(_) => ""
+        }
+    }
+}
+
+/// A script command added by a library user, without changing the parser.
+/// Register one with `RenderConfig::command`. Scripts then use it like a
+/// built-in command: its name followed by its arguments. When it runs, the
+/// handler gets the arguments, checked against the signature, and a
+/// `Context` with the scene, coordinate system stack, and screen. Built-in
+/// commands of the same name take precedence.
+///
+/// Each run of a plugin command counts as one shape for picking, however
+/// many times it draws.
+pub struct CommandPlugin {
+    pub name: String,
+    pub args: Vec<Arg>,
+    handler: Box<Handler>
+}
+
+/// What runs a plugin command
+type Handler = dyn Fn(&[Value], &mut Context) -> Result<()> + Send + Sync;
+
+impl CommandPlugin {
+    pub fn new<F>(name: &str, args: Vec<Arg>, handler: F) -> CommandPlugin
+        where F: Fn(&[Value], &mut Context) -> Result<()> + Send + Sync + 'static
+    {
+        CommandPlugin { name: String::from(name), args, handler: Box::new(handler) }
+    }
+
+    pub fn run(&self, args: &[Value], context: &mut Context) -> Result<()> {
+        (self.handler)(args, context)
+    }
+}
+
+impl fmt::Debug for CommandPlugin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CommandPlugin({})", self.name)
+    }
+}
+
+/// An example plugin, registered by the command-line program: `plot FUNCTION
+/// X0 X1 SCALE` graphs `y = SCALE * f(x / SCALE)` from `x = X0` to `X1` in the
+/// current coordinate system, where `f` is `sin`, `cos`, `sqrt`, or `exp`.
+pub fn plot() -> CommandPlugin {
+    CommandPlugin::new("plot", vec![Arg::Word, Arg::Number, Arg::Number, Arg::Number], |args, context| {
+        let f: fn(f64) -> f64 = match args[0].word() {
+            "sin" => f64::sin,
+            "cos" => f64::cos,
+            "sqrt" => f64::sqrt,
+            "exp" => f64::exp,
+            other => return Err(Error::Script(format!("Can't plot unknown function '{}'", other)))
+        };
+        let (x0, x1, scale) = (args[1].number(), args[2].number(), args[3].number());
+        if scale == 0.0 {
+            return Err(Error::Math(String::from("Can't plot at a scale of 0")));
+        }
+        // A point per pixel, or per step of at most 1
+        let steps = (x1 - x0).abs().ceil().max(1.0) as usize;
+        let points = (0..steps + 1).map(|i| {
+            let x = x0 + (x1 - x0) * i as f64 / steps as f64;
+            [x, scale * f(x / scale), 0.0, 1.0]
+        }).collect();
+        context.draw(&Polyline { points });
+        Ok(())
+    })
+}
+
+/// Line segments joining each point to the next
+struct Polyline {
+    points: Vec<[f64; 4]>
+}
+
+impl Drawable for Polyline {
+    fn emit(&self, edges: &mut EdgeList, _polys: &mut PolygonList) {
+        for pair in self.points.windows(2) {
+            // sqrt of negative x gives NaN; leave those out
+            if pair.iter().all(|p| p[1].is_finite()) {
+                edges.add_edge(pair[0], pair[1]);
+            }
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::io::{ self, Write };
+
+use render::Color;
+
+/// Write `image` as a PNG. The image data is stored uncompressed (in stored
+/// deflate blocks), which keeps the encoder tiny at the cost of file size.
+pub fn write<W: Write>(out: &mut W, image: &[Vec<Color>]) -> io::Result<()> {
+    let height = image.len();
+    let width = if height > 0 { image[0].len() } else { 0 };
+
+    out.write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'])?;
+
+    let mut ihdr = vec![];
+    push_u32(&mut ihdr, width as u32);
+    push_u32(&mut ihdr, height as u32);
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit RGB, no interlacing
+    write_chunk(out, b"IHDR", &ihdr)?;
+
+    // Each scanline is preceded by its filter type (0, none)
+    let mut raw = Vec::with_capacity(height * (1 + 3 * width));
+    for row in image {
+        raw.push(0);
+        for px in row {
+            raw.extend_from_slice(&[px.r, px.g, px.b]);
+        }
+    }
+    write_chunk(out, b"IDAT", &zlib_stored(&raw))?;
+
+    write_chunk(out, b"IEND", &[])
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut len = vec![];
+    push_u32(&mut len, data.len() as u32);
+    out.write_all(&len)?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+    let mut sum = vec![];
+    push_u32(&mut sum, crc.finish());
+    out.write_all(&sum)
+}
+
+/// Wrap `data` in a zlib stream without compressing it.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut z = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        z.extend_from_slice(&[1, 0, 0, 0xff, 0xff]); // a single, empty, final block
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        z.push(if last { 1 } else { 0 });
+        z.extend_from_slice(&[len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8]);
+        z.extend_from_slice(block);
+    }
+    push_u32(&mut z, adler32(data));
+    z
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// Append `x` to `v` in big-endian order.
+fn push_u32(v: &mut Vec<u8>, x: u32) {
+    v.extend_from_slice(&[(x >> 24) as u8, (x >> 16) as u8, (x >> 8) as u8, x as u8]);
+}
+
+/// CRC-32 as used by PNG chunks.
+struct Crc32 {
+    table: [u32; 256],
+    crc: u32
+}
+
+impl Crc32 {
+    fn new() -> Crc32 {
+        let mut table = [0u32; 256];
+        for (n, entry) in table.iter_mut().enumerate() {
+            let mut c = n as u32;
+            for _ in 0..8 {
+                c = if c & 1 == 1 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            }
+            *entry = c;
+        }
+        Crc32 { table, crc: 0xffffffff }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.crc = self.table[((self.crc ^ byte as u32) & 0xff) as usize] ^ (self.crc >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.crc ^ 0xffffffff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An image of `width` by `height` pixels, each a different color
+    fn pattern(width: usize, height: usize) -> Vec<Vec<Color>> {
+        (0..height).map(|y| (0..width).map(|x| Color::rgb(x as u8, y as u8, (x * 7 + y * 13) as u8)).collect()).collect()
+    }
+
+    fn u32_at(bytes: &[u8], i: usize) -> u32 {
+        (bytes[i] as u32) << 24 | (bytes[i + 1] as u32) << 16 | (bytes[i + 2] as u32) << 8 | bytes[i + 3] as u32
+    }
+
+    /// CRC-32 a bit at a time, rather than with `Crc32`'s table
+    fn slow_crc(data: &[u8]) -> u32 {
+        let mut crc = 0xffffffffu32;
+        for &byte in data {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+            }
+        }
+        !crc
+    }
+
+    /// The (type, data) of each chunk of `png`, checking its signature and
+    /// each chunk's CRC
+    fn chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']);
+        let mut chunks = vec![];
+        let mut pos = 8;
+        while pos < png.len() {
+            let len = u32_at(png, pos) as usize;
+            let (kind, data) = (&png[pos + 4..pos + 8], &png[pos + 8..pos + 8 + len]);
+            assert_eq!(u32_at(png, pos + 8 + len), slow_crc(&png[pos + 4..pos + 8 + len]));
+            chunks.push((String::from_utf8(kind.to_vec()).unwrap(), data.to_vec()));
+            pos += 12 + len;
+        }
+        chunks
+    }
+
+    /// Inflate the zlib stream `z`, which may only have stored blocks,
+    /// checking its header and Adler-32 checksum.
+    fn inflate_stored(z: &[u8]) -> Vec<u8> {
+        assert_eq!((z[0] as u32 * 256 + z[1] as u32) % 31, 0);
+        let mut data = vec![];
+        let mut pos = 2;
+        loop {
+            let header = z[pos];
+            assert_eq!(header & 0b110, 0, "only stored blocks are expected");
+            let len = z[pos + 1] as usize | (z[pos + 2] as usize) << 8;
+            let nlen = z[pos + 3] as usize | (z[pos + 4] as usize) << 8;
+            assert_eq!(len ^ 0xffff, nlen);
+            data.extend_from_slice(&z[pos + 5..pos + 5 + len]);
+            pos += 5 + len;
+            if header & 1 == 1 {
+                break;
+            }
+        }
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in &data {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(u32_at(z, pos), b << 16 | a);
+        assert_eq!(pos + 4, z.len());
+        data
+    }
+
+    /// Decode `png`, an 8-bit RGB image with unfiltered scanlines.
+    fn decode(png: &[u8]) -> Vec<Vec<Color>> {
+        let chunks = chunks(png);
+        let kinds: Vec<_> = chunks.iter().map(|c| &c.0[..]).collect();
+        assert_eq!(kinds, ["IHDR", "IDAT", "IEND"]);
+        let ihdr = &chunks[0].1;
+        let (width, height) = (u32_at(ihdr, 0) as usize, u32_at(ihdr, 4) as usize);
+        assert_eq!(&ihdr[8..], &[8, 2, 0, 0, 0]);
+        let raw = inflate_stored(&chunks[1].1);
+        assert_eq!(raw.len(), height * (1 + 3 * width));
+        raw.chunks(1 + 3 * width).map(|line| {
+            assert_eq!(line[0], 0);
+            line[1..].chunks(3).map(|px| Color::rgb(px[0], px[1], px[2])).collect()
+        }).collect()
+    }
+
+    fn round_trip(image: &[Vec<Color>]) -> Vec<Vec<Color>> {
+        let mut png = vec![];
+        write(&mut png, image).unwrap();
+        decode(&png)
+    }
+
+    #[test]
+    fn crc_matches_the_standard_check_value() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.finish(), 0xcbf43926);
+    }
+
+    #[test]
+    fn small_images_decode_to_themselves() {
+        let image = pattern(3, 2);
+        assert!(round_trip(&image) == image);
+    }
+
+    #[test]
+    fn images_over_a_block_long_decode_to_themselves() {
+        // 200 * 120 pixels take 72,120 bytes, over the 65,535 of a block
+        let image = pattern(200, 120);
+        assert!(round_trip(&image) == image);
+    }
+
+    #[test]
+    fn empty_images_have_an_empty_final_block() {
+        let mut png = vec![];
+        write(&mut png, &[]).unwrap();
+        assert!(decode(&png).is_empty());
+    }
+}
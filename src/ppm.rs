@@ -1,78 +1,335 @@
-use std::error::Error;
 use std::path::Path;
 use std::fs::File;
 use std::io::prelude::*;
-use std::process::Command;
+use std::io::{ self, BufWriter, IoSlice };
+use std::process::{ self, Command };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::fs;
 
 use render::Color;
-use consts::*;
-
-pub fn save_ppm(image: &Vec<Vec<Color>>, filename: &str) {
-    let path = Path::new(filename);
-    let path_display = path.display(); // For safe string formatting
-    let mut file = match File::create(&path) {
-        Err(reason) => {
-            panic!("could not create {}. Error: {}",
-                   path_display,
-                   reason.description())
+use png;
+use bmp;
+use tga;
+use gif;
+use error::{ Error, Result };
+
+/// The two kinds of PPM: plain, with each channel written out in decimal,
+/// and raw, with a byte for each
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PpmFormat {
+    /// Plain (ASCII), which can be read and diffed as text
+    P3,
+    /// Raw (binary), about a quarter of the size and much faster to write
+    P6
+}
+
+/// Save `image` to `filename` as a PPM of the kind `format`.
+pub fn save_ppm(image: &[Vec<Color>], filename: &str, format: PpmFormat) -> Result<()> {
+    let mut file = File::create(filename).map_err(|e| Error::io(filename, e))?;
+    let width = if !image.is_empty() { image[0].len() } else { 0 };
+    let (magic, body) = match format {
+        PpmFormat::P3 => ("P3", format_image(image).into_bytes()),
+        PpmFormat::P6 => ("P6", raw_image(image))
+    };
+    let header = format!("{}\n{} {} 255\n", magic, width, image.len());
+    write_all_vectored(&mut file, &mut [IoSlice::new(header.as_bytes()), IoSlice::new(&body)])
+        .map_err(|e| Error::io(filename, e))
+}
+
+/// Read the PPM (plain or raw, with a maximum value of at most 255) in
+/// `filename`.
+pub fn load_ppm(filename: &str) -> Result<Vec<Vec<Color>>> {
+    let mut bytes = vec![];
+    File::open(filename).and_then(|mut file| file.read_to_end(&mut bytes)).map_err(|e| Error::io(filename, e))?;
+    parse_ppm(&bytes).map_err(|msg| Error::io(filename, io::Error::new(io::ErrorKind::InvalidData, msg)))
+}
+
+/// Read the PPM (as `load_ppm` does) in `bytes`.
+pub fn parse_ppm(bytes: &[u8]) -> ::std::result::Result<Vec<Vec<Color>>, String> {
+    let mut pos = 0;
+    let magic = ppm_token(bytes, &mut pos);
+    let raw = match magic {
+        b"P3" => false,
+        b"P6" => true,
+        _ => return Err(String::from("not a PPM"))
+    };
+    let mut header = [0; 3];
+    for n in header.iter_mut() {
+        *n = ppm_number(bytes, &mut pos)?;
+    }
+    let (width, height, max) = (header[0], header[1], header[2]);
+    if max == 0 || max > 255 {
+        return Err(format!("unsupported maximum value {}", max));
+    }
+    // A single whitespace byte separates the header from raw pixel data
+    pos += 1;
+    let mut image = vec![vec![Color::black(); width]; height];
+    for row in image.iter_mut() {
+        for px in row.iter_mut() {
+            let mut rgb = [0; 3];
+            for c in rgb.iter_mut() {
+                *c = if raw {
+                    let b = *bytes.get(pos).ok_or("pixel data ends early")?;
+                    pos += 1;
+                    b as usize
+                } else {
+                    ppm_number(bytes, &mut pos)?
+                };
+                if *c > max {
+                    return Err(format!("value {} is over the maximum of {}", c, max));
+                }
+            }
+            *px = Color::rgb((rgb[0] * 255 / max) as u8, (rgb[1] * 255 / max) as u8, (rgb[2] * 255 / max) as u8);
+        }
+    }
+    Ok(image)
+}
+
+/// Get the next whitespace-separated token of a PPM header, skipping comments.
+fn ppm_token<'a>(bytes: &'a [u8], pos: &mut usize) -> &'a [u8] {
+    loop {
+        while *pos < bytes.len() && (bytes[*pos] as char).is_whitespace() {
+            *pos += 1;
+        }
+        if *pos < bytes.len() && bytes[*pos] == b'#' {
+            while *pos < bytes.len() && bytes[*pos] != b'\n' {
+                *pos += 1;
+            }
+        } else {
+            break;
+        }
+    }
+    let start = *pos;
+    while *pos < bytes.len() && !(bytes[*pos] as char).is_whitespace() {
+        *pos += 1;
+    }
+    &bytes[start..*pos]
+}
+
+fn ppm_number(bytes: &[u8], pos: &mut usize) -> ::std::result::Result<usize, String> {
+    let token = ppm_token(bytes, pos);
+    String::from_utf8_lossy(token).parse::<usize>().map_err(|_| {
+        if token.is_empty() {
+            String::from("file ends early")
+        } else {
+            format!("expected a number, found '{}'", String::from_utf8_lossy(token))
+        }
+    })
+}
+
+/// Write all of `bufs` to `file`, with as few system calls as possible.
+fn write_all_vectored(file: &mut File, mut bufs: &mut [IoSlice]) -> io::Result<()> {
+    while !bufs.is_empty() {
+        match file.write_vectored(bufs) {
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write whole image")),
+            Ok(n) => IoSlice::advance_slices(&mut bufs, n),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e)
+        }
+    }
+    Ok(())
+}
+
+/// Image file formats that can be written without any external tools
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// A plain PPM
+    Ppm,
+    /// A raw PPM, with the same extension
+    RawPpm,
+    Png,
+    Bmp,
+    Tga,
+    Gif
+}
+
+impl Format {
+    /// The format called `name` (e.g. "png"), ignoring case.
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name.to_lowercase().as_str() {
+            "ppm" | "p3" => Some(Format::Ppm),
+            "p6" => Some(Format::RawPpm),
+            "png" => Some(Format::Png),
+            "bmp" => Some(Format::Bmp),
+            "tga" => Some(Format::Tga),
+            "gif" => Some(Format::Gif),
+            _ => None
+        }
+    }
+
+    /// The format named by the extension of `path`, if it has one this can
+    /// write. A `.ppm` is taken to be plain.
+    pub fn from_path(path: &str) -> Option<Format> {
+        Path::new(path).extension().and_then(|ext| Format::from_name(&ext.to_string_lossy()))
+    }
+
+    /// The extension of files in this format.
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Format::Ppm | Format::RawPpm => "ppm",
+            Format::Png => "png",
+            Format::Bmp => "bmp",
+            Format::Tga => "tga",
+            Format::Gif => "gif"
         }
-        Ok(file) => file,
+    }
+}
+
+/// Save `image` to `filename` in `format`.
+pub fn save_as(image: &[Vec<Color>], filename: &str, format: Format) -> Result<()> {
+    match format {
+        Format::Ppm => return save_ppm(image, filename, PpmFormat::P3),
+        Format::RawPpm => return save_ppm(image, filename, PpmFormat::P6),
+        _ => {}
+    }
+    let mut file = BufWriter::new(File::create(filename).map_err(|e| Error::io(filename, e))?);
+    let result = match format {
+        Format::Png => png::write(&mut file, image),
+        Format::Bmp => bmp::write(&mut file, image),
+        Format::Tga => tga::write(&mut file, image),
+        Format::Gif => gif::write(&mut file, image),
+        Format::Ppm | Format::RawPpm => unreachable!()
+    };
+    result.and_then(|_| file.flush()).map_err(|e| Error::io(filename, e))
+}
+
+/// Save `image` to `filename`, choosing the format by its extension. Files
+/// without an extension are saved in `default`, and those with an extension
+/// of some other format are converted to it by ImageMagick's `convert`, or,
+/// where that isn't installed, saved as a PPM beside it instead.
+pub fn save(image: &[Vec<Color>], filename: &str, default: Format) -> Result<()> {
+    if Path::new(filename).extension().is_none() {
+        return save_as(image, filename, default);
+    }
+    match Format::from_path(filename) {
+        // A .ppm is raw if `default` is
+        Some(format) if format.extension() == default.extension() => save_as(image, filename, default),
+        Some(format) => save_as(image, filename, format),
+        None => save_converted(image, filename)
+    }
+}
+
+/// Save `image` to `filename` by converting a PPM with `convert`. If it
+/// can't be run, the PPM is saved with the extension `.ppm` instead.
+fn save_converted(image: &[Vec<Color>], filename: &str) -> Result<()> {
+    let temp = temp_path("ppm");
+    save_ppm(image, &temp, PpmFormat::P6)?;
+    let result = match run("convert", Command::new("convert").arg(&temp).arg(filename)) {
+        Err(Error::Io { ref err, .. }) if err.kind() == io::ErrorKind::NotFound => {
+            let fallback = Path::new(filename).with_extension("ppm").to_string_lossy().into_owned();
+            warn!("could not find `convert` to save {}, so saving {} instead", filename, fallback);
+            fs::copy(&temp, &fallback).map(|_| ()).map_err(|e| Error::io(&fallback, e))
+        },
+        result => result
     };
-    write_header(&mut file, WIDTH, HEIGHT);
-    write_image(&mut file, &image);
+    remove_temp(&temp);
+    result
+}
+
+pub fn display_file(filename: &str) -> Result<()> {
+    run("display", Command::new("display").arg(filename))
 }
 
-pub fn save_png(image: &Vec<Vec<Color>>, filename: &str) {
-    save_ppm(image, ".temp.ppm");
-    let status0 = Command::new("convert")
-        .arg(".temp.ppm")
-        .arg(filename)
-        .status().ok().unwrap();
+/// Play the images in `filenames` as an animation.
+pub fn animate_files(filenames: &[String]) -> Result<()> {
+    run("animate", Command::new("animate").arg("-delay").arg("4").args(filenames))
 }
 
-pub fn clean_up() {
-    let status1 = Command::new("rm")
-        .arg(".temp.ppm")
-        .status().ok().unwrap();
-    println!("Execution of `rm .temp.ppm` exited with status: {}", status1);
+pub fn display_image(image: &[Vec<Color>]) -> Result<()> {
+    let temp = temp_path("png");
+    save_as(image, &temp, Format::Png)?;
+    let result = display_file(&temp);
+    remove_temp(&temp);
+    result
 }
 
-#[allow(dead_code)]
-pub fn display_file(filename: &str) {
-    let status = Command::new("display")
-        .arg(filename)
-        .status().ok().unwrap();
-    println!("Execution of `display {}` exited with status: {}", filename, status);
+/// Run the external program `name` with `command`, failing if it can't be
+/// started or exits unsuccessfully.
+fn run(name: &str, command: &mut Command) -> Result<()> {
+    let status = command.status().map_err(|e| Error::io(name, e))?;
+    debug!("Execution of `{}` exited with status: {}", name, status);
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::io(name, io::Error::other(format!("exited with {}", status))))
+    }
 }
 
-pub fn display_image(image: &Vec<Vec<Color>>) {
-    save_png(image, ".temp.png");
-    let status0 = Command::new("display")
-        .arg(".temp.png")
-        .status().ok().unwrap();
-    println!("Execution of `display .temp.png` exited with status: {}", status0);
-    let status1 = Command::new("rm")
-        .arg(".temp.png")
-        .status().ok().unwrap();
-    println!("Execution of `rm .temp.ppm` exited with status: {}", status1);
+static TEMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Make a name for a temporary file with extension `ext`. Names are unique
+/// within the process, so scripts rendering on different threads don't clobber
+/// each other's temporary files.
+fn temp_path(ext: &str) -> String {
+    let n = TEMP_COUNT.fetch_add(1, Ordering::Relaxed);
+    format!(".temp-{}-{}.{}", process::id(), n, ext)
 }
 
-pub fn write_header(file: &mut File, width: usize, height: usize) {
-    if let Err(reason) = write!(file, "P3\n{} {} 255\n", width, height) {
-        panic!("could not write header to file. Error: {}",
-               reason.description());
+fn remove_temp(path: &str) {
+    if let Err(e) = fs::remove_file(path) {
+        warn!("could not remove temporary file {}: {}", path, e);
     }
 }
 
-pub fn write_image(file: &mut File, image: &Vec<Vec<Color>>) {
-    let mut contents = String::with_capacity(image.len() * image[0].len());
-    for px in 0..WIDTH {
-        for py in 0..HEIGHT {
-            contents.push_str(&image[px][py].fmt_ppm());
+/// Format the pixels of `image` as the body of a P3 PPM.
+fn format_image(image: &[Vec<Color>]) -> String {
+    let mut contents = String::with_capacity(12 * image.len() * image[0].len());
+    for row in image {
+        for px in row {
+            contents.push_str(&px.fmt_ppm());
         }
     }
-    if let Err(reason) = file.write_all(contents.as_bytes()) {
-        panic!("could not write image to file. Error: {}",
-               reason.description());
+    contents
+}
+
+/// The pixels of `image` as the body of a P6 PPM: three bytes for each.
+fn raw_image(image: &[Vec<Color>]) -> Vec<u8> {
+    let mut contents = Vec::with_capacity(3 * image.len() * image.first().map_or(0, |row| row.len()));
+    for px in image.iter().flat_map(|row| row.iter()) {
+        contents.extend_from_slice(&[px.r, px.g, px.b]);
+    }
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn image() -> Vec<Vec<Color>> {
+        vec![
+            vec![Color::rgb(1, 2, 3), Color::rgb(4, 5, 6), Color::rgb(255, 0, 128)],
+            vec![Color::rgb(10, 11, 12), Color::rgb(13, 14, 15), Color::rgb(16, 17, 18)]
+        ]
+    }
+
+    /// Save `image()` as a PPM of the kind `format`, and read the file back.
+    fn saved(format: PpmFormat) -> Vec<u8> {
+        let path = env::temp_dir().join(format!("cstack-ppm-{:?}-{}.ppm", format, process::id()));
+        let path = path.to_string_lossy();
+        save_ppm(&image(), &path, format).unwrap();
+        let bytes = fs::read(&*path).unwrap();
+        assert!(load_ppm(&path).unwrap() == image());
+        fs::remove_file(&*path).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn p6_is_a_header_then_a_byte_a_channel() {
+        let bytes = saved(PpmFormat::P6);
+        assert_eq!(&bytes[..11], b"P6\n3 2 255\n");
+        assert_eq!(&bytes[11..], &[1, 2, 3, 4, 5, 6, 255, 0, 128, 10, 11, 12, 13, 14, 15, 16, 17, 18]);
+    }
+
+    #[test]
+    fn p3_reads_back_the_same_as_p6() {
+        let bytes = saved(PpmFormat::P3);
+        assert!(bytes.starts_with(b"P3\n3 2 255\n"));
+        assert!(parse_ppm(&bytes).unwrap() == parse_ppm(&saved(PpmFormat::P6)).unwrap());
+    }
+
+    #[test]
+    fn truncated_p6_is_an_error() {
+        assert_eq!(parse_ppm(b"P6\n2 1 255\n\x01\x02\x03\x04"), Err(String::from("pixel data ends early")));
     }
 }
@@ -0,0 +1,130 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{ Duration, Instant };
+
+/// How far along a render is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Frames finished, of `frames` (1 for a still image)
+    pub frames_done: usize,
+    pub frames: usize,
+    /// Rows of a still image rasterized, of `rows`. Animations are only
+    /// tracked by frame, so these are 0 for them.
+    pub rows_done: usize,
+    pub rows: usize,
+    /// How much longer the render should take, once anything is done
+    pub eta: Option<Duration>
+}
+
+impl Progress {
+    /// The fraction of the render done, from 0 to 1.
+    pub fn fraction(&self) -> f64 {
+        if self.frames == 0 {
+            return 1.0;
+        }
+        let rows = if self.rows > 0 { self.rows_done as f64 / self.rows as f64 } else { 0.0 };
+        ((self.frames_done as f64 + rows) / self.frames as f64).min(1.0)
+    }
+}
+
+/// Follows the progress of a render, telling a callback each time it
+/// advances. Share one between threads (and `RenderConfig`s) in an `Arc`.
+/// The callback may be called from any of them, but only one at a time, and
+/// must not call the `Tracker` itself.
+pub struct Tracker {
+    callback: Box<dyn Fn(Progress) + Send + Sync>,
+    state: Mutex<(Progress, Instant)>
+}
+
+impl Tracker {
+    pub fn new<F: Fn(Progress) + Send + Sync + 'static>(callback: F) -> Tracker {
+        Tracker {
+            callback: Box::new(callback),
+            state: Mutex::new((Progress { frames_done: 0, frames: 0, rows_done: 0, rows: 0, eta: None }, Instant::now()))
+        }
+    }
+
+    /// Start following a render of `frames` frames.
+    pub fn start(&self, frames: usize) {
+        self.update(|p, start| {
+            *p = Progress { frames_done: 0, frames, rows_done: 0, rows: 0, eta: None };
+            *start = Instant::now();
+            true
+        });
+    }
+
+    /// Note that a frame has been finished.
+    pub fn frame_done(&self) {
+        self.update(|p, _| {
+            p.frames_done += 1;
+            p.rows_done = 0;
+            p.rows = 0;
+            true
+        });
+    }
+
+    /// Note that rasterizing an image of `rows` rows has begun. This is
+    /// ignored when rendering an animation.
+    pub fn start_rows(&self, rows: usize) {
+        self.update(|p, _| {
+            if p.frames > 1 {
+                return false;
+            }
+            p.rows_done = 0;
+            p.rows = rows;
+            true
+        });
+    }
+
+    /// Note that `n` more rows of the image have been rasterized.
+    pub fn rows_done(&self, n: usize) {
+        self.update(|p, _| {
+            if p.rows == 0 {
+                return false;
+            }
+            p.rows_done += n;
+            true
+        });
+    }
+
+    /// Change the progress with `f`, update the ETA, and tell the callback,
+    /// unless `f` returns false for no change.
+    fn update<F: FnOnce(&mut Progress, &mut Instant) -> bool>(&self, f: F) {
+        let mut state = self.state.lock().unwrap();
+        let (ref mut p, ref mut start) = *state;
+        if !f(p, start) {
+            return;
+        }
+        let done = p.fraction();
+        p.eta = if done > 0.0 {
+            Some(start.elapsed().mul_f64((1.0 - done) / done))
+        } else {
+            None
+        };
+        (self.callback)(*p);
+    }
+}
+
+impl fmt::Debug for Tracker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Tracker({:?})", self.state.lock().unwrap().0)
+    }
+}
+
+/// Make a one-line progress bar showing `p`, e.g.
+///
+///     [#########           ]  45%  9/20 frames, about 3s left
+pub fn bar(p: Progress) -> String {
+    const WIDTH: usize = 20;
+    let filled = (p.fraction() * WIDTH as f64) as usize;
+    let mut s = format!("[{}{}] {:>3}%", "#".repeat(filled), " ".repeat(WIDTH - filled), (p.fraction() * 100.0) as usize);
+    if p.frames > 1 {
+        s.push_str(&format!("  {}/{} frames", p.frames_done, p.frames));
+    } else if p.rows > 0 {
+        s.push_str(&format!("  {}/{} rows", p.rows_done, p.rows));
+    }
+    if let Some(eta) = p.eta {
+        s.push_str(&format!(", about {}s left", eta.as_secs()));
+    }
+    s
+}
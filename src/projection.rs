@@ -0,0 +1,54 @@
+use matrix::Matrix;
+use transform::Transform;
+
+/// A preset view for technical illustrations, turning or shearing the scene
+/// so that three of its faces show at once. Depth is kept, so hidden
+/// surfaces are still removed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// The x, y, and z axes are equally foreshortened and 120° apart.
+    Isometric,
+    /// The x and y axes are equally foreshortened, and z by half as much.
+    Dimetric,
+    /// An oblique view: the front is drawn as is, with depth receding up and
+    /// to the right at 45°, at half scale.
+    Cabinet
+}
+
+impl Projection {
+    pub fn from_name(name: &str) -> Option<Projection> {
+        match name {
+            "isometric" => Some(Projection::Isometric),
+            "dimetric" => Some(Projection::Dimetric),
+            "cabinet" => Some(Projection::Cabinet),
+            _ => None
+        }
+    }
+
+    /// The matrix of the projection for an image `width` by `height`, which
+    /// keeps the center of the image in place.
+    pub fn matrix(self, width: usize, height: usize) -> Matrix {
+        let projection = match self {
+            // Turn the right side and then the top towards the viewer
+            Projection::Isometric => turned(-45.0, (1.0 / 2.0f64.sqrt()).atan().to_degrees()),
+            Projection::Dimetric => turned(-22.208, 20.705),
+            Projection::Cabinet => {
+                // Further away is smaller z, so shift by -z
+                let d = 0.5 * 45.0f64.to_radians().cos();
+                Matrix::new4x4([
+                    [1.0, 0.0, -d, 0.0],
+                    [0.0, 1.0, -d, 0.0],
+                    [0.0, 0.0, 1.0, 0.0],
+                    [0.0, 0.0, 0.0, 1.0]])
+            }
+        };
+        let (cx, cy) = (width as f64 / 2.0, height as f64 / 2.0);
+        Transform::new().translate(-cx, -cy, 0.0).then(&projection).translate(cx, cy, 0.0).matrix()
+    }
+}
+
+/// Rotate by `y` degrees about the y axis and then `x` degrees about the x
+/// axis.
+fn turned(y: f64, x: f64) -> Matrix {
+    Transform::new().rotate_y(y.to_radians()).rotate_x(x.to_radians()).matrix()
+}
@@ -0,0 +1,43 @@
+/// A small, deterministic pseudo-random number generator (xorshift64*).
+/// Randomized features should make one of these with `Rng::new` from the
+/// seed of their `RenderConfig` rather than using any other source of
+/// randomness, so that `--seed` makes renders reproducible.
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    /// Make a generator for the stream `stream` (e.g. a frame number),
+    /// derived from `seed`. The same seed and stream always give the same
+    /// numbers, whatever thread they are used on.
+    pub fn new(seed: u64, stream: u64) -> Rng {
+        let mut rng = Rng { state: splitmix(seed ^ splitmix(stream)) | 1 };
+        rng.next_u64();
+        rng
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    /// A float uniformly distributed in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A float uniformly distributed in [lo, hi).
+    pub fn range(&mut self, lo: f64, hi: f64) -> f64 {
+        lo + (hi - lo) * self.next_f64()
+    }
+}
+
+/// Scramble the bits of `x` (the finalizer of splitmix64).
+fn splitmix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,286 @@
+use bounds::Aabb;
+use geometry::PolygonList;
+use std::f64;
+use std::cmp::Ordering;
+
+/// A half-line from `origin` in `direction`. Its points are `at(t)` for
+/// every `t` from 0 up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: [f64; 3],
+    pub direction: [f64; 3]
+}
+
+impl Ray {
+    /// The ray from the viewer through the center of the pixel in column `x`
+    /// and row `y` (from the top) of a screen `height` pixels high, starting
+    /// at depth `z` (which should be in front of everything), or `None` if
+    /// the row is below the screen.
+    pub fn through_pixel(x: usize, y: usize, height: usize, z: f64) -> Option<Ray> {
+        if y >= height {
+            return None;
+        }
+        Some(Ray {
+            origin: [x as f64 + 0.5, (height - 1 - y) as f64 + 0.5, z],
+            direction: [0.0, 0.0, -1.0]
+        })
+    }
+
+    pub fn at(&self, t: f64) -> [f64; 3] {
+        [self.origin[0] + t * self.direction[0], self.origin[1] + t * self.direction[1], self.origin[2] + t * self.direction[2]]
+    }
+}
+
+/// Where a ray meets a triangle list
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    /// How far along the ray the hit is (see `Ray`)
+    pub t: f64,
+    /// The index of the triangle hit in the triangle list
+    pub triangle: usize,
+    pub point: [f64; 3]
+}
+
+/// How far along `ray` it first meets the triangle with corners `a`, `b`,
+/// and `c` (from either side), if it does. This is the Möller-Trumbore
+/// algorithm.
+pub fn ray_triangle(ray: &Ray, a: [f64; 4], b: [f64; 4], c: [f64; 4]) -> Option<f64> {
+    let e1 = sub(b, a);
+    let e2 = sub(c, a);
+    let p = cross(ray.direction, e2);
+    let det = dot(e1, p);
+    if det.abs() < 1e-12 {
+        // The ray is parallel to the triangle
+        return None;
+    }
+    let s = sub([ray.origin[0], ray.origin[1], ray.origin[2], 1.0], a);
+    let u = dot(s, p) / det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = cross(s, e1);
+    let v = dot(ray.direction, q) / det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = dot(e2, q) / det;
+    if t >= 0.0 { Some(t) } else { None }
+}
+
+/// How far along `ray` it first reaches the inside of `aabb` (0 if it starts
+/// inside), if it does.
+pub fn ray_aabb(ray: &Ray, aabb: &Aabb) -> Option<f64> {
+    let (mut near, mut far) = (0.0, f64::INFINITY);
+    for axis in 0..3 {
+        if ray.direction[axis] == 0.0 {
+            if ray.origin[axis] < aabb.min[axis] || ray.origin[axis] > aabb.max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let t0 = (aabb.min[axis] - ray.origin[axis]) / ray.direction[axis];
+        let t1 = (aabb.max[axis] - ray.origin[axis]) / ray.direction[axis];
+        near = f64::max(near, f64::min(t0, t1));
+        far = f64::min(far, f64::max(t0, t1));
+        if near > far {
+            return None;
+        }
+    }
+    Some(near)
+}
+
+/// Triangles in each leaf of a `Bvh`, at most
+const LEAF_SIZE: usize = 4;
+
+/// A bounding volume hierarchy over a triangle list: a tree of boxes, each
+/// holding the triangles of the boxes in it, so a ray only has to be checked
+/// against the few triangles in the boxes it passes through.
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    /// The boxes, with the whole list's first
+    nodes: Vec<BvhNode>,
+    /// The indices of the triangles, in the order the leaves hold them
+    order: Vec<usize>
+}
+
+#[derive(Debug, Clone)]
+struct BvhNode {
+    aabb: Aabb,
+    /// For a leaf, the range of `order` it holds; otherwise the indices of
+    /// the two boxes in it
+    leaf: bool,
+    first: usize,
+    second: usize
+}
+
+impl Bvh {
+    /// The hierarchy of the triangles of `triangles`, split in half along
+    /// the way they're most spread out, until only a few are left in each box
+    pub fn new(triangles: &PolygonList) -> Bvh {
+        let mut bvh = Bvh { nodes: vec![], order: (0..triangles.len()).collect() };
+        if !triangles.is_empty() {
+            let centers: Vec<[f64; 4]> = (0..triangles.len()).map(|i| {
+                let [a, b, c] = triangles.triangle(i);
+                [(a[0] + b[0] + c[0]) / 3.0, (a[1] + b[1] + c[1]) / 3.0, (a[2] + b[2] + c[2]) / 3.0, 1.0]
+            }).collect();
+            let len = bvh.order.len();
+            bvh.build(triangles, &centers, 0, len);
+        }
+        bvh
+    }
+
+    /// Add the box of the triangles `order[start..end]` and those in it,
+    /// returning its index
+    fn build(&mut self, triangles: &PolygonList, centers: &[[f64; 4]], start: usize, end: usize) -> usize {
+        let corners: Vec<[f64; 4]> = self.order[start..end].iter().flat_map(|&i| triangles.triangle(i).to_vec()).collect();
+        let aabb = Aabb::of(&corners).expect("a box of no triangles");
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode { aabb, leaf: true, first: start, second: end });
+        if end - start <= LEAF_SIZE {
+            return index;
+        }
+        let spread = Aabb::of(&self.order[start..end].iter().map(|&i| centers[i]).collect::<Vec<_>>()).expect("a box of no triangles");
+        let axis = (0..3).fold(0, |best, axis| {
+            if spread.max[axis] - spread.min[axis] > spread.max[best] - spread.min[best] { axis } else { best }
+        });
+        self.order[start..end].sort_by(|&i, &j| centers[i][axis].partial_cmp(&centers[j][axis]).unwrap_or(Ordering::Equal));
+        let middle = (start + end) / 2;
+        let first = self.build(triangles, centers, start, middle);
+        let second = self.build(triangles, centers, middle, end);
+        self.nodes[index] = BvhNode { aabb, leaf: false, first, second };
+        index
+    }
+
+    /// Find where `ray` first meets any triangle of `triangles` (the list the
+    /// hierarchy was made of) less than `max_t` along it, if it does.
+    pub fn cast(&self, ray: &Ray, triangles: &PolygonList, max_t: f64) -> Option<Hit> {
+        let mut nearest: Option<Hit> = None;
+        let mut stack = if self.nodes.is_empty() { vec![] } else { vec![0] };
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let limit = nearest.map_or(max_t, |hit| hit.t);
+            match ray_aabb(ray, &node.aabb) {
+                Some(t) if t < limit => {},
+                _ => continue
+            }
+            if !node.leaf {
+                stack.push(node.first);
+                stack.push(node.second);
+                continue;
+            }
+            for &i in &self.order[node.first..node.second] {
+                let [a, b, c] = triangles.triangle(i);
+                if let Some(t) = ray_triangle(ray, a, b, c) {
+                    if t < nearest.map_or(max_t, |hit| hit.t) {
+                        nearest = Some(Hit { t, triangle: i, point: ray.at(t) });
+                    }
+                }
+            }
+        }
+        nearest
+    }
+}
+
+fn sub(p: [f64; 4], q: [f64; 4]) -> [f64; 3] {
+    [p[0] - q[0], p[1] - q[1], p[2] - q[2]]
+}
+
+fn dot(p: [f64; 3], q: [f64; 3]) -> f64 {
+    p[0] * q[0] + p[1] * q[1] + p[2] * q[2]
+}
+
+fn cross(p: [f64; 3], q: [f64; 3]) -> [f64; 3] {
+    [p[1] * q[2] - p[2] * q[1], p[2] * q[0] - p[0] * q[2], p[0] * q[1] - p[1] * q[0]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use random::Rng;
+
+    /// Where `ray` first meets any triangle of `triangles`, checking every
+    /// one of them
+    fn cast(ray: &Ray, triangles: &PolygonList) -> Option<Hit> {
+        let mut nearest: Option<Hit> = None;
+        for i in 0..triangles.len() {
+            let [a, b, c] = triangles.triangle(i);
+            if let Some(t) = ray_triangle(ray, a, b, c) {
+                if nearest.is_none_or(|hit| t < hit.t) {
+                    nearest = Some(Hit { t, triangle: i, point: ray.at(t) });
+                }
+            }
+        }
+        nearest
+    }
+
+    const A: [f64; 4] = [0.0, 0.0, 0.0, 1.0];
+    const B: [f64; 4] = [4.0, 0.0, 0.0, 1.0];
+    const C: [f64; 4] = [0.0, 4.0, 0.0, 1.0];
+
+    fn down_from(x: f64, y: f64, z: f64) -> Ray {
+        Ray { origin: [x, y, z], direction: [0.0, 0.0, -1.0] }
+    }
+
+    #[test]
+    fn rays_hit_triangles_inside_and_miss_outside() {
+        assert_eq!(ray_triangle(&down_from(1.0, 1.0, 5.0), A, B, C), Some(5.0));
+        // From below, through the back
+        let up = Ray { origin: [1.0, 2.0, -3.0], direction: [0.0, 0.0, 2.0] };
+        assert_eq!(ray_triangle(&up, A, B, C), Some(1.5));
+        assert_eq!(ray_triangle(&down_from(3.0, 3.0, 5.0), A, B, C), None);
+        assert_eq!(ray_triangle(&down_from(-0.1, 1.0, 5.0), A, B, C), None);
+    }
+
+    #[test]
+    fn rays_miss_triangles_alongside_or_behind_them() {
+        let parallel = Ray { origin: [-1.0, 1.0, 0.0], direction: [1.0, 0.0, 0.0] };
+        assert_eq!(ray_triangle(&parallel, A, B, C), None);
+        // The triangle is at z = 0, behind a ray starting below it going down
+        assert_eq!(ray_triangle(&down_from(1.0, 1.0, -1.0), A, B, C), None);
+    }
+
+    #[test]
+    fn rays_reach_boxes_where_they_enter_them() {
+        let aabb = Aabb { min: [0.0, 0.0, 0.0], max: [2.0, 2.0, 2.0] };
+        assert_eq!(ray_aabb(&down_from(1.0, 1.0, 5.0), &aabb), Some(3.0));
+        assert_eq!(ray_aabb(&down_from(1.0, 1.0, 1.0), &aabb), Some(0.0));
+        assert_eq!(ray_aabb(&down_from(1.0, 1.0, -1.0), &aabb), None);
+        // Parallel to the x and y slabs, but outside the x one
+        assert_eq!(ray_aabb(&down_from(3.0, 1.0, 5.0), &aabb), None);
+        let diagonal = Ray { origin: [-1.0, -1.0, 1.0], direction: [1.0, 1.0, 0.0] };
+        assert_eq!(ray_aabb(&diagonal, &aabb), Some(1.0));
+    }
+
+    #[test]
+    fn rays_through_pixels_start_at_their_centers() {
+        assert_eq!(Ray::through_pixel(3, 0, 10, 7.0), Some(down_from(3.5, 9.5, 7.0)));
+        assert_eq!(Ray::through_pixel(0, 9, 10, 7.0), Some(down_from(0.5, 0.5, 7.0)));
+        assert_eq!(Ray::through_pixel(0, 10, 10, 7.0), None);
+        assert_eq!(Ray::through_pixel(0, 0, 0, 7.0), None);
+    }
+
+    #[test]
+    fn the_hierarchy_finds_the_same_hits_as_checking_every_triangle() {
+        let mut rng = Rng::new(3, 4);
+        let mut triangles = PolygonList::new();
+        for _ in 0..300 {
+            let (x, y, z) = (rng.range(0.0, 100.0), rng.range(0.0, 100.0), rng.range(-50.0, 50.0));
+            let mut corner = || [x + rng.range(-8.0, 8.0), y + rng.range(-8.0, 8.0), z + rng.range(-8.0, 8.0), 1.0];
+            let (a, b, c) = (corner(), corner(), corner());
+            triangles.add_triangle(a, b, c);
+        }
+        let bvh = Bvh::new(&triangles);
+        let mut hits = 0;
+        for _ in 0..500 {
+            let ray = Ray {
+                origin: [rng.range(-10.0, 110.0), rng.range(-10.0, 110.0), rng.range(-60.0, 60.0)],
+                direction: [rng.range(-1.0, 1.0), rng.range(-1.0, 1.0), rng.range(-1.0, 1.0)]
+            };
+            let expected = cast(&ray, &triangles);
+            assert_eq!(bvh.cast(&ray, &triangles, f64::INFINITY), expected);
+            hits += expected.is_some() as usize;
+        }
+        assert!(hits > 50, "only {} rays hit anything", hits);
+        assert_eq!(Bvh::new(&PolygonList::new()).cast(&down_from(0.0, 0.0, 0.0), &PolygonList::new(), f64::INFINITY), None);
+    }
+}
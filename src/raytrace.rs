@@ -0,0 +1,218 @@
+use std::cmp;
+use std::f64;
+
+use geometry::PolygonList;
+use render::{ self, Canvas, Color, Drawn, Shading, Style };
+use lighting::{ self, Light };
+use ray::{ Bvh, Hit, Ray };
+use scene::Scene;
+use config::RenderConfig;
+use screen::{ Rows, Screen };
+use stats::RenderStats;
+
+/// How far off a surface shadow rays start, so they don't hit the triangle
+/// they start from
+const SHADOW_OFFSET: f64 = 1e-3;
+
+/// How scenes are turned into images
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Renderer {
+    /// Filling in each triangle's pixels, nearest last
+    Rasterize,
+    /// Casting a ray through each pixel and coloring what it hits first
+    Raytrace
+}
+
+impl Renderer {
+    /// The renderer called `name` (e.g. "raytrace").
+    pub fn from_name(name: &str) -> Option<Renderer> {
+        match name {
+            "rasterize" => Some(Renderer::Rasterize),
+            "raytrace" => Some(Renderer::Raytrace),
+            _ => None
+        }
+    }
+}
+
+/// Fill `screen` with the background of `scene` and draw `polygons` (the
+/// visible ones of the scene, in the styles `styles`) on it by casting rays
+/// through each pixel, `config.samples` along each side of it, then draw
+/// the scene's edges over them as the rasterizer does.
+///
+/// Lit polygons are lit at every ray's hit, as with Phong shading, facing
+/// the way of their faces for flat shading and of their vertex normals
+/// otherwise, and when there are shadows, rays are cast towards each light
+/// to find them, which only the polygons drawn block, as with shadow maps.
+/// Wireframes have nothing to trace, so are left to the rasterizer (see
+/// `scene::draw_visible`). Stencils, scissors, blending, and shaders are the
+/// rasterizer's alone, and are ignored.
+///
+/// Rays are traced in screen space, after any `camera` has projected the
+/// polygons, not in the world: every ray goes straight into the screen, and
+/// shadows and the distances to point lights are measured among the
+/// projected polygons. Under a camera they're warped by its perspective,
+/// just as the rasterizer's lighting is.
+pub fn render(scene: &Scene, polygons: &PolygonList, styles: &[Style], screen: &mut Screen, config: &RenderConfig) -> RenderStats {
+    let mut stats = RenderStats::default();
+    stats.submitted = scene.polygons.len();
+    stats.drawn = polygons.len();
+    stats.culled = stats.submitted - stats.drawn;
+    stats.clipped = scene.clipped;
+    if let Some(ref tracker) = config.progress {
+        tracker.start_rows(screen.height());
+    }
+    screen.clear(scene.background);
+    let bvh = Bvh::new(polygons);
+    let shadows = config.shadows && config.shading.is_lit();
+    let normals = match config.shading {
+        Shading::Gouraud | Shading::Phong => lighting::vertex_normals(polygons, config.crease_angle),
+        _ => vec![]
+    };
+    // Rays start in front of everything
+    let start = polygons.bounds().map_or(0.0, |bounds| bounds.aabb.max[2] + 1.0);
+    let n = cmp::max(config.samples, 1);
+    let (width, height) = (screen.width(), screen.height());
+    let shade = |hit: &Hit| {
+        let i = hit.triangle;
+        let style = &styles[i];
+        let weights = weights(polygons.triangle(i), hit.point);
+        let mut color = if config.shading.is_lit() {
+            let normal = if normals.is_empty() {
+                render::face_normal(polygons, i)
+            } else {
+                let corners = normals[i];
+                let blend = |c: usize| weights[0] * corners[0][c] + weights[1] * corners[1][c] + weights[2] * corners[2][c];
+                lighting::normalized([blend(0), blend(1), blend(2)])
+            };
+            scene.lighting.phong_where(&style.material, hit.point, normal, |light| {
+                !shadows || reaches(&scene.lighting.lights[light], hit.point, render::face_normal(polygons, i), &bvh, polygons)
+            })
+        } else {
+            Color::white()
+        };
+        if let Some(texture) = style.texture {
+            color = render::modulate(color, scene.textures[texture].sample(render::blend_uvs(polygons.uvs(i), weights)));
+        }
+        color
+    };
+    let filled = render::for_each_band(screen, config.threads, |rows: Rows| {
+        let Rows { top, mut pixels, depth, ids, .. } = rows;
+        let mut filled = 0;
+        for (r, depth_row) in depth.iter_mut().enumerate() {
+            let y = height - 1 - (top + r);
+            for x in 0..width {
+                let mut sum = [0u32; 3];
+                // What the middle of the pixel shows, for its depth and ID
+                let mut middle: Option<Hit> = None;
+                for sy in 0..n {
+                    for sx in 0..n {
+                        let ray = Ray {
+                            origin: [x as f64 + (sx as f64 + 0.5) / n as f64, y as f64 + (sy as f64 + 0.5) / n as f64, start],
+                            direction: [0.0, 0.0, -1.0]
+                        };
+                        let hit = bvh.cast(&ray, polygons, f64::INFINITY);
+                        let color = hit.as_ref().map_or(scene.background, &shade);
+                        sum[0] += color.r as u32;
+                        sum[1] += color.g as u32;
+                        sum[2] += color.b as u32;
+                        if sx == n / 2 && sy == n / 2 {
+                            middle = hit;
+                        }
+                    }
+                }
+                let count = (n * n) as u32;
+                if let Some(row) = pixels.row_mut(y as i64) {
+                    row[x] = Color::rgb((sum[0] / count) as u8, (sum[1] / count) as u8, (sum[2] / count) as u8);
+                }
+                if let Some(hit) = middle {
+                    depth_row[x] = hit.point[2];
+                    if let Some(ids) = ids.get_mut(r) {
+                        ids[x] = styles[hit.triangle].object;
+                    }
+                    filled += 1;
+                }
+            }
+        }
+        if let Some(ref tracker) = config.progress {
+            tracker.rows_done(depth.len());
+        }
+        Drawn { pixels: filled, z_rejects: 0 }
+    });
+    let mut image = render::Counted::new(&mut screen.pixels);
+    render::edge_list(&mut image, &scene.edges, &scene.edge_styles, &mut screen.depth, &mut screen.stencil, &mut screen.ids);
+    let drawn = filled + image.drawn();
+    stats.pixels_filled = drawn.pixels;
+    stats.z_rejects = drawn.z_rejects;
+    stats
+}
+
+/// Whether `light` reaches `point`, on a triangle facing the way of `face`,
+/// with nothing of `occluders` (the hierarchy of `triangles`) in the way
+fn reaches(light: &Light, point: [f64; 3], face: [f64; 3], occluders: &Bvh, triangles: &PolygonList) -> bool {
+    let towards = light.towards(point);
+    // Off whichever side of the triangle the light is on
+    let side = if lighting::dot(face, towards) < 0.0 { -SHADOW_OFFSET } else { SHADOW_OFFSET };
+    let origin = [point[0] + side * face[0], point[1] + side * face[1], point[2] + side * face[2]];
+    let max_t = match *light {
+        Light::Point { position, .. } => {
+            let v = [position[0] - origin[0], position[1] - origin[1], position[2] - origin[2]];
+            lighting::dot(v, v).sqrt()
+        },
+        Light::Directional { .. } => f64::INFINITY
+    };
+    occluders.cast(&Ray { origin, direction: towards }, triangles, max_t).is_none()
+}
+
+/// How much each corner of the triangle `corners` counts towards `point` on
+/// it, as seen from in front and corrected for perspective (as in
+/// `Fragment`)
+fn weights(corners: [[f64; 4]; 3], point: [f64; 3]) -> [f64; 3] {
+    let [a, b, c] = corners;
+    let area = |p: [f64; 4], q: [f64; 4], x: f64, y: f64| (q[0] - p[0]) * (y - p[1]) - (q[1] - p[1]) * (x - p[0]);
+    let total = area(a, b, c[0], c[1]);
+    if total == 0.0 {
+        return [1.0, 0.0, 0.0];
+    }
+    let weights = [area(b, c, point[0], point[1]) / total, area(c, a, point[0], point[1]) / total, area(a, b, point[0], point[1]) / total];
+    if a[3] == b[3] && b[3] == c[3] {
+        weights
+    } else {
+        render::perspective_correct(weights, [a[3], b[3], c[3]])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exec;
+
+    fn config(renderer: Renderer) -> RenderConfig {
+        RenderConfig::new().resolution(100, 100).shading(Shading::Flat).renderer(renderer).ids(true)
+    }
+
+    #[test]
+    fn rays_cover_the_same_pixels_as_the_rasterizer() {
+        // Two boxes, one partly in front of the other, with their corners on
+        // whole pixels, which is where flat shading rounds them to
+        let script = "box 20 70 0 40 40 40\nbox 40 50 100 40 30 10\n";
+        let (traced, _, _) = exec::render_still(script, &config(Renderer::Raytrace)).unwrap();
+        let (rasterized, _, _) = exec::render_still(script, &config(Renderer::Rasterize)).unwrap();
+        let count = |id: u32| traced.ids.iter().flat_map(|row| row.iter()).filter(|&&i| i == id).count();
+        assert_eq!((count(1), count(2)), (40 * 40 - 20 * 20, 40 * 30));
+        assert_eq!(traced.ids, rasterized.ids);
+    }
+
+    #[test]
+    fn occluders_shadow_what_is_behind_them_from_a_point_light() {
+        // A wall, with a block in front of it and a light in front of that,
+        // to the left, so the shadow falls to the right of the block
+        let script = "point_light 255 255 255 0 50 100\nbox 0 100 0 100 100 10\nbox 20 60 40 20 20 10\n";
+        let (lit, _, _) = exec::render_still(script, &config(Renderer::Raytrace)).unwrap();
+        let (shadowed, _, _) = exec::render_still(script, &config(Renderer::Raytrace).shadows(true)).unwrap();
+        let brightness = |c: Color| c.r as u32 + c.g as u32 + c.b as u32;
+        assert!(brightness(shadowed.pixels[50][50]) < brightness(lit.pixels[50][50]));
+        // Out of the shadow, and on the block itself, nothing changes
+        assert_eq!(shadowed.pixels[50][80], lit.pixels[50][80]);
+        assert_eq!(shadowed.pixels[50][30], lit.pixels[50][30]);
+    }
+}
@@ -1,17 +1,88 @@
-use matrix::Matrix;
+use geometry::{ EdgeList, PolygonList };
+use screen::{ Rows, Screen };
+use shader::Shader;
+use lighting::{ self, Lighting, Material };
+use shadow::Shadows;
+use texture::Texture;
 use std::fmt;
-use consts::*;
+use std::iter;
+use std::ops;
+use std::sync::Arc;
+use std::cmp;
+#[cfg(not(feature = "parallel"))]
+use std::thread;
+use std::mem;
+use std::f64;
+use std::time::Instant;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
     pub b: u8,
+    /// Opacity, from 0 (clear) to 255 (opaque, the default). Images are
+    /// saved without it.
+    pub a: u8
 }
 
 impl Color {
+    /// An opaque color.
     pub fn rgb(r: u8, g: u8, b: u8) -> Color {
-        Color { r: r, g: g, b: b }
+        Color::rgba(r, g, b, 255)
+    }
+
+    pub fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    /// An opaque color from channels which may be fractional or out of
+    /// range, rounding each and clamping it to 0 to 255.
+    pub fn clamped(r: f64, g: f64, b: f64) -> Color {
+        Color::rgb(clamp_channel(r), clamp_channel(g), clamp_channel(b))
+    }
+
+    /// An opaque color from its hue `h` in degrees (0 is red, 120 green,
+    /// and 240 blue), and its saturation `s` and value `v` from 0 to 1.
+    pub fn hsv(h: f64, s: f64, v: f64) -> Color {
+        let h = h.rem_euclid(360.0) / 60.0;
+        let c = v * s;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x)
+        };
+        let m = v - c;
+        Color::clamped(255.0 * (r + m), 255.0 * (g + m), 255.0 * (b + m))
+    }
+
+    /// Parse a color written `#rrggbb`, or `#rrggbbaa` with its opacity, in
+    /// hexadecimal, or shortened to `#rgb` or `#rgba`, with each digit
+    /// standing for two of the same.
+    pub fn from_hex(hex: &str) -> Option<Color> {
+        if !hex.starts_with('#') {
+            return None;
+        }
+        let digits = hex[1..].chars().map(|c| c.to_digit(16).map(|d| d as u8)).collect::<Option<Vec<_>>>()?;
+        let channels: Vec<u8> = match digits.len() {
+            3 | 4 => digits.iter().map(|&d| d * 17).collect(),
+            6 | 8 => digits.chunks(2).map(|pair| pair[0] * 16 + pair[1]).collect(),
+            _ => return None
+        };
+        Some(Color::rgba(channels[0], channels[1], channels[2], channels.get(3).cloned().unwrap_or(255)))
+    }
+
+    /// The color `t` of the way from `self` to `other`, channel by channel
+    /// (opacity included). `t` is clamped to 0 to 1.
+    pub fn lerp(self, other: Color, t: f64) -> Color {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |p: u8, q: u8| clamp_channel(p as f64 + (q as f64 - p as f64) * t);
+        Color::rgba(mix(self.r, other.r), mix(self.g, other.g), mix(self.b, other.b), mix(self.a, other.a))
     }
 
     pub fn black() -> Color {
@@ -27,6 +98,11 @@ impl Color {
     }
 }
 
+/// Round `v` and clamp it to 0 to 255.
+fn clamp_channel(v: f64) -> u8 {
+    v.round().clamp(0.0, 255.0) as u8
+}
+
 impl fmt::Display for Color {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "({}, {}, {})", self.r, self.g, self.b)
@@ -41,12 +117,7 @@ pub struct Point {
 
 impl Point {
     pub fn xy(x: i64, y: i64) -> Point {
-        Point { x: x, y: y }
-    }
-
-    #[allow(dead_code)]
-    pub fn vector_sum(&self, p: Point) -> Point {
-        Point { x: self.x + p.x, y: self.y + p.y }
+        Point { x, y }
     }
 
     pub fn vector_diff(&self, p: Point) -> Point {
@@ -58,46 +129,243 @@ impl Point {
     }
 }
 
-/// Draw edges in an edge list matrix. Each successive pair of
-/// columns are considered the endpoints of a distinct edge
-/// (i.e. [A-start | A-end | B-start | B-end | etc...]).
+/// Draw the edges in an edge list.
 ///
-/// All edges are drawn in white.
-pub fn edge_list(image: &mut Vec<Vec<Color>>, edges: &Matrix) {
-    let mut c = 0;
-    while c + 1 < edges.width() {
-        let pcol = edges.col(c);
-        let qcol = edges.col(c + 1);
-        let p = Point::xy(pcol[0] as i64, pcol[1] as i64);
-        let q = Point::xy(qcol[0] as i64, qcol[1] as i64);
-        line(image, p, q, Color::white());
-        c += 2;
-    }
-}
-
-pub fn triangle_list(image: &mut Vec<Vec<Color>>, edges: &Matrix) {
-    let mut i = 0;
-    while i + 2 < edges.width() {
-        let pcol = edges.col(i);
-        let p = Point::xy(pcol[0] as i64, pcol[1] as i64);
-        let qcol = edges.col(i + 1);
-        let q = Point::xy(qcol[0] as i64, qcol[1] as i64);
-        let rcol = edges.col(i + 2);
-        let r = Point::xy(rcol[0] as i64, rcol[1] as i64);
+/// All edges are drawn in white, each in the matching style of `styles`.
+/// `depth`, `stencil`, and `ids` are the depth, stencil, and ID buffers of
+/// the whole image; edges behind what's already drawn are hidden.
+///
+/// Dashes carry on from one edge to the next where the edges join, so that a
+/// curve made of many short edges is dashed along its whole length.
+pub fn edge_list<C: Canvas + ?Sized>(image: &mut C, edges: &EdgeList, styles: &[Style], depth: &mut [Vec<f64>], stencil: &mut [Vec<u8>], ids: &mut [Vec<u32>]) {
+    let mut last_end = None;
+    let mut phase = 0.0;
+    for (i, style) in styles.iter().enumerate().take(edges.len()) {
+        let pcol = edges.col(2 * i);
+        let qcol = edges.col(2 * i + 1);
+        if last_end != Some([pcol[0], pcol[1]]) {
+            phase = 0.0;
+        }
+        let mut styled = Styled::new(image, *style, depth, stencil, ids, 0);
+        styled.depth_plane = Some(line_plane(pcol, qcol));
+        phase = styled_line(&mut styled, [pcol[0], pcol[1]], [qcol[0], qcol[1]], style, phase, Color::white());
+        last_end = Some([qcol[0], qcol[1]]);
+    }
+}
+
+/// Draw a line from `start` to `end` as `style` says: `width` wide, dashed,
+/// and antialiased, or not. `phase` is how far into the dash pattern the line
+/// starts; the return value is how far into it the line ends, for a line
+/// continuing on from this one.
+pub fn styled_line<C: Canvas + ?Sized>(image: &mut C, start: [f64; 2], end: [f64; 2], style: &Style, phase: f64, color: Color) -> f64 {
+    let line_style = style.line;
+    let piece = |image: &mut C, from: [f64; 2], to: [f64; 2]| {
+        if line_style.width > 1.0 {
+            wide_line(image, from, to, line_style.width, color);
+        } else if style.antialias {
+            smooth_line(image, from, to, color);
+        } else {
+            line(image, Point::xy(from[0] as i64, from[1] as i64), Point::xy(to[0] as i64, to[1] as i64), color);
+        }
+    };
+    let (dash, gap) = match line_style.dash_pattern {
+        Some([dash, gap]) => (dash, gap),
+        None => {
+            piece(image, start, end);
+            return 0.0;
+        }
+    };
+    let period = dash + gap;
+    let length = ((end[0] - start[0]).powi(2) + (end[1] - start[1]).powi(2)).sqrt();
+    let at = |t: f64| {
+        let t = if length > 0.0 { t / length } else { 0.0 };
+        [start[0] + (end[0] - start[0]) * t, start[1] + (end[1] - start[1]) * t]
+    };
+    // Walk along the line a dash or gap at a time
+    let mut t = 0.0;
+    while t < length {
+        let into = (phase + t) % period;
+        if into < dash {
+            let next = (t + dash - into).min(length);
+            piece(image, at(t), at(next));
+            t = next;
+        } else {
+            t += period - into;
+        }
+    }
+    (phase + length) % period
+}
+
+/// Draw a line `width` wide from `start` to `end`, as a rectangle filled in
+/// as two triangles.
+fn wide_line<C: Canvas + ?Sized>(image: &mut C, start: [f64; 2], end: [f64; 2], width: f64, color: Color) {
+    let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length == 0.0 {
+        return;
+    }
+    // Half the width, across the line
+    let (nx, ny) = (-dy / length * width / 2.0, dx / length * width / 2.0);
+    let corner = |p: [f64; 2], side: f64| Point::xy((p[0] + side * nx) as i64, (p[1] + side * ny) as i64);
+    let (a, b) = (corner(start, 1.0), corner(end, 1.0));
+    let (c, d) = (corner(end, -1.0), corner(start, -1.0));
+    fill_triangle(image, a, b, c, color);
+    fill_triangle(image, a, c, d, color);
+}
+
+/// Remove the triangles of a triangle list which face away from the viewer,
+/// and the matching items of `tags` (one per triangle). This is done in
+/// place, moving the remaining triangles to the front.
+pub fn cull_backfaces<T: Copy>(triangles: &mut PolygonList, tags: &mut Vec<T>) {
+    let mut kept = 0;
+    for i in 0..triangles.len() {
+        let corners = triangles.triangle(i);
+        let p = screen_point(corners[0]);
+        let q = screen_point(corners[1]);
+        let r = screen_point(corners[2]);
         if r.vector_diff(p).clockwise_of(q.vector_diff(p)) {
+            triangles.copy_triangle(i, kept);
+            tags[kept] = tags[i];
+            kept += 1;
+        }
+    }
+    debug!("Culled {} of {} triangles", triangles.len() - kept, triangles.len());
+    triangles.truncate(kept);
+    tags.truncate(kept);
+}
+
+/// How the triangles of a triangle list are drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shading {
+    /// Just their edges
+    Wireframe,
+    /// Filled in
+    Filled,
+    /// Filled in, each with one color, lit as a flat surface by the
+    /// scene's lights (see `lighting`)
+    Flat,
+    /// Filled in, lit at each corner (facing the average of the directions
+    /// of the triangles there), with the colors blended in between
+    Gouraud,
+    /// Filled in, lit at each pixel, facing the direction blended between
+    /// those of its corners, so highlights don't smear
+    Phong
+}
+
+impl Shading {
+    /// Whether triangles are lit by the scene's lights
+    pub fn is_lit(&self) -> bool {
+        match *self {
+            Shading::Flat | Shading::Gouraud | Shading::Phong => true,
+            Shading::Wireframe | Shading::Filled => false
+        }
+    }
+}
+
+/// Draw each triangle in a triangle list which reaches the band `rows` of an
+/// image, each in the matching style of `styles`, keeping the nearest at each
+/// pixel by the band's depth buffer. Filled triangles are the color of the
+/// first corner of the matching colors of `colors`, if there are any, and
+/// everything else is white.
+pub fn triangle_list(rows: Rows, triangles: &PolygonList, styles: &[Style], colors: &[[Color; 3]], shading: Shading) -> Drawn {
+    let Rows { mut pixels, depth, stencil, ids, .. } = rows;
+    let bottom = pixels.bottom;
+    let mut band = Counted::new(&mut pixels);
+    for (i, style) in styles.iter().enumerate().take(triangles.len()) {
+        let [a, b, c] = triangles.triangle(i);
+        let p = screen_point(a);
+        let q = screen_point(b);
+        let r = screen_point(c);
+        // Antialiased and wide edges reach past the corners
+        let reach = style.line.width.ceil() as i64;
+        let lo = cmp::min(p.y, cmp::min(q.y, r.y)) - reach;
+        let hi = cmp::max(p.y, cmp::max(q.y, r.y)) + reach;
+        if band.canvas.overlaps(lo, hi) {
+            let mut styled = Styled::new(&mut band, *style, depth, stencil, ids, bottom);
+            styled.depth_plane = Some(triangle_plane(a, b, c));
+            if style.is_plain_line() || shading != Shading::Wireframe {
+                triangle(&mut styled, p, q, r, shading, colors.get(i).map_or(Color::white(), |corners| corners[0]));
+            } else {
+                let phase = styled_line(&mut styled, [a[0], a[1]], [b[0], b[1]], style, 0.0, Color::white());
+                let phase = styled_line(&mut styled, [b[0], b[1]], [c[0], c[1]], style, phase, Color::white());
+                styled_line(&mut styled, [c[0], c[1]], [a[0], a[1]], style, phase, Color::white());
+            }
+        }
+    }
+    band.drawn()
+}
+
+/// Draw the triangle with corners `p`, `q`, and `r`, filled with `color`, or
+/// as a white wireframe.
+fn triangle<C: Canvas + ?Sized>(image: &mut C, p: Point, q: Point, r: Point, shading: Shading, color: Color) {
+    match shading {
+        Shading::Wireframe => {
             line(image, p, q, Color::white());
             line(image, q, r, Color::white());
             line(image, r, p, Color::white());
-        }
-        i += 3;
+        },
+        Shading::Filled | Shading::Flat | Shading::Gouraud | Shading::Phong => fill_triangle(image, p, q, r, color)
     }
 }
 
+fn screen_point(col: [f64; 4]) -> Point {
+    Point::xy(col[0] as i64, col[1] as i64)
+}
+
+/// The coefficients `[a, b, c]` of the plane z = a x + b y + c through the
+/// triangle with corners `p`, `q`, and `r`, for finding the depth of each
+/// pixel drawn of it. A triangle seen edge on is given the depth of its
+/// nearest corner.
+fn triangle_plane(p: [f64; 4], q: [f64; 4], r: [f64; 4]) -> [f64; 3] {
+    let (u, v) = ([q[0] - p[0], q[1] - p[1], q[2] - p[2]], [r[0] - p[0], r[1] - p[1], r[2] - p[2]]);
+    let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    if n[2].abs() < 1e-9 {
+        return [0.0, 0.0, p[2].max(q[2]).max(r[2])];
+    }
+    let (a, b) = (-n[0] / n[2], -n[1] / n[2]);
+    [a, b, p[2] - a * p[0] - b * p[1]]
+}
+
+/// The coefficients of the plane z = a x + b y + c which has the depth along
+/// the line from `p` to `q`, and is level across it, as for
+/// `triangle_plane`.
+fn line_plane(p: [f64; 4], q: [f64; 4]) -> [f64; 3] {
+    let (dx, dy) = (q[0] - p[0], q[1] - p[1]);
+    let length2 = dx * dx + dy * dy;
+    if length2 == 0.0 {
+        return [0.0, 0.0, p[2].max(q[2])];
+    }
+    let slope = (q[2] - p[2]) / length2;
+    let (a, b) = (slope * dx, slope * dy);
+    [a, b, p[2] - a * p[0] - b * p[1]]
+}
+
 /// Draw a line in `image` using Bresenham's line algorithm (and variants for each octant).
-pub fn line(image: &mut Vec<Vec<Color>>, start: Point, end: Point, color: Color) {
-    if start.x > end.x {
+/// Horizontal and vertical lines, which are common in 2D drawings, skip the
+/// stepping. The line is first clipped to the image, if it knows its bounds,
+/// so however far off it the ends are, only the pixels on it are stepped
+/// through.
+pub fn line<C: Canvas + ?Sized>(image: &mut C, start: Point, end: Point, color: Color) {
+    let (start, end) = match image.bounds() {
+        Some((lo, hi)) => match clip_line(start, end, lo, hi) {
+            Some(ends) => ends,
+            None => return
+        },
+        None => (start, end)
+    };
+    unclipped_line(image, start, end, color);
+}
+
+fn unclipped_line<C: Canvas + ?Sized>(image: &mut C, start: Point, end: Point, color: Color) {
+    if start.y == end.y {
+        fill_span(image, start.y, start.x, end.x, color);
+    } else if start.x == end.x {
+        for y in cmp::min(start.y, end.y)..cmp::max(start.y, end.y) + 1 {
+            image.plot(Point::xy(start.x, y), color);
+        }
+    } else if start.x > end.x {
         // Swap `start` and `end` so `start` is on the left
-        line(image, end, start, color);
+        unclipped_line(image, end, start, color);
     } else {
         // Dispatch to various functions based on octant
         let more_vertical = (end.y - start.y).abs() > (end.x - start.x).abs();
@@ -117,21 +385,1244 @@ pub fn line(image: &mut Vec<Vec<Color>>, start: Point, end: Point, color: Color)
     }
 }
 
-fn within_screen(image: &mut Vec<Vec<Color>>, p: Point) -> bool {
-    let within_y = p.y >= 0 && p.y < image.len() as i64;
-    let within_x = p.x >= 0 && image.len() > 0 && p.x < image[0].len() as i64;
-    within_y && within_x
+/// Which sides of the rectangle from `lo` to `hi` `p` is past, as the bits
+/// of a Cohen-Sutherland outcode
+fn outcode(p: Point, lo: Point, hi: Point) -> u8 {
+    let mut code = 0;
+    if p.x < lo.x { code |= 1; } else if p.x > hi.x { code |= 2; }
+    if p.y < lo.y { code |= 4; } else if p.y > hi.y { code |= 8; }
+    code
+}
+
+/// The part of the line from `start` to `end` in the rectangle of pixels
+/// from `lo` to `hi` (inclusive), if any, by the Cohen-Sutherland
+/// algorithm: ends past a side are moved to where the line crosses it, until
+/// both ends are in the rectangle or both are past the same side.
+pub fn clip_line(start: Point, end: Point, lo: Point, hi: Point) -> Option<(Point, Point)> {
+    let (mut p, mut q) = (start, end);
+    let (mut code_p, mut code_q) = (outcode(p, lo, hi), outcode(q, lo, hi));
+    loop {
+        if code_p | code_q == 0 {
+            return Some((p, q));
+        }
+        if code_p & code_q != 0 {
+            return None;
+        }
+        // Move whichever end is outside, keeping the other where it is
+        let code = if code_p != 0 { code_p } else { code_q };
+        let (x0, y0, x1, y1) = (p.x as f64, p.y as f64, q.x as f64, q.y as f64);
+        let moved = if code & 4 != 0 || code & 8 != 0 {
+            let y = if code & 4 != 0 { lo.y } else { hi.y };
+            Point::xy((x0 + (x1 - x0) * (y as f64 - y0) / (y1 - y0)).round() as i64, y)
+        } else {
+            let x = if code & 1 != 0 { lo.x } else { hi.x };
+            Point::xy(x, (y0 + (y1 - y0) * (x as f64 - x0) / (x1 - x0)).round() as i64)
+        };
+        if code == code_p {
+            p = moved;
+            code_p = outcode(p, lo, hi);
+        } else {
+            q = moved;
+            code_q = outcode(q, lo, hi);
+        }
+    }
+}
+
+/// Draw an antialiased line in `image` from `start` to `end` using Xiaolin
+/// Wu's algorithm: each step along the line covers two pixels across it,
+/// which get `color` in proportion to how close the line passes to them.
+/// The ends are kept where they are, rather than rounded to whole pixels,
+/// and pixel (x, y) is the square from (x, y) to (x + 1, y + 1), as for
+/// `line`.
+pub fn smooth_line<C: Canvas + ?Sized>(image: &mut C, start: [f64; 2], end: [f64; 2], color: Color) {
+    // Work with the centers of pixels at whole coordinates
+    let (mut x0, mut y0) = (start[0] - 0.5, start[1] - 0.5);
+    let (mut x1, mut y1) = (end[0] - 0.5, end[1] - 0.5);
+    // Step along x, swapping x and y for steep lines
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    if steep {
+        mem::swap(&mut x0, &mut y0);
+        mem::swap(&mut x1, &mut y1);
+    }
+    if x0 > x1 {
+        mem::swap(&mut x0, &mut x1);
+        mem::swap(&mut y0, &mut y1);
+    }
+    let gradient = if x1 > x0 { (y1 - y0) / (x1 - x0) } else { 1.0 };
+    let mut plot = |x: i64, y: i64, coverage: f64| {
+        let p = if steep { Point::xy(y, x) } else { Point::xy(x, y) };
+        image.blend_pixel(p, color, coverage);
+    };
+    let fract = |v: f64| v - v.floor();
+
+    // The ends cover only part of their column
+    let xend = x0.round();
+    let yend = y0 + gradient * (xend - x0);
+    let xgap = 1.0 - fract(x0 + 0.5);
+    let first = xend as i64;
+    plot(first, yend.floor() as i64, (1.0 - fract(yend)) * xgap);
+    plot(first, yend.floor() as i64 + 1, fract(yend) * xgap);
+    let mut y = yend + gradient;
+
+    let xend = x1.round();
+    let yend = y1 + gradient * (xend - x1);
+    let xgap = fract(x1 + 0.5);
+    let last = xend as i64;
+    plot(last, yend.floor() as i64, (1.0 - fract(yend)) * xgap);
+    plot(last, yend.floor() as i64 + 1, fract(yend) * xgap);
+
+    for x in first + 1..last {
+        plot(x, y.floor() as i64, 1.0 - fract(y));
+        plot(x, y.floor() as i64 + 1, fract(y));
+        y += gradient;
+    }
 }
 
 /// If the point `p` is within the width and height of `image`, plot `color` at `p`.
-pub fn plot_if_visible(image: &mut Vec<Vec<Color>>, p: Point, color: Color) {
-    if within_screen(image, p) {
-        image[HEIGHT - (p.y + 1) as usize][p.x as usize] = color;
+pub fn plot_if_visible<C: Canvas + ?Sized>(image: &mut C, p: Point, color: Color) {
+    image.plot(p, color);
+}
+
+/// Something that can be drawn on: a whole image, or a band of rows of one.
+/// Points are given with y increasing upwards, from 0 at the bottom of the
+/// image.
+pub trait Canvas {
+    /// Get the row of pixels at height `y`, or `None` if it isn't on this
+    /// canvas.
+    fn row_mut(&mut self, y: i64) -> Option<&mut [Color]>;
+
+    /// The lowest and highest pixels on this canvas, if it knows them, so
+    /// lines can be clipped to it before they're drawn.
+    fn bounds(&self) -> Option<(Point, Point)> {
+        None
+    }
+
+    /// Note that `n` pixels were just written. Canvases which count the
+    /// pixels drawn on them override this.
+    fn filled(&mut self, _n: usize) {}
+
+    /// Note that `n` pixels were just left alone for having something nearer
+    /// on them, as `filled` counts those written.
+    fn z_rejected(&mut self, _n: usize) {}
+
+    /// Set the pixels from `lo` to `hi` (inclusive, and all on the canvas)
+    /// of the row at height `y` to `color`. Canvases which blend or mask
+    /// what's drawn on them override this.
+    fn write_span(&mut self, y: i64, lo: usize, hi: usize, color: Color) {
+        if let Some(row) = self.row_mut(y) {
+            set_pixels(&mut row[lo..hi + 1], color);
+        } else {
+            return;
+        }
+        self.filled(hi - lo + 1);
+    }
+
+    /// Set the pixel at `p` to `color`, if it is on this canvas.
+    fn plot(&mut self, p: Point, color: Color) {
+        let on_canvas = match self.row_mut(p.y) {
+            Some(row) => p.x >= 0 && p.x < row.len() as i64,
+            None => false
+        };
+        if on_canvas {
+            self.write_span(p.y, p.x as usize, p.x as usize, color);
+        }
+    }
+
+    /// Mix `color` into the pixel at `p`, if it is on this canvas, by
+    /// `coverage`, the fraction of the pixel covered (from 0 to 1).
+    fn blend_pixel(&mut self, p: Point, color: Color, coverage: f64) {
+        if let Some(dst) = pixel_at(self, p) {
+            self.plot(p, dst.lerp(color, coverage));
+        }
+    }
+}
+
+/// The color of the pixel at `p` of `image`, if it is on the image.
+fn pixel_at<C: Canvas + ?Sized>(image: &mut C, p: Point) -> Option<Color> {
+    match image.row_mut(p.y) {
+        Some(row) if p.x >= 0 && p.x < row.len() as i64 => Some(row[p.x as usize]),
+        _ => None
+    }
+}
+
+/// How many pixels a pass wrote, and how many it didn't for something
+/// nearer being there already
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Drawn {
+    pub pixels: usize,
+    pub z_rejects: usize
+}
+
+impl ops::Add for Drawn {
+    type Output = Drawn;
+
+    fn add(self, other: Drawn) -> Drawn {
+        Drawn { pixels: self.pixels + other.pixels, z_rejects: self.z_rejects + other.z_rejects }
+    }
+}
+
+impl ops::AddAssign for Drawn {
+    fn add_assign(&mut self, other: Drawn) {
+        *self = *self + other;
+    }
+}
+
+impl iter::Sum for Drawn {
+    fn sum<I: Iterator<Item = Drawn>>(iter: I) -> Drawn {
+        iter.fold(Drawn::default(), |a, b| a + b)
+    }
+}
+
+/// A canvas which counts the pixels drawn on another
+pub struct Counted<'a, C: Canvas + ?Sized + 'a> {
+    canvas: &'a mut C,
+    pub pixels: usize,
+    pub z_rejects: usize
+}
+
+impl<'a, C: Canvas + ?Sized> Counted<'a, C> {
+    pub fn new(canvas: &'a mut C) -> Counted<'a, C> {
+        Counted { canvas, pixels: 0, z_rejects: 0 }
+    }
+
+    /// What has been drawn so far
+    pub fn drawn(&self) -> Drawn {
+        Drawn { pixels: self.pixels, z_rejects: self.z_rejects }
+    }
+}
+
+impl<'a, C: Canvas + ?Sized> Canvas for Counted<'a, C> {
+    fn row_mut(&mut self, y: i64) -> Option<&mut [Color]> {
+        self.canvas.row_mut(y)
+    }
+
+    fn bounds(&self) -> Option<(Point, Point)> {
+        self.canvas.bounds()
+    }
+
+    fn filled(&mut self, n: usize) {
+        self.pixels += n;
+    }
+
+    fn z_rejected(&mut self, n: usize) {
+        self.z_rejects += n;
+    }
+}
+
+/// How a color drawn on a pixel is combined with the pixel's color
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Blend {
+    /// Just the new color
+    Replace,
+    /// The sum of the colors, for glows
+    Add,
+    /// The product of the colors (as fractions of 255), which darkens, for
+    /// masks
+    Multiply,
+    /// The inverse of the product of the inverses, which lightens, for overlays
+    Screen,
+    /// The lesser of each channel
+    Min,
+    /// The greater of each channel
+    Max
+}
+
+impl Blend {
+    pub fn from_name(name: &str) -> Option<Blend> {
+        match name {
+            "replace" => Some(Blend::Replace),
+            "add" => Some(Blend::Add),
+            "multiply" => Some(Blend::Multiply),
+            "screen" => Some(Blend::Screen),
+            "min" => Some(Blend::Min),
+            "max" => Some(Blend::Max),
+            _ => None
+        }
+    }
+
+    /// The color of a pixel colored `dst` after `src` is drawn on it.
+    pub fn apply(self, dst: Color, src: Color) -> Color {
+        let channel = |d: u8, s: u8| match self {
+            Blend::Replace => s,
+            Blend::Add => d.saturating_add(s),
+            Blend::Multiply => ((d as u32 * s as u32 + 127) / 255) as u8,
+            Blend::Screen => 255 - (((255 - d) as u32 * (255 - s) as u32 + 127) / 255) as u8,
+            Blend::Min => cmp::min(d, s),
+            Blend::Max => cmp::max(d, s)
+        };
+        Color::rgb(channel(dst.r, src.r), channel(dst.g, src.g), channel(dst.b, src.b))
+    }
+}
+
+/// What a shape does with the stencil buffer, which holds a number for each
+/// pixel of the image, starting at 0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stencil {
+    /// Nothing: the shape is drawn everywhere
+    Off,
+    /// Set the stencil buffer to this where the shape covers, without
+    /// drawing it
+    Write(u8),
+    /// Draw the shape only where the stencil buffer is this
+    Equal(u8),
+    /// Draw the shape only where the stencil buffer isn't this
+    NotEqual(u8)
+}
+
+impl Stencil {
+    /// Whether a shape is drawn on a pixel whose stencil value is `value`.
+    pub fn passes(self, value: u8) -> bool {
+        match self {
+            Stencil::Off => true,
+            Stencil::Write(_) => false,
+            Stencil::Equal(v) => value == v,
+            Stencil::NotEqual(v) => value != v
+        }
+    }
+}
+
+/// A rectangle of pixels, with its bottom left corner at `x`, `y`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: i64,
+    pub y: i64,
+    pub width: i64,
+    pub height: i64
+}
+
+impl Rect {
+    pub fn contains(&self, x: i64, y: i64) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// The part of the pixels from `lo` to `hi` (inclusive) of the row at
+    /// height `y` inside the rectangle, if any.
+    pub fn clip_span(&self, y: i64, lo: usize, hi: usize) -> Option<(usize, usize)> {
+        if y < self.y || y >= self.y + self.height {
+            return None;
+        }
+        let lo = cmp::max(lo as i64, self.x);
+        let hi = cmp::min(hi as i64, self.x + self.width - 1);
+        if lo > hi {
+            None
+        } else {
+            Some((lo as usize, hi as usize))
+        }
+    }
+
+    /// The rectangle covering the same part of an image `n` times bigger.
+    pub fn scaled(&self, n: usize) -> Rect {
+        let n = n as i64;
+        Rect { x: self.x * n, y: self.y * n, width: self.width * n, height: self.height * n }
+    }
+}
+
+/// How a shape is drawn into the image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Style {
+    pub blend: Blend,
+    pub stencil: Stencil,
+    /// The only part of the image the shape is drawn on, if it's limited
+    pub scissor: Option<Rect>,
+    /// The number of the shape (from 1) in the order shapes were drawn,
+    /// recorded in the ID buffer where it's drawn
+    pub object: u32,
+    /// Whether the shape's edges are drawn antialiased (see `smooth_line`).
+    /// Wide edges aren't.
+    pub antialias: bool,
+    /// How wide the shape's edges are, and how they're dashed
+    pub line: LineStyle,
+    /// What the shape is made of, for lighting it (see `Shading::Flat`)
+    pub material: Material,
+    /// The index in the scene's textures of the texture filled triangles
+    /// are painted with, if any
+    pub texture: Option<usize>
+}
+
+/// How lines are drawn: how wide they are, and whether they are dashed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineStyle {
+    /// Width in pixels. Lines up to 1 wide are drawn a pixel wide.
+    pub width: f64,
+    /// The length of each dash and of the gap after it, in pixels along the
+    /// line, or `None` for solid lines
+    pub dash_pattern: Option<[f64; 2]>
+}
+
+impl Default for LineStyle {
+    fn default() -> LineStyle {
+        LineStyle::new()
+    }
+}
+
+impl LineStyle {
+    /// Solid lines a pixel wide
+    pub fn new() -> LineStyle {
+        LineStyle { width: 1.0, dash_pattern: None }
+    }
+
+    /// The same style for an image `n` times bigger.
+    pub fn scaled(&self, n: usize) -> LineStyle {
+        let n = n as f64;
+        LineStyle { width: self.width * n, dash_pattern: self.dash_pattern.map(|[dash, gap]| [dash * n, gap * n]) }
+    }
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style::new()
+    }
+}
+
+impl Style {
+    /// Plain drawing, replacing what's there
+    pub fn new() -> Style {
+        Style {
+            blend: Blend::Replace,
+            stencil: Stencil::Off,
+            scissor: None,
+            object: 0,
+            antialias: false,
+            line: LineStyle::new(),
+            material: Material::new(),
+            texture: None
+        }
+    }
+
+    /// The same style for an image `n` times bigger.
+    pub fn scaled(&self, n: usize) -> Style {
+        Style { scissor: self.scissor.map(|r| r.scaled(n)), line: self.line.scaled(n), ..*self }
+    }
+
+    /// Whether edges are drawn as plain lines, a pixel wide and solid,
+    /// without antialiasing.
+    pub fn is_plain_line(&self) -> bool {
+        !self.antialias && self.line == LineStyle::new()
+    }
+
+    /// Draw `color` on the pixel `px`, whose stencil value is `stencil`.
+    /// Returns whether the pixel was drawn on.
+    pub fn apply(self, px: &mut Color, stencil: &mut u8, color: Color) -> bool {
+        if let Stencil::Write(v) = self.stencil {
+            *stencil = v;
+        }
+        if !self.stencil.passes(*stencil) {
+            return false;
+        }
+        *px = self.blend.apply(*px, color);
+        true
+    }
+}
+
+/// A canvas which draws on another in a `Style`, using (and changing) the
+/// depth and stencil buffers under it, and recording the style's object in
+/// the ID buffer under it
+pub struct Styled<'a, C: Canvas + ?Sized + 'a> {
+    canvas: &'a mut C,
+    style: Style,
+    /// The depth of what's drawn, as the coefficients `[a, b, c]` of
+    /// z = a x + b y + c, or `None` to draw without testing depth
+    pub depth_plane: Option<[f64; 3]>,
+    /// The rows of the depth buffer, top first
+    depth: &'a mut [Vec<f64>],
+    /// The rows of the stencil buffer, top first
+    stencil: &'a mut [Vec<u8>],
+    /// The rows of the ID buffer, top first, or none if it isn't kept
+    ids: &'a mut [Vec<u32>],
+    /// Height, in the whole image, of the bottom row of `stencil`
+    bottom: i64
+}
+
+impl<'a, C: Canvas + ?Sized> Styled<'a, C> {
+    pub fn new(canvas: &'a mut C, style: Style, depth: &'a mut [Vec<f64>], stencil: &'a mut [Vec<u8>], ids: &'a mut [Vec<u32>], bottom: i64) -> Styled<'a, C> {
+        Styled { canvas, style, depth_plane: None, depth, stencil, ids, bottom }
+    }
+}
+
+impl<'a, C: Canvas + ?Sized> Canvas for Styled<'a, C> {
+    fn row_mut(&mut self, y: i64) -> Option<&mut [Color]> {
+        self.canvas.row_mut(y)
+    }
+
+    fn bounds(&self) -> Option<(Point, Point)> {
+        self.canvas.bounds()
+    }
+
+    fn filled(&mut self, n: usize) {
+        self.canvas.filled(n);
+    }
+
+    fn z_rejected(&mut self, n: usize) {
+        self.canvas.z_rejected(n);
+    }
+
+    fn write_span(&mut self, y: i64, lo: usize, hi: usize, color: Color) {
+        let (lo, hi) = match self.style.scissor {
+            Some(rect) => match rect.clip_span(y, lo, hi) {
+                Some(span) => span,
+                None => return
+            },
+            None => (lo, hi)
+        };
+        let i = y - self.bottom;
+        let len = self.stencil.len() as i64;
+        if i < 0 || i >= len {
+            return;
+        }
+        let row_index = (len - 1 - i) as usize;
+        let stencil = &mut self.stencil[row_index][lo..hi + 1];
+        let mut ids = self.ids.get_mut(row_index).map(|row| &mut row[lo..hi + 1]);
+        let mut depth = match self.depth_plane {
+            Some(plane) => self.depth.get_mut(row_index).map(|row| (plane, &mut row[lo..hi + 1])),
+            None => None
+        };
+        if depth.is_none() && self.style.stencil == Stencil::Off && self.style.blend == Blend::Replace {
+            if let Some(ref mut ids) = ids {
+                for id in ids.iter_mut() {
+                    *id = self.style.object;
+                }
+            }
+            return self.canvas.write_span(y, lo, hi, color);
+        }
+        let (mut n, mut rejects) = (0, 0);
+        if let Some(row) = self.canvas.row_mut(y) {
+            let span = &mut row[lo..hi + 1];
+            // The depth of the plane at, and whether it's hidden at, each
+            // pixel of a run of `LANES` of them
+            let (mut z, mut hidden) = ([0.0; LANES], [false; LANES]);
+            let plain = self.style.stencil == Stencil::Off && self.style.blend == Blend::Replace;
+            for start in (0..span.len()).step_by(LANES) {
+                let end = cmp::min(start + LANES, span.len());
+                if let Some((plane, ref zs)) = depth {
+                    depth_test(plane, y, lo + start, &zs[start..end], &mut z, &mut hidden);
+                }
+                if let (true, &mut Some((_, ref mut zs))) = (plain, &mut depth) {
+                    // Only the depth test decides what's drawn
+                    for j in start..end {
+                        if !hidden[j - start] {
+                            span[j] = color;
+                            zs[j] = z[j - start];
+                            if let Some(ref mut ids) = ids {
+                                ids[j] = self.style.object;
+                            }
+                            n += 1;
+                        } else {
+                            rejects += 1;
+                        }
+                    }
+                    continue;
+                }
+                for j in start..end {
+                    let (px, s) = (&mut span[j], &mut stencil[j]);
+                    if hidden[j - start] {
+                        // The stencil buffer is still written wherever the
+                        // shape covers, as by `shade_triangle_list`
+                        if let Stencil::Write(v) = self.style.stencil {
+                            *s = v;
+                        } else {
+                            rejects += 1;
+                        }
+                        continue;
+                    }
+                    if self.style.apply(px, s, color) {
+                        if let Some((_, ref mut zs)) = depth {
+                            zs[j] = z[j - start];
+                        }
+                        if let Some(ref mut ids) = ids {
+                            ids[j] = self.style.object;
+                        }
+                        n += 1;
+                    }
+                }
+            }
+        }
+        self.canvas.filled(n);
+        self.canvas.z_rejected(rejects);
+    }
+
+    fn blend_pixel(&mut self, p: Point, color: Color, coverage: f64) {
+        // Mix in what the style's blend mode would draw on the pixel, so
+        // that a fully covered pixel is drawn as it would be without
+        // antialiasing
+        if let Some(dst) = pixel_at(self, p) {
+            let blend = self.style.blend;
+            self.style.blend = Blend::Replace;
+            self.plot(p, dst.lerp(blend.apply(dst, color), coverage));
+            self.style.blend = blend;
+        }
+    }
+}
+
+impl Canvas for Vec<Vec<Color>> {
+    fn row_mut(&mut self, y: i64) -> Option<&mut [Color]> {
+        let height = self.len() as i64;
+        if y >= 0 && y < height {
+            Some(&mut self[(height - 1 - y) as usize])
+        } else {
+            None
+        }
+    }
+
+    fn bounds(&self) -> Option<(Point, Point)> {
+        let width = self.first().map_or(0, |row| row.len());
+        Some((Point::xy(0, 0), Point::xy(width as i64 - 1, self.len() as i64 - 1)))
+    }
+}
+
+/// A screen is drawn on as its back buffer, with y going the way its y axis
+/// says (up, by default, as for other canvases).
+impl Canvas for Screen {
+    fn row_mut(&mut self, y: i64) -> Option<&mut [Color]> {
+        Screen::row_mut(self, y)
+    }
+
+    fn bounds(&self) -> Option<(Point, Point)> {
+        Some((Point::xy(0, 0), Point::xy(self.width() as i64 - 1, self.height() as i64 - 1)))
+    }
+}
+
+/// A horizontal band of an image, which can be drawn on independently of
+/// (and concurrently with) the other bands of the image.
+pub struct Band<'a> {
+    rows: &'a mut [Vec<Color>],
+    /// Height, in the whole image, of the bottom row of the band
+    bottom: i64
+}
+
+impl<'a> Band<'a> {
+    /// Split `image` into `n` bands of about equal height.
+    pub fn split(image: &'a mut [Vec<Color>], n: usize) -> Vec<Band<'a>> {
+        let height = image.len();
+        let per_band = cmp::max(1, (height + n - 1) / cmp::max(n, 1));
+        let mut bands = vec![];
+        let mut top = 0;
+        for rows in image.chunks_mut(per_band) {
+            top += rows.len();
+            bands.push(Band { rows, bottom: (height - top) as i64 });
+        }
+        bands
+    }
+
+    /// The number of rows in the band.
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Whether any of the heights from `lo` to `hi` are in the band.
+    pub fn overlaps(&self, lo: i64, hi: i64) -> bool {
+        lo < self.bottom + self.rows.len() as i64 && hi >= self.bottom
+    }
+}
+
+impl<'a> Canvas for Band<'a> {
+    fn row_mut(&mut self, y: i64) -> Option<&mut [Color]> {
+        let i = y - self.bottom;
+        let len = self.rows.len() as i64;
+        if i >= 0 && i < len {
+            Some(&mut self.rows[(len - 1 - i) as usize])
+        } else {
+            None
+        }
+    }
+
+    fn bounds(&self) -> Option<(Point, Point)> {
+        let width = self.rows.first().map_or(0, |row| row.len());
+        Some((Point::xy(0, self.bottom), Point::xy(width as i64 - 1, self.bottom + self.rows.len() as i64 - 1)))
+    }
+}
+
+/// Draw each triangle in a triangle list as `triangle_list` does, splitting
+/// the screen into bands of rows which `threads` threads draw on
+/// concurrently (see `for_each_band`).
+/// `rows_done` is called with the height of each band as it is finished.
+pub fn triangle_list_parallel(screen: &mut Screen, triangles: &PolygonList, styles: &[Style], colors: &[[Color; 3]], shading: Shading, threads: usize, rows_done: &(dyn Fn(usize) + Sync)) -> Drawn {
+    for_each_band(screen, threads, |rows: Rows| {
+        let height = rows.pixels.height();
+        let filled = triangle_list(rows, triangles, styles, colors, shading);
+        rows_done(height);
+        filled
+    })
+}
+
+/// Split `screen` into `threads` bands of rows, call `draw_band` on each
+/// concurrently, and add up what it returns. With one thread, the screen is
+/// drawn as a single band.
+#[cfg(not(feature = "parallel"))]
+pub fn for_each_band<F: Fn(Rows) -> Drawn + Sync>(screen: &mut Screen, threads: usize, draw_band: F) -> Drawn {
+    let draw_band = |rows: Rows| draw_timed(&draw_band, rows);
+    if threads <= 1 {
+        return screen.split_rows_mut(1).into_iter().map(draw_band).sum();
+    }
+    let draw_band = &draw_band;
+    thread::scope(|s| {
+        let handles: Vec<_> = screen.split_rows_mut(threads).into_iter().map(|rows| {
+            s.spawn(move || draw_band(rows))
+        }).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    })
+}
+
+/// Rows in each tile of the screen drawn on by `for_each_band`, with the
+/// `parallel` feature
+#[cfg(feature = "parallel")]
+const TILE_ROWS: usize = 16;
+
+/// Split `screen` into tiles of `TILE_ROWS` rows, call `draw_band` on each
+/// on rayon's thread pool, and add up what it returns. There are many more
+/// tiles than threads, so a thread which finishes an empty part of the
+/// screen takes on another rather than waiting for the rest. With one
+/// thread, the screen is drawn as a single band.
+#[cfg(feature = "parallel")]
+pub fn for_each_band<F: Fn(Rows) -> Drawn + Sync + Send>(screen: &mut Screen, threads: usize, draw_band: F) -> Drawn {
+    let draw_band = |rows: Rows| draw_timed(&draw_band, rows);
+    if threads <= 1 {
+        return screen.split_rows_mut(1).into_iter().map(draw_band).sum();
+    }
+    let tiles = (screen.height() + TILE_ROWS - 1) / TILE_ROWS;
+    screen.split_rows_mut(cmp::max(tiles, 1)).into_par_iter().map(draw_band).sum()
+}
+
+/// Call `draw_band` on `rows`, logging how long it took and how many pixels
+/// it drew, to show how evenly the work is split.
+fn draw_timed<F: Fn(Rows) -> Drawn>(draw_band: &F, rows: Rows) -> Drawn {
+    let (top, height) = (rows.top, rows.pixels.height());
+    let start = Instant::now();
+    let drawn = draw_band(rows);
+    debug!("Drew {} pixels in the {} rows from row {} in {:?}", drawn.pixels, height, top, start.elapsed());
+    drawn
+}
+
+/// A triangle list to fill, and how: each triangle in the matching style of
+/// `styles`, with the textures of those styles indices into `textures`. The
+/// screen is split into bands of rows drawn on by `threads` threads, and
+/// `rows_done` is called with the height of each band as it is finished.
+pub struct Fill<'a> {
+    pub triangles: &'a PolygonList,
+    pub styles: &'a [Style],
+    pub textures: &'a [Arc<Texture>],
+    pub threads: usize,
+    pub rows_done: &'a (dyn Fn(usize) + Sync)
+}
+
+/// Fill each triangle of `fill` with the colors `shader` gives (from the
+/// matching colors of `colors`, blended between its corners as by
+/// `gouraud_triangle_list`, or white), as `fill_fragments` does.
+pub fn shade_triangle_list(screen: &mut Screen, fill: &Fill, colors: &[[Color; 3]], shader: &Shader) -> Drawn {
+    let normals: Vec<_> = (0..fill.triangles.len()).map(|i| face_normal(fill.triangles, i)).collect();
+    fill_fragments(screen, fill, |f, y| {
+        let base = colors.get(f.triangle).map_or(Color::white(), |&corners| blend_corners(corners, f.weights));
+        shader.shade(f.x, y, f.z, normals[f.triangle], [f.weights[1], f.weights[2]], base)
+    })
+}
+
+/// Fill each triangle of `fill` with the matching colors of `colors` at its
+/// corners (or white, if there are none), blended across it by how near each
+/// pixel is to each corner (Gouraud shading), as `fill_fragments` does.
+pub fn gouraud_triangle_list(screen: &mut Screen, fill: &Fill, colors: &[[Color; 3]]) -> Drawn {
+    fill_fragments(screen, fill, |f, _| {
+        colors.get(f.triangle).map_or(Color::white(), |&corners| blend_corners(corners, f.weights))
+    })
+}
+
+/// Fill each triangle of `fill` with its material lit by `lighting` at each
+/// pixel, facing the direction blended across it from the matching normals
+/// of `normals` (those of its corners) (Phong shading), as `fill_fragments`
+/// does, in the shadows `shadows`. The screen is `scale` times the size of
+/// the coordinates the lights are in.
+pub fn phong_triangle_list(screen: &mut Screen, fill: &Fill, normals: &[[[f64; 3]; 3]], lighting: &Lighting, shadows: &Shadows, scale: f64) -> Drawn {
+    let styles = fill.styles;
+    fill_fragments(screen, fill, |f, y| {
+        let [a, b, c] = normals[f.triangle];
+        let w = f.weights;
+        let normal = lighting::normalized([
+            w[0] * a[0] + w[1] * b[0] + w[2] * c[0],
+            w[0] * a[1] + w[1] * b[1] + w[2] * c[1],
+            w[0] * a[2] + w[1] * b[2] + w[2] * c[2]
+        ]);
+        lighting.phong(&styles[f.triangle].material, [f.x as f64 / scale, y as f64 / scale, f.z], normal, shadows)
+    })
+}
+
+/// The texture coordinates of a point of a triangle with the weights
+/// `weights` (as in `Fragment`), given those of its corners
+pub fn blend_uvs(corners: [[f64; 2]; 3], weights: [f64; 3]) -> [f64; 2] {
+    let coord = |c: usize| weights[0] * corners[0][c] + weights[1] * corners[1][c] + weights[2] * corners[2][c];
+    [coord(0), coord(1)]
+}
+
+/// `color` multiplied channel by channel by `by`, as if each went from 0 to 1
+pub fn modulate(color: Color, by: Color) -> Color {
+    let mul = |p: u8, q: u8| ((p as u32 * q as u32 + 127) / 255) as u8;
+    Color::rgba(mul(color.r, by.r), mul(color.g, by.g), mul(color.b, by.b), color.a)
+}
+
+/// The color of a point of a triangle with the weights `weights` (as in
+/// `Fragment`), given the colors of its corners
+fn blend_corners(corners: [Color; 3], weights: [f64; 3]) -> Color {
+    let channel = |c: fn(&Color) -> u8| {
+        weights[0] * c(&corners[0]) as f64 + weights[1] * c(&corners[1]) as f64 + weights[2] * c(&corners[2]) as f64
+    };
+    Color::clamped(channel(|c| c.r), channel(|c| c.g), channel(|c| c.b))
+}
+
+/// Fill each triangle of `fill` with the color `color` gives for each of its
+/// fragments and the fragment's height (from the bottom), keeping the
+/// nearest at each pixel by the screen's depth buffer. Triangles whose
+/// styles have textures have the color multiplied by the texture's there.
+/// Triangles which only write to the stencil buffer don't change the depth
+/// buffer.
+fn fill_fragments<F>(screen: &mut Screen, fill: &Fill, color: F) -> Drawn
+    where F: Fn(&Fragment, usize) -> Color + Sync + Send
+{
+    let (width, height) = (screen.width(), screen.height());
+    let Fill { triangles, styles, textures, threads, rows_done } = *fill;
+    let color = &color;
+    for_each_band(screen, threads, |rows: Rows| {
+        let Rows { top, mut pixels, depth, stencil, ids } = rows;
+        let mut band = Counted::new(&mut pixels);
+        for row in depth.iter_mut() {
+            for z in row.iter_mut() {
+                *z = f64::NEG_INFINITY;
+            }
+        }
+        fragments(triangles, width, height, (top, top + depth.len()), |f| {
+            let style = styles[f.triangle];
+            let y = height - 1 - f.row;
+            if style.scissor.is_some_and(|r| !r.contains(f.x as i64, y as i64)) {
+                return;
+            }
+            let s = &mut stencil[f.row - top][f.x];
+            if let Stencil::Write(v) = style.stencil {
+                *s = v;
+                return;
+            }
+            let z = &mut depth[f.row - top][f.x];
+            if !style.stencil.passes(*s) {
+                return;
+            }
+            if f.z > *z {
+                *z = f.z;
+                let mut color = color(&f, y);
+                if let Some(texture) = style.texture {
+                    color = modulate(color, textures[texture].sample(blend_uvs(triangles.uvs(f.triangle), f.weights)));
+                }
+                if let Some(row) = band.row_mut(y as i64) {
+                    row[f.x] = style.blend.apply(row[f.x], color);
+                }
+                if let Some(ids) = ids.get_mut(f.row - top) {
+                    ids[f.x] = style.object;
+                }
+                band.filled(1);
+            } else {
+                band.z_rejected(1);
+            }
+        });
+        rows_done(band.canvas.height());
+        band.drawn()
+    })
+}
+
+/// A pixel covered by a triangle, as found by `fragments`
+#[derive(Debug, Clone, Copy)]
+pub struct Fragment {
+    /// Column of the pixel
+    pub x: usize,
+    /// Row of the pixel, counted from the top as in the screen
+    pub row: usize,
+    /// Depth of the triangle at the pixel's center
+    pub z: f64,
+    /// Index of the triangle in its triangle list
+    pub triangle: usize,
+    /// Barycentric coordinates of the pixel's center: the weights of the
+    /// triangle's corners, in order, corrected for perspective (see
+    /// `perspective_correct`)
+    pub weights: [f64; 3]
+}
+
+/// Call `f` with each pixel of a `width` by `height` screen whose center is
+/// covered by a triangle of the triangle list `triangles`. Rows are counted
+/// from the top, as in the screen, and only those from `rows.0` up to (but
+/// not including) `rows.1` are covered. A center on an edge shared by two
+/// triangles is covered by only one of them. The corners are snapped to
+/// 1/256 of a pixel, and the edge functions stepped from pixel to pixel in
+/// fixed point, unless they're too far off for it.
+pub fn fragments<F: FnMut(Fragment)>(triangles: &PolygonList, width: usize, height: usize, rows: (usize, usize), mut f: F) {
+    // The heights (counted from the bottom) of the rows
+    let (bottom, top) = ((height - rows.1) as f64, (height - rows.0) as f64 - 1.0);
+    for i in 0..triangles.len() {
+        let [a, b, c] = triangles.triangle(i);
+        let lo_x = a[0].min(b[0]).min(c[0]).floor().max(0.0) as usize;
+        let hi_x = a[0].max(b[0]).max(c[0]).ceil().min(width as f64 - 1.0);
+        let lo_y = a[1].min(b[1]).min(c[1]).floor().max(bottom) as usize;
+        let hi_y = a[1].max(b[1]).max(c[1]).ceil().min(top);
+        if hi_x < 0.0 || hi_y < bottom {
+            continue;
+        }
+        let (hi_x, hi_y) = (hi_x as usize, hi_y as usize);
+        let q = [a[3], b[3], c[3]];
+        let flat = q[0] == q[1] && q[1] == q[2];
+        let mut fragment = |x: usize, y: usize, weights: [f64; 3]| f(Fragment {
+            x,
+            row: height - 1 - y,
+            z: weights[0] * a[2] + weights[1] * b[2] + weights[2] * c[2],
+            triangle: i,
+            weights: if flat { weights } else { perspective_correct(weights, q) }
+        });
+        if [a, b, c].iter().all(|p| p[0].abs() < FIXED_REACH as f64 && p[1].abs() < FIXED_REACH as f64) {
+            // Step the edge functions from pixel to pixel in fixed point
+            let fixed = |p: [f64; 4]| [(p[0] * SUB_PIXELS).round() as i64, (p[1] * SUB_PIXELS).round() as i64];
+            let (edges, area) = edge_functions([fixed(a), fixed(b), fixed(c)]);
+            if area == 0 {
+                continue;
+            }
+            let steps = [edges[0].a << SUB_BITS, edges[1].a << SUB_BITS, edges[2].a << SUB_BITS];
+            let area = area as f64;
+            for y in lo_y..hi_y + 1 {
+                let mut e = [0; 3];
+                for k in 0..3 {
+                    e[k] = edges[k].at(lo_x as i64, y as i64);
+                }
+                for x in lo_x..hi_x + 1 {
+                    if edges[0].covers(e[0]) && edges[1].covers(e[1]) && edges[2].covers(e[2]) {
+                        fragment(x, y, [e[0] as f64 / area, e[1] as f64 / area, e[2] as f64 / area]);
+                    }
+                    for k in 0..3 {
+                        e[k] += steps[k];
+                    }
+                }
+            }
+        } else {
+            // Too far off to step in fixed point without overflowing
+            let (mut b, mut c) = (b, c);
+            let mut area = edge_function(a, b, c[0], c[1]);
+            let swapped = area < 0.0;
+            if swapped {
+                // Go around the other way, so each edge function is positive
+                // inside
+                mem::swap(&mut b, &mut c);
+                area = -area;
+            }
+            if area == 0.0 {
+                continue;
+            }
+            let owned = [owns_edge(b, c), owns_edge(c, a), owns_edge(a, b)];
+            for y in lo_y..hi_y + 1 {
+                for x in lo_x..hi_x + 1 {
+                    let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+                    // Barycentric coordinates of the pixel's center
+                    let wa = edge_function(b, c, px, py) / area;
+                    let wb = edge_function(c, a, px, py) / area;
+                    let wc = edge_function(a, b, px, py) / area;
+                    if inside(wa, owned[0]) && inside(wb, owned[1]) && inside(wc, owned[2]) {
+                        fragment(x, y, if swapped { [wa, wc, wb] } else { [wa, wb, wc] });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The weights of corners with the reciprocals of their w `q`, from their
+/// weights `weights` on the screen. Only depth (which goes with 1/w) blends
+/// evenly across the screen under a perspective projection; anything else,
+/// such as texture coordinates, blends evenly as itself over w, so a corner
+/// counts for its weight over its w, out of the total.
+pub fn perspective_correct(weights: [f64; 3], q: [f64; 3]) -> [f64; 3] {
+    let scaled = [weights[0] * q[0], weights[1] * q[1], weights[2] * q[2]];
+    let total = scaled[0] + scaled[1] + scaled[2];
+    if total == 0.0 {
+        return weights;
+    }
+    [scaled[0] / total, scaled[1] / total, scaled[2] / total]
+}
+
+/// Fractional bits of the fixed-point positions `fragments` and
+/// `fill_triangle` step through triangles at
+const SUB_BITS: u32 = 8;
+
+/// Steps in each pixel, in that fixed point
+const SUB_PIXELS: f64 = (1 << SUB_BITS) as f64;
+
+/// How far from the origin, in pixels, a triangle's corners can be to step
+/// through it in fixed point without overflowing
+const FIXED_REACH: i64 = 1 << 21;
+
+/// The edge function of an edge of a triangle in fixed point, `a x + b y +
+/// c` at (x, y), which is twice the area of the triangle from the edge to
+/// that point: positive inside the triangle and zero along the edge
+#[derive(Debug, Clone, Copy)]
+struct EdgeFunction {
+    a: i64,
+    b: i64,
+    c: i64,
+    /// Whether points on the edge are in the triangle (see `owns_edge`)
+    owned: bool
+}
+
+impl EdgeFunction {
+    /// The edge function of the edge from `p` to `q`, for the triangle on
+    /// its left
+    fn new(p: [i64; 2], q: [i64; 2]) -> EdgeFunction {
+        let (dx, dy) = (q[0] - p[0], q[1] - p[1]);
+        EdgeFunction {
+            a: -dy,
+            b: dx,
+            c: dy * p[0] - dx * p[1],
+            owned: dy < 0 || (dy == 0 && dx > 0)
+        }
+    }
+
+    /// The value at the center of the pixel in column `x` and row `y`
+    /// (counted from the bottom)
+    fn at(&self, x: i64, y: i64) -> i64 {
+        let center = |n: i64| (n << SUB_BITS) + (1 << (SUB_BITS - 1));
+        self.a * center(x) + self.b * center(y) + self.c
+    }
+
+    /// Whether a point where the edge function is `e` is inside the edge
+    fn covers(&self, e: i64) -> bool {
+        e > 0 || (e == 0 && self.owned)
+    }
+}
+
+/// The edge functions of the edges opposite each corner of the triangle
+/// with corners `corners` (in fixed point), going around it whichever way
+/// makes them positive inside, and twice its area.
+fn edge_functions(corners: [[i64; 2]; 3]) -> ([EdgeFunction; 3], i64) {
+    let [a, b, c] = corners;
+    let mut edges = [EdgeFunction::new(b, c), EdgeFunction::new(c, a), EdgeFunction::new(a, b)];
+    let mut area = edges[2].a * c[0] + edges[2].b * c[1] + edges[2].c;
+    if area < 0 {
+        edges = [EdgeFunction::new(c, b), EdgeFunction::new(a, c), EdgeFunction::new(b, a)];
+        area = -area;
+    }
+    (edges, area)
+}
+
+/// Twice the signed area of the triangle from `p` to `q` to (x, y).
+fn edge_function(p: [f64; 4], q: [f64; 4], x: f64, y: f64) -> f64 {
+    (q[0] - p[0]) * (y - p[1]) - (q[1] - p[1]) * (x - p[0])
+}
+
+/// Whether a point on the edge from `p` to `q` belongs to the triangle on
+/// its left. Of the two triangles sharing an edge, which go along it in
+/// opposite directions, exactly one owns it.
+fn owns_edge(p: [f64; 4], q: [f64; 4]) -> bool {
+    let (dx, dy) = (q[0] - p[0], q[1] - p[1]);
+    dy < 0.0 || (dy == 0.0 && dx > 0.0)
+}
+
+/// Whether a point with barycentric coordinate `w` for an edge is on the
+/// inside of it, given whether the triangle `owned` the edge.
+fn inside(w: f64, owned: bool) -> bool {
+    w > 0.0 || (w == 0.0 && owned)
+}
+
+/// The unit normal of triangle `i` of the triangle list `triangles`.
+pub fn face_normal(triangles: &PolygonList, i: usize) -> [f64; 3] {
+    let [a, b, c] = triangles.triangle(i);
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if len == 0.0 {
+        return [0.0, 0.0, 0.0];
+    }
+    [n[0] / len, n[1] / len, n[2] / len]
+}
+
+/// Fill the triangle with corners `p0`, `p1`, and `p2` with `color`, one
+/// scanline at a time, covering the same pixels as `fragments` does: those
+/// whose centers are inside it. The ends of each span are found from the
+/// edge functions in fixed point, so no floating-point math is done per
+/// scanline or per pixel.
+pub fn fill_triangle<C: Canvas + ?Sized>(image: &mut C, p0: Point, p1: Point, p2: Point, color: Color) {
+    // Corners farther off than fixed point reaches are pulled in to it
+    let clamp = |n: i64| n.clamp(-FIXED_REACH, FIXED_REACH) << SUB_BITS;
+    let corner = |p: Point| [clamp(p.x), clamp(p.y)];
+    let (edges, area) = edge_functions([corner(p0), corner(p1), corner(p2)]);
+    if area == 0 {
+        return;
+    }
+    let mut lo_y = cmp::min(p0.y, cmp::min(p1.y, p2.y));
+    let mut hi_y = cmp::max(p0.y, cmp::max(p1.y, p2.y));
+    let mut lo_x = cmp::min(p0.x, cmp::min(p1.x, p2.x));
+    let mut hi_x = cmp::max(p0.x, cmp::max(p1.x, p2.x));
+    if let Some((low, high)) = image.bounds() {
+        lo_y = cmp::max(lo_y, low.y);
+        hi_y = cmp::min(hi_y, high.y);
+        lo_x = cmp::max(lo_x, low.x);
+        hi_x = cmp::min(hi_x, high.x);
+    }
+    let mut sides = [Side::new(&edges[0], lo_y), Side::new(&edges[1], lo_y), Side::new(&edges[2], lo_y)];
+    for y in lo_y..hi_y + 1 {
+        let (mut lo, mut hi) = (lo_x, hi_x);
+        for side in sides.iter_mut() {
+            match *side {
+                Side::Left(ref column) => lo = cmp::max(lo, column.q),
+                Side::Right(ref column) => hi = cmp::min(hi, column.q),
+                Side::Level(e, _, owned) => if !(e > 0 || (e == 0 && owned)) {
+                    hi = lo - 1;
+                }
+            }
+            side.step();
+        }
+        if lo <= hi {
+            fill_span(image, y, lo, hi, color);
+        }
+    }
+}
+
+/// How an edge of a triangle bounds the span of it on a row, as
+/// `fill_triangle` goes up it a row at a time
+enum Side {
+    /// The first column inside the edge
+    Left(Stepped),
+    /// The last column inside the edge
+    Right(Stepped),
+    /// For a level edge, the edge function along the row, how much it
+    /// changes from row to row, and whether points on the edge are inside
+    Level(i64, i64, bool)
+}
+
+impl Side {
+    /// How `edge` bounds the row at height `y`
+    fn new(edge: &EdgeFunction, y: i64) -> Side {
+        // The edge function at the pixel in column x is `step * x + base`,
+        // and goes up by `rise` from row to row
+        let (step, rise) = (edge.a << SUB_BITS, edge.b << SUB_BITS);
+        let base = edge.at(0, y);
+        if step > 0 {
+            // The first x with `step * x + base > 0` (or `>= 0`)
+            Side::Left(Stepped::new(step - base - if edge.owned { 1 } else { 0 }, -rise, step))
+        } else if step < 0 {
+            // The last such x
+            Side::Right(Stepped::new(base - if edge.owned { 0 } else { 1 }, rise, -step))
+        } else {
+            Side::Level(base, rise, edge.owned)
+        }
+    }
+
+    fn step(&mut self) {
+        match *self {
+            Side::Left(ref mut column) | Side::Right(ref mut column) => column.step(),
+            Side::Level(ref mut e, rise, _) => *e += rise
+        }
+    }
+}
+
+/// `n / d`, rounded down, for a numerator `n` which goes up by the same
+/// amount each step, kept as a quotient and remainder so that it can be
+/// stepped without dividing
+struct Stepped {
+    q: i64,
+    r: i64,
+    dq: i64,
+    dr: i64,
+    d: i64
+}
+
+impl Stepped {
+    /// `n / d` (for positive `d`), with `n` going up by `dn` each step
+    fn new(n: i64, dn: i64, d: i64) -> Stepped {
+        let (q, dq) = (floor_div(n, d), floor_div(dn, d));
+        Stepped { q, r: n - q * d, dq, dr: dn - dq * d, d }
+    }
+
+    fn step(&mut self) {
+        self.q += self.dq;
+        self.r += self.dr;
+        if self.r >= self.d {
+            self.q += 1;
+            self.r -= self.d;
+        }
+    }
+}
+
+/// `n / d` rounded down, for positive `d`
+fn floor_div(n: i64, d: i64) -> i64 {
+    let q = n / d;
+    if n % d != 0 && n < 0 { q - 1 } else { q }
+}
+
+/// Fill the pixels from `x0` to `x1` (inclusive, in either order) on
+/// scanline `y`, skipping any that are off the screen.
+fn fill_span<C: Canvas + ?Sized>(image: &mut C, y: i64, x0: i64, x1: i64, color: Color) {
+    let width = match image.row_mut(y) {
+        Some(row) => row.len() as i64,
+        None => return
+    };
+    let (lo, hi) = if x0 < x1 { (x0, x1) } else { (x1, x0) };
+    let lo = cmp::max(lo, 0);
+    let hi = cmp::min(hi, width - 1);
+    if lo > hi {
+        return;
+    }
+    image.write_span(y, lo as usize, hi as usize, color);
+}
+
+/// Number of pixels of a span `depth_test` is given at a time
+const LANES: usize = 8;
+
+/// Find the depth of the plane `plane` (as in `Styled::depth_plane`) at
+/// each pixel of the row at height `y` from column `x` on, one for each of
+/// `zs` (the depths already there, no more than `LANES`), in `z`, and
+/// whether it's behind what's there in `hidden`. With the `simd` feature on
+/// x86-64, two pixels are done at once with SSE2 (which every x86-64
+/// processor has), and the last pixel, if there's an odd one, by itself.
+/// The depths are added up in the same order either way, so they're the
+/// same to the bit.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+fn depth_test(plane: [f64; 3], y: i64, x: usize, zs: &[f64], z: &mut [f64; LANES], hidden: &mut [bool; LANES]) {
+    let pairs = zs.len() / 2 * 2;
+    unsafe {
+        simd::depth_test_sse2(plane, y, x, &zs[..pairs], &mut z[..pairs], &mut hidden[..pairs]);
+    }
+    for j in pairs..zs.len() {
+        z[j] = plane[0] * (x + j) as f64 + plane[1] * y as f64 + plane[2];
+        hidden[j] = z[j] < zs[j];
+    }
+}
+
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+fn depth_test(plane: [f64; 3], y: i64, x: usize, zs: &[f64], z: &mut [f64; LANES], hidden: &mut [bool; LANES]) {
+    for j in 0..zs.len() {
+        z[j] = plane[0] * (x + j) as f64 + plane[1] * y as f64 + plane[2];
+        hidden[j] = z[j] < zs[j];
+    }
+}
+
+/// The vectorized kernel for `depth_test`
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd {
+    use std::arch::x86_64::*;
+
+    /// Does two pixels at a time, so there must be an even number of `zs`.
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn depth_test_sse2(plane: [f64; 3], y: i64, x: usize, zs: &[f64], z: &mut [f64], hidden: &mut [bool]) {
+        let a = _mm_set1_pd(plane[0]);
+        let by = _mm_set1_pd(plane[1] * y as f64);
+        let c = _mm_set1_pd(plane[2]);
+        for j in (0..zs.len()).step_by(2) {
+            let xs = _mm_set_pd((x + j + 1) as f64, (x + j) as f64);
+            let depth = _mm_add_pd(_mm_add_pd(_mm_mul_pd(a, xs), by), c);
+            _mm_storeu_pd(z.as_mut_ptr().add(j), depth);
+            let behind = _mm_movemask_pd(_mm_cmplt_pd(depth, _mm_loadu_pd(zs.as_ptr().add(j))));
+            hidden[j] = behind & 1 != 0;
+            hidden[j + 1] = behind & 2 != 0;
+        }
+    }
+}
+
+/// Set every pixel in `span` to `color`.
+fn set_pixels(span: &mut [Color], color: Color) {
+    for px in span {
+        *px = color;
     }
 }
 
 /// Bresenham's Line Algorithm for octant 1
-fn bline_oct1(image: &mut Vec<Vec<Color>>, mut start: Point, end: Point, color: Color) {
+fn bline_oct1<C: Canvas + ?Sized>(image: &mut C, mut start: Point, end: Point, color: Color) {
     let dx: i64 = end.x - start.x;
     let dy: i64 = end.y - start.y;
     let mut d: i64 = 2 * dy - dx;
@@ -148,24 +1639,24 @@ fn bline_oct1(image: &mut Vec<Vec<Color>>, mut start: Point, end: Point, color:
 }
 
 /// Bresenham's Line Algorithm for octant 2
-fn bline_oct2(image: &mut Vec<Vec<Color>>, mut start: Point, end: Point, color: Color) {
+fn bline_oct2<C: Canvas + ?Sized>(image: &mut C, mut start: Point, end: Point, color: Color) {
     let dx: i64 = end.x - start.x;
     let dy: i64 = end.y - start.y;
-    let mut d: i64 = 2 * dy - dx;
+    let mut d: i64 = 2 * dx - dy;
     // move `start` along the line and plot it as we go
     while start.y <= end.y {
         plot_if_visible(image, start, color);
+        start.y += 1;
+        d += dx;
         if d > 0 {
             start.x += 1;
             d -= dy;
         }
-        start.y += 1;
-        d += dx;
     }
 }
 
 /// Bresenham's Line Algorithm for octant 7
-fn bline_oct7(image: &mut Vec<Vec<Color>>, mut start: Point, end: Point, color: Color) {
+fn bline_oct7<C: Canvas + ?Sized>(image: &mut C, mut start: Point, end: Point, color: Color) {
     let dx: i64 = end.x - start.x;
     let dy: i64 = end.y - start.y;
     let mut d: i64 = dy + 2 * dx;
@@ -184,7 +1675,7 @@ fn bline_oct7(image: &mut Vec<Vec<Color>>, mut start: Point, end: Point, color:
 }
 
 /// Bresenham's Line Algorithm for octant 8
-fn bline_oct8(image: &mut Vec<Vec<Color>>, mut start: Point, end: Point, color: Color) {
+fn bline_oct8<C: Canvas + ?Sized>(image: &mut C, mut start: Point, end: Point, color: Color) {
     let dx: i64 = end.x - start.x;
     let dy: i64 = end.y - start.y;
     let mut d: i64 = 2 * dy + dx;
@@ -201,3 +1692,172 @@ fn bline_oct8(image: &mut Vec<Vec<Color>>, mut start: Point, end: Point, color:
         d += a;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use camera::Camera;
+    use geometry::EdgeList;
+
+    #[test]
+    fn hex_colors_can_be_shortened() {
+        assert_eq!(Color::from_hex("#ff8800"), Some(Color::rgb(255, 136, 0)));
+        assert_eq!(Color::from_hex("#f80"), Color::from_hex("#ff8800"));
+        assert_eq!(Color::from_hex("#1a2B3c80"), Some(Color::rgba(26, 43, 60, 128)));
+        assert_eq!(Color::from_hex("#f808"), Color::from_hex("#ff880088"));
+    }
+
+    #[test]
+    fn malformed_hex_colors_are_refused() {
+        for hex in &["ff8800", "#", "#ff88f", "#ff88000", "#gg8800", "#ff 800", "#ff880\u{e9}"] {
+            assert_eq!(Color::from_hex(hex), None, "{}", hex);
+        }
+    }
+
+    #[test]
+    fn hues_wrap_around_every_360_degrees() {
+        assert_eq!(Color::hsv(0.0, 1.0, 1.0), Color::rgb(255, 0, 0));
+        assert_eq!(Color::hsv(120.0, 1.0, 1.0), Color::rgb(0, 255, 0));
+        assert_eq!(Color::hsv(240.0, 1.0, 1.0), Color::rgb(0, 0, 255));
+        assert_eq!(Color::hsv(360.0, 1.0, 1.0), Color::hsv(0.0, 1.0, 1.0));
+        assert_eq!(Color::hsv(420.0, 1.0, 1.0), Color::rgb(255, 255, 0));
+        assert_eq!(Color::hsv(-60.0, 1.0, 1.0), Color::rgb(255, 0, 255));
+        // No saturation is gray, whatever the hue
+        assert_eq!(Color::hsv(77.0, 0.0, 0.5), Color::rgb(128, 128, 128));
+    }
+
+    #[test]
+    fn lerp_reaches_each_end_and_no_further() {
+        let (a, b) = (Color::rgba(0, 100, 200, 255), Color::rgba(255, 50, 0, 0));
+        assert_eq!(a.lerp(b, 0.0), a);
+        assert_eq!(a.lerp(b, 1.0), b);
+        assert_eq!(a.lerp(b, 0.5), Color::rgba(128, 75, 100, 128));
+        assert_eq!(a.lerp(b, -1.0), a);
+        assert_eq!(a.lerp(b, 2.0), b);
+    }
+
+    #[test]
+    fn channels_are_rounded_and_clamped() {
+        assert_eq!(Color::clamped(-20.0, 127.5, 300.0), Color::rgb(0, 128, 255));
+        assert_eq!(clamp_channel(254.4), 254);
+    }
+
+    /// Width and height of the images triangles are filled on
+    const SIZE: usize = 24;
+
+    /// The pixels (column, and row from the bottom) `fill_triangle` fills of
+    /// the triangle with `corners`
+    fn filled(corners: [[i64; 2]; 3]) -> HashSet<(usize, usize)> {
+        let mut image = vec![vec![Color::black(); SIZE]; SIZE];
+        let point = |c: [i64; 2]| Point::xy(c[0], c[1]);
+        fill_triangle(&mut image, point(corners[0]), point(corners[1]), point(corners[2]), Color::white());
+        let mut pixels = HashSet::new();
+        for y in 0..SIZE {
+            let row = image.row_mut(y as i64).unwrap();
+            for (x, px) in row.iter().enumerate() {
+                if *px == Color::white() {
+                    pixels.insert((x, y));
+                }
+            }
+        }
+        pixels
+    }
+
+    /// The pixels `fragments` gives of the triangles with `corners`, in
+    /// order, with any given twice
+    fn covered(triangles: &[[[i64; 2]; 3]]) -> Vec<(usize, usize)> {
+        let mut list = PolygonList::new();
+        for corners in triangles {
+            let point = |c: [i64; 2]| [c[0] as f64, c[1] as f64, 0.0, 1.0];
+            list.add_triangle(point(corners[0]), point(corners[1]), point(corners[2]));
+        }
+        let mut pixels = vec![];
+        fragments(&list, SIZE, SIZE, (0, SIZE), |f| pixels.push((f.x, SIZE - 1 - f.row)));
+        pixels
+    }
+
+    /// Triangles with flat tops and bottoms, slivers, both windings, and
+    /// corners off the image
+    const TRIANGLES: [[[i64; 2]; 3]; 10] = [
+        [[2, 3], [20, 3], [11, 18]],
+        [[2, 18], [11, 3], [20, 18]],
+        [[2, 3], [11, 18], [20, 3]],
+        [[0, 0], [23, 1], [23, 2]],
+        [[1, 1], [2, 22], [3, 1]],
+        [[0, 0], [23, 23], [22, 23]],
+        [[5, 5], [5, 15], [15, 5]],
+        [[-10, -4], [30, 8], [6, 40]],
+        [[4, 4], [4, 4], [12, 9]],
+        [[3, 7], [9, 7], [21, 7]]
+    ];
+
+    #[test]
+    fn fill_triangle_matches_fragments() {
+        for corners in TRIANGLES.iter() {
+            let by_fragments: HashSet<_> = covered(&[*corners]).into_iter().collect();
+            assert_eq!(filled(*corners), by_fragments, "{:?}", corners);
+        }
+    }
+
+    #[test]
+    fn shared_edges_are_covered_once() {
+        // A square cut along a diagonal, and a fan around a point in it
+        let square = [[[2, 2], [20, 2], [20, 20]], [[2, 2], [20, 20], [2, 20]]];
+        let fan = [[[11, 9], [2, 2], [20, 2]], [[11, 9], [20, 2], [20, 20]], [[11, 9], [20, 20], [2, 20]], [[11, 9], [2, 20], [2, 2]]];
+        for triangles in [&square[..], &fan[..]].iter() {
+            let pixels = covered(triangles);
+            let unique: HashSet<_> = pixels.iter().cloned().collect();
+            assert_eq!(pixels.len(), unique.len());
+            assert_eq!(unique.len(), 18 * 18);
+            let mut by_fill = HashSet::new();
+            for corners in triangles.iter() {
+                let f = filled(*corners);
+                assert!(by_fill.is_disjoint(&f), "{:?}", corners);
+                by_fill.extend(f);
+            }
+            assert_eq!(by_fill, unique);
+        }
+    }
+
+    #[test]
+    fn fragments_far_off_match_nearby() {
+        // Corners too far off for fixed point are covered in floating point,
+        // by the same rule: these cover every pixel right of x = 2 and above
+        // y = 3
+        for &reach in [1e5, 1e7].iter() {
+            let mut list = PolygonList::new();
+            list.add_triangle([2.0, 3.0, 0.0, 1.0], [reach, 3.0, 0.0, 1.0], [2.0, reach, 0.0, 1.0]);
+            let mut pixels = HashSet::new();
+            fragments(&list, SIZE, SIZE, (0, SIZE), |f| { pixels.insert((f.x, SIZE - 1 - f.row)); });
+            let expected: HashSet<_> = (2..SIZE).flat_map(|x| (3..SIZE).map(move |y| (x, y))).collect();
+            assert_eq!(pixels, expected, "{}", reach);
+        }
+    }
+
+    #[test]
+    fn weights_are_perspective_correct() {
+        // A triangle leaning away from the camera, so its far end is shrunk
+        let camera = Camera::new([0.0, 0.0, 100.0], [0.0, 0.0, 0.0]);
+        let world = [[-20.0, -15.0, 40.0, 1.0], [20.0, -15.0, 40.0, 1.0], [0.0, 15.0, -200.0, 1.0]];
+        let mut list = PolygonList::new();
+        list.add_triangle(world[0], world[1], world[2]);
+        let (projected, _) = camera.project_polygons(&list, SIZE, SIZE);
+        // All in view, so none of it is clipped off
+        assert_eq!(projected.len(), 1);
+        let mut count = 0;
+        fragments(&projected, SIZE, SIZE, (0, SIZE), |f| {
+            // The point blended from the corners in the world should be
+            // where the pixel's center is on the screen
+            let w = f.weights;
+            let blend = |c: usize| w[0] * world[0][c] + w[1] * world[1][c] + w[2] * world[2][c];
+            let mut point = EdgeList::new();
+            point.add_edge([blend(0), blend(1), blend(2), 1.0], [blend(0), blend(1), blend(2), 1.0]);
+            let seen = camera.project_edges(&point, SIZE, SIZE).col(0);
+            let center = [f.x as f64 + 0.5, (SIZE - 1 - f.row) as f64 + 0.5];
+            assert!((seen[0] - center[0]).abs() < 0.01 && (seen[1] - center[1]).abs() < 0.01, "{:?} {:?}", seen, center);
+            count += 1;
+        });
+        assert!(count > 30);
+    }
+}
@@ -0,0 +1,379 @@
+use matrix::Matrix;
+use geometry::{ EdgeList, PolygonList };
+use render::{ self, Color, Drawn, Fill, Shading, Style };
+use lighting::{ self, Lighting };
+use shadow::Shadows;
+use texture::Texture;
+use config::RenderConfig;
+use debug_view::{ self, View };
+use raytrace::{ self, Renderer };
+use screen::Screen;
+use stats::RenderStats;
+use timing::{ Timings, Stage };
+use std::thread;
+use std::sync::Arc;
+use random::Rng;
+use bounds::Aabb;
+use std::f64;
+
+/// Everything to be drawn in an image: its geometry, already transformed to
+/// screen coordinates, and the color behind it. The renderer takes it all at
+/// once, in `render`.
+pub struct Scene {
+    pub edges: EdgeList,
+    pub polygons: PolygonList,
+    /// How each edge and each triangle is drawn
+    pub edge_styles: Vec<Style>,
+    pub polygon_styles: Vec<Style>,
+    pub background: Color,
+    /// What lights the polygons, when they're lit
+    pub lighting: Lighting,
+    /// The textures polygons are painted with, by the index in their styles
+    pub textures: Vec<Arc<Texture>>,
+    /// The number of the animation frame it is (0 for a still image), which
+    /// randomized rendering is seeded with
+    pub frame: usize,
+    /// How many triangles a camera's view clipped as they were added
+    pub clipped: usize
+}
+
+impl Scene {
+    /// Make an empty scene with the given background.
+    pub fn new(background: Color) -> Scene {
+        Scene {
+            edges: EdgeList::new(),
+            polygons: PolygonList::new(),
+            edge_styles: vec![],
+            polygon_styles: vec![],
+            background,
+            lighting: Lighting::new(),
+            textures: vec![],
+            frame: 0,
+            clipped: 0
+        }
+    }
+
+    /// Remove all the geometry and textures from the scene, and go back to
+    /// the default lighting.
+    pub fn clear(&mut self) {
+        self.edges.clear();
+        self.polygons.clear();
+        self.edge_styles.clear();
+        self.polygon_styles.clear();
+        self.lighting = Lighting::new();
+        self.textures.clear();
+        self.clipped = 0;
+    }
+}
+
+/// Draw `scene` on `screen`, replacing whatever was there.
+pub fn render(scene: &Scene, screen: &mut Screen, config: &RenderConfig) -> RenderStats {
+    let (visible, styles) = visible_polygons(scene, config);
+    draw_visible(scene, &visible, &styles, screen, config, &mut Timings::new(false))
+}
+
+/// Draw `polygons` (the visible ones of `scene`, in the styles `styles`)
+/// and the edges of `scene` on `screen` with the renderer `config` chooses,
+/// timing lighting them and drawing them in `timings`. Debug views and
+/// wireframes are always rasterized.
+pub fn draw_visible(scene: &Scene, polygons: &PolygonList, styles: &[Style], screen: &mut Screen, config: &RenderConfig, timings: &mut Timings) -> RenderStats {
+    if config.renderer != Renderer::Raytrace || config.view != View::Shaded || config.shading == Shading::Wireframe {
+        let lit = timings.time(Stage::Shade, || light(scene, polygons, styles, config));
+        return timings.time(Stage::Rasterize, || rasterize(scene, polygons, styles, &lit, screen, config));
+    }
+    // The ray tracer lights each point as it finds it
+    let stats = timings.time(Stage::Rasterize, || raytrace::render(scene, polygons, styles, screen, config));
+    if config.gamma != 1.0 {
+        correct_gamma(&mut screen.pixels, config.gamma);
+    }
+    stats
+}
+
+/// Fill in the depth buffer of `screen` from the polygons of `scene` (all of
+/// them, whether culled or not), splitting it into `threads` bands of rows
+/// which are filled in concurrently.
+pub fn render_depth(scene: &Scene, screen: &mut Screen, threads: usize) {
+    let height = screen.depth.len();
+    let width = if height > 0 { screen.depth[0].len() } else { 0 };
+    thread::scope(|s| {
+        for rows in screen.split_rows_mut(threads) {
+            s.spawn(move || debug_view::fill_depth(&scene.polygons, width, height, rows.top, rows.depth));
+        }
+    });
+}
+
+/// Get the polygons of `scene` which are to be drawn, and their styles:
+/// those at least partly on the screen and facing the viewer (or whichever
+/// way, if culling is off).
+pub fn visible_polygons(scene: &Scene, config: &RenderConfig) -> (PolygonList, Vec<Style>) {
+    let mut polygons = scene.polygons.clone();
+    let mut styles = scene.polygon_styles.clone();
+    // Anything within this box may reach a pixel, even once jittered
+    let margin = 2.0;
+    let view = Aabb {
+        min: [-margin, -margin, f64::NEG_INFINITY],
+        max: [config.width as f64 + margin, config.height as f64 + margin, f64::INFINITY]
+    };
+    // Usually the whole scene is in view, and no triangle need be checked
+    if !polygons.bounds().is_none_or(|bounds| view.contains_sphere(&bounds.sphere)) {
+        cull_outside(&mut polygons, &mut styles, &view);
+    } else {
+        debug!("All {} triangles are on the screen", polygons.len());
+    }
+    if config.cull_backfaces {
+        render::cull_backfaces(&mut polygons, &mut styles);
+    }
+    (polygons, styles)
+}
+
+/// Remove the triangles of a triangle list which lie entirely outside `view`,
+/// and their styles, moving the rest to the front.
+fn cull_outside(triangles: &mut PolygonList, styles: &mut Vec<Style>, view: &Aabb) {
+    let mut kept = 0;
+    for i in 0..triangles.len() {
+        let corners = triangles.triangle(i);
+        if Aabb::of(&corners).is_some_and(|aabb| aabb.intersects(view)) {
+            triangles.copy_triangle(i, kept);
+            styles[kept] = styles[i];
+            kept += 1;
+        }
+    }
+    debug!("{} of {} triangles are off the screen", triangles.len() - kept, triangles.len());
+    triangles.truncate(kept);
+    styles.truncate(kept);
+}
+
+/// The lighting of the visible polygons of a frame which is worked out before
+/// they're drawn: the shadows falling on them, and the colors of their
+/// corners or the normals there, as the shading needs
+struct Lit {
+    shadows: Shadows,
+    colors: Vec<[Color; 3]>,
+    normals: Vec<[[f64; 3]; 3]>
+}
+
+/// Light `polygons` (the visible ones of `scene`, in the styles `styles`) as
+/// far as can be done before drawing them, as `config` says.
+fn light(scene: &Scene, polygons: &PolygonList, styles: &[Style], config: &RenderConfig) -> Lit {
+    // Lit before any supersampling stretches them
+    let shadows = if config.shadows && config.shading.is_lit() {
+        Shadows::new(&scene.lighting, polygons, config.shadow_resolution)
+    } else {
+        Shadows::none()
+    };
+    let colors = match config.shading {
+        Shading::Flat => lighting::flat_colors(polygons, styles, &scene.lighting, &shadows).into_iter().map(|color| [color; 3]).collect(),
+        Shading::Gouraud => lighting::gouraud_colors(polygons, styles, &scene.lighting, &shadows, config.crease_angle),
+        _ => vec![]
+    };
+    let normals = if config.shading == Shading::Phong {
+        lighting::vertex_normals(polygons, config.crease_angle)
+    } else {
+        vec![]
+    };
+    Lit { shadows, colors, normals }
+}
+
+/// Fill `screen` with the background of `scene` and draw `polygons` (the
+/// visible ones of the scene, in the styles `styles`, lit as `lit` says) and
+/// its edges on it.
+fn rasterize(scene: &Scene, polygons: &PolygonList, styles: &[Style], lit: &Lit, screen: &mut Screen, config: &RenderConfig) -> RenderStats {
+    let mut stats = RenderStats::default();
+    stats.submitted = scene.polygons.len();
+    stats.drawn = polygons.len();
+    stats.culled = stats.submitted - stats.drawn;
+    stats.clipped = scene.clipped;
+    let Lit { ref shadows, ref colors, ref normals } = *lit;
+    let drawn = if config.jitter > 1 && config.view == View::Shaded {
+        // Average several passes, each shifted by up to half a pixel. The
+        // shifts only depend on the seed and the frame, so a frame comes out
+        // the same whichever thread renders it, and in whatever order.
+        let mut rng = Rng::new(config.seed, scene.frame as u64);
+        let mut filled = Drawn::default();
+        let (mut shifted_polygons, mut shifted_edges) = (PolygonList::new(), EdgeList::new());
+        screen.clear_accumulation();
+        for _ in 0..config.jitter {
+            let (dx, dy) = (rng.range(-0.5, 0.5), rng.range(-0.5, 0.5));
+            // Shifted without a matrix, which would scale the shift by the
+            // last coordinate, 1/w after a camera
+            let shift = |p: [f64; 4]| [p[0] + dx, p[1] + dy, p[2], p[3]];
+            shifted_polygons.clear();
+            shifted_polygons.extend(polygons);
+            shifted_polygons.map_points(shift);
+            shifted_edges.clear();
+            shifted_edges.extend(&scene.edges);
+            shifted_edges.map_points(shift);
+            let drawing = Drawing {
+                polygons: &shifted_polygons,
+                styles,
+                colors,
+                normals,
+                shadows,
+                edges: &shifted_edges,
+                edge_styles: &scene.edge_styles
+            };
+            filled += draw_sampled(scene, &drawing, screen, config);
+            screen.accumulate(1.0 / config.jitter as f64);
+        }
+        screen.resolve_accumulation();
+        filled
+    } else {
+        let drawing = Drawing {
+            polygons,
+            styles,
+            colors,
+            normals,
+            shadows,
+            edges: &scene.edges,
+            edge_styles: &scene.edge_styles
+        };
+        draw_sampled(scene, &drawing, screen, config)
+    };
+    stats.pixels_filled = drawn.pixels;
+    stats.z_rejects = drawn.z_rejects;
+    // Debug views show data, which shouldn't be gamma corrected
+    if config.gamma != 1.0 && config.view == View::Shaded {
+        correct_gamma(&mut screen.pixels, config.gamma);
+    }
+    stats
+}
+
+/// What one pass of `draw` draws: the triangles and edges of a frame, in
+/// their styles, with the colors of the triangles' corners (if there are
+/// any), their normals for Phong shading, and the shadows falling on them.
+struct Drawing<'a> {
+    polygons: &'a PolygonList,
+    styles: &'a [Style],
+    colors: &'a [[Color; 3]],
+    normals: &'a [[[f64; 3]; 3]],
+    shadows: &'a Shadows,
+    edges: &'a EdgeList,
+    edge_styles: &'a [Style]
+}
+
+/// Draw `drawing` on `screen` over the background of `scene`, supersampling
+/// as `config` says, and return what was drawn.
+fn draw_sampled(scene: &Scene, drawing: &Drawing, screen: &mut Screen, config: &RenderConfig) -> Drawn {
+    if config.samples <= 1 {
+        return draw(screen, scene, drawing, 1, config);
+    }
+    // Draw everything `samples` times bigger, then shrink it back down
+    let n = config.samples;
+    let (width, height) = (screen.width(), screen.height());
+    let mut big = Screen::new(width * n, height * n, scene.background);
+    let scale = Matrix::dilation_xyz(n as f64, n as f64, 1.0);
+    if !screen.ids.is_empty() {
+        big.track_ids();
+    }
+    let big_polygons = drawing.polygons.transformed(&scale);
+    let big_styles: Vec<_> = drawing.styles.iter().map(|style| style.scaled(n)).collect();
+    let big_edges = drawing.edges.transformed(&scale);
+    let big_edge_styles: Vec<_> = drawing.edge_styles.iter().map(|style| style.scaled(n)).collect();
+    let big_drawing = Drawing {
+        polygons: &big_polygons,
+        styles: &big_styles,
+        edges: &big_edges,
+        edge_styles: &big_edge_styles,
+        ..*drawing
+    };
+    let filled = draw(&mut big, scene, &big_drawing, n, config);
+    downsample(&big.pixels, &mut screen.pixels, n);
+    // Each pixel goes to whatever drew the middle of its block
+    for (y, row) in screen.ids.iter_mut().enumerate() {
+        for (x, id) in row.iter_mut().enumerate() {
+            *id = big.ids[y * n + n / 2][x * n + n / 2];
+        }
+    }
+    filled
+}
+
+/// Draw `drawing` on `screen` over the background of `scene`, with a cleared
+/// stencil buffer, returning what was drawn (which isn't counted for debug
+/// views). It's drawn at `samples` times the final resolution.
+fn draw(screen: &mut Screen, scene: &Scene, drawing: &Drawing, samples: usize, config: &RenderConfig) -> Drawn {
+    let Drawing { polygons, styles, colors, normals, shadows, edges, edge_styles } = *drawing;
+    let rows_done = |rows| if let Some(ref tracker) = config.progress {
+        tracker.rows_done(rows);
+    };
+    if let Some(ref tracker) = config.progress {
+        tracker.start_rows(screen.height());
+    }
+    if config.view != View::Shaded {
+        for row in screen.pixels.iter_mut() {
+            for px in row.iter_mut() {
+                *px = Color::black();
+            }
+        }
+        debug_view::draw(&mut screen.pixels, polygons, config.view);
+        rows_done(screen.height());
+        return Drawn::default();
+    }
+    screen.clear(scene.background);
+    // Textures are painted on a pixel at a time, as Gouraud shading is, even
+    // when every corner is the same color
+    let textured = config.shading != Shading::Wireframe && styles.iter().any(|style| style.texture.is_some());
+    let textures = &scene.textures;
+    let fill = Fill { triangles: polygons, styles, textures, threads: config.threads, rows_done: &rows_done };
+    let filled = match config.shader {
+        Some(ref shader) => render::shade_triangle_list(screen, &fill, colors, shader),
+        None if config.shading == Shading::Phong => render::phong_triangle_list(screen, &fill, normals, &scene.lighting, shadows, samples as f64),
+        None if config.shading == Shading::Gouraud || textured => render::gouraud_triangle_list(screen, &fill, colors),
+        None => render::triangle_list_parallel(screen, polygons, styles, colors, config.shading, config.threads, &rows_done)
+    };
+    let mut image = render::Counted::new(&mut screen.pixels);
+    render::edge_list(&mut image, edges, edge_styles, &mut screen.depth, &mut screen.stencil, &mut screen.ids);
+    filled + image.drawn()
+}
+
+/// Set each pixel of `screen` to the average of the `n` by `n` block of
+/// pixels of `big` covering it.
+fn downsample(big: &[Vec<Color>], screen: &mut [Vec<Color>], n: usize) {
+    for (y, row) in screen.iter_mut().enumerate() {
+        for (x, px) in row.iter_mut().enumerate() {
+            let mut sum = [0u32; 3];
+            for big_row in &big[y * n..(y + 1) * n] {
+                for c in &big_row[x * n..(x + 1) * n] {
+                    sum[0] += c.r as u32;
+                    sum[1] += c.g as u32;
+                    sum[2] += c.b as u32;
+                }
+            }
+            let count = (n * n) as u32;
+            *px = Color::rgb((sum[0] / count) as u8, (sum[1] / count) as u8, (sum[2] / count) as u8);
+        }
+    }
+}
+
+/// Raise each channel of each pixel (as a fraction of 255) to `1 / gamma`.
+fn correct_gamma(screen: &mut [Vec<Color>], gamma: f64) {
+    let mut table = [0u8; 256];
+    for (i, v) in table.iter_mut().enumerate() {
+        *v = (255.0 * (i as f64 / 255.0).powf(1.0 / gamma)).round() as u8;
+    }
+    for row in screen.iter_mut() {
+        for px in row.iter_mut() {
+            *px = Color::rgb(table[px.r as usize], table[px.g as usize], table[px.b as usize]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exec;
+
+    #[test]
+    fn stats_count_clipped_triangles_and_depth_rejects() {
+        let config = RenderConfig::new().resolution(100, 100).shading(Shading::Filled);
+        // The second box is hidden behind the first; the third runs past the camera
+        let script = "camera 0 0 100 0 0 0\n\
+                      box -20 20 0 40 40 10\n\
+                      box -10 10 -20 20 20 10\n\
+                      box -5 5 150 10 10 100\n";
+        let (mut screen, scene, _) = exec::render_still(script, &config).unwrap();
+        let stats = render(&scene, &mut screen, &config);
+        assert!(stats.clipped > 0, "{:?}", stats);
+        assert!(stats.z_rejects > 0, "{:?}", stats);
+        assert!(stats.pixels_filled > 0, "{:?}", stats);
+    }
+}
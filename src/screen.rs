@@ -0,0 +1,382 @@
+use std::f64;
+use std::fs::File;
+use std::io::{ BufWriter, Write };
+use std::path::Path;
+use std::mem;
+use std::cmp;
+
+use render::{ Color, Band };
+use ppm::{ self, Format, PpmFormat };
+use error::{ Error, Result };
+
+/// The image being drawn, the depth of what was drawn at each pixel, the
+/// stencil buffer, and optionally the ID buffer. All are indexed top row
+/// first.
+///
+/// The screen is double buffered: `pixels` is the back buffer, which is drawn
+/// on, and the front buffer is the last image finished, which is what should
+/// be shown. `swap` makes the back buffer the front one once it is finished,
+/// so a viewer never shows a half-drawn image.
+pub struct Screen {
+    pub pixels: Vec<Vec<Color>>,
+    front: Vec<Vec<Color>>,
+    /// The z of the nearest polygon at each pixel (greater is nearer), or
+    /// negative infinity where there is none. It is only filled in by
+    /// `scene::render_depth`.
+    pub depth: Vec<Vec<f64>>,
+    /// A number for each pixel, which shapes can set and be masked by (see
+    /// `render::Stencil`)
+    pub stencil: Vec<Vec<u8>>,
+    /// The object (see `render::Style`) which last drew on each pixel, or 0
+    /// where none has. This is empty unless `track_ids` is called.
+    pub ids: Vec<Vec<u32>>,
+    /// Weighted sums of colors from several passes (see `accumulate`), or
+    /// nothing until the first pass
+    accumulation: Vec<Vec<[f64; 3]>>,
+    /// Which way y goes for `plot`, `row`, and `row_mut`
+    y_axis: YAxis
+}
+
+/// Which way y coordinates go on a screen, from 0 at one edge
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum YAxis {
+    /// From the bottom, as in the renderer (see `render::Canvas`)
+    Up,
+    /// From the top, as image viewers count rows
+    Down
+}
+
+impl Screen {
+    /// Make a `width` by `height` screen filled with `background`, with
+    /// nothing in the depth buffer and a stencil buffer of zeros.
+    pub fn new(width: usize, height: usize, background: Color) -> Screen {
+        Screen {
+            pixels: vec![vec![background; width]; height],
+            front: vec![vec![background; width]; height],
+            depth: vec![vec![f64::NEG_INFINITY; width]; height],
+            stencil: vec![vec![0; width]; height],
+            ids: vec![],
+            accumulation: vec![],
+            y_axis: YAxis::Up
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        if !self.pixels.is_empty() { self.pixels[0].len() } else { 0 }
+    }
+
+    pub fn height(&self) -> usize {
+        self.pixels.len()
+    }
+
+    /// Make `plot`, `row`, and `row_mut` count y from the bottom (the
+    /// default) or the top. The buffers themselves are still indexed top row
+    /// first.
+    pub fn set_y_axis(&mut self, y_axis: YAxis) {
+        self.y_axis = y_axis;
+    }
+
+    /// Index in the buffers of the row `y` up or down, by the y axis, if
+    /// it's on the screen
+    fn row_index(&self, y: i64) -> Option<usize> {
+        let height = self.height() as i64;
+        if y < 0 || y >= height {
+            return None;
+        }
+        match self.y_axis {
+            YAxis::Up => Some((height - 1 - y) as usize),
+            YAxis::Down => Some(y as usize)
+        }
+    }
+
+    /// The row of the back buffer at `y`, if it's on the screen.
+    pub fn row(&self, y: i64) -> Option<&[Color]> {
+        self.row_index(y).map(move |i| &self.pixels[i][..])
+    }
+
+    pub fn row_mut(&mut self, y: i64) -> Option<&mut [Color]> {
+        match self.row_index(y) {
+            Some(i) => Some(&mut self.pixels[i][..]),
+            None => None
+        }
+    }
+
+    /// Set the pixel of the back buffer at (x, y) to `color`, if it's on the
+    /// screen.
+    pub fn plot(&mut self, x: i64, y: i64, color: Color) {
+        if let Some(row) = self.row_mut(y) {
+            if x >= 0 && (x as usize) < row.len() {
+                row[x as usize] = color;
+            }
+        }
+    }
+
+    /// Fill the back buffer with `color`, and empty the depth buffer and
+    /// the stencil (and ID) buffer, to start a new image.
+    pub fn clear(&mut self, color: Color) {
+        for row in self.pixels.iter_mut() {
+            for px in row.iter_mut() {
+                *px = color;
+            }
+        }
+        for row in self.depth.iter_mut() {
+            for z in row.iter_mut() {
+                *z = f64::NEG_INFINITY;
+            }
+        }
+        self.clear_stencil();
+    }
+
+    /// The last image finished (see `swap`).
+    pub fn front(&self) -> &Vec<Vec<Color>> {
+        &self.front
+    }
+
+    /// Finish drawing on the back buffer, making it the front buffer. The
+    /// old front buffer becomes the back buffer, to be drawn over.
+    pub fn swap(&mut self) {
+        mem::swap(&mut self.pixels, &mut self.front);
+    }
+
+    /// Get the front buffer, dropping the rest of the screen.
+    pub fn into_front(self) -> Vec<Vec<Color>> {
+        self.front
+    }
+
+    /// Add the back buffer, times `weight`, to the accumulation buffer. Once
+    /// several passes have been drawn and accumulated, with weights adding
+    /// up to 1, `resolve_accumulation` puts their average in the back buffer.
+    /// Sums are kept in floating point, so nothing is lost to rounding until
+    /// the end.
+    pub fn accumulate(&mut self, weight: f64) {
+        if self.accumulation.len() != self.pixels.len() {
+            self.clear_accumulation();
+        }
+        for (acc_row, row) in self.accumulation.iter_mut().zip(self.pixels.iter()) {
+            for (acc, px) in acc_row.iter_mut().zip(row.iter()) {
+                acc[0] += weight * px.r as f64;
+                acc[1] += weight * px.g as f64;
+                acc[2] += weight * px.b as f64;
+            }
+        }
+    }
+
+    /// Empty the accumulation buffer, to start accumulating a new image.
+    pub fn clear_accumulation(&mut self) {
+        self.accumulation = vec![vec![[0.0; 3]; self.width()]; self.height()];
+    }
+
+    /// Copy the accumulation buffer to the back buffer, rounding and
+    /// clamping each channel.
+    pub fn resolve_accumulation(&mut self) {
+        for (row, acc_row) in self.pixels.iter_mut().zip(self.accumulation.iter()) {
+            for (px, acc) in row.iter_mut().zip(acc_row.iter()) {
+                *px = Color::clamped(acc[0], acc[1], acc[2]);
+            }
+        }
+    }
+
+    /// Set the whole stencil buffer, and the ID buffer if it's kept, back
+    /// to 0.
+    pub fn clear_stencil(&mut self) {
+        for row in self.stencil.iter_mut() {
+            for s in row.iter_mut() {
+                *s = 0;
+            }
+        }
+        for row in self.ids.iter_mut() {
+            for id in row.iter_mut() {
+                *id = 0;
+            }
+        }
+    }
+
+    /// Start keeping the ID buffer, so that `pick` works.
+    pub fn track_ids(&mut self) {
+        self.ids = vec![vec![0; self.width()]; self.height()];
+    }
+
+    /// The object which drew the pixel in column `x` and row `y` (counting
+    /// from the top, as image viewers do) when the screen was last drawn, if
+    /// any. This needs the ID buffer; see `track_ids`.
+    pub fn pick(&self, x: usize, y: usize) -> Option<u32> {
+        match self.ids.get(y).and_then(|row| row.get(x)) {
+            Some(&id) if id > 0 => Some(id),
+            _ => None
+        }
+    }
+
+    /// Count the pixels of the back buffer (the image being drawn) at each
+    /// level of each channel and of luminance.
+    pub fn histogram(&self) -> Histogram {
+        let mut histogram = Histogram {
+            red: [0; 256],
+            green: [0; 256],
+            blue: [0; 256],
+            luminance: [0; 256]
+        };
+        for c in self.pixels.iter().flat_map(|row| row.iter()) {
+            histogram.red[c.r as usize] += 1;
+            histogram.green[c.g as usize] += 1;
+            histogram.blue[c.b as usize] += 1;
+            histogram.luminance[luminance(c.r as f64, c.g as f64, c.b as f64).round() as usize] += 1;
+        }
+        histogram
+    }
+
+    /// Split the screen into `n` bands of rows of about equal height, which
+    /// can be drawn on independently (and sent to other threads).
+    pub fn split_rows_mut<'a>(&'a mut self, n: usize) -> Vec<Rows<'a>> {
+        let mut depth = &mut self.depth[..];
+        let mut stencil = &mut self.stencil[..];
+        let mut ids = &mut self.ids[..];
+        let mut top = 0;
+        let mut bands = vec![];
+        for band in Band::split(&mut self.pixels, n) {
+            let height = band.height();
+            let (band_depth, rest) = std::mem::take(&mut depth).split_at_mut(height);
+            depth = rest;
+            let (band_stencil, rest) = std::mem::take(&mut stencil).split_at_mut(height);
+            stencil = rest;
+            // The ID buffer might not be kept
+            let ids_height = cmp::min(height, ids.len());
+            let (band_ids, rest) = std::mem::take(&mut ids).split_at_mut(ids_height);
+            ids = rest;
+            bands.push(Rows { top, pixels: band, depth: band_depth, stencil: band_stencil, ids: band_ids });
+            top += height;
+        }
+        bands
+    }
+
+    /// Save the back buffer to `path` as a PPM of the kind `format`.
+    pub fn write_ppm(&self, path: &str, format: PpmFormat) -> Result<()> {
+        ppm::save_ppm(&self.pixels, path, format)
+    }
+
+    /// Save the back buffer to `path` as a BMP.
+    pub fn write_bmp(&self, path: &str) -> Result<()> {
+        ppm::save_as(&self.pixels, path, Format::Bmp)
+    }
+
+    /// Save the back buffer to `path` as a TGA.
+    pub fn write_tga(&self, path: &str) -> Result<()> {
+        ppm::save_as(&self.pixels, path, Format::Tga)
+    }
+
+    /// Save the back buffer to `path`, in the format its extension names (see
+    /// `ppm::save`), or as a plain PPM if it has none.
+    pub fn save(&self, path: &str) -> Result<()> {
+        ppm::save(&self.pixels, path, Format::Ppm)
+    }
+
+    /// Save the depth buffer to `path`. If its extension is `f32`, the depths
+    /// are written as they are, as little-endian 32-bit floats, a row at a
+    /// time from the top. Otherwise they are normalized to a grayscale image
+    /// (nearest white, farthest dark gray, and empty pixels black), which is
+    /// saved like the screen.
+    pub fn save_depth(&self, path: &str, default: Format) -> Result<()> {
+        if Path::new(path).extension().is_some_and(|ext| ext == "f32") {
+            let mut file = BufWriter::new(File::create(path).map_err(|e| Error::io(path, e))?);
+            for &z in self.depth.iter().flat_map(|row| row.iter()) {
+                file.write_all(&(z as f32).to_le_bytes()).map_err(|e| Error::io(path, e))?;
+            }
+            return file.flush().map_err(|e| Error::io(path, e));
+        }
+        ppm::save(&depth_image(&self.depth), path, default)
+    }
+}
+
+/// A band of rows of a `Screen`, made by `Screen::split_rows_mut`
+pub struct Rows<'a> {
+    /// Index in the screen of the top row of the band
+    pub top: usize,
+    pub pixels: Band<'a>,
+    /// The depth buffer of the rows, top row first
+    pub depth: &'a mut [Vec<f64>],
+    /// The stencil buffer of the rows, top row first
+    pub stencil: &'a mut [Vec<u8>],
+    /// The ID buffer of the rows, top row first, or nothing if it isn't kept
+    pub ids: &'a mut [Vec<u32>]
+}
+
+/// How many pixels of an image have each level (0 to 255) of red, green,
+/// blue, and luminance
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    pub red: [usize; 256],
+    pub green: [usize; 256],
+    pub blue: [usize; 256],
+    pub luminance: [usize; 256]
+}
+
+impl Histogram {
+    /// The number of pixels counted
+    pub fn pixels(&self) -> usize {
+        self.red.iter().sum()
+    }
+
+    pub fn stats(&self) -> ImageStats {
+        let pixels = self.pixels();
+        let mean = |counts: &[usize; 256]| {
+            let sum: usize = counts.iter().enumerate().map(|(level, &n)| level * n).sum();
+            if pixels > 0 { sum as f64 / pixels as f64 } else { 0.0 }
+        };
+        let min = |counts: &[usize; 256]| counts.iter().position(|&n| n > 0).unwrap_or(0) as u8;
+        let max = |counts: &[usize; 256]| counts.iter().rposition(|&n| n > 0).unwrap_or(0) as u8;
+        let mean = [mean(&self.red), mean(&self.green), mean(&self.blue)];
+        ImageStats {
+            mean,
+            mean_luminance: luminance(mean[0], mean[1], mean[2]),
+            min: Color::rgb(min(&self.red), min(&self.green), min(&self.blue)),
+            max: Color::rgb(max(&self.red), max(&self.green), max(&self.blue))
+        }
+    }
+}
+
+/// A summary of the colors of an image, for checking its exposure
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageStats {
+    /// The average red, green, and blue, from 0 to 255
+    pub mean: [f64; 3],
+    /// The average luminance, from 0 to 255
+    pub mean_luminance: f64,
+    /// The lowest and highest level of each channel
+    pub min: Color,
+    pub max: Color
+}
+
+/// The luminance of a color (with channels from 0 to 255), by the Rec. 709
+/// weights.
+fn luminance(r: f64, g: f64, b: f64) -> f64 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Map `depth` to grayscale: the nearest depth to white, the farthest to
+/// dark gray, and empty pixels to black.
+pub fn depth_image(depth: &[Vec<f64>]) -> Vec<Vec<Color>> {
+    let (near, far) = depth_range(depth);
+    depth.iter().map(|row| row.iter().map(|&z| {
+        if z == f64::NEG_INFINITY {
+            Color::black()
+        } else {
+            // The farthest polygons are never quite black
+            let t = if near > far { (z - far) / (near - far) } else { 1.0 };
+            let v = (32.0 + 223.0 * t) as u8;
+            Color::rgb(v, v, v)
+        }
+    }).collect()).collect()
+}
+
+/// The nearest and farthest depths in `depth`, ignoring empty pixels, or
+/// (0, 0) if every pixel is empty.
+fn depth_range(depth: &[Vec<f64>]) -> (f64, f64) {
+    let mut near = f64::NEG_INFINITY;
+    let mut far = f64::INFINITY;
+    for &z in depth.iter().flat_map(|row| row.iter()) {
+        if z != f64::NEG_INFINITY {
+            near = near.max(z);
+            far = far.min(z);
+        }
+    }
+    if near == f64::NEG_INFINITY { (0.0, 0.0) } else { (near, far) }
+}
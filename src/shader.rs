@@ -0,0 +1,114 @@
+use render::Color;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// A function run for each pixel of each filled polygon, which decides the
+/// pixel's color. Share one between threads (and `RenderConfig`s) in an
+/// `Arc`. It's called as `shade(x, y, depth, normal, uv, base)`, where
+///
+/// * `x` and `y` are the pixel's column and height, counted from the bottom
+///   left corner of the image;
+/// * `depth` is the polygon's z there (bigger is nearer);
+/// * `normal` is the polygon's unit normal;
+/// * `uv` are the weights of the polygon's second and third corners there,
+///   so (0, 0) at the first corner, (1, 0) at the second and (0, 1) at the
+///   third;
+/// * `base` is the color the polygon would have been filled with.
+///
+/// Only the nearest polygon at each pixel is kept, but the shader may be
+/// called for the pixel more than once.
+pub struct Shader {
+    shade: Box<ShadeFn>
+}
+
+/// The function a `Shader` calls for each pixel
+type ShadeFn = dyn Fn(usize, usize, f64, [f64; 3], [f64; 2], Color) -> Color + Send + Sync;
+
+impl Shader {
+    pub fn new<F>(shade: F) -> Shader
+        where F: Fn(usize, usize, f64, [f64; 3], [f64; 2], Color) -> Color + Send + Sync + 'static
+    {
+        Shader { shade: Box::new(shade) }
+    }
+
+    /// One of the built-in shaders, by name: `lambert`, which lights polygons
+    /// by how directly they face the viewer, or `checker`, which paints a
+    /// checkerboard across each polygon.
+    pub fn from_name(name: &str) -> Option<Shader> {
+        match name {
+            "lambert" => Some(Shader::new(|_, _, _, normal, _, base| scale(base, normal[2].abs()))),
+            "checker" => Some(Shader::new(|_, _, _, _, uv, base| {
+                const SQUARES: f64 = 4.0;
+                if ((uv[0] * SQUARES) as usize + (uv[1] * SQUARES) as usize).is_multiple_of(2) {
+                    base
+                } else {
+                    scale(base, 0.25)
+                }
+            })),
+            _ => None
+        }
+    }
+
+    pub fn shade(&self, x: usize, y: usize, depth: f64, normal: [f64; 3], uv: [f64; 2], base: Color) -> Color {
+        (self.shade)(x, y, depth, normal, uv, base)
+    }
+}
+
+impl fmt::Debug for Shader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Shader")
+    }
+}
+
+/// A function run on each vertex of each shape as it's drawn, after the
+/// shape is transformed by the coordinate system but before it's projected
+/// onto the screen, which can move the vertex. Share one in an `Arc`, like a
+/// `Shader`. It's called as `displace(vertex, frame)`, where `frame` is the
+/// number of the animation frame being drawn (0 for a still image), so that
+/// effects can move over time.
+pub struct VertexShader {
+    displace: Box<dyn Fn([f64; 4], usize) -> [f64; 4] + Send + Sync>
+}
+
+impl VertexShader {
+    pub fn new<F>(displace: F) -> VertexShader
+        where F: Fn([f64; 4], usize) -> [f64; 4] + Send + Sync + 'static
+    {
+        VertexShader { displace: Box::new(displace) }
+    }
+
+    /// One of the built-in vertex shaders, by name: `wave`, which ripples
+    /// shapes up and down in a wave travelling to the right, or `wobble`,
+    /// which sways them from side to side.
+    pub fn from_name(name: &str) -> Option<VertexShader> {
+        // In pixels, pixels, and frames
+        const AMPLITUDE: f64 = 10.0;
+        const WAVELENGTH: f64 = 100.0;
+        const PERIOD: f64 = 30.0;
+        let phase = |d: f64, frame: usize| 2.0 * PI * (d / WAVELENGTH - frame as f64 / PERIOD);
+        match name {
+            "wave" => Some(VertexShader::new(move |v, frame| {
+                [v[0], v[1] + AMPLITUDE * phase(v[0], frame).sin(), v[2], v[3]]
+            })),
+            "wobble" => Some(VertexShader::new(move |v, frame| {
+                [v[0] + AMPLITUDE * phase(v[1], frame).sin(), v[1], v[2], v[3]]
+            })),
+            _ => None
+        }
+    }
+
+    pub fn displace(&self, vertex: [f64; 4], frame: usize) -> [f64; 4] {
+        (self.displace)(vertex, frame)
+    }
+}
+
+impl fmt::Debug for VertexShader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "VertexShader")
+    }
+}
+
+/// Multiply each channel of `color` by `k`, from 0 to 1.
+fn scale(color: Color, k: f64) -> Color {
+    Color::clamped(color.r as f64 * k, color.g as f64 * k, color.b as f64 * k)
+}
@@ -0,0 +1,240 @@
+use std::f64;
+
+use geometry::PolygonList;
+use lighting::{ Light, Lighting, dot, normalized };
+
+/// How far, in texels of a shadow map, a point must be behind what the light
+/// sees there to be in shadow, so surfaces don't shadow themselves
+const BIAS: f64 = 2.0;
+
+/// How far, in texels, points are moved out along their normals before
+/// they're looked up, so surfaces the light just grazes don't speckle
+/// themselves with shadow
+const NORMAL_OFFSET: f64 = 1.5;
+
+/// Points nearer a point light than this (in pixels, along the way it
+/// faces) are left out of its shadow map
+const NEAR: f64 = 1.0;
+
+/// How far from straight ahead a point light's shadow map reaches, as the
+/// tangent of the angle
+const MAX_SPREAD: f64 = 6.0;
+
+/// What a light sees: for each texel, how near to the light the nearest
+/// surface there is. Directional lights see the scene straight on, and point
+/// lights in perspective, looking towards its middle.
+#[derive(Debug, Clone)]
+pub struct ShadowMap {
+    /// Where a point light is, or `None` for a directional light
+    eye: Option<[f64; 3]>,
+    /// The way the light looks, and the directions of the map's columns and
+    /// rows
+    forward: [f64; 3],
+    right: [f64; 3],
+    up: [f64; 3],
+    /// The least coordinates across the map, and the size of a texel
+    min: [f64; 2],
+    texel: f64,
+    size: usize,
+    /// Nearness of the nearest surface at each texel: distance towards a
+    /// directional light, or the reciprocal of the distance from a point light
+    /// along `forward`. Texels which see nothing are negative infinity.
+    nearness: Vec<f64>
+}
+
+impl ShadowMap {
+    /// The shadow map of `light` of the triangles in `triangles` (in screen
+    /// coordinates), `size` texels on each side, or `None` if the light sees
+    /// none of them.
+    pub fn new(light: &Light, triangles: &PolygonList, size: usize) -> Option<ShadowMap> {
+        let points: Vec<[f64; 3]> = (0..triangles.len())
+            .flat_map(|i| triangles.triangle(i).to_vec())
+            .map(|p| [p[0], p[1], p[2]])
+            .collect();
+        if points.is_empty() || size == 0 {
+            return None;
+        }
+        let (eye, forward) = match *light {
+            Light::Directional { direction, .. } => (None, normalized([-direction[0], -direction[1], -direction[2]])),
+            Light::Point { position, .. } => {
+                let n = points.len() as f64;
+                let mut center = [0.0; 3];
+                for p in &points {
+                    for c in 0..3 {
+                        center[c] += p[c] / n;
+                    }
+                }
+                let forward = normalized([center[0] - position[0], center[1] - position[1], center[2] - position[2]]);
+                if dot(forward, forward) == 0.0 {
+                    return None;
+                }
+                (Some(position), forward)
+            }
+        };
+        let (right, up) = basis(forward);
+        let mut map = ShadowMap { eye, forward, right, up, min: [0.0; 2], texel: 1.0, size, nearness: vec![f64::NEG_INFINITY; size * size] };
+        let (mut min, mut max) = ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]);
+        for &p in &points {
+            if let Some((uv, _)) = map.project(p) {
+                for c in 0..2 {
+                    let coord = if map.eye.is_some() { uv[c].clamp(-MAX_SPREAD, MAX_SPREAD) } else { uv[c] };
+                    min[c] = min[c].min(coord);
+                    max[c] = max[c].max(coord);
+                }
+            }
+        }
+        if min[0] > max[0] {
+            return None;
+        }
+        let extent = (max[0] - min[0]).max(max[1] - min[1]);
+        map.min = min;
+        // So the outermost points fall on the outermost texels
+        map.texel = if extent > 0.0 { extent / (size as f64 - 1.0).max(1.0) } else { 1.0 };
+        for i in 0..triangles.len() {
+            let corners = triangles.triangle(i);
+            let mut projected = [([0.0; 2], 0.0); 3];
+            let mut seen = true;
+            for (out, p) in projected.iter_mut().zip(corners.iter()) {
+                match map.project([p[0], p[1], p[2]]) {
+                    Some((uv, nearness)) => *out = (map.texel_coords(uv), nearness),
+                    None => seen = false
+                }
+            }
+            if seen {
+                map.fill(projected);
+            }
+        }
+        Some(map)
+    }
+
+    /// Where `p` is across the map, and how near it is to the light, if the
+    /// light can see it
+    fn project(&self, p: [f64; 3]) -> Option<([f64; 2], f64)> {
+        match self.eye {
+            None => Some(([dot(p, self.right), dot(p, self.up)], -dot(p, self.forward))),
+            Some(eye) => {
+                let v = [p[0] - eye[0], p[1] - eye[1], p[2] - eye[2]];
+                let d = dot(v, self.forward);
+                if d < NEAR {
+                    return None;
+                }
+                Some(([dot(v, self.right) / d, dot(v, self.up) / d], 1.0 / d))
+            }
+        }
+    }
+
+    fn texel_coords(&self, uv: [f64; 2]) -> [f64; 2] {
+        [(uv[0] - self.min[0]) / self.texel, (uv[1] - self.min[1]) / self.texel]
+    }
+
+    /// Record the nearness of the triangle with the texel coordinates and
+    /// nearnesses `corners` at each texel it covers, where it's the nearest
+    /// yet.
+    fn fill(&mut self, corners: [([f64; 2], f64); 3]) {
+        let [(a, za), (b, zb), (c, zc)] = corners;
+        let area = (b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1]);
+        if area == 0.0 {
+            return;
+        }
+        let last = self.size as f64 - 1.0;
+        let lo = |i: usize| a[i].min(b[i]).min(c[i]).max(0.0).ceil() as usize;
+        let hi = |i: usize| a[i].max(b[i]).max(c[i]).min(last).floor();
+        let (x0, y0) = (lo(0), lo(1));
+        let (x1, y1) = (hi(0), hi(1));
+        if x1 < 0.0 || y1 < 0.0 {
+            return;
+        }
+        for y in y0..(y1 as usize + 1) {
+            for x in x0..(x1 as usize + 1) {
+                let (px, py) = (x as f64, y as f64);
+                let wb = ((px - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (py - a[1])) / area;
+                let wc = ((b[0] - a[0]) * (py - a[1]) - (px - a[0]) * (b[1] - a[1])) / area;
+                let wa = 1.0 - wb - wc;
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+                let z = wa * za + wb * zb + wc * zc;
+                let texel = &mut self.nearness[y * self.size + x];
+                if z > *texel {
+                    *texel = z;
+                }
+            }
+        }
+    }
+
+    /// Whether the light can see `point`, on a surface facing the way of the
+    /// unit vector `normal`, rather than something in front of it. Points
+    /// outside the map are taken to be lit.
+    pub fn lit(&self, point: [f64; 3], normal: [f64; 3]) -> bool {
+        let texel = match self.project(point) {
+            Some((_, nearness)) if self.eye.is_some() => self.texel / nearness,
+            Some(_) => self.texel,
+            None => return true
+        };
+        let offset = NORMAL_OFFSET * texel;
+        let point = [point[0] + offset * normal[0], point[1] + offset * normal[1], point[2] + offset * normal[2]];
+        let (uv, nearness) = match self.project(point) {
+            Some(projected) => projected,
+            None => return true
+        };
+        let [x, y] = self.texel_coords(uv);
+        let (x, y) = (x.round(), y.round());
+        if x < 0.0 || y < 0.0 || x >= self.size as f64 || y >= self.size as f64 {
+            return true;
+        }
+        let nearest = self.nearness[y as usize * self.size + x as usize];
+        if nearest == f64::NEG_INFINITY {
+            return true;
+        }
+        match self.eye {
+            None => nearest - nearness <= BIAS * self.texel,
+            // Compare distances, with texels growing further from the light
+            Some(_) => {
+                let (d, d_nearest) = (1.0 / nearness, 1.0 / nearest);
+                d - d_nearest <= BIAS * self.texel * d
+            }
+        }
+    }
+}
+
+/// The shadow maps of each of the lights of a scene, `None` for those that
+/// don't cast shadows
+#[derive(Debug, Clone)]
+pub struct Shadows {
+    maps: Vec<Option<ShadowMap>>
+}
+
+impl Shadows {
+    /// No shadows at all
+    pub fn none() -> Shadows {
+        Shadows { maps: vec![] }
+    }
+
+    /// The shadows the triangles `triangles` (in screen coordinates) cast in
+    /// the light of `lighting`, with maps `size` texels on each side
+    pub fn new(lighting: &Lighting, triangles: &PolygonList, size: usize) -> Shadows {
+        Shadows { maps: lighting.lights.iter().map(|light| ShadowMap::new(light, triangles, size)).collect() }
+    }
+
+    /// Whether light `i` of the scene reaches `point`, on a surface facing
+    /// the way of `normal`
+    pub fn lit(&self, i: usize, point: [f64; 3], normal: [f64; 3]) -> bool {
+        match self.maps.get(i) {
+            Some(Some(map)) => map.lit(point, normal),
+            _ => true
+        }
+    }
+}
+
+/// Two unit vectors at right angles to each other and to the unit vector
+/// `forward`
+fn basis(forward: [f64; 3]) -> ([f64; 3], [f64; 3]) {
+    // Whichever axis is furthest from `forward` makes a steady cross product
+    let axis = if forward[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+    let right = normalized(cross(forward, axis));
+    (right, cross(right, forward))
+}
+
+fn cross(u: [f64; 3], v: [f64; 3]) -> [f64; 3] {
+    [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]]
+}
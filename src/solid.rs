@@ -1,97 +1,423 @@
-use matrix::Matrix;
+use geometry::{ EdgeList, PolygonList };
+use drawable::Drawable;
+use std::cell::RefCell;
 use std::f64::consts::PI;
+use std::cmp;
+
+thread_local! {
+    /// Scratch space for the points of spheres and tori, reused by every one
+    /// drawn on a thread
+    static POINTS: RefCell<Vec<[f64; 4]>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The number of steps of the spheres of scripts (see `add_sphere`)
+pub const SPHERE_STEPS: usize = 20;
+
+/// The number of sides of the cylinders and cones of scripts
+pub const ROUND_STEPS: usize = 24;
+
+/// Texture coordinates stretching a whole texture over a quadrilateral, in
+/// the order of the corners passed to `push_quad`
+const QUAD_UVS: [[f64; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+
+/// A rectangular prism; see `add_box`
+pub struct RectPrism {
+    pub x: f64, pub y: f64, pub z: f64,
+    pub dx: f64, pub dy: f64, pub dz: f64
+}
+
+impl Drawable for RectPrism {
+    fn emit(&self, _edges: &mut EdgeList, polys: &mut PolygonList) {
+        add_box(polys, self.x, self.y, self.z, self.dx, self.dy, self.dz);
+    }
+}
+
+/// A flat rectangle in the plane z = 0, from (x, y) to (x + w, y + h), facing
+/// the viewer
+pub struct Rect {
+    pub x: f64, pub y: f64,
+    pub w: f64, pub h: f64
+}
+
+impl Drawable for Rect {
+    fn emit(&self, _edges: &mut EdgeList, polys: &mut PolygonList) {
+        push_quad(polys,
+            [self.x, self.y + self.h, 0.0, 1.0],
+            [self.x, self.y, 0.0, 1.0],
+            [self.x + self.w, self.y, 0.0, 1.0],
+            [self.x + self.w, self.y + self.h, 0.0, 1.0],
+            [[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]]);
+    }
+}
+
+/// A sphere centered at (cx, cy, cz) of radius `r`
+pub struct Sphere {
+    pub cx: f64, pub cy: f64, pub cz: f64,
+    pub r: f64
+}
+
+impl Drawable for Sphere {
+    fn emit(&self, _edges: &mut EdgeList, polys: &mut PolygonList) {
+        add_sphere(polys, self.cx, self.cy, self.cz, self.r, SPHERE_STEPS);
+    }
+}
+
+/// A cylinder standing on the circle of radius `r` about (x, y, z) in the
+/// plane through it parallel to the x and z axes, `h` high; see
+/// `add_cylinder`
+pub struct Cylinder {
+    pub x: f64, pub y: f64, pub z: f64,
+    pub r: f64, pub h: f64
+}
+
+impl Drawable for Cylinder {
+    fn emit(&self, _edges: &mut EdgeList, polys: &mut PolygonList) {
+        add_cylinder(polys, self.x, self.y, self.z, self.r, self.h, ROUND_STEPS);
+    }
+}
+
+/// A cone on a base like a `Cylinder`'s, with its tip `h` above the center
+/// of its base; see `add_cone`
+pub struct Cone {
+    pub x: f64, pub y: f64, pub z: f64,
+    pub r: f64, pub h: f64
+}
+
+impl Drawable for Cone {
+    fn emit(&self, _edges: &mut EdgeList, polys: &mut PolygonList) {
+        add_cone(polys, self.x, self.y, self.z, self.r, self.h, ROUND_STEPS);
+    }
+}
+
+/// The surface swept out by turning the path through each point of
+/// `profile` (x from the y axis, and y along it) around the y axis; see
+/// `revolve`
+pub struct Revolution {
+    pub profile: Vec<[f64; 2]>
+}
+
+impl Drawable for Revolution {
+    fn emit(&self, _edges: &mut EdgeList, polys: &mut PolygonList) {
+        let mut profile = EdgeList::new();
+        for pair in self.profile.windows(2) {
+            profile.add_edge([pair[0][0], pair[0][1], 0.0, 1.0], [pair[1][0], pair[1][1], 0.0, 1.0]);
+        }
+        revolve(polys, &profile, ROUND_STEPS);
+    }
+}
+
+/// The prism made by pushing the polygon `outline`, in the plane z = 0, out
+/// to z = `depth`; see `extrude`
+pub struct Extrusion {
+    pub outline: Vec<[f64; 2]>,
+    pub depth: f64
+}
+
+impl Drawable for Extrusion {
+    fn emit(&self, _edges: &mut EdgeList, polys: &mut PolygonList) {
+        extrude(polys, &self.outline, self.depth);
+    }
+}
+
+/// A torus centered at (x, y, z) whose cross-sections have radius `r1` and
+/// are centered `r2` from the center
+pub struct Torus {
+    pub x: f64, pub y: f64, pub z: f64,
+    pub r1: f64, pub r2: f64
+}
+
+impl Drawable for Torus {
+    fn emit(&self, _edges: &mut EdgeList, polys: &mut PolygonList) {
+        POINTS.with(|points| {
+            torus(polys, &mut points.borrow_mut(), self.x, self.y, self.z, self.r1, self.r2);
+        });
+    }
+}
 
 // The points should be passed in clockwise order, and they will be
-// added to the list clockwise
-fn push_quad(edges: &mut Matrix, tl: [f64; 4], tr: [f64; 4], br: [f64; 4], bl: [f64; 4]) {
-    edges.push_triangle(
+// added to the list clockwise, with the texture coordinates `uvs` at them
+fn push_quad(triangles: &mut PolygonList, tl: [f64; 4], tr: [f64; 4], br: [f64; 4], bl: [f64; 4], uvs: [[f64; 2]; 4]) {
+    triangles.add_textured([
         [tl[0], tl[1], tl[2], tl[3]],
         [tr[0], tr[1], tr[2], tr[3]],
-        [br[0], br[1], br[2], br[3]]);
-    edges.push_triangle(
+        [br[0], br[1], br[2], br[3]]],
+        [uvs[0], uvs[1], uvs[2]]);
+    triangles.add_textured([
         [tl[0], tl[1], tl[2], tl[3]],
         [br[0], br[1], br[2], tr[3]],
-        [bl[0], bl[1], bl[2], br[3]]);
+        [bl[0], bl[1], bl[2], br[3]]],
+        [uvs[0], uvs[2], uvs[3]]);
 }
 
-/// Generate the triangles of a rectangular prism whose front-upper-left vertex
-/// is (x, y, z) and whose dimensions are (dx, dy, dz).
-pub fn rect_prism(triangles: &mut Matrix, x: f64, y: f64, z: f64, dx: f64, dy: f64, dz: f64) {
+/// Add the 12 triangles of an axis-aligned box (a rectangular prism) to
+/// `triangles`, untransformed. Its front-upper-left vertex is (x, y, z), and
+/// it reaches `width` along x, `height` down y, and `depth` along z. Each
+/// face has a whole texture stretched over it.
+pub fn add_box(triangles: &mut PolygonList, x: f64, y: f64, z: f64, width: f64, height: f64, depth: f64) {
+    let (dx, dy, dz) = (width, height, depth);
     // Front face:
     push_quad(triangles,
         [x, y, z, 1.0],
         [x + dx, y, z, 1.0],
         [x + dx, y - dy, z, 1.0],
-        [x, y - dy, z, 1.0]);
+        [x, y - dy, z, 1.0],
+        QUAD_UVS);
     // Back face:
     push_quad(triangles,
         [x, y, z + dz, 1.0],
         [x, y - dy, z + dz, 1.0],
         [x + dx, y - dy, z + dz, 1.0],
-        [x + dx, y, z + dz, 1.0]);
+        [x + dx, y, z + dz, 1.0],
+        QUAD_UVS);
     // Left face:
     push_quad(triangles,
         [x, y, z, 1.0],
         [x, y - dy, z, 1.0],
         [x, y - dy, z + dz, 1.0],
-        [x, y, z + dz, 1.0]);
+        [x, y, z + dz, 1.0],
+        QUAD_UVS);
     // Right face:
     push_quad(triangles,
         [x + dx, y, z, 1.0],
         [x + dx, y, z + dz, 1.0],
         [x + dx, y - dy, z + dz, 1.0],
-        [x + dx, y - dy, z, 1.0]);
+        [x + dx, y - dy, z, 1.0],
+        QUAD_UVS);
     // Top face:
     push_quad(triangles,
         [x, y, z, 1.0],
         [x, y, z + dz, 1.0],
         [x + dx, y, z + dz, 1.0],
-        [x + dx, y, z, 1.0]);
+        [x + dx, y, z, 1.0],
+        QUAD_UVS);
     // Bottom face:
     push_quad(triangles,
         [x, y - dy, z, 1.0],
         [x + dx, y - dy, z, 1.0],
         [x + dx, y - dy, z + dz, 1.0],
-        [x, y - dy, z + dz, 1.0]);
-}
-
-pub fn sphere(triangles: &mut Matrix, cx: f64, cy: f64, cz: f64, r: f64) {
-    let mut sphere_points = vec![];
-    let semicircles = 16;
-    let points_per_semi = 10; // points per semicircle
-    // Generate `semicircles` semicircles (comprising the sphere)
-    for semicirc in 0..semicircles {
-        // `a` is the angle of rotation of this semicircle
-        let a = semicirc as f64 / semicircles as f64 * 2.0 * PI;
-        let cos_a = a.cos();
-        let sin_a = a.sin();
-        // Generate the points on this semicircle
-        for pt in 0..points_per_semi + 1 {
-            let b = pt as f64 / points_per_semi as f64 * PI;
-            let cos_b = b.cos();
-            let sin_b = b.sin();
-            sphere_points.push([
-                cx + r * cos_b,
-                cy + r * sin_b * cos_a,
-                cz + r * sin_b * sin_a,
-                1.0]);
+        [x, y - dy, z + dz, 1.0],
+        QUAD_UVS);
+}
+
+/// Add the triangles of a sphere centered at (cx, cy, cz) of radius `r` to
+/// `triangles`. It's made of `steps` semicircles from pole to pole, rotated
+/// evenly about the x axis, each split into `steps / 2` arcs; more steps make
+/// a rounder sphere out of more triangles. At least 4 steps are used. Next
+/// to the poles, where a semicircle's arcs meet those of the next at a
+/// point, single triangles are added rather than quadrilaterals with a
+/// degenerate half. Textures wrap around it with u going around the x axis
+/// and v from pole to pole.
+pub fn add_sphere(triangles: &mut PolygonList, cx: f64, cy: f64, cz: f64, r: f64, steps: usize) {
+    let semicircles = cmp::max(steps, 4);
+    let arcs = semicircles / 2; // arcs per semicircle
+    POINTS.with(|points| {
+        let mut sphere_points = points.borrow_mut();
+        sphere_points.clear();
+        for semicirc in 0..semicircles {
+            // `a` is the angle of rotation of this semicircle
+            let a = semicirc as f64 / semicircles as f64 * 2.0 * PI;
+            let cos_a = a.cos();
+            let sin_a = a.sin();
+            // Generate the points on this semicircle
+            for pt in 0..arcs + 1 {
+                let b = pt as f64 / arcs as f64 * PI;
+                let cos_b = b.cos();
+                let sin_b = b.sin();
+                sphere_points.push([
+                    cx + r * cos_b,
+                    cy + r * sin_b * cos_a,
+                    cz + r * sin_b * sin_a,
+                    1.0]);
+            }
+        }
+        let point = |semicirc: usize, pt: usize| sphere_points[(semicirc % semicircles) * (arcs + 1) + pt];
+        // Not wrapped around, so the last semicircle's texture ends at 1
+        let uv = |semicirc: usize, pt: usize| [semicirc as f64 / semicircles as f64, pt as f64 / arcs as f64];
+        // Join each arc to the one beside it on the next semicircle
+        for semicirc in 0..semicircles {
+            for pt in 0..arcs {
+                let tl = point(semicirc, pt);
+                let tr = point(semicirc, pt + 1);
+                let br = point(semicirc + 1, pt + 1);
+                let bl = point(semicirc + 1, pt);
+                let uvs = [uv(semicirc, pt), uv(semicirc, pt + 1), uv(semicirc + 1, pt + 1), uv(semicirc + 1, pt)];
+                if pt == 0 {
+                    // `tl` and `bl` are the same pole
+                    triangles.add_textured([tl, tr, br], [uvs[0], uvs[1], uvs[2]]);
+                } else if pt == arcs - 1 {
+                    // `tr` and `br` are the same pole
+                    triangles.add_textured([tl, br, bl], [uvs[0], uvs[2], uvs[3]]);
+                } else {
+                    push_quad(triangles, tl, tr, br, bl, uvs);
+                }
+            }
         }
+    });
+}
+
+/// Add the triangles of a cylinder to `triangles`: its base is the circle of
+/// radius `r` about (x, y, z), parallel to the x and z axes, and it reaches
+/// `height` up the y axis. The round side is made of `steps` flat ones (at
+/// least 3), and both ends are capped. Textures wrap around the side, and
+/// are laid flat over each cap.
+pub fn add_cylinder(triangles: &mut PolygonList, x: f64, y: f64, z: f64, r: f64, height: f64, steps: usize) {
+    let steps = cmp::max(steps, 3);
+    let bottom_center = [x, y, z, 1.0];
+    let top_center = [x, y + height, z, 1.0];
+    let u = |i: usize| i as f64 / steps as f64;
+    for i in 0..steps {
+        let (b0, b1) = (rim(x, y, z, r, i, steps), rim(x, y, z, r, i + 1, steps));
+        let (t0, t1) = (rim(x, y + height, z, r, i, steps), rim(x, y + height, z, r, i + 1, steps));
+        push_quad(triangles, t0, t1, b1, b0, [[u(i), 0.0], [u(i + 1), 0.0], [u(i + 1), 1.0], [u(i), 1.0]]);
+        triangles.add_textured([top_center, t1, t0], [[0.5, 0.5], cap_uv(i + 1, steps), cap_uv(i, steps)]);
+        triangles.add_textured([bottom_center, b0, b1], [[0.5, 0.5], cap_uv(i, steps), cap_uv(i + 1, steps)]);
     }
-    // Add the triangles of the sphere to the triangle matrix
-    let len = sphere_points.len();
-    for i in 0..len {
-        // TODO: Skip degenerate triangles occuring at the poles (they're benign for now)
-        // Draw a quadrilateral on this part of the sphere
-        push_quad(triangles,
-                  sphere_points[i],
-                  sphere_points[(i + 1) % len],
-                  sphere_points[(i + points_per_semi + 1) % len],
-                  sphere_points[(i + points_per_semi) % len]);
+}
+
+/// Add the triangles of a cone to `triangles`: its base is a circle like
+/// that of a cylinder (see `add_cylinder`), which is capped, and its tip is
+/// `height` above the center of the base. The round side is made of `steps`
+/// triangles (at least 3). Textures wrap around the side, from the tip at
+/// the top to the base at the bottom, and are laid flat over the base.
+pub fn add_cone(triangles: &mut PolygonList, x: f64, y: f64, z: f64, r: f64, height: f64, steps: usize) {
+    let steps = cmp::max(steps, 3);
+    let center = [x, y, z, 1.0];
+    let tip = [x, y + height, z, 1.0];
+    let u = |i: usize| i as f64 / steps as f64;
+    for i in 0..steps {
+        let (b0, b1) = (rim(x, y, z, r, i, steps), rim(x, y, z, r, i + 1, steps));
+        triangles.add_textured([tip, b1, b0], [[(u(i) + u(i + 1)) / 2.0, 0.0], [u(i + 1), 1.0], [u(i), 1.0]]);
+        triangles.add_textured([center, b0, b1], [[0.5, 0.5], cap_uv(i, steps), cap_uv(i + 1, steps)]);
+    }
+}
+
+/// Add the triangles of the surface swept out by turning `profile` all the
+/// way around the y axis to `triangles`, in `steps` steps (at least 3). The
+/// profile is read as a curve in the plane z = 0, with x the distance from
+/// the axis, such as one made by `curve::add_hermite`; each of its edges
+/// sweeps out a band of `steps` quadrilaterals. They face outwards where the
+/// profile runs down the y axis (on the side of positive x), so a profile
+/// traced from top to bottom makes a solid, like a vase or a goblet. Where
+/// the profile touches the axis, the bands close up with single triangles.
+/// Textures wrap around it, with v going from band to band.
+pub fn revolve(triangles: &mut PolygonList, profile: &EdgeList, steps: usize) {
+    let steps = cmp::max(steps, 3);
+    let uv = |i: usize, e: usize| [i as f64 / steps as f64, e as f64 / profile.len() as f64];
+    for e in 0..profile.len() {
+        let (p, q) = (profile.col(2 * e), profile.col(2 * e + 1));
+        for i in 0..steps {
+            let (p0, p1) = (rim(0.0, p[1], 0.0, p[0], i, steps), rim(0.0, p[1], 0.0, p[0], i + 1, steps));
+            let (q0, q1) = (rim(0.0, q[1], 0.0, q[0], i, steps), rim(0.0, q[1], 0.0, q[0], i + 1, steps));
+            let uvs = [uv(i, e), uv(i + 1, e), uv(i + 1, e + 1), uv(i, e + 1)];
+            match (p[0] == 0.0, q[0] == 0.0) {
+                (false, false) => push_quad(triangles, p0, p1, q1, q0, uvs),
+                (true, false) => triangles.add_textured([p0, q1, q0], [uvs[0], uvs[2], uvs[3]]),
+                (false, true) => triangles.add_textured([p0, p1, q1], [uvs[0], uvs[1], uvs[2]]),
+                (true, true) => {}
+            }
+        }
     }
 }
 
-pub fn torus(triangles: &mut Matrix, x: f64, y: f64, z: f64, r1: f64, r2: f64) {
-    let mut torus_points = vec![];
+/// Add the triangles of a prism to `triangles`: `outline` is a closed
+/// polygon in the plane z = 0 (its last point joins back up to its first),
+/// which is pushed out to z = `depth`. Both ends are capped, each cut into
+/// triangles by ear clipping, so the outline needn't be convex, but it
+/// mustn't cross itself. It may go either way around, and `depth` may be
+/// negative; the prism faces outwards either way. Textures are laid flat
+/// over the caps, stretched to the outline's bounding box, and wrapped around
+/// the sides.
+pub fn extrude(triangles: &mut PolygonList, outline: &[[f64; 2]], depth: f64) {
+    let mut outline = outline.to_vec();
+    if outline.len() > 1 && outline[0] == outline[outline.len() - 1] {
+        outline.pop();
+    }
+    if outline.len() < 3 {
+        return;
+    }
+    // Go counterclockwise, so the caps and sides face outwards
+    let area: f64 = (0..outline.len()).map(|i| {
+        let (a, b) = (outline[i], outline[(i + 1) % outline.len()]);
+        a[0] * b[1] - b[0] * a[1]
+    }).sum();
+    if area < 0.0 {
+        outline.reverse();
+    }
+    // The front cap is the nearer, whichever way it's pushed out
+    let (near, far) = if depth >= 0.0 { (depth, 0.0) } else { (0.0, depth) };
+    let front = |p: [f64; 2]| [p[0], p[1], near, 1.0];
+    let back = |p: [f64; 2]| [p[0], p[1], far, 1.0];
+    let (mut min, mut max) = (outline[0], outline[0]);
+    for p in &outline {
+        for c in 0..2 {
+            min[c] = min[c].min(p[c]);
+            max[c] = max[c].max(p[c]);
+        }
+    }
+    // With the top of the texture at the top of the outline
+    let cap_uv = |p: [f64; 2]| [
+        if max[0] > min[0] { (p[0] - min[0]) / (max[0] - min[0]) } else { 0.0 },
+        if max[1] > min[1] { (max[1] - p[1]) / (max[1] - min[1]) } else { 0.0 }
+    ];
+    for [a, b, c] in ear_clip(&outline) {
+        triangles.add_textured([front(a), front(b), front(c)], [cap_uv(a), cap_uv(b), cap_uv(c)]);
+        triangles.add_textured([back(a), back(c), back(b)], [cap_uv(a), cap_uv(c), cap_uv(b)]);
+    }
+    let u = |i: usize| i as f64 / outline.len() as f64;
+    for i in 0..outline.len() {
+        let (a, b) = (outline[i], outline[(i + 1) % outline.len()]);
+        push_quad(triangles, front(a), back(a), back(b), front(b), [[u(i), 0.0], [u(i), 1.0], [u(i + 1), 1.0], [u(i + 1), 0.0]]);
+    }
+}
+
+/// Cut the counterclockwise polygon `outline` into triangles, each
+/// counterclockwise, by cutting off one ear (a corner with no other corner
+/// inside it) at a time. Stops early if none can be found, as for a polygon
+/// which crosses itself.
+fn ear_clip(outline: &[[f64; 2]]) -> Vec<[[f64; 2]; 3]> {
+    // Twice the signed area of the triangle `a`, `b`, `c`: positive if it's
+    // counterclockwise
+    let cross = |a: [f64; 2], b: [f64; 2], c: [f64; 2]| (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]);
+    let mut left: Vec<[f64; 2]> = outline.to_vec();
+    let mut ears = vec![];
+    while left.len() > 3 {
+        let n = left.len();
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = (left[(i + n - 1) % n], left[i], left[(i + 1) % n]);
+            cross(a, b, c) > 0.0 && left.iter().all(|&p| {
+                p == a || p == b || p == c || cross(a, b, p) < 0.0 || cross(b, c, p) < 0.0 || cross(c, a, p) < 0.0
+            })
+        });
+        match ear {
+            Some(i) => {
+                ears.push([left[(i + n - 1) % n], left[i], left[(i + 1) % n]]);
+                left.remove(i);
+            },
+            None => return ears
+        }
+    }
+    ears.push([left[0], left[1], left[2]]);
+    ears
+}
+
+/// Point `i` of `steps` around the circle of radius `r` about (x, y, z)
+/// parallel to the x and z axes
+fn rim(x: f64, y: f64, z: f64, r: f64, i: usize, steps: usize) -> [f64; 4] {
+    let a = (i % steps) as f64 / steps as f64 * 2.0 * PI;
+    [x + r * a.cos(), y, z + r * a.sin(), 1.0]
+}
+
+/// The texture coordinates of point `i` of `steps` around a circle (see
+/// `rim`), with a texture laid flat over it
+fn cap_uv(i: usize, steps: usize) -> [f64; 2] {
+    let a = (i % steps) as f64 / steps as f64 * 2.0 * PI;
+    [0.5 + a.cos() / 2.0, 0.5 + a.sin() / 2.0]
+}
+
+/// Generate the triangles of a torus. `torus_points` is scratch space, which
+/// is cleared before use.
+pub fn torus(triangles: &mut PolygonList, torus_points: &mut Vec<[f64; 4]>, x: f64, y: f64, z: f64, r1: f64, r2: f64) {
+    torus_points.clear();
     let circles = 20;
     let pts_per_circ = 16;
     // Populate `torus_points` with points of the torus
@@ -110,13 +436,104 @@ pub fn torus(triangles: &mut Matrix, x: f64, y: f64, z: f64, r1: f64, r2: f64) {
                 1.0]);
         }
     }
-    // Add torus to triangle list
+    // Add torus to triangle list, with textures wrapped around it once each
+    // way
     let len = torus_points.len();
+    let uv = |circ: usize, pt: usize| [circ as f64 / circles as f64, pt as f64 / pts_per_circ as f64];
     for i in 0..len {
+        let (circ, pt) = (i / pts_per_circ, i % pts_per_circ);
         push_quad(triangles,
             torus_points[i],
             torus_points[(i + pts_per_circ) % len],
             torus_points[(i + pts_per_circ + 1) % len],
-            torus_points[(i + 1) % len]);
+            torus_points[(i + 1) % len],
+            [uv(circ, pt), uv(circ + 1, pt), uv(circ + 1, pt + 1), uv(circ, pt + 1)]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use render::face_normal;
+
+    /// Whether triangle `i` of `triangles` faces away from `inside`, as the
+    /// outside of a solid should, so that it's kept by `cull_backfaces` only
+    /// when the viewer is on the outside
+    fn faces_out(triangles: &PolygonList, i: usize, inside: [f64; 3]) -> bool {
+        let [a, b, c] = triangles.triangle(i);
+        let n = face_normal(triangles, i);
+        let out: Vec<f64> = (0..3).map(|k| (a[k] + b[k] + c[k]) / 3.0 - inside[k]).collect();
+        n[0] * out[0] + n[1] * out[1] + n[2] * out[2] > 0.0
+    }
+
+    /// The edges from each of `points` to the next, as a profile for
+    /// `revolve`
+    fn profile(points: &[[f64; 2]]) -> EdgeList {
+        let mut edges = EdgeList::new();
+        for pair in points.windows(2) {
+            edges.add_edge([pair[0][0], pair[0][1], 0.0, 1.0], [pair[1][0], pair[1][1], 0.0, 1.0]);
+        }
+        edges
+    }
+
+    #[test]
+    fn revolve_sweeps_a_band_per_edge() {
+        let mut triangles = PolygonList::new();
+        // A can: a lid, a side, and a bottom, traced from the top down
+        revolve(&mut triangles, &profile(&[[0.0, 20.0], [10.0, 20.0], [10.0, 0.0], [0.0, 0.0]]), 12);
+        // A quadrilateral a step for the side, and a triangle for the ends
+        assert_eq!(triangles.len(), 12 * 2 + 12 + 12);
+        for i in 0..triangles.len() {
+            assert!(faces_out(&triangles, i, [0.0, 10.0, 0.0]), "{}", i);
+        }
+    }
+
+    #[test]
+    fn revolve_has_at_least_three_steps() {
+        let mut triangles = PolygonList::new();
+        revolve(&mut triangles, &profile(&[[10.0, 10.0], [10.0, 0.0]]), 1);
+        assert_eq!(triangles.len(), 3 * 2);
+    }
+
+    /// The square from (-5, -5) to (5, 5), counterclockwise
+    const SQUARE: [[f64; 2]; 4] = [[-5.0, -5.0], [5.0, -5.0], [5.0, 5.0], [-5.0, 5.0]];
+
+    #[test]
+    fn extrude_caps_and_sides_face_outwards() {
+        for &depth in [10.0, -10.0].iter() {
+            for outline in [SQUARE.to_vec(), SQUARE.iter().rev().cloned().collect()].iter() {
+                let mut triangles = PolygonList::new();
+                extrude(&mut triangles, outline, depth);
+                // Two triangles a cap and two a side
+                assert_eq!(triangles.len(), 2 * 2 + 4 * 2);
+                for i in 0..triangles.len() {
+                    assert!(faces_out(&triangles, i, [0.0, 0.0, depth / 2.0]), "{} {:?} {}", depth, outline, i);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn extrude_clips_concave_caps() {
+        // An L, whose inner corner no cap triangle may cover
+        let outline = [[0.0, 0.0], [10.0, 0.0], [10.0, 4.0], [4.0, 4.0], [4.0, 10.0], [0.0, 10.0], [0.0, 0.0]];
+        let mut triangles = PolygonList::new();
+        extrude(&mut triangles, &outline, 5.0);
+        // The repeated first point is dropped: 6 corners, so 4 triangles a
+        // cap, and 6 sides
+        assert_eq!(triangles.len(), 4 * 2 + 6 * 2);
+        let caps = (0..triangles.len()).filter(|&i| face_normal(&triangles, i)[2].abs() > 0.5);
+        let area: f64 = caps.map(|i| {
+            let [a, b, c] = triangles.triangle(i);
+            ((b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])).abs() / 2.0
+        }).sum();
+        assert_eq!(area, 2.0 * (10.0 * 4.0 + 4.0 * 6.0));
+    }
+
+    #[test]
+    fn extrude_needs_three_corners() {
+        let mut triangles = PolygonList::new();
+        extrude(&mut triangles, &[[0.0, 0.0], [1.0, 0.0], [0.0, 0.0]], 1.0);
+        assert_eq!(triangles.len(), 0);
     }
 }
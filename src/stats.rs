@@ -0,0 +1,123 @@
+use screen::{ ImageStats, Screen };
+
+/// Counts of the work done rendering an image
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderStats {
+    /// Polygons in the scene
+    pub submitted: usize,
+    /// Polygons skipped for facing away from the viewer or lying off the
+    /// screen
+    pub culled: usize,
+    /// Polygons cut down to fit the view
+    pub clipped: usize,
+    /// Polygons rasterized
+    pub drawn: usize,
+    /// Pixels written, counting each time a pixel is written again
+    pub pixels_filled: usize,
+    /// Pixels not written because something nearer was already there
+    pub z_rejects: usize
+}
+
+impl RenderStats {
+    pub fn add(&mut self, other: &RenderStats) {
+        self.submitted += other.submitted;
+        self.culled += other.culled;
+        self.clipped += other.clipped;
+        self.drawn += other.drawn;
+        self.pixels_filled += other.pixels_filled;
+        self.z_rejects += other.z_rejects;
+    }
+
+    fn summary(&self) -> String {
+        format!("{} polygons: {} culled, {} clipped, {} drawn; {} pixels filled, {} z-test rejects",
+            self.submitted, self.culled, self.clipped, self.drawn, self.pixels_filled, self.z_rejects)
+    }
+}
+
+/// A summary of the colors of the image rendered
+fn image_summary(image: &ImageStats) -> String {
+    format!("mean luminance {:.1}, mean color ({:.1}, {:.1}, {:.1}), channels from {} to {}",
+        image.mean_luminance, image.mean[0], image.mean[1], image.mean[2], image.min, image.max)
+}
+
+/// The `RenderStats` of each frame rendered (a still image being frame 0),
+/// and the `ImageStats` of its image. When disabled, nothing is recorded.
+pub struct Stats {
+    enabled: bool,
+    /// The stats of the latest render of the current frame
+    current: RenderStats,
+    current_image: Option<ImageStats>,
+    /// (frame number, stats, image stats) for each frame rendered
+    frames: Vec<(usize, RenderStats, Option<ImageStats>)>
+}
+
+impl Stats {
+    pub fn new(enabled: bool) -> Stats {
+        Stats {
+            enabled,
+            current: RenderStats::default(),
+            current_image: None,
+            frames: vec![]
+        }
+    }
+
+    /// Make a `Stats` with nothing recorded, enabled only if `self` is.
+    pub fn empty_copy(&self) -> Stats {
+        Stats::new(self.enabled)
+    }
+
+    /// Record `stats` as those of the current frame. Each render of a frame
+    /// draws everything drawn so far, so this replaces any earlier render of
+    /// it (by a `save` partway through the script, say).
+    pub fn record(&mut self, stats: &RenderStats) {
+        if self.enabled {
+            self.current = *stats;
+        }
+    }
+
+    /// Record the image on `screen` as the current frame's, like `record`.
+    pub fn record_image(&mut self, screen: &Screen) {
+        if self.enabled {
+            self.current_image = Some(screen.histogram().stats());
+        }
+    }
+
+    /// Finish the current frame, which was frame `frame`.
+    pub fn frame(&mut self, frame: usize) {
+        if self.enabled {
+            self.frames.push((frame, self.current, self.current_image.take()));
+            self.current = RenderStats::default();
+        }
+    }
+
+    /// Add the frames recorded in `other` (e.g. by another thread) to `self`.
+    pub fn merge(&mut self, other: Stats) {
+        self.frames.extend(other.frames);
+        self.frames.sort_by_key(|&(frame, _, _)| frame);
+    }
+
+    /// Print the totals, and the stats of each frame if there are several,
+    /// of running `script`. Like `Timings::report`, the report is printed all
+    /// at once.
+    pub fn report(&self, script: &str) {
+        if !self.enabled {
+            return;
+        }
+        let mut total = RenderStats::default();
+        for (_, stats, _) in &self.frames {
+            total.add(stats);
+        }
+        let mut s = format!("Render stats for {}:\n  {}\n", script, total.summary());
+        if self.frames.len() > 1 {
+            for &(i, ref stats, ref image) in &self.frames {
+                s.push_str(&format!("    frame {:<5} {}\n", i, stats.summary()));
+                if let Some(ref image) = *image {
+                    s.push_str(&format!("                {}\n", image_summary(image)));
+                }
+            }
+        } else if let Some(&(_, _, Some(ref image))) = self.frames.first() {
+            s.push_str(&format!("  {}\n", image_summary(image)));
+        }
+        print!("{}", s);
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+/// How a material reflects light: the fraction of each of red, green, and
+/// blue reflected of ambient light, diffusely, and specularly, and the color
+/// it glows with by itself, if any
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Constants {
+    pub ambient: [f64; 3],
+    pub diffuse: [f64; 3],
+    pub specular: [f64; 3],
+    pub intensity: Option<[f64; 3]>
+}
+
+/// The names a script defines: lighting constants, with the `constants`
+/// command, lights, with `light` and `point_light`, knobs, which are set by
+/// `vary` for each frame of an animation and by `set` and `setknobs`, and
+/// knob lists, the values of all the knobs saved by name with `save_knobs`.
+/// The interpreter keeps one for each thread rendering, and changes its
+/// knobs from one frame to the next.
+#[derive(Debug, Clone)]
+pub struct SymbolTable {
+    constants: HashMap<String, Constants>,
+    /// The index of each named light in the scene's lights
+    lights: HashMap<String, usize>,
+    knobs: HashMap<String, f64>,
+    knob_lists: HashMap<String, HashMap<String, f64>>
+}
+
+impl Default for SymbolTable {
+    fn default() -> SymbolTable {
+        SymbolTable::new()
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable { constants: HashMap::new(), lights: HashMap::new(), knobs: HashMap::new(), knob_lists: HashMap::new() }
+    }
+
+    /// Define `name` as `constants`, replacing what it was before.
+    pub fn set_constants(&mut self, name: &str, constants: Constants) {
+        self.constants.insert(String::from(name), constants);
+    }
+
+    pub fn constants(&self, name: &str) -> Option<&Constants> {
+        self.constants.get(name)
+    }
+
+    /// Name the light at `index` in the scene's lights `name`, unless there's
+    /// already a light called that, in which case its index is returned
+    /// instead.
+    pub fn set_light(&mut self, name: &str, index: usize) -> Option<usize> {
+        match self.lights.get(name) {
+            Some(&i) => Some(i),
+            None => {
+                self.lights.insert(String::from(name), index);
+                None
+            }
+        }
+    }
+
+    /// Set the knob `name` to `value`, adding it if it's new.
+    pub fn set_knob(&mut self, name: &str, value: f64) {
+        self.knobs.insert(String::from(name), value);
+    }
+
+    pub fn knob(&self, name: &str) -> Option<f64> {
+        self.knobs.get(name).cloned()
+    }
+
+    /// Set every knob there is to `value`.
+    pub fn set_all_knobs(&mut self, value: f64) {
+        for val in self.knobs.values_mut() {
+            *val = value;
+        }
+    }
+
+    /// Save the values of all the knobs as the knob list `name`, replacing
+    /// any saved as it before.
+    pub fn save_knobs(&mut self, name: &str) {
+        self.knob_lists.insert(String::from(name), self.knobs.clone());
+    }
+
+    /// Set each knob in the knob list `name` to its value there, leaving any
+    /// others as they are. Returns false, changing nothing, if there is no
+    /// such list.
+    pub fn restore_knobs(&mut self, name: &str) -> bool {
+        match self.knob_lists.get(name) {
+            Some(list) => {
+                for (knob, &val) in list {
+                    self.knobs.insert(knob.clone(), val);
+                }
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Forget every knob, knob list, and light, to start a new frame.
+    pub fn clear_frame(&mut self) {
+        self.lights.clear();
+        self.knobs.clear();
+        self.knob_lists.clear();
+    }
+}
@@ -0,0 +1,73 @@
+use render::Color;
+use ppm;
+use error::Result;
+
+/// How a texture is read between the centers of its texels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Filter {
+    /// The color of the nearest texel, for a blocky look
+    Nearest,
+    /// A blend of the four nearest texels, for a smooth one
+    Bilinear
+}
+
+impl Filter {
+    pub fn from_name(name: &str) -> Option<Filter> {
+        match name {
+            "nearest" => Some(Filter::Nearest),
+            "bilinear" => Some(Filter::Bilinear),
+            _ => None
+        }
+    }
+}
+
+/// An image to paint over polygons, by their texture coordinates (see
+/// `PolygonList`). It repeats in every direction past (0, 0) to (1, 1).
+#[derive(Debug, Clone)]
+pub struct Texture {
+    /// The texels, top row first
+    pub image: Vec<Vec<Color>>,
+    pub filter: Filter
+}
+
+impl Texture {
+    pub fn new(image: Vec<Vec<Color>>, filter: Filter) -> Texture {
+        Texture { image, filter }
+    }
+
+    /// Load the PPM `filename` as a texture.
+    pub fn load(filename: &str, filter: Filter) -> Result<Texture> {
+        Ok(Texture::new(ppm::load_ppm(filename)?, filter))
+    }
+
+    fn width(&self) -> usize {
+        self.image.first().map_or(0, |row| row.len())
+    }
+
+    /// The texel in column `x` and row `y`, wrapping around
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let (width, height) = (self.width() as i64, self.image.len() as i64);
+        self.image[y.rem_euclid(height) as usize][x.rem_euclid(width) as usize]
+    }
+
+    /// The color of the texture at the texture coordinates `uv`.
+    pub fn sample(&self, uv: [f64; 2]) -> Color {
+        if self.width() == 0 {
+            return Color::white();
+        }
+        // In texels, with texel centers at whole numbers
+        let x = uv[0] * self.width() as f64 - 0.5;
+        let y = uv[1] * self.image.len() as f64 - 0.5;
+        match self.filter {
+            Filter::Nearest => self.texel(x.round() as i64, y.round() as i64),
+            Filter::Bilinear => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = self.texel(x0, y0).lerp(self.texel(x0 + 1, y0), tx);
+                let bottom = self.texel(x0, y0 + 1).lerp(self.texel(x0 + 1, y0 + 1), tx);
+                top.lerp(bottom, ty)
+            }
+        }
+    }
+}
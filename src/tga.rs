@@ -0,0 +1,65 @@
+use std::io::{ self, Write };
+
+use render::Color;
+
+/// Write `image` as an uncompressed 24-bit TGA.
+pub fn write<W: Write>(out: &mut W, image: &[Vec<Color>]) -> io::Result<()> {
+    let height = image.len();
+    let width = if height > 0 { image[0].len() } else { 0 };
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "TGA images can be at most 65535 pixels across"));
+    }
+
+    let mut header = vec![];
+    header.push(0); // no image ID
+    header.push(0); // no color map
+    header.push(2); // uncompressed true color
+    header.extend_from_slice(&[0; 5]); // color map specification
+    push_u16(&mut header, 0); // x origin
+    push_u16(&mut header, 0); // y origin
+    push_u16(&mut header, width as u16);
+    push_u16(&mut header, height as u16);
+    header.push(24); // bits per pixel
+    header.push(0x20); // rows stored top-down
+    out.write_all(&header)?;
+
+    // Pixels are stored as BGR
+    let mut data = Vec::with_capacity(3 * width * height);
+    for row in image {
+        for px in row {
+            data.extend_from_slice(&[px.b, px.g, px.r]);
+        }
+    }
+    out.write_all(&data)
+}
+
+/// Append `x` to `v` in little-endian order.
+fn push_u16(v: &mut Vec<u8>, x: u16) {
+    v.extend_from_slice(&[x as u8, (x >> 8) as u8]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_stored_top_down_without_padding() {
+        let image = vec![
+            vec![Color::rgb(1, 2, 3), Color::rgb(4, 5, 6), Color::rgb(7, 8, 9)],
+            vec![Color::rgb(10, 11, 12), Color::rgb(13, 14, 15), Color::rgb(16, 17, 18)]
+        ];
+        let mut tga = vec![];
+        write(&mut tga, &image).unwrap();
+        assert_eq!(&tga[..18], &[0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 0, 2, 0, 24, 0x20]);
+        assert_eq!(&tga[18..], &[
+            3, 2, 1, 6, 5, 4, 9, 8, 7,
+            12, 11, 10, 15, 14, 13, 18, 17, 16
+        ]);
+    }
+
+    #[test]
+    fn images_too_wide_are_refused() {
+        let image = vec![vec![Color::black(); 65536]];
+        assert!(write(&mut vec![], &image).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use std::time::{Duration, Instant};
+
+/// The stages of the pipeline whose time is tracked
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    Parse,
+    Transform,
+    Cull,
+    /// Lighting the corners or faces of polygons and building shadow maps.
+    /// Lighting done a pixel at a time (for Phong shading, or by the ray
+    /// tracer) is part of `Rasterize`.
+    Shade,
+    Rasterize,
+    Write
+}
+
+const STAGES: [Stage; 6] = [Stage::Parse, Stage::Transform, Stage::Cull, Stage::Shade, Stage::Rasterize, Stage::Write];
+
+/// Wall-clock time spent in each stage, and in each frame of an animation.
+/// When disabled, `time` just runs its closure.
+pub struct Timings {
+    enabled: bool,
+    totals: [Duration; 6],
+    /// (frame number, time taken) for each frame rendered
+    frames: Vec<(usize, Duration)>
+}
+
+impl Timings {
+    pub fn new(enabled: bool) -> Timings {
+        Timings {
+            enabled,
+            totals: [Duration::new(0, 0); 6],
+            frames: vec![]
+        }
+    }
+
+    /// Make a `Timings` with nothing recorded, enabled only if `self` is.
+    pub fn empty_copy(&self) -> Timings {
+        Timings::new(self.enabled)
+    }
+
+    /// Run `f`, counting the time it takes towards `stage`, in a span named
+    /// after the stage.
+    pub fn time<T, F: FnOnce() -> T>(&mut self, stage: Stage, f: F) -> T {
+        let _span = match stage {
+            Stage::Parse => span!("parse"),
+            Stage::Transform => span!("transform"),
+            Stage::Cull => span!("cull"),
+            Stage::Shade => span!("shade"),
+            Stage::Rasterize => span!("rasterize"),
+            Stage::Write => span!("encode")
+        };
+        if !self.enabled {
+            return f();
+        }
+        let start = Instant::now();
+        let result = f();
+        self.totals[stage as usize] += start.elapsed();
+        result
+    }
+
+    /// Record the total time taken by frame `frame` of an animation.
+    pub fn frame(&mut self, frame: usize, duration: Duration) {
+        if self.enabled {
+            self.frames.push((frame, duration));
+        }
+    }
+
+    /// Add the times recorded in `other` (e.g. by another thread) to `self`.
+    pub fn merge(&mut self, other: Timings) {
+        for i in 0..self.totals.len() {
+            self.totals[i] += other.totals[i];
+        }
+        self.frames.extend(other.frames);
+        self.frames.sort_by_key(|&(frame, _)| frame);
+    }
+
+    /// Print the time spent in each stage and each frame of running `script`.
+    /// The report is printed all at once so reports from different threads
+    /// don't interleave.
+    pub fn report(&self, script: &str) {
+        if !self.enabled {
+            return;
+        }
+        let mut s = format!("Timings for {}:\n", script);
+        for &stage in STAGES.iter() {
+            s.push_str(&format!("  {:<10} {:>10.3} ms\n", format!("{:?}", stage), millis(self.totals[stage as usize])));
+        }
+        if !self.frames.is_empty() {
+            let total = self.frames.iter().fold(Duration::new(0, 0), |acc, &(_, d)| acc + d);
+            s.push_str(&format!("  {} frames, {:.3} ms average\n", self.frames.len(), millis(total) / self.frames.len() as f64));
+            for &(i, d) in &self.frames {
+                s.push_str(&format!("    frame {:<5} {:>10.3} ms\n", i, millis(d)));
+            }
+        }
+        print!("{}", s);
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1000.0 + d.subsec_nanos() as f64 / 1_000_000.0
+}
@@ -0,0 +1,50 @@
+/// Enter a `tracing` span named `$name` (a string literal), with any fields
+/// as for `tracing::info_span!`, and return a guard that exits it when
+/// dropped. Without the `tracing` feature this does nothing.
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($name:expr) => { ::tracing::info_span!($name).entered() };
+    ($name:expr, $($fields:tt)*) => { ::tracing::info_span!($name, $($fields)*).entered() };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($($args:tt)*) => { $crate::trace::NoSpan };
+}
+
+/// What `span!` gives without the `tracing` feature, in place of a guard
+#[cfg(not(feature = "tracing"))]
+pub struct NoSpan;
+
+#[cfg(feature = "tracing")]
+use std::env;
+#[cfg(feature = "tracing")]
+use std::fs::File;
+#[cfg(feature = "tracing")]
+use std::io::BufWriter;
+#[cfg(feature = "tracing")]
+use tracing_flame::{ FlameLayer, FlushGuard };
+#[cfg(feature = "tracing")]
+use tracing_subscriber::prelude::*;
+
+/// If the environment variable CSTACK_TRACE names a file, record every span
+/// to it as folded stacks, which `inferno-flamegraph` (or `flamegraph.pl`)
+/// turns into a flame graph. The stacks are flushed when the returned guard
+/// is dropped.
+#[cfg(feature = "tracing")]
+pub fn init() -> Option<FlushGuard<BufWriter<File>>> {
+    let path = match env::var("CSTACK_TRACE") {
+        Ok(path) => path,
+        Err(_) => return None
+    };
+    match FlameLayer::with_file(&path) {
+        Ok((layer, guard)) => {
+            tracing_subscriber::registry().with(layer).init();
+            Some(guard)
+        },
+        Err(e) => {
+            warn!("Could not trace to {}: {}", path, e);
+            None
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use matrix::Matrix;
+
+/// Builds a transformation out of steps, which happen to points in the order
+/// they're added: `Transform::new().translate(1.0, 0.0, 0.0).scale(2.0, 2.0,
+/// 2.0)` moves points and then scales them (so a point at the origin ends up
+/// at (2, 0, 0)). Each step is multiplied into a single 4x4 matrix as it's
+/// added. Angles are in radians, as for `Matrix`'s rotations.
+#[derive(Clone)]
+pub struct Transform {
+    matrix: Matrix
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::new()
+    }
+}
+
+impl Transform {
+    /// The transformation that leaves points where they are
+    pub fn new() -> Transform {
+        Transform { matrix: Matrix::identity() }
+    }
+
+    /// Follow the transformation with `step`.
+    pub fn then(self, step: &Matrix) -> Transform {
+        Transform { matrix: step * &self.matrix }
+    }
+
+    pub fn translate(self, dx: f64, dy: f64, dz: f64) -> Transform {
+        self.then(&Matrix::translation(dx, dy, dz))
+    }
+
+    pub fn scale(self, sx: f64, sy: f64, sz: f64) -> Transform {
+        self.then(&Matrix::dilation_xyz(sx, sy, sz))
+    }
+
+    pub fn rotate_x(self, angle: f64) -> Transform {
+        self.then(&Matrix::rotation_about_x(angle))
+    }
+
+    pub fn rotate_y(self, angle: f64) -> Transform {
+        self.then(&Matrix::rotation_about_y(angle))
+    }
+
+    pub fn rotate_z(self, angle: f64) -> Transform {
+        self.then(&Matrix::rotation_about_z(angle))
+    }
+
+    /// Rotate about `axis` (see `Matrix::rotation_about_axis`), or do nothing
+    /// if it's the zero vector.
+    pub fn rotate(self, axis: [f64; 3], angle: f64) -> Transform {
+        match Matrix::rotation_about_axis(axis, angle) {
+            Some(rotation) => self.then(&rotation),
+            None => self
+        }
+    }
+
+    /// The matrix of the whole transformation
+    pub fn matrix(self) -> Matrix {
+        self.matrix
+    }
+}
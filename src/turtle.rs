@@ -0,0 +1,94 @@
+use geometry::{ EdgeList, PolygonList };
+use drawable::Drawable;
+
+/// A turtle which draws as it moves, carrying a pen that can be lifted. It
+/// starts at the origin heading along the x axis, with its left along the y
+/// axis and its back to the viewer, so turning it with just `turn` draws in
+/// the plane of the screen.
+#[derive(Debug, Clone, Copy)]
+pub struct Turtle {
+    pub position: [f64; 3],
+    /// Unit vectors pointing ahead of, to the left of, and up from the turtle
+    pub heading: [f64; 3],
+    pub left: [f64; 3],
+    pub up: [f64; 3],
+    pub pen_down: bool
+}
+
+impl Default for Turtle {
+    fn default() -> Turtle {
+        Turtle::new()
+    }
+}
+
+impl Turtle {
+    pub fn new() -> Turtle {
+        Turtle {
+            position: [0.0, 0.0, 0.0],
+            heading: [1.0, 0.0, 0.0],
+            left: [0.0, 1.0, 0.0],
+            up: [0.0, 0.0, 1.0],
+            pen_down: true
+        }
+    }
+
+    /// Move `distance` ahead (or back, if it's negative), returning the path
+    /// taken, which is drawn if the pen is down.
+    pub fn forward(&mut self, distance: f64) -> Segment {
+        let from = self.position;
+        for axis in 0..3 {
+            self.position[axis] += distance * self.heading[axis];
+        }
+        Segment { from, to: self.position, drawn: self.pen_down }
+    }
+
+    /// Turn left by `degrees` (right, if it's negative).
+    pub fn turn(&mut self, degrees: f64) {
+        let (heading, left) = rotate(self.heading, self.left, degrees);
+        self.heading = heading;
+        self.left = left;
+    }
+
+    /// Tilt the nose up by `degrees` (down, if it's negative).
+    pub fn pitch(&mut self, degrees: f64) {
+        let (heading, up) = rotate(self.heading, self.up, degrees);
+        self.heading = heading;
+        self.up = up;
+    }
+
+    /// Roll over to the right by `degrees` (left, if it's negative).
+    pub fn roll(&mut self, degrees: f64) {
+        let (left, up) = rotate(self.left, self.up, degrees);
+        self.left = left;
+        self.up = up;
+    }
+}
+
+/// Rotate the perpendicular unit vectors `a` and `b` by `degrees` in their
+/// plane, from `a` towards `b`.
+fn rotate(a: [f64; 3], b: [f64; 3], degrees: f64) -> ([f64; 3], [f64; 3]) {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let mut ra = [0.0; 3];
+    let mut rb = [0.0; 3];
+    for axis in 0..3 {
+        ra[axis] = cos * a[axis] + sin * b[axis];
+        rb[axis] = cos * b[axis] - sin * a[axis];
+    }
+    (ra, rb)
+}
+
+/// A move of a turtle, from one point to another
+pub struct Segment {
+    pub from: [f64; 3],
+    pub to: [f64; 3],
+    /// Whether the pen was down, drawing a line along the way
+    pub drawn: bool
+}
+
+impl Drawable for Segment {
+    fn emit(&self, edges: &mut EdgeList, _polys: &mut PolygonList) {
+        if self.drawn {
+            edges.add_edge([self.from[0], self.from[1], self.from[2], 1.0], [self.to[0], self.to[1], self.to[2], 1.0]);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::mpsc::{ self, Receiver, Sender, SyncSender };
+use std::thread::{ self, JoinHandle };
+use std::sync::Arc;
+
+use render::Color;
+use ppm::{ self, Format };
+use timing::{ Timings, Stage };
+use error::{ Error, Result };
+use checkpoint::{ self, Checkpoint };
+
+/// Work for the writer thread
+enum Job {
+    /// Save an image to a file
+    Save(Vec<Vec<Color>>, String),
+    /// Record in the checkpoint that a frame is saved, given its entry
+    Record(String)
+}
+
+/// Saves the frames of an animation on a background thread, so that writing
+/// one frame to disk overlaps with rendering the next. Jobs are done in the
+/// order they are given.
+pub struct FrameWriter {
+    jobs: SyncSender<Job>,
+    checkpoint: Option<Arc<Checkpoint>>,
+    /// Images the writer thread is done with, to be drawn on again
+    spare: Receiver<Vec<Vec<Color>>>,
+    thread: JoinHandle<Result<Timings>>
+}
+
+impl FrameWriter {
+    /// Start a writer thread saving images in `format`, recording the time
+    /// it takes in `timings`, and the frames saved in `checkpoint`.
+    pub fn start(format: Format, mut timings: Timings, checkpoint: Option<Arc<Checkpoint>>) -> FrameWriter {
+        // Let the renderer get at most one frame ahead of the writer
+        let (jobs, job_rx) = mpsc::sync_channel(1);
+        let (spare_tx, spare) = mpsc::channel();
+        let thread_checkpoint = checkpoint.clone();
+        let thread = thread::spawn(move || {
+            for job in job_rx {
+                run_job(job, format, &mut timings, &spare_tx, &thread_checkpoint)?;
+            }
+            Ok(timings)
+        });
+        FrameWriter { jobs, checkpoint, spare, thread }
+    }
+
+    /// Save `image` to `filename`.
+    pub fn save(&self, image: Vec<Vec<Color>>, filename: String) {
+        // If the writer thread has stopped, `finish` reports why
+        let _ = self.jobs.send(Job::Save(image, filename));
+    }
+
+    /// Record frame `frame`, with knob values `knobs`, in the checkpoint (if
+    /// there is one) once the jobs before this are done.
+    pub fn frame_saved(&self, frame: usize, knobs: &[(&str, f64)]) {
+        if self.checkpoint.is_some() {
+            let _ = self.jobs.send(Job::Record(checkpoint::entry(frame, knobs)));
+        }
+    }
+
+    /// Get an image that has been saved and can be reused, if there is one.
+    pub fn spare_image(&self) -> Option<Vec<Vec<Color>>> {
+        self.spare.try_recv().ok()
+    }
+
+    /// Wait for every job to be done, and return the time they took.
+    pub fn finish(self) -> Result<Timings> {
+        drop(self.jobs);
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(_) => Err(Error::Script(String::from("The frame writer thread panicked")))
+        }
+    }
+}
+
+fn run_job(job: Job, format: Format, timings: &mut Timings, spare: &Sender<Vec<Vec<Color>>>, checkpoint: &Option<Arc<Checkpoint>>) -> Result<()> {
+    match job {
+        Job::Save(image, filename) => {
+            timings.time(Stage::Write, || ppm::save_as(&image, &filename, format))?;
+            let _ = spare.send(image);
+            Ok(())
+        },
+        Job::Record(entry) => match *checkpoint {
+            Some(ref checkpoint) => checkpoint.record(&entry),
+            None => Ok(())
+        }
+    }
+}